@@ -0,0 +1,2288 @@
+//! Compiles a checked program to bytecode.
+//!
+//! Names are resolved again here, the way the checker resolved them, into
+//! slots: a function's parameters and locals are slots of its frame, what
+//! it uses from the functions around it are captures, and the top-level
+//! bindings of the program are globals. A mutable local lives in a cell,
+//! so that the closures capturing it see it change.
+//!
+//! Every handler is compiled to a function that builds it: it runs the
+//! handler's setup, makes a closure of each clause and puts them together
+//! with `Instruction::Handler`. A clause takes the arguments of its
+//! operation and then the continuation, which `resume` refers to.
+
+use super::{
+    Capture, Chunk, ClauseShape, Constant, EffectSignature, Function, HandlerShape, Instruction,
+    Module, OperationSignature,
+};
+use crate::error::CodegenError;
+use crate::lexing::token::Span;
+use crate::parsing::ast::{
+    self, BinaryOp, DataConstructor, Declaration, Expression, Field, Handler, ImplMember,
+    ImportDeclaration, MatchArm, Program, RecordKey, Spanned, Statement, TemplatePart, UnaryOp,
+};
+use crate::types::decision::{Decision, Path, Step};
+use crate::types::exhaustiveness::Ctor;
+use crate::types::Checked;
+use std::collections::HashMap;
+
+/// Compiles `program`, which must have been checked into `checked`.
+pub fn compile(program: &Program, checked: &Checked) -> Result<Module, CodegenError> {
+    let mut compiler = Compiler {
+        checked,
+        declarations: Declarations::collect(&program.statements),
+        module: Module::default(),
+        frames: Vec::new(),
+        helpers: HashMap::new(),
+        impls: Vec::new(),
+    };
+
+    compiler.begin_function("<module>".to_string(), &[], &program.span)?;
+    compiler.block_value(&program.statements, &program.span, false)?;
+    compiler.emit(Instruction::Return, &program.span);
+    compiler.end_function();
+    compiler.signatures(&program.statements)?;
+    Ok(compiler.module)
+}
+
+// What the program declares that names may refer to without being bound in
+// a scope, gathered before anything is compiled.
+#[derive(Default)]
+struct Declarations {
+    // The constructors of each data type, with how many fields they take.
+    data: HashMap<String, Vec<(String, usize)>>,
+    effects: HashMap<String, Vec<OperationInfo>>,
+    // The members of the `impl`s of each effect, as in `State.local`.
+    members: HashMap<String, Vec<String>>,
+    // The effects that aliases such as `alias IntQueue = Queue<Int>` name.
+    aliases: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+struct OperationInfo {
+    name: String,
+    arity: usize,
+    // Whether the operation returns `Never`, so that its clauses can never
+    // resume the computation.
+    never: bool,
+}
+
+impl Declarations {
+    fn collect(statements: &[Statement]) -> Self {
+        let mut declarations = Declarations::default();
+        declarations.data.insert(
+            "Option".to_string(),
+            vec![("None".to_string(), 0), ("Some".to_string(), 1)],
+        );
+        for statement in statements {
+            match statement {
+                Statement::Declaration(Declaration::Data {
+                    name,
+                    data_constructors,
+                    ..
+                }) => {
+                    let constructors = data_constructors
+                        .iter()
+                        .map(|constructor| match constructor {
+                            DataConstructor::Void { name, .. } => (name.to_string(), 0),
+                            DataConstructor::Tuple { name, fields, .. } => {
+                                (name.to_string(), fields.len())
+                            }
+                            // The fields are passed as one record.
+                            DataConstructor::Record { name, .. } => (name.to_string(), 1),
+                        })
+                        .collect();
+                    declarations.data.insert(name.to_string(), constructors);
+                }
+                Statement::Declaration(Declaration::Effect { name, fields, .. }) => {
+                    let operations = fields
+                        .iter()
+                        .map(|field| OperationInfo {
+                            name: field.name.to_string(),
+                            arity: field.parameters.len(),
+                            never: matches!(
+                                field.return_type,
+                                ast::Type::Reference { name: "Never", .. }
+                            ),
+                        })
+                        .collect();
+                    declarations.effects.insert(name.to_string(), operations);
+                }
+                Statement::Declaration(Declaration::Impl {
+                    effect: ast::Type::Reference { name, .. },
+                    members,
+                    ..
+                }) => {
+                    let names = members.iter().filter_map(|member| match member {
+                        ImplMember::Function(Declaration::Function { name, .. }) => Some(*name),
+                        ImplMember::Handler(handler) => handler.name,
+                        ImplMember::Function(_) => None,
+                    });
+                    declarations
+                        .members
+                        .entry(name.to_string())
+                        .or_default()
+                        .extend(names.map(str::to_string));
+                }
+                Statement::Declaration(Declaration::TypeAlias {
+                    name,
+                    type_parameters,
+                    alias: ast::Type::Reference { name: effect, .. },
+                    ..
+                }) if type_parameters.is_empty() => {
+                    declarations
+                        .aliases
+                        .insert(name.to_string(), effect.to_string());
+                }
+                Statement::Import { module, .. } => {
+                    for (effect, operations) in host_effects(module) {
+                        declarations
+                            .effects
+                            .entry(effect.to_string())
+                            .or_insert(operations);
+                    }
+                }
+                _ => {}
+            }
+        }
+        declarations
+    }
+
+    fn operation(&self, effect: &str, name: &str) -> Option<&OperationInfo> {
+        self.effects
+            .get(effect)?
+            .iter()
+            .find(|operation| operation.name == name)
+    }
+
+    fn constructor(&self, owner: &str, name: &str) -> Option<usize> {
+        self.data
+            .get(owner)?
+            .iter()
+            .find(|(constructor, _)| constructor == name)
+            .map(|(_, fields)| *fields)
+    }
+
+    /// The fields of a constructor of `Option`, which the prelude binds by
+    /// name, as in `Some(1)`.
+    fn prelude_constructor(&self, name: &str) -> Option<usize> {
+        self.constructor("Option", name)
+    }
+
+    /// The effect `name` refers to, seeing through an alias of one.
+    fn effect<'n>(&'n self, name: &'n str) -> &'n str {
+        match self.aliases.get(name) {
+            Some(effect) if self.effects.contains_key(effect) => effect,
+            _ => name,
+        }
+    }
+}
+
+/// The effects performed by the values of a built-in module.
+fn host_effects(module: &str) -> Vec<(&'static str, Vec<OperationInfo>)> {
+    match module {
+        "std:Console" => vec![(
+            "Console",
+            vec![OperationInfo {
+                name: "log".to_string(),
+                arity: 1,
+                never: false,
+            }],
+        )],
+        _ => Vec::new(),
+    }
+}
+
+/// What the value `name` exported by a built-in module is.
+fn host_export(module: &str, name: &str) -> Option<Name> {
+    match (module, name) {
+        ("std:Console", "log") => Some(Name::Operation {
+            effect: "Console".to_string(),
+            name: "log".to_string(),
+        }),
+        ("std:Console", "Platform") => Some(Name::Global {
+            name: "Console.Platform".to_string(),
+            mutable: false,
+        }),
+        _ => None,
+    }
+}
+
+// What a name refers to where it is used.
+#[derive(Debug, Clone)]
+enum Name {
+    Local {
+        slot: u16,
+        cell: bool,
+        mutable: bool,
+    },
+    Capture {
+        index: u16,
+        cell: bool,
+        mutable: bool,
+    },
+    Global {
+        name: String,
+        mutable: bool,
+    },
+    Operation {
+        effect: String,
+        name: String,
+    },
+    // A constructor of `Option`, which the prelude binds, as in `None`.
+    Constructor {
+        owner: String,
+        name: String,
+        fields: usize,
+    },
+    // A built-in module imported as a whole, as in `Console.log`.
+    Namespace(String),
+}
+
+impl Name {
+    fn is_mutable(&self) -> bool {
+        match self {
+            Name::Local { mutable, .. }
+            | Name::Capture { mutable, .. }
+            | Name::Global { mutable, .. } => *mutable,
+            Name::Operation { .. } | Name::Constructor { .. } | Name::Namespace(_) => false,
+        }
+    }
+}
+
+// What a call calls, which decides the instruction it is compiled to.
+enum Callee {
+    Operation { effect: String, name: String },
+    Constructor { name: String },
+    Value,
+}
+
+// Somewhere a value can be stored, with what locates it already evaluated
+// into temporaries.
+enum Place {
+    Name(Name),
+    Key { record: u16, key: u16 },
+    Index { object: u16, index: u16 },
+}
+
+// A function being compiled.
+struct Frame {
+    index: u16,
+    name: String,
+    arity: u16,
+    locals: u16,
+    captures: Vec<Capture>,
+    chunk: Chunk,
+    scopes: Vec<HashMap<String, Name>>,
+    loops: Vec<Loop>,
+    // Whether a clause's `resume` is used, by the clause or its lambdas.
+    resumed: bool,
+}
+
+// The jumps out of a loop still to be patched.
+#[derive(Default)]
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct Compiler<'p> {
+    checked: &'p Checked,
+    declarations: Declarations,
+    module: Module,
+    frames: Vec<Frame>,
+    // The functions made for constructors and operations used as values,
+    // such as `Either.Left` passed to `map`.
+    helpers: HashMap<String, u16>,
+    // The effects of the `impl`s being compiled, innermost last.
+    impls: Vec<String>,
+}
+
+impl<'p> Compiler<'p> {
+    // Functions
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("there is always a function")
+    }
+
+    fn begin_function(
+        &mut self,
+        name: String,
+        parameters: &[Field],
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        let index = operand(self.module.functions.len(), "functions", span)?;
+        self.module.functions.push(Function {
+            name: name.clone(),
+            arity: 0,
+            locals: 0,
+            captures: Vec::new(),
+            chunk: Chunk::default(),
+        });
+        self.frames.push(Frame {
+            index,
+            name,
+            arity: 0,
+            locals: 0,
+            captures: Vec::new(),
+            chunk: Chunk::default(),
+            scopes: vec![HashMap::new()],
+            loops: Vec::new(),
+            resumed: false,
+        });
+        for parameter in parameters {
+            match parameter {
+                Field::Named { name, span, .. } => self.parameter(name, span)?,
+                Field::Typed { span, .. } => self.parameter("", span)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parameter(&mut self, name: &str, span: &Span) -> Result<(), CodegenError> {
+        let slot = self.local(span)?;
+        self.frame().arity += 1;
+        if !name.is_empty() {
+            self.bind(name, slot, false);
+        }
+        Ok(())
+    }
+
+    /// Finishes the innermost function, returning its index.
+    fn end_function(&mut self) -> u16 {
+        let frame = self.frames.pop().expect("begun before");
+        self.module.functions[frame.index as usize] = Function {
+            name: frame.name,
+            arity: frame.arity,
+            locals: frame.locals,
+            captures: frame.captures,
+            chunk: frame.chunk,
+        };
+        frame.index
+    }
+
+    /// Compiles a function whose body is `body`, and leaves a closure of it.
+    fn function(
+        &mut self,
+        name: String,
+        parameters: &[Field],
+        body: &[Statement],
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        self.begin_function(name, parameters, span)?;
+        self.block_value(body, span, true)?;
+        self.emit(Instruction::Return, span);
+        let index = self.end_function();
+        self.emit(Instruction::Closure(index), span);
+        Ok(())
+    }
+
+    /// Compiles `expression` into a function of no arguments, as `with`
+    /// needs, and leaves a closure of it.
+    fn thunk(&mut self, expression: &Expression) -> Result<(), CodegenError> {
+        let span = expression.span();
+        self.begin_function("<with>".to_string(), &[], &span)?;
+        self.expression(expression, true)?;
+        self.emit(Instruction::Return, &span);
+        let index = self.end_function();
+        self.emit(Instruction::Closure(index), &span);
+        Ok(())
+    }
+
+    // Code
+
+    fn emit(&mut self, instruction: Instruction, span: &Span) -> usize {
+        let chunk = &mut self.frame().chunk;
+        let offset = chunk.code.len();
+        if chunk.spans.last().map(|(_, last)| last) != Some(span) {
+            chunk.spans.push((offset as u32, span.clone()));
+        }
+        chunk.code.push(instruction);
+        offset
+    }
+
+    fn offset(&mut self) -> u32 {
+        self.frame().chunk.code.len() as u32
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.offset();
+        match &mut self.frame().chunk.code[at] {
+            Instruction::Jump(offset) | Instruction::JumpIfFalse(offset) => *offset = target,
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    fn constant(&mut self, constant: Constant, span: &Span) -> Result<u16, CodegenError> {
+        let constants = &mut self.frame().chunk.constants;
+        let index = match constants.iter().position(|other| *other == constant) {
+            Some(index) => index,
+            None => {
+                constants.push(constant);
+                constants.len() - 1
+            }
+        };
+        operand(index, "constants in a function", span)
+    }
+
+    fn string(&mut self, value: &str, span: &Span) -> Result<u16, CodegenError> {
+        self.constant(Constant::String(value.to_string()), span)
+    }
+
+    fn global(&mut self, name: &str, span: &Span) -> Result<u16, CodegenError> {
+        let index = match self.module.global(name) {
+            Some(index) => return Ok(index),
+            None => self.module.globals.len(),
+        };
+        self.module.globals.push(name.to_string());
+        operand(index, "globals", span)
+    }
+
+    fn local(&mut self, span: &Span) -> Result<u16, CodegenError> {
+        let frame = self.frame();
+        let slot = frame.locals;
+        frame.locals = slot
+            .checked_add(1)
+            .ok_or_else(|| CodegenError::Unsupported {
+                feature: "more than 65535 locals in a function".to_string(),
+                span: span.clone(),
+            })?;
+        Ok(slot)
+    }
+
+    /// Stores the value on top of the stack in a new local.
+    fn temporary(&mut self, span: &Span) -> Result<u16, CodegenError> {
+        let slot = self.local(span)?;
+        self.emit(Instruction::SetLocal(slot), span);
+        Ok(slot)
+    }
+
+    // Scopes
+
+    fn push_scope(&mut self) {
+        self.frame().scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.frame().scopes.pop();
+    }
+
+    fn insert(&mut self, name: &str, binding: Name) {
+        let scope = self
+            .frame()
+            .scopes
+            .last_mut()
+            .expect("there is always a scope");
+        scope.insert(name.to_string(), binding);
+    }
+
+    fn bind(&mut self, name: &str, slot: u16, mutable: bool) {
+        self.insert(
+            name,
+            Name::Local {
+                slot,
+                cell: mutable,
+                mutable,
+            },
+        );
+    }
+
+    /// Whether bindings made now are the program's own globals.
+    fn at_top_level(&self) -> bool {
+        self.frames.len() == 1 && self.frames[0].scopes.len() == 1
+    }
+
+    /// Binds `name` to the value on top of the stack.
+    fn define(&mut self, name: &str, mutable: bool, span: &Span) -> Result<(), CodegenError> {
+        if self.at_top_level() {
+            let global = self.global(name, span)?;
+            self.emit(Instruction::SetGlobal(global), span);
+            self.insert(
+                name,
+                Name::Global {
+                    name: name.to_string(),
+                    mutable,
+                },
+            );
+            return Ok(());
+        }
+        if mutable {
+            self.emit(Instruction::NewCell, span);
+        }
+        let slot = self.temporary(span)?;
+        self.bind(name, slot, mutable);
+        Ok(())
+    }
+
+    /// What `name` refers to in scope, without capturing it.
+    fn peek(&self, name: &str) -> Option<&Name> {
+        self.frames
+            .iter()
+            .rev()
+            .flat_map(|frame| frame.scopes.iter().rev())
+            .find_map(|scope| scope.get(name))
+    }
+
+    /// What `name` refers to in scope from the function at `depth`, which
+    /// captures it from the functions around it when it has to.
+    fn resolve_at(&mut self, depth: usize, name: &str) -> Option<Name> {
+        let frame = &mut self.frames[depth];
+        if let Some(binding) = frame.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            if name == "resume" {
+                frame.resumed = true;
+            }
+            return Some(binding.clone());
+        }
+        if depth == 0 {
+            return None;
+        }
+
+        let (capture, cell, mutable) = match self.resolve_at(depth - 1, name)? {
+            Name::Local {
+                slot,
+                cell,
+                mutable,
+            } => (Capture::Local(slot), cell, mutable),
+            Name::Capture {
+                index,
+                cell,
+                mutable,
+            } => (Capture::Capture(index), cell, mutable),
+            other => return Some(other),
+        };
+        let captures = &mut self.frames[depth].captures;
+        let index = match captures.iter().position(|other| *other == capture) {
+            Some(index) => index,
+            None => {
+                captures.push(capture);
+                captures.len() - 1
+            }
+        };
+        Some(Name::Capture {
+            index: index as u16,
+            cell,
+            mutable,
+        })
+    }
+
+    fn resolve(&mut self, name: &str) -> Option<Name> {
+        self.resolve_at(self.frames.len() - 1, name)
+    }
+
+    /// What `name` refers to where it is used as a value: a binding in
+    /// scope, then the operation of an effect with a single one, then a
+    /// constructor of `Option`, and then a global of the program or of the
+    /// host.
+    fn lookup(&mut self, name: &str) -> Name {
+        if let Some(binding) = self.resolve(name) {
+            return binding;
+        }
+        if self.declarations.operation(name, name).is_some() {
+            return Name::Operation {
+                effect: name.to_string(),
+                name: name.to_string(),
+            };
+        }
+        if let Some(fields) = self.declarations.prelude_constructor(name) {
+            return Name::Constructor {
+                owner: "Option".to_string(),
+                name: name.to_string(),
+                fields,
+            };
+        }
+        Name::Global {
+            name: name.to_string(),
+            mutable: false,
+        }
+    }
+
+    /// What assigning to `name` updates: a binding of the function itself,
+    /// or a mutable one of a function around it. Otherwise the assignment
+    /// declares a new constant.
+    fn assignable(&mut self, name: &str) -> Option<Name> {
+        let own = self
+            .frame()
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned();
+        if own.is_some() {
+            return own;
+        }
+        match self.peek(name) {
+            Some(binding) if binding.is_mutable() => self.resolve(name),
+            _ => None,
+        }
+    }
+
+    fn load(&mut self, name: &Name, span: &Span) -> Result<(), CodegenError> {
+        match name {
+            Name::Local { slot, cell, .. } => {
+                self.emit(Instruction::GetLocal(*slot), span);
+                if *cell {
+                    self.emit(Instruction::Deref, span);
+                }
+            }
+            Name::Capture { index, cell, .. } => {
+                self.emit(Instruction::GetCapture(*index), span);
+                if *cell {
+                    self.emit(Instruction::Deref, span);
+                }
+            }
+            Name::Global { name, .. } => {
+                let global = self.global(name, span)?;
+                self.emit(Instruction::GetGlobal(global), span);
+            }
+            Name::Operation { effect, name } => self.performer(effect, name, span)?,
+            Name::Constructor {
+                name, fields: 0, ..
+            } => self.variant(name, 0, span)?,
+            Name::Constructor {
+                owner,
+                name,
+                fields,
+            } => self.constructor_function(owner, name, *fields, span)?,
+            Name::Namespace(module) => {
+                return Err(CodegenError::Unsupported {
+                    feature: format!("the module `{}` as a value", module),
+                    span: span.clone(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the value on top of the stack where `name` refers to.
+    fn store(&mut self, name: &Name, span: &Span) -> Result<(), CodegenError> {
+        match name {
+            Name::Local {
+                slot, cell: true, ..
+            } => {
+                self.emit(Instruction::GetLocal(*slot), span);
+                self.emit(Instruction::SetCell, span);
+            }
+            Name::Local { slot, .. } => {
+                self.emit(Instruction::SetLocal(*slot), span);
+            }
+            Name::Capture {
+                index, cell: true, ..
+            } => {
+                self.emit(Instruction::GetCapture(*index), span);
+                self.emit(Instruction::SetCell, span);
+            }
+            Name::Global { name, .. } => {
+                let global = self.global(name, span)?;
+                self.emit(Instruction::SetGlobal(global), span);
+            }
+            Name::Capture { .. }
+            | Name::Operation { .. }
+            | Name::Constructor { .. }
+            | Name::Namespace(_) => {
+                return Err(CodegenError::Unsupported {
+                    feature: "assigning to a constant".to_string(),
+                    span: span.clone(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// A function performing the operation `effect.name`, for when the
+    /// operation is used as a value rather than called.
+    fn performer(&mut self, effect: &str, name: &str, span: &Span) -> Result<(), CodegenError> {
+        let arity = self
+            .declarations
+            .operation(effect, name)
+            .map_or(0, |operation| operation.arity);
+        let operation = Constant::Operation {
+            effect: effect.to_string(),
+            name: name.to_string(),
+        };
+        self.helper(format!("{}.{}", effect, name), arity, span, |arguments| {
+            (
+                operation,
+                Instruction::Perform {
+                    operation: 0,
+                    arguments,
+                },
+            )
+        })
+    }
+
+    /// A function building values with the constructor `name`.
+    fn constructor_function(
+        &mut self,
+        owner: &str,
+        name: &str,
+        fields: usize,
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        let tag = Constant::String(name.to_string());
+        self.helper(format!("{}.{}", owner, name), fields, span, |fields| {
+            (tag, Instruction::Variant { tag: 0, fields })
+        })
+    }
+
+    /// Leaves a closure of the function `name`, which passes its `arity`
+    /// arguments to the instruction `body` makes, the only constant of the
+    /// function being the one `body` gives with it.
+    fn helper(
+        &mut self,
+        name: String,
+        arity: usize,
+        span: &Span,
+        body: impl FnOnce(u8) -> (Constant, Instruction),
+    ) -> Result<(), CodegenError> {
+        let index = match self.helpers.get(&name) {
+            Some(index) => *index,
+            None => {
+                let count = small(arity, "arguments", span)?;
+                let (constant, instruction) = body(count);
+                self.begin_function(name.clone(), &[], span)?;
+                for _ in 0..arity {
+                    self.parameter("", span)?;
+                }
+                self.constant(constant, span)?;
+                for slot in 0..arity as u16 {
+                    self.emit(Instruction::GetLocal(slot), span);
+                }
+                self.emit(instruction, span);
+                self.emit(Instruction::Return, span);
+                let index = self.end_function();
+                self.helpers.insert(name, index);
+                index
+            }
+        };
+        self.emit(Instruction::Closure(index), span);
+        Ok(())
+    }
+
+    // Modules
+
+    /// Records what the program exports and the effects it declares, from
+    /// its top-level `statements`.
+    fn signatures(&mut self, statements: &[Statement]) -> Result<(), CodegenError> {
+        for statement in statements {
+            let declaration = match statement {
+                Statement::Declaration(declaration) => declaration,
+                _ => continue,
+            };
+            let exported = match declaration {
+                Declaration::Function {
+                    exported: true,
+                    name,
+                    span,
+                    ..
+                }
+                | Declaration::Let {
+                    exported: true,
+                    name,
+                    span,
+                    ..
+                } => vec![(name.to_string(), span)],
+                Declaration::Impl {
+                    exported: true,
+                    effect: ast::Type::Reference { name: owner, .. },
+                    members,
+                    span,
+                    ..
+                } => members
+                    .iter()
+                    .filter_map(|member| match member {
+                        ImplMember::Function(Declaration::Function { name, .. }) => Some(*name),
+                        ImplMember::Handler(handler) => Some(handler.name.unwrap_or("handler")),
+                        ImplMember::Function(_) => None,
+                    })
+                    .map(|name| (format!("{}.{}", owner, name), span))
+                    .collect(),
+                Declaration::Effect { name, span, .. } => {
+                    let operations = self.declarations.effects[*name]
+                        .iter()
+                        .map(|operation| {
+                            Ok(OperationSignature {
+                                name: operation.name.clone(),
+                                arity: small(operation.arity, "parameters", span)?,
+                                never: operation.never,
+                            })
+                        })
+                        .collect::<Result<_, CodegenError>>()?;
+                    self.module.effects.push(EffectSignature {
+                        name: name.to_string(),
+                        operations,
+                    });
+                    continue;
+                }
+                _ => continue,
+            };
+            for (name, span) in exported {
+                let global = self.global(&name, span)?;
+                self.module.exports.push(global);
+            }
+        }
+        Ok(())
+    }
+
+    // Statements
+
+    /// Binds what a block declares before any of it runs: its imports, and
+    /// its functions and `impl`s, so that they can be used before they are
+    /// written.
+    fn hoist(&mut self, statements: &[Statement]) -> Result<(), CodegenError> {
+        let top_level = self.at_top_level();
+        for statement in statements {
+            match statement {
+                Statement::Import {
+                    module,
+                    declaration,
+                    ..
+                } => self.import(module, declaration)?,
+                Statement::Declaration(Declaration::Function { name, span, .. }) => {
+                    if top_level {
+                        self.insert(
+                            name,
+                            Name::Global {
+                                name: name.to_string(),
+                                mutable: false,
+                            },
+                        );
+                    } else {
+                        // A cell, so that the function and those declared
+                        // with it can capture it before it is created.
+                        self.emit(Instruction::Unit, span);
+                        self.emit(Instruction::NewCell, span);
+                        let slot = self.temporary(span)?;
+                        self.insert(
+                            name,
+                            Name::Local {
+                                slot,
+                                cell: true,
+                                mutable: false,
+                            },
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for statement in statements {
+            match statement {
+                Statement::Declaration(Declaration::Function {
+                    name,
+                    parameters,
+                    body,
+                    span,
+                    ..
+                }) if top_level => {
+                    self.function(name.to_string(), parameters, body, span)?;
+                    let global = self.global(name, span)?;
+                    self.emit(Instruction::SetGlobal(global), span);
+                }
+                Statement::Declaration(Declaration::Impl {
+                    effect, members, ..
+                }) => self.implementation(effect, members)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn import(
+        &mut self,
+        module: &str,
+        declaration: &ImportDeclaration,
+    ) -> Result<(), CodegenError> {
+        match declaration {
+            ImportDeclaration::NamespaceImport { name, .. } => {
+                self.insert(name, Name::Namespace(module.to_string()));
+            }
+            ImportDeclaration::NamedImports { imports, .. } => {
+                for import in imports {
+                    let export = host_export(module, import.name).ok_or_else(|| {
+                        CodegenError::Unsupported {
+                            feature: format!("`{}` from `{}`", import.name, module),
+                            span: import.span.clone(),
+                        }
+                    })?;
+                    let name = import.alias.as_deref().unwrap_or(import.name);
+                    self.insert(name, export);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles the members of an `impl` into globals named after the
+    /// effect: `State.local`, or `Calc.handler` for its default handler.
+    fn implementation(
+        &mut self,
+        effect: &ast::Type,
+        members: &[ImplMember],
+    ) -> Result<(), CodegenError> {
+        let owner = match effect {
+            ast::Type::Reference { name, .. } => name.to_string(),
+            _ => return Ok(()),
+        };
+
+        // Within the `impl`, its functions can be called by name.
+        self.push_scope();
+        for member in members {
+            if let ImplMember::Function(Declaration::Function { name, .. }) = member {
+                self.insert(
+                    name,
+                    Name::Global {
+                        name: format!("{}.{}", owner, name),
+                        mutable: false,
+                    },
+                );
+            }
+        }
+
+        self.impls.push(owner.clone());
+        for member in members {
+            let (name, span) = match member {
+                ImplMember::Function(Declaration::Function {
+                    name,
+                    parameters,
+                    body,
+                    span,
+                    ..
+                }) => {
+                    let name = format!("{}.{}", owner, name);
+                    self.function(name.clone(), parameters, body, span)?;
+                    (name, span)
+                }
+                ImplMember::Function(_) => continue,
+                ImplMember::Handler(handler) => {
+                    let name = format!("{}.{}", owner, handler.name.unwrap_or("handler"));
+                    self.handler(handler, &owner, name.clone(), handler.name.is_some())?;
+                    (name, &handler.span)
+                }
+            };
+            let global = self.global(&name, span)?;
+            self.emit(Instruction::SetGlobal(global), span);
+        }
+        self.impls.pop();
+        self.pop_scope();
+        Ok(())
+    }
+
+    /// Compiles the function building `handler`, a handler of `owner`, and
+    /// leaves a closure of it. A `built` handler that is not written as the
+    /// clause of its effect's only operation takes the handler's own
+    /// parameters; every other one takes none.
+    fn handler(
+        &mut self,
+        handler: &Handler,
+        owner: &str,
+        name: String,
+        built: bool,
+    ) -> Result<(), CodegenError> {
+        let operations = self
+            .declarations
+            .effects
+            .get(owner)
+            .cloned()
+            .unwrap_or_default();
+        let shorthand = handler.operations.is_empty() && operations.len() == 1;
+        let parameters = match shorthand || !built {
+            true => &[][..],
+            false => &handler.parameters[..],
+        };
+        let span = &handler.span;
+        self.begin_function(name.clone(), parameters, span)?;
+
+        let mut clauses = Vec::new();
+        if shorthand {
+            let operation = &operations[0];
+            let resumed = self.clause(
+                format!("{}.{}", name, operation.name),
+                &handler.parameters,
+                &handler.body,
+                span,
+            )?;
+            clauses.push(ClauseShape {
+                operation: operation.name.clone(),
+                tail_resumptive: !resumed && !operation.never,
+            });
+        } else {
+            // The setup runs each time the handler is built, and its
+            // bindings are shared by the clauses.
+            self.statements(&handler.body)?;
+            for clause in &handler.operations {
+                let never = self
+                    .declarations
+                    .operation(owner, clause.name)
+                    .is_some_and(|operation| operation.never);
+                let resumed = self.clause(
+                    format!("{}.{}", name, clause.name),
+                    &clause.parameters,
+                    &clause.body,
+                    &clause.span,
+                )?;
+                clauses.push(ClauseShape {
+                    operation: clause.name.to_string(),
+                    tail_resumptive: !resumed && !never,
+                });
+            }
+        }
+
+        if let Some(clause) = &handler.return_clause {
+            self.function(
+                format!("{}.return", name),
+                std::slice::from_ref(&clause.parameter),
+                &clause.body,
+                &clause.span,
+            )?;
+        }
+        let shape = self.constant(
+            Constant::Handler(HandlerShape {
+                effect: owner.to_string(),
+                clauses,
+                finish: handler.return_clause.is_some(),
+                multishot: handler.multishot,
+            }),
+            span,
+        )?;
+        self.emit(Instruction::Handler(shape), span);
+        self.emit(Instruction::Return, span);
+        let index = self.end_function();
+        self.emit(Instruction::Closure(index), span);
+        Ok(())
+    }
+
+    /// Compiles an operation clause and leaves a closure of it, returning
+    /// whether it uses its continuation.
+    fn clause(
+        &mut self,
+        name: String,
+        parameters: &[Field],
+        body: &[Statement],
+        span: &Span,
+    ) -> Result<bool, CodegenError> {
+        self.begin_function(name, parameters, span)?;
+        self.parameter("resume", span)?;
+        self.block_value(body, span, true)?;
+        self.emit(Instruction::Return, span);
+        let resumed = self.frame().resumed;
+        let index = self.end_function();
+        self.emit(Instruction::Closure(index), span);
+        Ok(resumed)
+    }
+
+    /// Compiles `statements` in the current scope, leaving nothing.
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), CodegenError> {
+        self.hoist(statements)?;
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles `statements` in the current scope, leaving the value of the
+    /// last one, or `()` when it is not an expression. `span` is the block's.
+    fn block_value(
+        &mut self,
+        statements: &[Statement],
+        span: &Span,
+        tail: bool,
+    ) -> Result<(), CodegenError> {
+        self.hoist(statements)?;
+        let (last, rest) = match statements.split_last() {
+            Some(split) => split,
+            None => {
+                self.emit(Instruction::Unit, span);
+                return Ok(());
+            }
+        };
+        for statement in rest {
+            self.statement(statement)?;
+        }
+        match last {
+            Statement::Expression { expr, .. } => self.expression(expr, tail),
+            statement => {
+                self.statement(statement)?;
+                self.emit(Instruction::Unit, &statement.span());
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles `statements` in a scope of their own, leaving nothing.
+    fn body(&mut self, statements: &[Statement]) -> Result<(), CodegenError> {
+        self.push_scope();
+        self.statements(statements)?;
+        self.pop_scope();
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CodegenError> {
+        match statement {
+            Statement::Expression { expr, span } => {
+                self.expression(expr, false)?;
+                self.emit(Instruction::Pop, span);
+            }
+            Statement::Return { expr, span } => {
+                self.expression(expr, true)?;
+                self.emit(Instruction::Return, span);
+            }
+            Statement::Break { span } | Statement::Continue { span } => {
+                let jump = self.emit(Instruction::Jump(0), span);
+                let breaks = matches!(statement, Statement::Break { .. });
+                match self.frame().loops.last_mut() {
+                    Some(innermost) if breaks => innermost.breaks.push(jump),
+                    Some(innermost) => innermost.continues.push(jump),
+                    None => {
+                        return Err(CodegenError::Unsupported {
+                            feature: "`break` and `continue` outside a loop".to_string(),
+                            span: span.clone(),
+                        })
+                    }
+                }
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_if_branches,
+                else_branch,
+                span,
+            } => {
+                let mut ends = Vec::new();
+                let branches = std::iter::once((condition, then_branch)).chain(
+                    else_if_branches
+                        .iter()
+                        .map(|(condition, branch)| (condition, branch)),
+                );
+                for (condition, branch) in branches {
+                    self.expression(condition, false)?;
+                    let next = self.emit(Instruction::JumpIfFalse(0), span);
+                    self.body(branch)?;
+                    ends.push(self.emit(Instruction::Jump(0), span));
+                    self.patch(next);
+                }
+                if let Some(branch) = else_branch {
+                    self.body(branch)?;
+                }
+                for end in ends {
+                    self.patch(end);
+                }
+            }
+            Statement::While {
+                condition,
+                body,
+                span,
+            } => {
+                let start = self.offset();
+                self.expression(condition, false)?;
+                let exit = self.emit(Instruction::JumpIfFalse(0), span);
+                self.loop_body(body, start, span)?;
+                self.patch(exit);
+                self.end_loop(start);
+            }
+            Statement::For {
+                initializer,
+                condition,
+                increment,
+                body,
+                span,
+            } => {
+                self.push_scope();
+                self.expression(initializer, false)?;
+                self.emit(Instruction::Pop, span);
+                let start = self.offset();
+                self.expression(condition, false)?;
+                let exit = self.emit(Instruction::JumpIfFalse(0), span);
+                self.frame().loops.push(Loop::default());
+                self.body(body)?;
+                let next = self.offset();
+                self.expression(increment, false)?;
+                self.emit(Instruction::Pop, span);
+                self.emit(Instruction::Jump(start), span);
+                self.patch(exit);
+                self.end_loop(next);
+                self.pop_scope();
+            }
+            Statement::ForOf {
+                variable,
+                iterable,
+                body,
+                span,
+            } => {
+                self.expression(iterable, false)?;
+                self.each(variable, body, span)?;
+            }
+            Statement::ForIn {
+                variable,
+                iterable,
+                body,
+                span,
+            } => {
+                self.expression(iterable, false)?;
+                self.emit(Instruction::Keys, span);
+                self.each(variable, body, span)?;
+            }
+            // Bound when the block was hoisted.
+            Statement::Import { .. } => {}
+            Statement::Declaration(declaration) => self.declaration(declaration)?,
+            Statement::Error { span } => {
+                return Err(CodegenError::Unsupported {
+                    feature: "a statement that failed to parse".to_string(),
+                    span: span.clone(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// The body of a `while`, which goes back to `start` when it finishes.
+    fn loop_body(
+        &mut self,
+        body: &[Statement],
+        start: u32,
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        self.frame().loops.push(Loop::default());
+        self.body(body)?;
+        self.emit(Instruction::Jump(start), span);
+        Ok(())
+    }
+
+    /// Patches the jumps out of the innermost loop, whose `continue`s go to
+    /// `next`, now that its end is reached.
+    fn end_loop(&mut self, next: u32) {
+        let innermost = self.frame().loops.pop().expect("pushed by the loop");
+        for jump in innermost.breaks {
+            self.patch(jump);
+        }
+        for jump in innermost.continues {
+            self.frame().chunk.code[jump] = Instruction::Jump(next);
+        }
+    }
+
+    /// Runs `body` for each element of the array on top of the stack, with
+    /// `variable` bound to it.
+    fn each(
+        &mut self,
+        variable: &Expression,
+        body: &[Statement],
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        let array = self.temporary(span)?;
+        let zero = self.constant(Constant::Integer(0), span)?;
+        let one = self.constant(Constant::Integer(1), span)?;
+        self.emit(Instruction::Constant(zero), span);
+        let index = self.temporary(span)?;
+
+        let start = self.offset();
+        self.emit(Instruction::GetLocal(index), span);
+        self.emit(Instruction::GetLocal(array), span);
+        self.emit(Instruction::Length, span);
+        self.emit(Instruction::Less, span);
+        let exit = self.emit(Instruction::JumpIfFalse(0), span);
+
+        self.frame().loops.push(Loop::default());
+        self.push_scope();
+        self.emit(Instruction::GetLocal(array), span);
+        self.emit(Instruction::GetLocal(index), span);
+        self.emit(Instruction::Index, span);
+        self.bind_target(variable)?;
+        self.statements(body)?;
+        self.pop_scope();
+
+        let next = self.offset();
+        self.emit(Instruction::GetLocal(index), span);
+        self.emit(Instruction::Constant(one), span);
+        self.emit(Instruction::Add, span);
+        self.emit(Instruction::SetLocal(index), span);
+        self.emit(Instruction::Jump(start), span);
+        self.patch(exit);
+        self.end_loop(next);
+        Ok(())
+    }
+
+    /// Binds the names of `target`, such as `(a, b)`, to the parts of the
+    /// value on top of the stack.
+    fn bind_target(&mut self, target: &Expression) -> Result<(), CodegenError> {
+        match target {
+            Expression::Identifier { name, span } => self.define(name, false, span),
+            Expression::Tuple { elements, span } => {
+                let tuple = self.temporary(span)?;
+                for (index, element) in elements.iter().enumerate() {
+                    self.emit(Instruction::GetLocal(tuple), span);
+                    let index = operand(index, "tuple elements", span)?;
+                    self.emit(Instruction::Field(index), span);
+                    self.bind_target(element)?;
+                }
+                Ok(())
+            }
+            target => Err(CodegenError::Unsupported {
+                feature: "binding this pattern".to_string(),
+                span: target.span(),
+            }),
+        }
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) -> Result<(), CodegenError> {
+        match declaration {
+            Declaration::Function {
+                name,
+                parameters,
+                body,
+                span,
+                ..
+            } => {
+                // Top-level functions are globals made when hoisting.
+                if let Some(cell @ Name::Local { .. }) = self.resolve(name) {
+                    self.function(name.to_string(), parameters, body, span)?;
+                    self.store(&cell, span)?;
+                }
+            }
+            Declaration::Let {
+                mutable,
+                name,
+                value,
+                span,
+                ..
+            } => {
+                self.expression(value, false)?;
+                self.define(name, *mutable, span)?;
+            }
+            // Compiled when the block was hoisted.
+            Declaration::Impl { .. } => {}
+            // Types leave nothing behind at runtime.
+            Declaration::Brand { .. }
+            | Declaration::Data { .. }
+            | Declaration::TypeAlias { .. }
+            | Declaration::Effect { .. } => {}
+        }
+        Ok(())
+    }
+
+    // Expressions
+
+    /// Compiles `expression`, leaving its value. A call in `tail` position
+    /// replaces the function it is made from.
+    fn expression(&mut self, expression: &Expression, tail: bool) -> Result<(), CodegenError> {
+        match expression {
+            Expression::String { value, span } => {
+                let constant = self.string(value, span)?;
+                self.emit(Instruction::Constant(constant), span);
+            }
+            Expression::Template { parts, span } => {
+                for part in parts {
+                    match part {
+                        TemplatePart::Literal { value, span } => {
+                            let constant = self.string(value, span)?;
+                            self.emit(Instruction::Constant(constant), span);
+                        }
+                        TemplatePart::Expression { expression, .. } => {
+                            self.expression(expression, false)?
+                        }
+                    }
+                }
+                let count = operand(parts.len(), "parts of a template", span)?;
+                self.emit(Instruction::Interpolate(count), span);
+            }
+            Expression::Integer { value, span } => {
+                self.literal(Constant::Integer(*value), span)?;
+            }
+            Expression::Decimal { value, span } => {
+                let value = value.parse().map_err(|_| CodegenError::Unsupported {
+                    feature: format!("the decimal `{}`", value),
+                    span: span.clone(),
+                })?;
+                self.literal(Constant::Float(value), span)?;
+            }
+            Expression::BigInteger { value, span } => {
+                self.literal(Constant::BigInteger(*value), span)?;
+            }
+            Expression::BigDecimal { value, span } => {
+                self.literal(Constant::BigDecimal(value.to_string()), span)?;
+            }
+            Expression::Boolean { value, span } => {
+                let instruction = match value {
+                    true => Instruction::True,
+                    false => Instruction::False,
+                };
+                self.emit(instruction, span);
+            }
+            Expression::Symbol { name, span } => {
+                self.literal(Constant::Symbol(name.to_string()), span)?;
+            }
+            Expression::Array { elements, span } => {
+                for element in elements {
+                    self.expression(element, false)?;
+                }
+                let count = operand(elements.len(), "array elements", span)?;
+                self.emit(Instruction::Array(count), span);
+            }
+            Expression::Tuple { elements, span } if elements.is_empty() => {
+                self.emit(Instruction::Unit, span);
+            }
+            Expression::Tuple { elements, span } => {
+                for element in elements {
+                    self.expression(element, false)?;
+                }
+                let count = operand(elements.len(), "tuple elements", span)?;
+                self.emit(Instruction::Tuple(count), span);
+            }
+            Expression::Record {
+                fields,
+                spreads,
+                span,
+            } => self.record(fields, spreads, span)?,
+            Expression::Identifier { name, span } => {
+                let binding = self.lookup(name);
+                self.load(&binding, span)?;
+            }
+            Expression::Member {
+                object,
+                property,
+                span,
+            } => self.member(object, property, span)?,
+            Expression::Index {
+                object,
+                index,
+                span,
+            } => {
+                self.expression(object, false)?;
+                self.expression(index, false)?;
+                self.emit(Instruction::Index, span);
+            }
+            Expression::Binary {
+                left,
+                op,
+                right,
+                span,
+            } => self.binary(left, op, right, span)?,
+            Expression::Unary { op, expr, span } => self.unary(op, expr, span)?,
+            Expression::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => {
+                self.expression(condition, false)?;
+                let otherwise = self.emit(Instruction::JumpIfFalse(0), span);
+                self.expression(then_branch, tail)?;
+                let end = self.emit(Instruction::Jump(0), span);
+                self.patch(otherwise);
+                self.expression(else_branch, tail)?;
+                self.patch(end);
+            }
+            Expression::Assignment {
+                target,
+                op,
+                value,
+                span,
+            } => self.assignment(target, op.as_ref(), value, span)?,
+            Expression::Lambda {
+                parameters,
+                body,
+                span,
+            } => {
+                self.begin_function("<lambda>".to_string(), parameters, span)?;
+                self.expression(body, true)?;
+                self.emit(Instruction::Return, span);
+                let index = self.end_function();
+                self.emit(Instruction::Closure(index), span);
+            }
+            Expression::Block { statements, span } => {
+                self.push_scope();
+                self.block_value(statements, span, tail)?;
+                self.pop_scope();
+            }
+            Expression::FunctionCall {
+                function,
+                arguments,
+                span,
+                ..
+            } => self.call(function, arguments, span, tail)?,
+            Expression::Match {
+                scrutinee,
+                arms,
+                span,
+            } => self.match_expression(scrutinee, arms, span, tail)?,
+            Expression::Resume { expression, span } => {
+                let continuation = self.lookup("resume");
+                self.load(&continuation, span)?;
+                self.expression(expression, false)?;
+                self.emit(Instruction::Resume, span);
+            }
+            Expression::Continuation { span } => {
+                let continuation = self.lookup("resume");
+                self.load(&continuation, span)?;
+            }
+            Expression::Handle {
+                effect,
+                expression,
+                span,
+            } => {
+                self.handler_operand(effect)?;
+                self.thunk(expression)?;
+                self.emit(Instruction::Handle, span);
+            }
+            Expression::Handler(handler) => {
+                let owner = match self.impls.last() {
+                    Some(owner) => owner.clone(),
+                    None => {
+                        return Err(CodegenError::Unsupported {
+                            feature: "a handler outside an `impl`".to_string(),
+                            span: handler.span.clone(),
+                        })
+                    }
+                };
+                let name = format!("{}.<handler>", owner);
+                self.handler(handler, &owner, name, false)?;
+                self.emit(Instruction::Call(0), &handler.span);
+            }
+            Expression::With {
+                expression,
+                handler,
+                span,
+            } => {
+                self.handler_operand(handler)?;
+                self.thunk(expression)?;
+                self.emit(Instruction::Handle, span);
+            }
+            Expression::Yield { span, .. } => {
+                return Err(CodegenError::Unsupported {
+                    feature: "`yield`".to_string(),
+                    span: span.clone(),
+                })
+            }
+            Expression::Perform { span, .. } => {
+                return Err(CodegenError::Unsupported {
+                    feature: "`perform`".to_string(),
+                    span: span.clone(),
+                })
+            }
+            Expression::Error { span } => {
+                return Err(CodegenError::Unsupported {
+                    feature: "an expression that failed to parse".to_string(),
+                    span: span.clone(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, constant: Constant, span: &Span) -> Result<(), CodegenError> {
+        let constant = self.constant(constant, span)?;
+        self.emit(Instruction::Constant(constant), span);
+        Ok(())
+    }
+
+    /// `{ ...a, ...b, x: 1 }` is built up from an empty record, extended by
+    /// each spread in turn and then by the fields written out.
+    fn record(
+        &mut self,
+        fields: &HashMap<RecordKey, Expression>,
+        spreads: &[Expression],
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        let mut fields: Vec<(&RecordKey, &Expression)> = fields.iter().collect();
+        fields.sort_by_key(|(_, value)| value.span().start);
+
+        if !spreads.is_empty() {
+            let empty = self.constant(Constant::Keys(Vec::new()), span)?;
+            self.emit(Instruction::Record(empty), span);
+            for spread in spreads {
+                self.expression(spread, false)?;
+                self.emit(Instruction::Extend, span);
+            }
+        }
+        let mut keys = Vec::new();
+        for (key, value) in fields {
+            self.expression(value, false)?;
+            keys.push(match key {
+                RecordKey::String(name, _) | RecordKey::Symbol(name, _) => name.to_string(),
+            });
+        }
+        let keys = self.constant(Constant::Keys(keys), span)?;
+        self.emit(Instruction::Record(keys), span);
+        if !spreads.is_empty() {
+            self.emit(Instruction::Extend, span);
+        }
+        Ok(())
+    }
+
+    /// `X.y`: a constructor of the data type `X`, an operation or member of
+    /// the effect `X`, a value exported by a module, or a field.
+    fn member(
+        &mut self,
+        object: &Expression,
+        property: &str,
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        if let Expression::Identifier { name, .. } = object {
+            match self.peek(name) {
+                None => {
+                    let name = self.declarations.effect(name).to_string();
+                    let name = name.as_str();
+                    if let Some(fields) = self.declarations.constructor(name, property) {
+                        return match fields {
+                            0 => self.variant(property, 0, span),
+                            fields => self.constructor_function(name, property, fields, span),
+                        };
+                    }
+                    if self.declarations.operation(name, property).is_some() {
+                        return self.performer(name, property, span);
+                    }
+                    let is_member = self
+                        .declarations
+                        .members
+                        .get(name)
+                        .is_some_and(|members| members.iter().any(|member| member == property));
+                    if is_member {
+                        let global = self.global(&format!("{}.{}", name, property), span)?;
+                        self.emit(Instruction::GetGlobal(global), span);
+                        return Ok(());
+                    }
+                }
+                Some(Name::Namespace(module)) => {
+                    let module = module.clone();
+                    let export = host_export(&module, property).ok_or_else(|| {
+                        CodegenError::Unsupported {
+                            feature: format!("`{}` from `{}`", property, module),
+                            span: span.clone(),
+                        }
+                    })?;
+                    return self.load(&export, span);
+                }
+                Some(_) => {}
+            }
+        }
+        self.expression(object, false)?;
+        let key = self.string(property, span)?;
+        self.emit(Instruction::GetKey(key), span);
+        Ok(())
+    }
+
+    fn variant(&mut self, name: &str, fields: u8, span: &Span) -> Result<(), CodegenError> {
+        let tag = self.string(name, span)?;
+        self.emit(Instruction::Variant { tag, fields }, span);
+        Ok(())
+    }
+
+    /// An operand of `with`, `handle` or `++`, where the name of an effect
+    /// stands for its default handler.
+    fn handler_operand(&mut self, expression: &Expression) -> Result<(), CodegenError> {
+        if let Expression::Identifier { name, span } = expression {
+            let effect = self.declarations.effect(name).to_string();
+            if self.peek(name).is_none() && self.declarations.effects.contains_key(&effect) {
+                let global = self.global(&format!("{}.handler", effect), span)?;
+                self.emit(Instruction::GetGlobal(global), span);
+                self.emit(Instruction::Call(0), span);
+                return Ok(());
+            }
+        }
+        self.expression(expression, false)
+    }
+
+    fn binary(
+        &mut self,
+        left: &Expression,
+        op: &BinaryOp,
+        right: &Expression,
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        let instruction = match op {
+            BinaryOp::Addition(_) => Instruction::Add,
+            BinaryOp::Subtraction(_) => Instruction::Subtract,
+            BinaryOp::Multiplication(_) => Instruction::Multiply,
+            BinaryOp::Division(_) => Instruction::Divide,
+            BinaryOp::Modulus(_) => Instruction::Remainder,
+            BinaryOp::Exponentiation(_) => Instruction::Power,
+            BinaryOp::Equal(_) => Instruction::Equal,
+            BinaryOp::NotEqual(_) => Instruction::NotEqual,
+            BinaryOp::LessThan(_) => Instruction::Less,
+            BinaryOp::LessThanOrEqual(_) => Instruction::LessEqual,
+            BinaryOp::GreaterThan(_) => Instruction::Greater,
+            BinaryOp::GreaterThanOrEqual(_) => Instruction::GreaterEqual,
+            BinaryOp::BitwiseAnd(_) => Instruction::BitAnd,
+            BinaryOp::BitwiseOr(_) => Instruction::BitOr,
+            BinaryOp::BitwiseXor(_) => Instruction::BitXor,
+            BinaryOp::LeftShift(_) => Instruction::ShiftLeft,
+            BinaryOp::RightShift(_) => Instruction::ShiftRight,
+            BinaryOp::Concatenation(_) => {
+                self.handler_operand(left)?;
+                self.handler_operand(right)?;
+                self.emit(Instruction::Concat, span);
+                return Ok(());
+            }
+            // The right operand only runs when the left one does not decide
+            // the result.
+            BinaryOp::LogicalAnd(_) => {
+                self.expression(left, false)?;
+                self.emit(Instruction::Dup, span);
+                let end = self.emit(Instruction::JumpIfFalse(0), span);
+                self.emit(Instruction::Pop, span);
+                self.expression(right, false)?;
+                self.patch(end);
+                return Ok(());
+            }
+            BinaryOp::LogicalOr(_) => {
+                self.expression(left, false)?;
+                self.emit(Instruction::Dup, span);
+                let otherwise = self.emit(Instruction::JumpIfFalse(0), span);
+                let end = self.emit(Instruction::Jump(0), span);
+                self.patch(otherwise);
+                self.emit(Instruction::Pop, span);
+                self.expression(right, false)?;
+                self.patch(end);
+                return Ok(());
+            }
+            BinaryOp::NullishCoalescing(_) => {
+                self.expression(left, false)?;
+                self.emit(Instruction::Dup, span);
+                self.emit(Instruction::Unit, span);
+                self.emit(Instruction::Equal, span);
+                let end = self.emit(Instruction::JumpIfFalse(0), span);
+                self.emit(Instruction::Pop, span);
+                self.expression(right, false)?;
+                self.patch(end);
+                return Ok(());
+            }
+            BinaryOp::OptionalChaining(_) => {
+                self.expression(left, false)?;
+                match right {
+                    Expression::Identifier { name, .. } => {
+                        self.emit(Instruction::Dup, span);
+                        self.emit(Instruction::Unit, span);
+                        self.emit(Instruction::NotEqual, span);
+                        let end = self.emit(Instruction::JumpIfFalse(0), span);
+                        let key = self.string(name, span)?;
+                        self.emit(Instruction::GetKey(key), span);
+                        self.patch(end);
+                    }
+                    right => {
+                        self.emit(Instruction::Pop, span);
+                        self.expression(right, false)?;
+                    }
+                }
+                return Ok(());
+            }
+            // `x |> f` is `f(x)`, with `x` evaluated first.
+            BinaryOp::PipeOperator(_) => {
+                self.expression(left, false)?;
+                let argument = self.temporary(span)?;
+                self.expression(right, false)?;
+                self.emit(Instruction::GetLocal(argument), span);
+                self.emit(Instruction::Call(1), span);
+                return Ok(());
+            }
+        };
+        self.expression(left, false)?;
+        self.expression(right, false)?;
+        self.emit(instruction, span);
+        Ok(())
+    }
+
+    fn unary(&mut self, op: &UnaryOp, expr: &Expression, span: &Span) -> Result<(), CodegenError> {
+        let (step, post) = match op {
+            UnaryOp::Negation(_) | UnaryOp::LogicalNot(_) | UnaryOp::BitwiseNot(_) => {
+                self.expression(expr, false)?;
+                let instruction = match op {
+                    UnaryOp::Negation(_) => Instruction::Negate,
+                    UnaryOp::LogicalNot(_) => Instruction::Not,
+                    _ => Instruction::BitNot,
+                };
+                self.emit(instruction, span);
+                return Ok(());
+            }
+            UnaryOp::PreIncrement(_) => (Instruction::Add, false),
+            UnaryOp::PostIncrement(_) => (Instruction::Add, true),
+            UnaryOp::PreDecrement(_) => (Instruction::Subtract, false),
+            UnaryOp::PostDecrement(_) => (Instruction::Subtract, true),
+        };
+
+        // The value of `x++` is `x` before it is incremented, and that of
+        // `++x` the value after.
+        let place = self.place(expr)?;
+        self.load_place(&place, span)?;
+        if post {
+            self.emit(Instruction::Dup, span);
+        }
+        let one = self.constant(Constant::Integer(1), span)?;
+        self.emit(Instruction::Constant(one), span);
+        self.emit(step, span);
+        if !post {
+            self.emit(Instruction::Dup, span);
+        }
+        self.store_place(&place, span)
+    }
+
+    /// An assignment, which leaves the value assigned.
+    fn assignment(
+        &mut self,
+        target: &Expression,
+        op: Option<&BinaryOp>,
+        value: &Expression,
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        match op {
+            Some(op) => {
+                let place = self.place(target)?;
+                self.load_place(&place, span)?;
+                self.expression(value, false)?;
+                let instruction = match op {
+                    BinaryOp::Addition(_) => Instruction::Add,
+                    BinaryOp::Subtraction(_) => Instruction::Subtract,
+                    BinaryOp::Multiplication(_) => Instruction::Multiply,
+                    BinaryOp::Division(_) => Instruction::Divide,
+                    BinaryOp::Modulus(_) => Instruction::Remainder,
+                    BinaryOp::Exponentiation(_) => Instruction::Power,
+                    BinaryOp::BitwiseAnd(_) => Instruction::BitAnd,
+                    BinaryOp::BitwiseOr(_) => Instruction::BitOr,
+                    BinaryOp::BitwiseXor(_) => Instruction::BitXor,
+                    BinaryOp::LeftShift(_) => Instruction::ShiftLeft,
+                    BinaryOp::RightShift(_) => Instruction::ShiftRight,
+                    BinaryOp::Concatenation(_) => Instruction::Concat,
+                    _ => {
+                        return Err(CodegenError::Unsupported {
+                            feature: "this compound assignment".to_string(),
+                            span: span.clone(),
+                        })
+                    }
+                };
+                self.emit(instruction, span);
+                self.emit(Instruction::Dup, span);
+                self.store_place(&place, span)
+            }
+            None => match target {
+                Expression::Identifier { .. } | Expression::Tuple { .. } => {
+                    self.expression(value, false)?;
+                    self.emit(Instruction::Dup, span);
+                    self.assign_to(target)
+                }
+                target => {
+                    let place = self.place(target)?;
+                    self.expression(value, false)?;
+                    self.emit(Instruction::Dup, span);
+                    self.store_place(&place, span)
+                }
+            },
+        }
+    }
+
+    /// Assigns the value on top of the stack to `target`: to a name in
+    /// scope that can be assigned, or else to a new constant, or to each
+    /// part of a tuple.
+    fn assign_to(&mut self, target: &Expression) -> Result<(), CodegenError> {
+        match target {
+            Expression::Identifier { name, span } => match self.assignable(name) {
+                Some(binding) => self.store(&binding, span),
+                None => self.define(name, false, span),
+            },
+            Expression::Tuple { elements, span } => {
+                let tuple = self.temporary(span)?;
+                for (index, element) in elements.iter().enumerate() {
+                    self.emit(Instruction::GetLocal(tuple), span);
+                    let index = operand(index, "tuple elements", span)?;
+                    self.emit(Instruction::Field(index), span);
+                    self.assign_to(element)?;
+                }
+                Ok(())
+            }
+            target => {
+                let span = target.span();
+                let place = self.place(target)?;
+                self.store_place(&place, &span)
+            }
+        }
+    }
+
+    /// Evaluates what locates the place `target` refers to.
+    fn place(&mut self, target: &Expression) -> Result<Place, CodegenError> {
+        match target {
+            Expression::Identifier { name, span } => match self.assignable(name) {
+                Some(binding) => Ok(Place::Name(binding)),
+                None => Err(CodegenError::Unsupported {
+                    feature: format!("updating `{}`, which is not in scope", name),
+                    span: span.clone(),
+                }),
+            },
+            Expression::Member {
+                object,
+                property,
+                span,
+            } => {
+                self.expression(object, false)?;
+                let record = self.temporary(span)?;
+                let key = self.string(property, span)?;
+                Ok(Place::Key { record, key })
+            }
+            Expression::Index {
+                object,
+                index,
+                span,
+            } => {
+                self.expression(object, false)?;
+                let object = self.temporary(span)?;
+                self.expression(index, false)?;
+                let index = self.temporary(span)?;
+                Ok(Place::Index { object, index })
+            }
+            target => Err(CodegenError::Unsupported {
+                feature: "assigning to this expression".to_string(),
+                span: target.span(),
+            }),
+        }
+    }
+
+    fn load_place(&mut self, place: &Place, span: &Span) -> Result<(), CodegenError> {
+        match place {
+            Place::Name(binding) => return self.load(binding, span),
+            Place::Key { record, key } => {
+                self.emit(Instruction::GetLocal(*record), span);
+                self.emit(Instruction::GetKey(*key), span);
+            }
+            Place::Index { object, index } => {
+                self.emit(Instruction::GetLocal(*object), span);
+                self.emit(Instruction::GetLocal(*index), span);
+                self.emit(Instruction::Index, span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the value on top of the stack in `place`.
+    fn store_place(&mut self, place: &Place, span: &Span) -> Result<(), CodegenError> {
+        match place {
+            Place::Name(binding) => return self.store(binding, span),
+            Place::Key { record, key } => {
+                self.emit(Instruction::GetLocal(*record), span);
+                self.emit(Instruction::SetKey(*key), span);
+            }
+            Place::Index { object, index } => {
+                self.emit(Instruction::GetLocal(*object), span);
+                self.emit(Instruction::GetLocal(*index), span);
+                self.emit(Instruction::SetIndex, span);
+            }
+        }
+        Ok(())
+    }
+
+    /// What calling `function` calls: an operation, which is performed, a
+    /// constructor, which builds a value, or anything else.
+    fn callee(&self, function: &Expression) -> Callee {
+        let operation = |effect: &str, name: &str| Callee::Operation {
+            effect: effect.to_string(),
+            name: name.to_string(),
+        };
+        match function {
+            Expression::Identifier { name, .. } => match self.peek(name) {
+                Some(Name::Operation { effect, name }) => operation(effect, name),
+                Some(_) => Callee::Value,
+                None if self.declarations.operation(name, name).is_some() => operation(name, name),
+                None if self.declarations.prelude_constructor(name).is_some() => {
+                    Callee::Constructor {
+                        name: name.to_string(),
+                    }
+                }
+                None => Callee::Value,
+            },
+            Expression::Member {
+                object, property, ..
+            } => match object.as_ref() {
+                Expression::Identifier { name, .. } => match self.peek(name) {
+                    None if self.declarations.constructor(name, property).is_some() => {
+                        Callee::Constructor {
+                            name: property.to_string(),
+                        }
+                    }
+                    None if self
+                        .declarations
+                        .operation(self.declarations.effect(name), property)
+                        .is_some() =>
+                    {
+                        operation(self.declarations.effect(name), property)
+                    }
+                    Some(Name::Namespace(module)) => match host_export(module, property) {
+                        Some(Name::Operation { effect, name }) => operation(&effect, &name),
+                        _ => Callee::Value,
+                    },
+                    _ => Callee::Value,
+                },
+                _ => Callee::Value,
+            },
+            _ => Callee::Value,
+        }
+    }
+
+    fn call(
+        &mut self,
+        function: &Expression,
+        arguments: &[Expression],
+        span: &Span,
+        tail: bool,
+    ) -> Result<(), CodegenError> {
+        let count = small(arguments.len(), "arguments", span)?;
+        let callee = self.callee(function);
+        if let Callee::Value = callee {
+            self.expression(function, false)?;
+        }
+        for argument in arguments {
+            self.expression(argument, false)?;
+        }
+        match callee {
+            Callee::Operation { effect, name } => {
+                let operation = self.constant(Constant::Operation { effect, name }, span)?;
+                self.emit(
+                    Instruction::Perform {
+                        operation,
+                        arguments: count,
+                    },
+                    span,
+                );
+            }
+            Callee::Constructor { name } => self.variant(&name, count, span)?,
+            Callee::Value if tail => {
+                self.emit(Instruction::TailCall(count), span);
+            }
+            Callee::Value => {
+                self.emit(Instruction::Call(count), span);
+            }
+        }
+        Ok(())
+    }
+
+    // Matches
+
+    /// Compiles a `match` by following the decision tree the checker built
+    /// for it, over the scrutinee kept in a temporary.
+    fn match_expression(
+        &mut self,
+        scrutinee: &Expression,
+        arms: &[MatchArm],
+        span: &Span,
+        tail: bool,
+    ) -> Result<(), CodegenError> {
+        let checked = self.checked;
+        let decision = checked
+            .decisions
+            .binary_search_by(|(other, _)| (other.start, other.end).cmp(&(span.start, span.end)))
+            .map(|index| &checked.decisions[index].1)
+            .map_err(|_| CodegenError::Unsupported {
+                feature: "a `match` the checker did not compile".to_string(),
+                span: span.clone(),
+            })?;
+
+        self.expression(scrutinee, false)?;
+        let value = self.temporary(span)?;
+        let mut ends = Vec::new();
+        self.decision(decision, value, arms, span, tail, &mut ends)?;
+        for end in ends {
+            self.patch(end);
+        }
+        Ok(())
+    }
+
+    fn decision(
+        &mut self,
+        decision: &Decision,
+        value: u16,
+        arms: &[MatchArm],
+        span: &Span,
+        tail: bool,
+        ends: &mut Vec<usize>,
+    ) -> Result<(), CodegenError> {
+        match decision {
+            Decision::Fail => {
+                self.emit(Instruction::NoMatch, span);
+            }
+            Decision::Leaf { arm, bindings } => {
+                let arm = &arms[*arm];
+                self.push_scope();
+                self.bindings(bindings, value, &arm.span)?;
+                self.expression(&arm.body, tail)?;
+                self.pop_scope();
+                ends.push(self.emit(Instruction::Jump(0), &arm.span));
+            }
+            Decision::Guard {
+                arm,
+                bindings,
+                otherwise,
+            } => {
+                let arm = &arms[*arm];
+                self.push_scope();
+                self.bindings(bindings, value, &arm.span)?;
+                if let Some(guard) = &arm.guard {
+                    self.expression(guard, false)?;
+                }
+                let next = self.emit(Instruction::JumpIfFalse(0), &arm.span);
+                self.expression(&arm.body, tail)?;
+                self.pop_scope();
+                ends.push(self.emit(Instruction::Jump(0), &arm.span));
+                self.patch(next);
+                self.decision(otherwise, value, arms, span, tail, ends)?;
+            }
+            Decision::Switch {
+                path,
+                cases,
+                default,
+            } => {
+                for (index, (ctor, decision)) in cases.iter().enumerate() {
+                    // The last of all the constructors a value can have
+                    // needs no test.
+                    let last = index + 1 == cases.len() && default.is_none();
+                    let next = match last {
+                        true => None,
+                        false => self.test(ctor, value, path, span)?,
+                    };
+                    self.decision(decision, value, arms, span, tail, ends)?;
+                    if let Some(next) = next {
+                        self.patch(next);
+                    }
+                }
+                if let Some(default) = default {
+                    self.decision(default, value, arms, span, tail, ends)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds the names an arm's pattern binds to the parts of the value.
+    fn bindings(
+        &mut self,
+        bindings: &[(String, Path)],
+        value: u16,
+        span: &Span,
+    ) -> Result<(), CodegenError> {
+        for (name, path) in bindings {
+            self.load_path(value, path, span)?;
+            let slot = self.temporary(span)?;
+            self.bind(name, slot, false);
+        }
+        Ok(())
+    }
+
+    fn load_path(&mut self, value: u16, path: &Path, span: &Span) -> Result<(), CodegenError> {
+        self.emit(Instruction::GetLocal(value), span);
+        for step in &path.0 {
+            let instruction = match step {
+                Step::Field(index) => Instruction::Field(operand(*index, "fields", span)?),
+                // A field only some members of a union of records have may
+                // be missing.
+                Step::Key(name) => Instruction::TryKey(self.string(name, span)?),
+                Step::Index(index) => Instruction::Element(operand(*index, "elements", span)?),
+                Step::Slice(start) => Instruction::Slice(operand(*start, "elements", span)?),
+            };
+            self.emit(instruction, span);
+        }
+        Ok(())
+    }
+
+    /// Tests whether the part of the value at `path` was built with `ctor`,
+    /// returning the jump taken when it was not, if it needs testing.
+    fn test(
+        &mut self,
+        ctor: &Ctor,
+        value: u16,
+        path: &Path,
+        span: &Span,
+    ) -> Result<Option<usize>, CodegenError> {
+        let test = match ctor {
+            Ctor::Tuple | Ctor::Record(_) => return Ok(None),
+            Ctor::Variant(name) => Some(Instruction::IsVariant(self.string(name, span)?)),
+            // `true` is tested by the value itself.
+            Ctor::Boolean(true) => None,
+            Ctor::Boolean(false) => Some(Instruction::Not),
+            Ctor::Literal(text) => {
+                let constant = literal_constant(text).ok_or_else(|| CodegenError::Unsupported {
+                    feature: format!("matching `{}`", text),
+                    span: span.clone(),
+                })?;
+                let constant = self.constant(constant, span)?;
+                self.load_path(value, path, span)?;
+                self.emit(Instruction::Constant(constant), span);
+                Some(Instruction::Equal)
+            }
+            Ctor::Array {
+                length,
+                rest: false,
+            } => Some(Instruction::IsLength(operand(*length, "elements", span)?)),
+            Ctor::Array { length, rest: true } => Some(Instruction::IsLengthAtLeast(operand(
+                *length, "elements", span,
+            )?)),
+        };
+        if !matches!(ctor, Ctor::Literal(_)) {
+            self.load_path(value, path, span)?;
+        }
+        if let Some(test) = test {
+            self.emit(test, span);
+        }
+        Ok(Some(self.emit(Instruction::JumpIfFalse(0), span)))
+    }
+}
+
+/// The constant for a literal in a decision tree, from the text the checker
+/// gives it, as in `5`, `1.5`, `5n`, `"x"` or `Symbol(x)`.
+fn literal_constant(text: &str) -> Option<Constant> {
+    if let Some(quoted) = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    {
+        return unescape(quoted).map(Constant::String);
+    }
+    if let Some(name) = text
+        .strip_prefix("Symbol(")
+        .and_then(|text| text.strip_suffix(')'))
+    {
+        return Some(Constant::Symbol(name.to_string()));
+    }
+    if let Some(number) = text.strip_suffix('n') {
+        return match number.parse() {
+            Ok(value) => Some(Constant::BigInteger(value)),
+            Err(_) => Some(Constant::BigDecimal(number.to_string())),
+        };
+    }
+    match text.parse() {
+        Ok(value) => Some(Constant::Integer(value)),
+        Err(_) => text.parse().ok().map(Constant::Float),
+    }
+}
+
+/// Undoes the escaping of a string written with `{:?}`.
+fn unescape(quoted: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => value.push('\n'),
+            't' => value.push('\t'),
+            'r' => value.push('\r'),
+            '0' => value.push('\0'),
+            'u' => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                value.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+            }
+            c => value.push(c),
+        }
+    }
+    Some(value)
+}
+
+/// `count` as the operand of an instruction.
+fn operand(count: usize, what: &str, span: &Span) -> Result<u16, CodegenError> {
+    u16::try_from(count).map_err(|_| CodegenError::Unsupported {
+        feature: format!("more than {} {}", u16::MAX, what),
+        span: span.clone(),
+    })
+}
+
+/// `count` as the operand of an instruction that takes a byte.
+fn small(count: usize, what: &str, span: &Span) -> Result<u8, CodegenError> {
+    u8::try_from(count).map_err(|_| CodegenError::Unsupported {
+        feature: format!("more than {} {}", u8::MAX, what),
+        span: span.clone(),
+    })
+}
@@ -0,0 +1,136 @@
+//! Renders modules as text for debugging, one instruction per line with its
+//! offset, the span it was compiled from and what its operands refer to.
+
+use super::{Capture, Chunk, Constant, Function, Instruction, Module};
+use std::fmt::Write;
+
+impl Module {
+    /// A listing of every function in the module, in order.
+    pub fn disassemble(&self) -> String {
+        let listings: Vec<String> = self
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(index, function)| format!("#{} {}", index, function.disassemble(self)))
+            .collect();
+        listings.join("\n")
+    }
+}
+
+impl Function {
+    /// A listing of the function, whose operands are looked up in `module`.
+    pub fn disassemble(&self, module: &Module) -> String {
+        let mut output = format!(
+            "function {} (arity {}, locals {}",
+            self.name, self.arity, self.locals
+        );
+        if !self.captures.is_empty() {
+            let captures: Vec<String> = self
+                .captures
+                .iter()
+                .map(|capture| match capture {
+                    Capture::Local(slot) => format!("local {}", slot),
+                    Capture::Capture(index) => format!("capture {}", index),
+                })
+                .collect();
+            write!(output, ", captures [{}]", captures.join(", ")).unwrap();
+        }
+        output.push_str(")\n");
+
+        let mut previous = None;
+        for (offset, instruction) in self.chunk.code.iter().enumerate() {
+            let span = self.chunk.span(offset);
+            let shown = match &span {
+                Some(span) if previous.as_ref() != Some(span) => {
+                    format!("{}..{}", span.start, span.end)
+                }
+                _ => "|".to_string(),
+            };
+            previous = span;
+
+            let operands = operands(instruction, &self.chunk, module);
+            let line = format!(
+                "  {:04}  {:>9}  {:<18} {}",
+                offset, shown, instruction, operands
+            );
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+        output
+    }
+}
+
+fn operands(instruction: &Instruction, chunk: &Chunk, module: &Module) -> String {
+    let constant = |index: &u16| match chunk.constants.get(*index as usize) {
+        Some(constant) => format!("{} ({})", index, show_constant(constant)),
+        None => format!("{} (?)", index),
+    };
+    match instruction {
+        Instruction::Constant(index)
+        | Instruction::Record(index)
+        | Instruction::GetKey(index)
+        | Instruction::TryKey(index)
+        | Instruction::SetKey(index)
+        | Instruction::IsVariant(index)
+        | Instruction::Handler(index) => constant(index),
+        Instruction::Variant { tag, fields } => format!("{}, {}", constant(tag), fields),
+        Instruction::Perform {
+            operation,
+            arguments,
+        } => format!("{}, {}", constant(operation), arguments),
+        Instruction::GetGlobal(index) | Instruction::SetGlobal(index) => {
+            match module.globals.get(*index as usize) {
+                Some(name) => format!("{} ({})", index, name),
+                None => format!("{} (?)", index),
+            }
+        }
+        Instruction::Closure(index) => match module.functions.get(*index as usize) {
+            Some(function) => format!("{} ({})", index, function.name),
+            None => format!("{} (?)", index),
+        },
+        Instruction::GetLocal(operand)
+        | Instruction::SetLocal(operand)
+        | Instruction::GetCapture(operand)
+        | Instruction::Array(operand)
+        | Instruction::Tuple(operand)
+        | Instruction::Field(operand)
+        | Instruction::Element(operand)
+        | Instruction::Slice(operand)
+        | Instruction::IsLength(operand)
+        | Instruction::IsLengthAtLeast(operand)
+        | Instruction::Interpolate(operand) => operand.to_string(),
+        Instruction::Call(count) | Instruction::TailCall(count) => count.to_string(),
+        Instruction::Jump(target) | Instruction::JumpIfFalse(target) => target.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn show_constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Integer(value) => value.to_string(),
+        Constant::Float(value) => format!("{:?}", value),
+        Constant::BigInteger(value) => format!("{}n", value),
+        Constant::BigDecimal(value) => format!("{}n", value),
+        Constant::String(value) => format!("{:?}", value),
+        Constant::Symbol(name) => format!("Symbol({})", name),
+        Constant::Keys(keys) => format!("{{ {} }}", keys.join(", ")),
+        Constant::Operation { effect, name } => format!("{}.{}", effect, name),
+        Constant::Handler(shape) => {
+            let clauses: Vec<String> = shape
+                .clauses
+                .iter()
+                .map(|clause| match clause.tail_resumptive {
+                    true => format!("{} (tail)", clause.operation),
+                    false => clause.operation.clone(),
+                })
+                .collect();
+            format!(
+                "{}handler {} {{ {} }}{}",
+                if shape.multishot { "multi " } else { "" },
+                shape.effect,
+                clauses.join(", "),
+                if shape.finish { " return" } else { "" }
+            )
+        }
+    }
+}
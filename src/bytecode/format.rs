@@ -0,0 +1,587 @@
+//! The `.asurac` file format modules are saved in. All numbers are little
+//! endian, and strings are UTF-8 after their length as a `u32`. A file is:
+//!
+//! - the magic number `MAGIC` and the `VERSION` of the format, a `u32`;
+//! - the names of the globals;
+//! - the exported globals, by index;
+//! - the signatures of the effects the module declares;
+//! - the function table: each function's name, arity, slots for locals,
+//!   captures, constant pool and code;
+//! - the debug info: each function's line table, in the same order.
+//!
+//! Lists start with their length as a `u32`. Reading a file checks no more
+//! than that it is well formed; `Module::from_bytes` verifies the module as
+//! well, before anything runs it.
+
+use super::verifier::VerifyError;
+use super::{
+    Capture, Chunk, ClauseShape, Constant, EffectSignature, Function, HandlerShape, Instruction,
+    Module, OperationSignature,
+};
+use std::fmt;
+
+pub const MAGIC: [u8; 4] = *b"\0asc";
+pub const VERSION: u32 = 1;
+/// The extension of files in this format.
+pub const EXTENSION: &str = "asurac";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    NotBytecode,
+    UnsupportedVersion {
+        found: u32,
+    },
+    // The file ends before what is being read at `offset`.
+    Truncated {
+        what: &'static str,
+        offset: usize,
+    },
+    InvalidTag {
+        what: &'static str,
+        tag: u8,
+        offset: usize,
+    },
+    InvalidString {
+        offset: usize,
+    },
+    TrailingBytes {
+        offset: usize,
+    },
+    // The file is well formed, but the module in it is not one that can be
+    // run, because of the function named, if the error is in one.
+    Invalid {
+        function: Option<String>,
+        offset: Option<usize>,
+        error: VerifyError,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotBytecode => write!(f, "not an .{} file", EXTENSION),
+            LoadError::UnsupportedVersion { found } => write!(
+                f,
+                "the file is of version {} of the format, but only version {} is supported",
+                found, VERSION
+            ),
+            LoadError::Truncated { what, offset } => {
+                write!(f, "the file ends in {} at byte {}", what, offset)
+            }
+            LoadError::InvalidTag { what, tag, offset } => {
+                write!(f, "invalid {} {} at byte {}", what, tag, offset)
+            }
+            LoadError::InvalidString { offset } => {
+                write!(f, "the string at byte {} is not valid UTF-8", offset)
+            }
+            LoadError::TrailingBytes { offset } => {
+                write!(f, "unexpected bytes after the module at byte {}", offset)
+            }
+            LoadError::Invalid {
+                function: Some(function),
+                offset: Some(offset),
+                error,
+            } => write!(f, "in `{}` at instruction {}: {}", function, offset, error),
+            LoadError::Invalid {
+                function: Some(function),
+                offset: None,
+                error,
+            } => write!(f, "in `{}`: {}", function, error),
+            LoadError::Invalid { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl Module {
+    /// The module as the contents of an `.asurac` file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Reads a module from the contents of an `.asurac` file, and verifies
+    /// that it can be run.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Module, LoadError> {
+        let module = decode(bytes)?;
+        module.verify()?;
+        Ok(module)
+    }
+}
+
+/// Writes `module` in the format.
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend(MAGIC);
+    writer.u32(VERSION);
+
+    writer.list(&module.globals, |writer, global| writer.string(global));
+    writer.list(&module.exports, |writer, export| writer.u16(*export));
+    writer.list(&module.effects, |writer, effect| {
+        writer.string(&effect.name);
+        writer.list(&effect.operations, |writer, operation| {
+            writer.string(&operation.name);
+            writer.u8(operation.arity);
+            writer.bool(operation.never);
+        });
+    });
+    writer.list(&module.functions, |writer, function| {
+        writer.string(&function.name);
+        writer.u16(function.arity);
+        writer.u16(function.locals);
+        writer.list(&function.captures, |writer, capture| match capture {
+            Capture::Local(slot) => {
+                writer.u8(0);
+                writer.u16(*slot);
+            }
+            Capture::Capture(index) => {
+                writer.u8(1);
+                writer.u16(*index);
+            }
+        });
+        writer.list(&function.chunk.constants, Writer::constant);
+        writer.list(&function.chunk.code, Writer::instruction);
+    });
+    for function in &module.functions {
+        writer.list(&function.chunk.spans, |writer, (offset, span)| {
+            writer.u32(*offset);
+            writer.u32(span.start as u32);
+            writer.u32(span.end as u32);
+        });
+    }
+    writer.bytes
+}
+
+/// Reads a module written in the format, without verifying it.
+pub fn decode(bytes: &[u8]) -> Result<Module, LoadError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::NotBytecode);
+    }
+    let mut reader = Reader {
+        bytes,
+        offset: MAGIC.len(),
+    };
+    let version = reader.u32("the version")?;
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion { found: version });
+    }
+
+    let globals = reader.list("the globals", |reader| reader.string("a global"))?;
+    let exports = reader.list("the exports", |reader| reader.u16("an export"))?;
+    let effects = reader.list("the effects", |reader| {
+        Ok(EffectSignature {
+            name: reader.string("an effect")?,
+            operations: reader.list("the operations of an effect", |reader| {
+                Ok(OperationSignature {
+                    name: reader.string("an operation")?,
+                    arity: reader.u8("an operation")?,
+                    never: reader.bool("an operation")?,
+                })
+            })?,
+        })
+    })?;
+    let mut functions = reader.list("the functions", |reader| {
+        Ok(Function {
+            name: reader.string("a function")?,
+            arity: reader.u16("a function")?,
+            locals: reader.u16("a function")?,
+            captures: reader.list("the captures of a function", |reader| {
+                let offset = reader.offset;
+                match reader.u8("a capture")? {
+                    0 => Ok(Capture::Local(reader.u16("a capture")?)),
+                    1 => Ok(Capture::Capture(reader.u16("a capture")?)),
+                    tag => Err(LoadError::InvalidTag {
+                        what: "capture",
+                        tag,
+                        offset,
+                    }),
+                }
+            })?,
+            chunk: Chunk {
+                constants: reader.list("a constant pool", Reader::constant)?,
+                code: reader.list("the code of a function", Reader::instruction)?,
+                spans: Vec::new(),
+            },
+        })
+    })?;
+    for function in &mut functions {
+        function.chunk.spans = reader.list("a line table", |reader| {
+            let offset = reader.u32("a line table")?;
+            let start = reader.u32("a line table")? as usize;
+            let end = reader.u32("a line table")? as usize;
+            Ok((offset, start..end))
+        })?;
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(LoadError::TrailingBytes {
+            offset: reader.offset,
+        });
+    }
+    Ok(Module {
+        functions,
+        globals,
+        exports,
+        effects,
+    })
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend(value.as_bytes());
+    }
+
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        self.u32(items.len() as u32);
+        for value in items {
+            item(self, value);
+        }
+    }
+
+    fn constant(&mut self, constant: &Constant) {
+        match constant {
+            Constant::Integer(value) => {
+                self.u8(0);
+                self.bytes.extend(value.to_le_bytes());
+            }
+            Constant::Float(value) => {
+                self.u8(1);
+                self.bytes.extend(value.to_bits().to_le_bytes());
+            }
+            Constant::BigInteger(value) => {
+                self.u8(2);
+                self.bytes.extend(value.to_le_bytes());
+            }
+            Constant::BigDecimal(value) => {
+                self.u8(3);
+                self.string(value);
+            }
+            Constant::String(value) => {
+                self.u8(4);
+                self.string(value);
+            }
+            Constant::Symbol(name) => {
+                self.u8(5);
+                self.string(name);
+            }
+            Constant::Keys(keys) => {
+                self.u8(6);
+                self.list(keys, |writer, key| writer.string(key));
+            }
+            Constant::Operation { effect, name } => {
+                self.u8(7);
+                self.string(effect);
+                self.string(name);
+            }
+            Constant::Handler(shape) => {
+                self.u8(8);
+                self.string(&shape.effect);
+                self.list(&shape.clauses, |writer, clause| {
+                    writer.string(&clause.operation);
+                    writer.bool(clause.tail_resumptive);
+                });
+                self.bool(shape.finish);
+                self.bool(shape.multishot);
+            }
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        self.u8(opcode(instruction));
+        match *instruction {
+            Instruction::Constant(operand)
+            | Instruction::GetLocal(operand)
+            | Instruction::SetLocal(operand)
+            | Instruction::GetCapture(operand)
+            | Instruction::GetGlobal(operand)
+            | Instruction::SetGlobal(operand)
+            | Instruction::Array(operand)
+            | Instruction::Tuple(operand)
+            | Instruction::Record(operand)
+            | Instruction::Field(operand)
+            | Instruction::GetKey(operand)
+            | Instruction::TryKey(operand)
+            | Instruction::SetKey(operand)
+            | Instruction::Element(operand)
+            | Instruction::Slice(operand)
+            | Instruction::IsVariant(operand)
+            | Instruction::IsLength(operand)
+            | Instruction::IsLengthAtLeast(operand)
+            | Instruction::Interpolate(operand)
+            | Instruction::Closure(operand)
+            | Instruction::Handler(operand) => self.u16(operand),
+            Instruction::Variant { tag, fields } => {
+                self.u16(tag);
+                self.u8(fields);
+            }
+            Instruction::Perform {
+                operation,
+                arguments,
+            } => {
+                self.u16(operation);
+                self.u8(arguments);
+            }
+            Instruction::Jump(target) | Instruction::JumpIfFalse(target) => self.u32(target),
+            Instruction::Call(count) | Instruction::TailCall(count) => self.u8(count),
+            _ => {}
+        }
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self, what: &'static str) -> Result<[u8; N], LoadError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + N)
+            .ok_or(LoadError::Truncated {
+                what,
+                offset: self.offset,
+            })?;
+        self.offset += N;
+        Ok(bytes.try_into().expect("took as many"))
+    }
+
+    fn u8(&mut self, what: &'static str) -> Result<u8, LoadError> {
+        Ok(self.take::<1>(what)?[0])
+    }
+
+    fn bool(&mut self, what: &'static str) -> Result<bool, LoadError> {
+        let offset = self.offset;
+        match self.u8(what)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(LoadError::InvalidTag {
+                what: "boolean",
+                tag,
+                offset,
+            }),
+        }
+    }
+
+    fn u16(&mut self, what: &'static str) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.take(what)?))
+    }
+
+    fn u32(&mut self, what: &'static str) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.take(what)?))
+    }
+
+    fn string(&mut self, what: &'static str) -> Result<String, LoadError> {
+        let length = self.u32(what)? as usize;
+        let offset = self.offset;
+        let bytes = self
+            .bytes
+            .get(offset..offset.saturating_add(length))
+            .ok_or(LoadError::Truncated { what, offset })?;
+        self.offset += length;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LoadError::InvalidString { offset })
+    }
+
+    fn list<T>(
+        &mut self,
+        what: &'static str,
+        mut item: impl FnMut(&mut Self) -> Result<T, LoadError>,
+    ) -> Result<Vec<T>, LoadError> {
+        let length = self.u32(what)? as usize;
+        // Every item takes at least a byte, so a longer list cannot fit in
+        // what is left of the file.
+        if length > self.bytes.len() - self.offset {
+            return Err(LoadError::Truncated {
+                what,
+                offset: self.offset,
+            });
+        }
+        (0..length).map(|_| item(self)).collect()
+    }
+
+    fn constant(&mut self) -> Result<Constant, LoadError> {
+        let offset = self.offset;
+        let what = "a constant";
+        Ok(match self.u8(what)? {
+            0 => Constant::Integer(i64::from_le_bytes(self.take(what)?)),
+            1 => Constant::Float(f64::from_bits(u64::from_le_bytes(self.take(what)?))),
+            2 => Constant::BigInteger(i128::from_le_bytes(self.take(what)?)),
+            3 => Constant::BigDecimal(self.string(what)?),
+            4 => Constant::String(self.string(what)?),
+            5 => Constant::Symbol(self.string(what)?),
+            6 => Constant::Keys(self.list(what, |reader| reader.string(what))?),
+            7 => Constant::Operation {
+                effect: self.string(what)?,
+                name: self.string(what)?,
+            },
+            8 => Constant::Handler(HandlerShape {
+                effect: self.string(what)?,
+                clauses: self.list(what, |reader| {
+                    Ok(ClauseShape {
+                        operation: reader.string(what)?,
+                        tail_resumptive: reader.bool(what)?,
+                    })
+                })?,
+                finish: self.bool(what)?,
+                multishot: self.bool(what)?,
+            }),
+            tag => {
+                return Err(LoadError::InvalidTag {
+                    what: "constant",
+                    tag,
+                    offset,
+                })
+            }
+        })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, LoadError> {
+        let offset = self.offset;
+        let what = "an instruction";
+        let opcode = self.u8(what)?;
+        let instruction = OPCODES.get(opcode as usize).ok_or(LoadError::InvalidTag {
+            what: "opcode",
+            tag: opcode,
+            offset,
+        })?;
+        // The operands are read into the instruction the opcode stands for.
+        Ok(match *instruction {
+            Instruction::Constant(_) => Instruction::Constant(self.u16(what)?),
+            Instruction::GetLocal(_) => Instruction::GetLocal(self.u16(what)?),
+            Instruction::SetLocal(_) => Instruction::SetLocal(self.u16(what)?),
+            Instruction::GetCapture(_) => Instruction::GetCapture(self.u16(what)?),
+            Instruction::GetGlobal(_) => Instruction::GetGlobal(self.u16(what)?),
+            Instruction::SetGlobal(_) => Instruction::SetGlobal(self.u16(what)?),
+            Instruction::Array(_) => Instruction::Array(self.u16(what)?),
+            Instruction::Tuple(_) => Instruction::Tuple(self.u16(what)?),
+            Instruction::Record(_) => Instruction::Record(self.u16(what)?),
+            Instruction::Variant { .. } => Instruction::Variant {
+                tag: self.u16(what)?,
+                fields: self.u8(what)?,
+            },
+            Instruction::Field(_) => Instruction::Field(self.u16(what)?),
+            Instruction::GetKey(_) => Instruction::GetKey(self.u16(what)?),
+            Instruction::TryKey(_) => Instruction::TryKey(self.u16(what)?),
+            Instruction::SetKey(_) => Instruction::SetKey(self.u16(what)?),
+            Instruction::Element(_) => Instruction::Element(self.u16(what)?),
+            Instruction::Slice(_) => Instruction::Slice(self.u16(what)?),
+            Instruction::IsVariant(_) => Instruction::IsVariant(self.u16(what)?),
+            Instruction::IsLength(_) => Instruction::IsLength(self.u16(what)?),
+            Instruction::IsLengthAtLeast(_) => Instruction::IsLengthAtLeast(self.u16(what)?),
+            Instruction::Interpolate(_) => Instruction::Interpolate(self.u16(what)?),
+            Instruction::Jump(_) => Instruction::Jump(self.u32(what)?),
+            Instruction::JumpIfFalse(_) => Instruction::JumpIfFalse(self.u32(what)?),
+            Instruction::Call(_) => Instruction::Call(self.u8(what)?),
+            Instruction::TailCall(_) => Instruction::TailCall(self.u8(what)?),
+            Instruction::Closure(_) => Instruction::Closure(self.u16(what)?),
+            Instruction::Perform { .. } => Instruction::Perform {
+                operation: self.u16(what)?,
+                arguments: self.u8(what)?,
+            },
+            Instruction::Handler(_) => Instruction::Handler(self.u16(what)?),
+            instruction => instruction,
+        })
+    }
+}
+
+/// Every instruction, at the index of its opcode, with its operands zeroed.
+/// New instructions go at the end, so that the opcodes of the others stay
+/// as they are.
+const OPCODES: [Instruction; 65] = [
+    Instruction::Constant(0),
+    Instruction::Unit,
+    Instruction::True,
+    Instruction::False,
+    Instruction::Pop,
+    Instruction::Dup,
+    Instruction::GetLocal(0),
+    Instruction::SetLocal(0),
+    Instruction::GetCapture(0),
+    Instruction::GetGlobal(0),
+    Instruction::SetGlobal(0),
+    Instruction::NewCell,
+    Instruction::Deref,
+    Instruction::SetCell,
+    Instruction::Array(0),
+    Instruction::Tuple(0),
+    Instruction::Record(0),
+    Instruction::Extend,
+    Instruction::Variant { tag: 0, fields: 0 },
+    Instruction::Field(0),
+    Instruction::GetKey(0),
+    Instruction::TryKey(0),
+    Instruction::SetKey(0),
+    Instruction::Index,
+    Instruction::SetIndex,
+    Instruction::Element(0),
+    Instruction::Slice(0),
+    Instruction::Length,
+    Instruction::Keys,
+    Instruction::IsVariant(0),
+    Instruction::IsLength(0),
+    Instruction::IsLengthAtLeast(0),
+    Instruction::Add,
+    Instruction::Subtract,
+    Instruction::Multiply,
+    Instruction::Divide,
+    Instruction::Remainder,
+    Instruction::Power,
+    Instruction::Negate,
+    Instruction::Not,
+    Instruction::BitAnd,
+    Instruction::BitOr,
+    Instruction::BitXor,
+    Instruction::BitNot,
+    Instruction::ShiftLeft,
+    Instruction::ShiftRight,
+    Instruction::Equal,
+    Instruction::NotEqual,
+    Instruction::Less,
+    Instruction::LessEqual,
+    Instruction::Greater,
+    Instruction::GreaterEqual,
+    Instruction::Concat,
+    Instruction::Interpolate(0),
+    Instruction::Jump(0),
+    Instruction::JumpIfFalse(0),
+    Instruction::Call(0),
+    Instruction::TailCall(0),
+    Instruction::Return,
+    Instruction::NoMatch,
+    Instruction::Closure(0),
+    Instruction::Perform {
+        operation: 0,
+        arguments: 0,
+    },
+    Instruction::Handler(0),
+    Instruction::Handle,
+    Instruction::Resume,
+];
+
+fn opcode(instruction: &Instruction) -> u8 {
+    OPCODES
+        .iter()
+        .position(|other| std::mem::discriminant(other) == std::mem::discriminant(instruction))
+        .expect("every instruction has an opcode") as u8
+}
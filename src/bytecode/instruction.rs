@@ -0,0 +1,248 @@
+//! The instruction set. Each instruction is described by what it takes from
+//! the top of the stack and what it leaves there, the topmost last: `Add`
+//! is `[a, b] -> [a + b]`. Operands written in parentheses are indices,
+//! into the chunk's constants unless said otherwise.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // Values
+    /// `[] -> [constant]`.
+    Constant(u16),
+    /// `[] -> [()]`.
+    Unit,
+    /// `[] -> [true]`.
+    True,
+    /// `[] -> [false]`.
+    False,
+    /// `[a] -> []`.
+    Pop,
+    /// `[a] -> [a, a]`.
+    Dup,
+
+    // Variables
+    /// `[] -> [local]`, by its slot.
+    GetLocal(u16),
+    /// `[a] -> []`, storing `a` in the slot.
+    SetLocal(u16),
+    /// `[] -> [capture]`, by its index among the closure's captures.
+    GetCapture(u16),
+    /// `[] -> [global]`, by its index among the module's globals.
+    GetGlobal(u16),
+    /// `[a] -> []`, storing `a` in the global.
+    SetGlobal(u16),
+    /// `[a] -> [cell]`: a cell holding `a`. Mutable locals live in cells,
+    /// so that closures capturing them share them.
+    NewCell,
+    /// `[cell] -> [a]`, what the cell holds.
+    Deref,
+    /// `[a, cell] -> []`, storing `a` in the cell.
+    SetCell,
+
+    // Aggregates
+    /// `[a1, ..., an] -> [[a1, ..., an]]`, an array of `n` elements.
+    Array(u16),
+    /// `[a1, ..., an] -> [(a1, ..., an)]`, a tuple of `n` elements.
+    Tuple(u16),
+    /// `[a1, ..., an] -> [{ k1: a1, ..., kn: an }]`, with the keys of a
+    /// `Constant::Keys`.
+    Record(u16),
+    /// `[r, s] -> [{ ...r, ...s }]`: a new record with the fields of both,
+    /// those of `s` winning.
+    Extend,
+    /// `[a1, ..., an] -> [Tag(a1, ..., an)]`, a value of a data type built
+    /// with the constructor named `tag` from `fields` fields.
+    Variant {
+        tag: u16,
+        fields: u8,
+    },
+    /// `[v] -> [v.n]`: an element of a tuple or a field of a variant.
+    Field(u16),
+    /// `[r] -> [r.key]`, with the name of the key.
+    GetKey(u16),
+    /// `[r] -> [r.key]`, or `[()]` when `r` has no such key or is not a
+    /// record. Matches on unions of records test fields only some have.
+    TryKey(u16),
+    /// `[a, r] -> []`, setting the key of `r` to `a`.
+    SetKey(u16),
+    /// `[v, i] -> [v[i]]`, an element of an array, tuple or string.
+    Index,
+    /// `[a, v, i] -> []`, setting the element of the array `v` to `a`.
+    SetIndex,
+    /// `[v] -> [v[n]]`, an element of an array at a known index.
+    Element(u16),
+    /// `[v] -> [v[n..]]`: the elements of an array from `n` on.
+    Slice(u16),
+    /// `[v] -> [length]` of an array, tuple or string.
+    Length,
+    /// `[v] -> [keys]`: an array of the indices of an array or tuple, or of
+    /// the keys of a record.
+    Keys,
+
+    // Tests
+    /// `[v] -> [is]`, whether `v` was built with the constructor named `tag`.
+    IsVariant(u16),
+    /// `[v] -> [is]`, whether `v` is an array of exactly `n` elements.
+    IsLength(u16),
+    /// `[v] -> [is]`, whether `v` is an array of at least `n` elements.
+    IsLengthAtLeast(u16),
+
+    // Operators
+    /// `[a, b] -> [a + b]`, which joins strings as well.
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    /// `[a] -> [-a]`.
+    Negate,
+    /// `[a] -> [!a]`.
+    Not,
+    BitAnd,
+    BitOr,
+    BitXor,
+    /// `[a] -> [~a]`.
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// `[a, b] -> [a ++ b]`: joins arrays or strings, or composes handlers
+    /// with `a` inside `b`.
+    Concat,
+    /// `[a1, ..., an] -> [string]`, the values written out one after the
+    /// other, as in a template.
+    Interpolate(u16),
+
+    // Control
+    /// Carries on at the instruction at this offset.
+    Jump(u32),
+    /// `[condition] -> []`, jumping to the offset when it is false.
+    JumpIfFalse(u32),
+    /// `[f, a1, ..., an] -> [f(a1, ..., an)]`, calling a closure,
+    /// continuation or function of the host.
+    Call(u8),
+    /// Like `Call`, but the callee replaces the current function, whose
+    /// caller gets its result.
+    TailCall(u8),
+    /// `[a] -> []`, returning `a` to the caller.
+    Return,
+    /// Stops the program: no arm of a `match` matched.
+    NoMatch,
+
+    // Functions
+    /// `[] -> [closure]` of the function with this index in the module,
+    /// capturing what it lists from the current function.
+    Closure(u16),
+
+    // Effects
+    /// `[a1, ..., an] -> [result]`: performs the operation of a
+    /// `Constant::Operation` with `arguments` arguments, suspending the
+    /// computation up to the nearest handler of its effect. The handler's
+    /// clause is called with the arguments and the continuation, the rest
+    /// of the computation, and decides what the result is.
+    Perform {
+        operation: u16,
+        arguments: u8,
+    },
+    /// `[c1, ..., cn, finish?] -> [handler]`: a handler with the clauses of
+    /// a `Constant::Handler`, each a closure taking the arguments of its
+    /// operation and the continuation, and the return clause if it has one.
+    Handler(u16),
+    /// `[handler, thunk] -> [answer]`: calls the closure `thunk` with the
+    /// handler installed, which turns its result into the answer.
+    Handle,
+    /// `[continuation, a] -> [result]`: resumes the computation a clause was
+    /// given with `a` as the result of its operation, the handler still
+    /// installed, and gives what handling it gives.
+    Resume,
+}
+
+impl Instruction {
+    /// The offset a jump goes to.
+    pub fn target(&self) -> Option<u32> {
+        match self {
+            Instruction::Jump(target) | Instruction::JumpIfFalse(target) => Some(*target),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Instruction::Constant(_) => "constant",
+            Instruction::Unit => "unit",
+            Instruction::True => "true",
+            Instruction::False => "false",
+            Instruction::Pop => "pop",
+            Instruction::Dup => "dup",
+            Instruction::GetLocal(_) => "get-local",
+            Instruction::SetLocal(_) => "set-local",
+            Instruction::GetCapture(_) => "get-capture",
+            Instruction::GetGlobal(_) => "get-global",
+            Instruction::SetGlobal(_) => "set-global",
+            Instruction::NewCell => "new-cell",
+            Instruction::Deref => "deref",
+            Instruction::SetCell => "set-cell",
+            Instruction::Array(_) => "array",
+            Instruction::Tuple(_) => "tuple",
+            Instruction::Record(_) => "record",
+            Instruction::Extend => "extend",
+            Instruction::Variant { .. } => "variant",
+            Instruction::Field(_) => "field",
+            Instruction::GetKey(_) => "get-key",
+            Instruction::TryKey(_) => "try-key",
+            Instruction::SetKey(_) => "set-key",
+            Instruction::Index => "index",
+            Instruction::SetIndex => "set-index",
+            Instruction::Element(_) => "element",
+            Instruction::Slice(_) => "slice",
+            Instruction::Length => "length",
+            Instruction::Keys => "keys",
+            Instruction::IsVariant(_) => "is-variant",
+            Instruction::IsLength(_) => "is-length",
+            Instruction::IsLengthAtLeast(_) => "is-length-at-least",
+            Instruction::Add => "add",
+            Instruction::Subtract => "subtract",
+            Instruction::Multiply => "multiply",
+            Instruction::Divide => "divide",
+            Instruction::Remainder => "remainder",
+            Instruction::Power => "power",
+            Instruction::Negate => "negate",
+            Instruction::Not => "not",
+            Instruction::BitAnd => "bit-and",
+            Instruction::BitOr => "bit-or",
+            Instruction::BitXor => "bit-xor",
+            Instruction::BitNot => "bit-not",
+            Instruction::ShiftLeft => "shift-left",
+            Instruction::ShiftRight => "shift-right",
+            Instruction::Equal => "equal",
+            Instruction::NotEqual => "not-equal",
+            Instruction::Less => "less",
+            Instruction::LessEqual => "less-equal",
+            Instruction::Greater => "greater",
+            Instruction::GreaterEqual => "greater-equal",
+            Instruction::Concat => "concat",
+            Instruction::Interpolate(_) => "interpolate",
+            Instruction::Jump(_) => "jump",
+            Instruction::JumpIfFalse(_) => "jump-if-false",
+            Instruction::Call(_) => "call",
+            Instruction::TailCall(_) => "tail-call",
+            Instruction::Return => "return",
+            Instruction::NoMatch => "no-match",
+            Instruction::Closure(_) => "closure",
+            Instruction::Perform { .. } => "perform",
+            Instruction::Handler(_) => "handler",
+            Instruction::Handle => "handle",
+            Instruction::Resume => "resume",
+        };
+        write!(f, "{}", name)
+    }
+}
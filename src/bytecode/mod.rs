@@ -0,0 +1,137 @@
+//! The bytecode a checked program is compiled to, for the virtual machine
+//! to run. A module is a table of functions, each with a chunk of code for
+//! a stack machine: instructions take their operands from the top of the
+//! stack and leave their result there, and name anything else, such as a
+//! constant, a local or a function, by its index.
+//!
+//! Every function has its own constant pool, and a line table that maps
+//! its instructions back to the spans of the source they were compiled
+//! from. The first function of a module runs its top-level statements.
+//!
+//! Modules are saved to `.asurac` files in the format of `format`, and
+//! checked by `verifier` when they are loaded back.
+
+pub mod compiler;
+pub mod disassembler;
+pub mod format;
+pub mod instruction;
+pub mod verifier;
+
+use crate::lexing::token::Span;
+
+pub use compiler::compile;
+pub use format::LoadError;
+pub use instruction::Instruction;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    // The names of the module's global variables: its top-level bindings,
+    // the members of its `impl`s and what the host provides, such as
+    // `assert_equal`.
+    pub globals: Vec<String>,
+    // The globals the program exports, by index.
+    pub exports: Vec<u16>,
+    // The effects the program declares.
+    pub effects: Vec<EffectSignature>,
+}
+
+impl Module {
+    /// The index of the function that runs the top-level statements.
+    pub const ENTRY: u16 = 0;
+
+    pub fn global(&self, name: &str) -> Option<u16> {
+        self.globals
+            .iter()
+            .position(|global| global == name)
+            .map(|index| index as u16)
+    }
+}
+
+/// An effect as its operations are performed: by name, with a number of
+/// arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectSignature {
+    pub name: String,
+    pub operations: Vec<OperationSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationSignature {
+    pub name: String,
+    pub arity: u8,
+    // Whether the operation returns `Never`, so that it is never resumed.
+    pub never: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: u16,
+    // Slots for locals, the parameters first.
+    pub locals: u16,
+    // What a closure over the function captures from the function that
+    // creates it, in the order `GetCapture` refers to them.
+    pub captures: Vec<Capture>,
+    pub chunk: Chunk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    Local(u16),
+    Capture(u16),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    pub constants: Vec<Constant>,
+    // The span of the source each run of instructions was compiled from, as
+    // the offset of the first instruction of the run and its span.
+    pub spans: Vec<(u32, Span)>,
+}
+
+impl Chunk {
+    /// The span of the source the instruction at `offset` was compiled from.
+    pub fn span(&self, offset: usize) -> Option<Span> {
+        let run = self
+            .spans
+            .partition_point(|(start, _)| *start as usize <= offset);
+        run.checked_sub(1).map(|run| self.spans[run].1.clone())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i64),
+    Float(f64),
+    BigInteger(i128),
+    // Kept as written, like decimal literals in the source.
+    BigDecimal(String),
+    // Strings, and names such as record keys and constructor tags.
+    String(String),
+    Symbol(String),
+    // The keys of a record, in the order its values are on the stack.
+    Keys(Vec<String>),
+    Operation { effect: String, name: String },
+    Handler(HandlerShape),
+}
+
+/// What `Instruction::Handler` builds a handler from: the effect, and for
+/// each clause on the stack, the operation it handles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerShape {
+    pub effect: String,
+    pub clauses: Vec<ClauseShape>,
+    // Whether a return clause is on the stack after the operation clauses.
+    pub finish: bool,
+    pub multishot: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClauseShape {
+    pub operation: String,
+    // A clause that never resumes the computation of an operation that can
+    // return is resumed with its value once it finishes.
+    pub tail_resumptive: bool,
+}
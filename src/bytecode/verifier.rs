@@ -0,0 +1,440 @@
+//! Checks that a module can be run before anything runs it, so that a file
+//! that was tampered with or written by something other than the compiler
+//! is turned away as a whole instead of failing halfway through.
+//!
+//! Every operand of every instruction must refer to something that exists
+//! and is of the kind the instruction expects: a constant of the pool, a
+//! local, a capture, a global or a function. The depth of the stack before
+//! each instruction is worked out along every path through the code, and
+//! must be the same along all the paths that meet at an instruction, so no
+//! instruction ever takes more from the stack than is on it. Operations
+//! of the effects the module declares must be performed with as many
+//! arguments as they take.
+
+use super::{Capture, Constant, Function, Instruction, LoadError, Module};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    NoEntry,
+    EntryTakesArguments,
+    NoExport(u16),
+    NoConstant(u16),
+    // The constant is not of the kind the instruction expects.
+    WrongConstant {
+        index: u16,
+        expected: &'static str,
+    },
+    NoLocal(u16),
+    NoCapture(u16),
+    NoGlobal(u16),
+    NoFunction(u16),
+    NoTarget(u32),
+    UnknownOperation {
+        effect: String,
+        operation: String,
+    },
+    OperationArity {
+        operation: String,
+        expected: u8,
+        found: u8,
+    },
+    StackUnderflow {
+        depth: usize,
+        needed: usize,
+    },
+    InconsistentStack {
+        expected: usize,
+        found: usize,
+    },
+    // The code runs past its last instruction without returning.
+    FallsOffEnd,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::NoEntry => write!(f, "the module has no functions"),
+            VerifyError::EntryTakesArguments => {
+                write!(
+                    f,
+                    "the function of the top-level statements takes arguments"
+                )
+            }
+            VerifyError::NoExport(index) => write!(f, "the export {} is not a global", index),
+            VerifyError::NoConstant(index) => write!(f, "there is no constant {}", index),
+            VerifyError::WrongConstant { index, expected } => {
+                write!(f, "the constant {} is not {}", index, expected)
+            }
+            VerifyError::NoLocal(slot) => write!(f, "there is no local {}", slot),
+            VerifyError::NoCapture(index) => write!(f, "there is no capture {}", index),
+            VerifyError::NoGlobal(index) => write!(f, "there is no global {}", index),
+            VerifyError::NoFunction(index) => write!(f, "there is no function {}", index),
+            VerifyError::NoTarget(target) => {
+                write!(f, "the jump to {} is past the end of the code", target)
+            }
+            VerifyError::UnknownOperation { effect, operation } => {
+                write!(
+                    f,
+                    "the effect `{}` has no operation `{}`",
+                    effect, operation
+                )
+            }
+            VerifyError::OperationArity {
+                operation,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} arguments but is performed with {}",
+                operation, expected, found
+            ),
+            VerifyError::StackUnderflow { depth, needed } => write!(
+                f,
+                "the instruction takes {} values from a stack of {}",
+                needed, depth
+            ),
+            VerifyError::InconsistentStack { expected, found } => write!(
+                f,
+                "the stack is {} deep along one path and {} along another",
+                expected, found
+            ),
+            VerifyError::FallsOffEnd => write!(f, "the code runs past its end"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl Module {
+    /// Checks that the module can be run.
+    pub fn verify(&self) -> Result<(), LoadError> {
+        let invalid = |error| LoadError::Invalid {
+            function: None,
+            offset: None,
+            error,
+        };
+        match self.functions.get(Module::ENTRY as usize) {
+            None => return Err(invalid(VerifyError::NoEntry)),
+            Some(entry) if entry.arity > 0 => {
+                return Err(invalid(VerifyError::EntryTakesArguments))
+            }
+            Some(_) => {}
+        }
+        if let Some(export) = self
+            .exports
+            .iter()
+            .find(|export| **export as usize >= self.globals.len())
+        {
+            return Err(invalid(VerifyError::NoExport(*export)));
+        }
+        for function in &self.functions {
+            Verifier {
+                module: self,
+                function,
+                depths: vec![None; function.chunk.code.len()],
+            }
+            .verify()
+            .map_err(|(offset, error)| LoadError::Invalid {
+                function: Some(function.name.clone()),
+                offset,
+                error,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+struct Verifier<'m> {
+    module: &'m Module,
+    function: &'m Function,
+    // The depth of the stack before each instruction, once reached.
+    depths: Vec<Option<usize>>,
+}
+
+// An error, and the offset of the instruction it is in if it is in one.
+type Failure = (Option<usize>, VerifyError);
+
+impl Verifier<'_> {
+    fn verify(mut self) -> Result<(), Failure> {
+        let mut pending = vec![(0, 0)];
+        while let Some((offset, depth)) = pending.pop() {
+            let instruction = match self.function.chunk.code.get(offset) {
+                Some(instruction) => *instruction,
+                None => return Err((None, VerifyError::FallsOffEnd)),
+            };
+            match self.depths[offset] {
+                Some(expected) if expected == depth => continue,
+                Some(expected) => {
+                    return Err((
+                        Some(offset),
+                        VerifyError::InconsistentStack {
+                            expected,
+                            found: depth,
+                        },
+                    ))
+                }
+                None => self.depths[offset] = Some(depth),
+            }
+
+            let (taken, left) = self
+                .effect(instruction)
+                .map_err(|error| (Some(offset), error))?;
+            if taken > depth {
+                return Err((
+                    Some(offset),
+                    VerifyError::StackUnderflow {
+                        depth,
+                        needed: taken,
+                    },
+                ));
+            }
+            let depth = depth - taken + left;
+            match instruction {
+                Instruction::Return | Instruction::NoMatch => {}
+                Instruction::Jump(target) => pending.push((self.target(offset, target)?, depth)),
+                Instruction::JumpIfFalse(target) => {
+                    pending.push((self.target(offset, target)?, depth));
+                    pending.push((offset + 1, depth));
+                }
+                _ => pending.push((offset + 1, depth)),
+            }
+        }
+        Ok(())
+    }
+
+    fn target(&self, offset: usize, target: u32) -> Result<usize, Failure> {
+        match (target as usize) < self.function.chunk.code.len() {
+            true => Ok(target as usize),
+            false => Err((Some(offset), VerifyError::NoTarget(target))),
+        }
+    }
+
+    /// How many values the instruction takes from the stack and how many it
+    /// leaves there, once its operands have been checked.
+    fn effect(&self, instruction: Instruction) -> Result<(usize, usize), VerifyError> {
+        Ok(match instruction {
+            Instruction::Constant(index) => {
+                match self.constant(index)? {
+                    Constant::Keys(_) | Constant::Operation { .. } | Constant::Handler(_) => {
+                        return Err(VerifyError::WrongConstant {
+                            index,
+                            expected: "a value",
+                        })
+                    }
+                    _ => {}
+                }
+                (0, 1)
+            }
+            Instruction::Unit | Instruction::True | Instruction::False => (0, 1),
+            Instruction::Pop => (1, 0),
+            Instruction::Dup => (1, 2),
+
+            Instruction::GetLocal(slot) => {
+                self.local(slot)?;
+                (0, 1)
+            }
+            Instruction::SetLocal(slot) => {
+                self.local(slot)?;
+                (1, 0)
+            }
+            Instruction::GetCapture(index) => {
+                self.capture(index)?;
+                (0, 1)
+            }
+            Instruction::GetGlobal(index) => {
+                self.global(index)?;
+                (0, 1)
+            }
+            Instruction::SetGlobal(index) => {
+                self.global(index)?;
+                (1, 0)
+            }
+            Instruction::NewCell | Instruction::Deref => (1, 1),
+            Instruction::SetCell => (2, 0),
+
+            Instruction::Array(count) | Instruction::Tuple(count) => (count as usize, 1),
+            Instruction::Record(index) => match self.constant(index)? {
+                Constant::Keys(keys) => (keys.len(), 1),
+                _ => {
+                    return Err(VerifyError::WrongConstant {
+                        index,
+                        expected: "the keys of a record",
+                    })
+                }
+            },
+            Instruction::Extend => (2, 1),
+            Instruction::Variant { tag, fields } => {
+                self.name(tag)?;
+                (fields as usize, 1)
+            }
+            Instruction::Field(_) | Instruction::Element(_) | Instruction::Slice(_) => (1, 1),
+            Instruction::GetKey(key) | Instruction::TryKey(key) => {
+                self.name(key)?;
+                (1, 1)
+            }
+            Instruction::SetKey(key) => {
+                self.name(key)?;
+                (2, 0)
+            }
+            Instruction::Index => (2, 1),
+            Instruction::SetIndex => (3, 0),
+            Instruction::Length | Instruction::Keys => (1, 1),
+
+            Instruction::IsVariant(tag) => {
+                self.name(tag)?;
+                (1, 1)
+            }
+            Instruction::IsLength(_) | Instruction::IsLengthAtLeast(_) => (1, 1),
+
+            Instruction::Negate | Instruction::Not | Instruction::BitNot => (1, 1),
+            Instruction::Add
+            | Instruction::Subtract
+            | Instruction::Multiply
+            | Instruction::Divide
+            | Instruction::Remainder
+            | Instruction::Power
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor
+            | Instruction::ShiftLeft
+            | Instruction::ShiftRight
+            | Instruction::Equal
+            | Instruction::NotEqual
+            | Instruction::Less
+            | Instruction::LessEqual
+            | Instruction::Greater
+            | Instruction::GreaterEqual
+            | Instruction::Concat => (2, 1),
+            Instruction::Interpolate(count) => (count as usize, 1),
+
+            Instruction::Jump(_) => (0, 0),
+            Instruction::JumpIfFalse(_) => (1, 0),
+            Instruction::Call(count) | Instruction::TailCall(count) => (count as usize + 1, 1),
+            Instruction::Return => (1, 0),
+            Instruction::NoMatch => (0, 0),
+
+            Instruction::Closure(index) => {
+                let function = self
+                    .module
+                    .functions
+                    .get(index as usize)
+                    .ok_or(VerifyError::NoFunction(index))?;
+                for capture in &function.captures {
+                    match *capture {
+                        Capture::Local(slot) => self.local(slot)?,
+                        Capture::Capture(index) => self.capture(index)?,
+                    }
+                }
+                (0, 1)
+            }
+
+            Instruction::Perform {
+                operation,
+                arguments,
+            } => match self.constant(operation)? {
+                Constant::Operation { effect, name } => {
+                    self.operation(effect, name, Some(arguments))?;
+                    (arguments as usize, 1)
+                }
+                _ => {
+                    return Err(VerifyError::WrongConstant {
+                        index: operation,
+                        expected: "an operation",
+                    })
+                }
+            },
+            Instruction::Handler(index) => match self.constant(index)? {
+                Constant::Handler(shape) => {
+                    for clause in &shape.clauses {
+                        self.operation(&shape.effect, &clause.operation, None)?;
+                    }
+                    (shape.clauses.len() + shape.finish as usize, 1)
+                }
+                _ => {
+                    return Err(VerifyError::WrongConstant {
+                        index,
+                        expected: "the shape of a handler",
+                    })
+                }
+            },
+            Instruction::Handle | Instruction::Resume => (2, 1),
+        })
+    }
+
+    fn constant(&self, index: u16) -> Result<&Constant, VerifyError> {
+        self.function
+            .chunk
+            .constants
+            .get(index as usize)
+            .ok_or(VerifyError::NoConstant(index))
+    }
+
+    // A key, a tag or another name, kept as a string constant.
+    fn name(&self, index: u16) -> Result<(), VerifyError> {
+        match self.constant(index)? {
+            Constant::String(_) => Ok(()),
+            _ => Err(VerifyError::WrongConstant {
+                index,
+                expected: "a name",
+            }),
+        }
+    }
+
+    fn local(&self, slot: u16) -> Result<(), VerifyError> {
+        match slot < self.function.locals.max(self.function.arity) {
+            true => Ok(()),
+            false => Err(VerifyError::NoLocal(slot)),
+        }
+    }
+
+    fn capture(&self, index: u16) -> Result<(), VerifyError> {
+        match (index as usize) < self.function.captures.len() {
+            true => Ok(()),
+            false => Err(VerifyError::NoCapture(index)),
+        }
+    }
+
+    fn global(&self, index: u16) -> Result<(), VerifyError> {
+        match (index as usize) < self.module.globals.len() {
+            true => Ok(()),
+            false => Err(VerifyError::NoGlobal(index)),
+        }
+    }
+
+    /// Checks an operation of an effect the module declares, and that it is
+    /// performed with as many arguments as it takes. The effects of the
+    /// host, such as `Console.Platform`, are not declared, and left alone.
+    fn operation(
+        &self,
+        effect: &str,
+        operation: &str,
+        arguments: Option<u8>,
+    ) -> Result<(), VerifyError> {
+        let signature = match self
+            .module
+            .effects
+            .iter()
+            .find(|signature| signature.name == effect)
+        {
+            Some(signature) => signature,
+            None => return Ok(()),
+        };
+        let declared = signature
+            .operations
+            .iter()
+            .find(|declared| declared.name == operation)
+            .ok_or_else(|| VerifyError::UnknownOperation {
+                effect: effect.to_string(),
+                operation: operation.to_string(),
+            })?;
+        match arguments {
+            Some(found) if found != declared.arity => Err(VerifyError::OperationArity {
+                operation: format!("{}.{}", effect, operation),
+                expected: declared.arity,
+                found,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
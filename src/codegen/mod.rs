@@ -1 +1 @@
-pub mod wasm;
\ No newline at end of file
+pub mod wasm;
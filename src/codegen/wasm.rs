@@ -1,3 +1,4 @@
+use crate::error::CodegenError;
 use crate::parsing::ast::Program;
 
 pub struct WasmCompiler {
@@ -11,8 +12,12 @@ impl WasmCompiler {
         }
     }
 
-    pub fn compile(&self, ast: &Program) -> Vec<u8> {
-        // Implement WASM compilation logic
-        unimplemented!("WASM compilation not yet implemented")
+    pub fn compile(&self, _ast: &Program) -> Result<Vec<u8>, CodegenError> {
+        // Only the module header is emitted until code generation is
+        // implemented, which is still a valid (empty) module.
+        let mut module = Vec::new();
+        module.extend_from_slice(b"\0asm");
+        module.extend_from_slice(&1u32.to_le_bytes());
+        Ok(module)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,637 @@
+use crate::error::{
+    self, CodegenError, CompileError, CompileWarning, EffectError, LexError, ParseError,
+    ResolveError, SourceId, TypeError,
+};
+use crate::lexing::token::Span;
+use ariadne::{
+    CharSet, Color, Config, IndexType, Label as ReportLabel, Report, ReportKind, Source,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// How a diagnostic is drawn. `Plain` uses no colour and only ASCII, which
+/// keeps CI logs and snapshots readable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Colored,
+    Plain,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Label {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+/// A compiler message ready to be shown to the user: a headline, the span it
+/// is about and any related spans, notes and help.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub source: SourceId,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(
+        code: &'static str,
+        message: impl Into<String>,
+        source: SourceId,
+        primary: Label,
+    ) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            source,
+            primary,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn warning(
+        code: &'static str,
+        message: impl Into<String>,
+        source: SourceId,
+        primary: Label,
+    ) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message, source, primary)
+        }
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.secondary.push(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Renders the diagnostic against the text of its source.
+    pub fn render(&self, source: &str, style: Style) -> String {
+        let config = Config::default()
+            .with_index_type(IndexType::Byte)
+            .with_color(style == Style::Colored)
+            .with_char_set(match style {
+                Style::Colored => CharSet::Unicode,
+                Style::Plain => CharSet::Ascii,
+            });
+        let (kind, color) = match self.severity {
+            Severity::Error => (ReportKind::Error, Color::Red),
+            Severity::Warning => (ReportKind::Warning, Color::Yellow),
+        };
+        let id = self.source.clone();
+
+        let mut report = Report::build(kind, (id.clone(), self.primary.span.clone()))
+            .with_config(config)
+            .with_code(self.code)
+            .with_message(&self.message)
+            .with_label(
+                ReportLabel::new((id.clone(), self.primary.span.clone()))
+                    .with_message(&self.primary.message)
+                    .with_color(color)
+                    .with_order(0),
+            );
+
+        for (order, label) in self.secondary.iter().enumerate() {
+            report = report.with_label(
+                ReportLabel::new((id.clone(), label.span.clone()))
+                    .with_message(&label.message)
+                    .with_color(Color::Blue)
+                    .with_order(order as i32 + 1),
+            );
+        }
+        for note in &self.notes {
+            report = report.with_note(note);
+        }
+        if let Some(help) = &self.help {
+            report = report.with_help(help);
+        }
+
+        let mut output = Vec::new();
+        report
+            .finish()
+            .write((id, Source::from(source)), &mut output)
+            .expect("writing to a Vec cannot fail");
+        String::from_utf8(output).expect("ariadne writes UTF-8")
+    }
+}
+
+/// Renders every error in order, separated by blank lines.
+pub fn render(errors: &[CompileError], source: &str, style: Style) -> String {
+    errors
+        .iter()
+        .map(|error| Diagnostic::from(error).render(source, style))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders every warning in order, separated by blank lines.
+pub fn render_warnings(warnings: &[CompileWarning], source: &str, style: Style) -> String {
+    warnings
+        .iter()
+        .map(|warning| Diagnostic::from(warning).render(source, style))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl From<&CompileWarning> for Diagnostic {
+    fn from(warning: &CompileWarning) -> Self {
+        match warning {
+            CompileWarning::UnreachablePattern { source, span } => Diagnostic::warning(
+                "W0001",
+                "unreachable pattern",
+                source.clone(),
+                Label::new(span.clone(), "no value can reach this arm"),
+            )
+            .with_note("every value it matches is matched by an earlier arm"),
+            CompileWarning::ShadowedHandler {
+                source,
+                effect,
+                shadowed_by,
+                span,
+            } => Diagnostic::warning(
+                "W0002",
+                format!("handler of `{}` is shadowed", effect),
+                source.clone(),
+                Label::new(
+                    span.clone(),
+                    format!("never handles `{}` for the computation", effect),
+                ),
+            )
+            .with_label(Label::new(
+                shadowed_by.clone(),
+                format!("`{}` is handled here first", effect),
+            ))
+            .with_note("in `left ++ right`, operations reach the handlers of `left` first"),
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        match error {
+            CompileError::Lex { source, error } => lex_diagnostic(source.clone(), error),
+            CompileError::Parse { source, error } => parse_diagnostic(source.clone(), error),
+            CompileError::Resolve { source, error } => resolve_diagnostic(source.clone(), error),
+            CompileError::Type { source, error } => type_diagnostic(source.clone(), error),
+            CompileError::Effect { source, error } => effect_diagnostic(source.clone(), error),
+            CompileError::Codegen { source, error } => codegen_diagnostic(source.clone(), error),
+        }
+    }
+}
+
+fn lex_diagnostic(source: SourceId, error: &LexError) -> Diagnostic {
+    match error {
+        LexError::InvalidToken { text, span } => Diagnostic::error(
+            "E0001",
+            "invalid token",
+            source,
+            Label::new(span.clone(), format!("`{}` is not valid here", text)),
+        ),
+        LexError::UnterminatedTemplate { span } => Diagnostic::error(
+            "E0002",
+            "unterminated template string",
+            source,
+            Label::new(span.clone(), "this template string is never closed"),
+        )
+        .with_note("a template string ends with a backtick"),
+    }
+}
+
+fn parse_diagnostic(source: SourceId, error: &ParseError) -> Diagnostic {
+    match error {
+        ParseError::Unexpected {
+            expected,
+            found,
+            span,
+        } => {
+            let found = match found {
+                Some(found) => format!("`{}`", found),
+                None => "end of input".to_string(),
+            };
+            let message = format!("unexpected {}", found);
+            let primary = match &expected[..] {
+                [expected] => format!("expected {}", expected),
+                _ => format!("unexpected {}", found),
+            };
+
+            let diagnostic =
+                Diagnostic::error("E0100", message, source, Label::new(span.clone(), primary));
+            if expected.len() > 1 {
+                diagnostic.with_note(format!("expected one of {}", expected.join(", ")))
+            } else {
+                diagnostic
+            }
+        }
+        ParseError::Unclosed {
+            delimiter,
+            delimiter_span,
+            found,
+            span,
+        } => {
+            let found = match found {
+                Some(found) => format!("found `{}`", found),
+                None => "found end of input".to_string(),
+            };
+            Diagnostic::error(
+                "E0101",
+                format!("unclosed delimiter `{}`", delimiter),
+                source,
+                Label::new(span.clone(), found),
+            )
+            .with_label(Label::new(delimiter_span.clone(), "opened here"))
+        }
+        ParseError::Custom { message, span } => Diagnostic::error(
+            "E0102",
+            message.clone(),
+            source,
+            Label::new(span.clone(), message.clone()),
+        ),
+    }
+}
+
+fn resolve_diagnostic(source: SourceId, error: &ResolveError) -> Diagnostic {
+    let message = error.to_string();
+    match error {
+        ResolveError::UnboundVariable { span, .. } => Diagnostic::error(
+            "E0200",
+            message,
+            source,
+            Label::new(span.clone(), "not found in this scope"),
+        ),
+        ResolveError::UnboundType { span, .. } => Diagnostic::error(
+            "E0201",
+            message,
+            source,
+            Label::new(span.clone(), "not found in this scope"),
+        ),
+        ResolveError::UnknownModule { span, .. } => Diagnostic::error(
+            "E0202",
+            message,
+            source,
+            Label::new(span.clone(), "no module with this name"),
+        ),
+        ResolveError::UnknownExport { module, span, .. } => Diagnostic::error(
+            "E0203",
+            message,
+            source,
+            Label::new(span.clone(), format!("not exported by `{}`", module)),
+        ),
+        ResolveError::DuplicateDefinition {
+            name,
+            previous,
+            span,
+        } => Diagnostic::error(
+            "E0204",
+            message,
+            source,
+            Label::new(span.clone(), format!("`{}` redefined here", name)),
+        )
+        .with_label(Label::new(
+            previous.clone(),
+            format!("previous definition of `{}` here", name),
+        )),
+        ResolveError::AssignToImmutable {
+            name,
+            declared,
+            span,
+        } => Diagnostic::error(
+            "E0205",
+            message,
+            source,
+            Label::new(span.clone(), "cannot assign twice to an immutable binding"),
+        )
+        .with_label(Label::new(declared.clone(), "first assigned here"))
+        .with_help(format!(
+            "declare it with `{} := ...` to make it mutable",
+            name
+        )),
+        ResolveError::InvalidAssignmentTarget { span } => Diagnostic::error(
+            "E0206",
+            message,
+            source,
+            Label::new(span.clone(), "cannot assign to this expression"),
+        ),
+        ResolveError::ReturnOutsideFunction { span } => Diagnostic::error(
+            "E0207",
+            message,
+            source,
+            Label::new(span.clone(), "not inside a function"),
+        ),
+        ResolveError::UnknownConstructor { ty, span, .. } => Diagnostic::error(
+            "E0208",
+            message,
+            source,
+            Label::new(span.clone(), format!("not a constructor of `{}`", ty)),
+        ),
+        ResolveError::UnboundConstructor { span, .. } => Diagnostic::error(
+            "E0209",
+            message,
+            source,
+            Label::new(span.clone(), "not found in this scope"),
+        ),
+        ResolveError::AmbiguousConstructor { name, owners, span } => Diagnostic::error(
+            "E0210",
+            message,
+            source,
+            Label::new(span.clone(), "could belong to more than one type"),
+        )
+        .with_note(format!(
+            "`{}` is a constructor of {}",
+            name,
+            owners
+                .iter()
+                .map(|owner| format!("`{}`", owner))
+                .collect::<Vec<_>>()
+                .join(" and ")
+        ))
+        .with_help(format!(
+            "qualify it with its type, e.g. `{}.{}`",
+            owners[0], name
+        )),
+        ResolveError::UnknownOperation { effect, span, .. } => Diagnostic::error(
+            "E0211",
+            message,
+            source,
+            Label::new(span.clone(), format!("not an operation of `{}`", effect)),
+        ),
+        ResolveError::MissingOperation { name, span, .. } => Diagnostic::error(
+            "E0212",
+            message,
+            source,
+            Label::new(span.clone(), format!("`{}` is not handled here", name)),
+        )
+        .with_help("a handler needs a clause for every operation of its effect"),
+        ResolveError::HandlerOutsideImpl { span } => Diagnostic::error(
+            "E0213",
+            message,
+            source,
+            Label::new(span.clone(), "not inside an `impl`"),
+        )
+        .with_note("a handler handles the effect of the `impl` it is written in"),
+        ResolveError::ResumeOutsideHandler { span } => Diagnostic::error(
+            "E0214",
+            message,
+            source,
+            Label::new(span.clone(), "not inside an operation clause"),
+        ),
+        ResolveError::NoDefaultHandler { effect, span } => Diagnostic::error(
+            "E0215",
+            message,
+            source,
+            Label::new(span.clone(), "used as a handler here"),
+        )
+        .with_help(format!(
+            "declare one with an unnamed `handler` in an `impl` for `{}`",
+            effect
+        )),
+    }
+}
+
+fn type_diagnostic(source: SourceId, error: &TypeError) -> Diagnostic {
+    match error {
+        TypeError::Mismatch {
+            expected,
+            found,
+            span,
+        } => Diagnostic::error(
+            "E0300",
+            "mismatched types",
+            source,
+            Label::new(
+                span.clone(),
+                format!("expected `{}`, found `{}`", expected, found),
+            ),
+        ),
+        TypeError::ArityMismatch { expected, span, .. } => Diagnostic::error(
+            "E0301",
+            error.to_string(),
+            source,
+            Label::new(
+                span.clone(),
+                format!(
+                    "expected {} argument{}",
+                    expected,
+                    if *expected == 1 { "" } else { "s" }
+                ),
+            ),
+        ),
+        TypeError::NotCallable { found, span } => Diagnostic::error(
+            "E0302",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), format!("this has type `{}`", found)),
+        ),
+        TypeError::UnknownField { ty, span, .. } => Diagnostic::error(
+            "E0303",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), format!("unknown field on `{}`", ty)),
+        ),
+        TypeError::UnsupportedOperator { operator, ty, span } => {
+            let diagnostic = Diagnostic::error(
+                "E0304",
+                error.to_string(),
+                source,
+                Label::new(
+                    span.clone(),
+                    format!("`{}` is not defined for `{}`", operator, ty),
+                ),
+            );
+            if operator == "??" {
+                diagnostic.with_note(
+                    "`??` replaces the `()` of a `T | ()`; an `Option` is unwrapped with `match`",
+                )
+            } else {
+                diagnostic
+            }
+        }
+        TypeError::InfiniteType { span, .. } => Diagnostic::error(
+            "E0305",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), "this would need an infinitely large type"),
+        )
+        .with_note("a type cannot contain itself"),
+        TypeError::WrongTypeArgumentCount { expected, span, .. } => Diagnostic::error(
+            "E0306",
+            error.to_string(),
+            source,
+            Label::new(
+                span.clone(),
+                format!(
+                    "expected {} type argument{}",
+                    expected,
+                    if *expected == 1 { "" } else { "s" }
+                ),
+            ),
+        ),
+        TypeError::NonExhaustiveMatch { missing, span } => Diagnostic::error(
+            "E0307",
+            error.to_string(),
+            source,
+            Label::new(
+                span.clone(),
+                format!(
+                    "pattern{} {} not covered",
+                    if missing.len() == 1 { "" } else { "s" },
+                    error::patterns(missing)
+                ),
+            ),
+        )
+        .with_help("add an arm for each missing pattern, or a `_` arm"),
+        TypeError::EscapedSkolem { generic, span } => Diagnostic::error(
+            "E0308",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), format!("`{}` escapes through this", generic)),
+        )
+        .with_note(format!(
+            "this must work for every `{}`, so nothing outside it can depend on which one",
+            generic
+        )),
+        TypeError::KindMismatch { found, span, .. } => Diagnostic::error(
+            "E0309",
+            "mismatched kinds",
+            source,
+            Label::new(span.clone(), error.to_string()),
+        )
+        .with_note(if found == "*" {
+            "this type has values and takes no type arguments".to_string()
+        } else {
+            format!(
+                "this type still expects type arguments: its kind is `{}`",
+                found
+            )
+        }),
+        TypeError::ParameterKindMismatch {
+            parameter,
+            found,
+            span,
+            ..
+        } => Diagnostic::error(
+            "E0309",
+            "mismatched kinds",
+            source,
+            Label::new(span.clone(), error.to_string()),
+        )
+        .with_help(format!(
+            "declare it as `{}<{}>` for it to take {} type argument{}",
+            parameter,
+            vec!["_"; *found].join(", "),
+            found,
+            if *found == 1 { "" } else { "s" }
+        )),
+        TypeError::VarianceMismatch {
+            parameter,
+            declared,
+            span,
+        } => Diagnostic::error(
+            "E0310",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), format!("`{}` is declared `{}` here", parameter, declared)),
+        )
+        .with_note(if declared == "out" {
+            "an `out` parameter may only appear where values are produced, such as fields and return types"
+        } else {
+            "an `in` parameter may only appear where values are consumed, such as function parameters"
+        }),
+        TypeError::UnsatisfiedBound { generic, ty, span, .. } => Diagnostic::error(
+            "E0311",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), format!("`{}` is `{}` here", generic, ty)),
+        ),
+        TypeError::InvalidSpread { ty, span } => Diagnostic::error(
+            "E0312",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), format!("this has type `{}`", ty)),
+        )
+        .with_note("only records can be spread, and only the first spread may have fields that are not known"),
+        TypeError::NotAnEffect { span, .. } => Diagnostic::error(
+            "E0313",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), "expected an effect"),
+        )
+        .with_note("effects are declared with `effect`"),
+    }
+}
+
+fn effect_diagnostic(source: SourceId, error: &EffectError) -> Diagnostic {
+    match error {
+        EffectError::Unhandled {
+            effect,
+            declared,
+            span,
+        } => Diagnostic::error(
+            "E0400",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), format!("`{}` is performed here", effect)),
+        )
+        .with_label(Label::new(declared.clone(), "effect declared here"))
+        .with_help(format!("provide a handler for `{}` with `with`", effect)),
+        // In a loop, both are the same `resume`.
+        EffectError::ResumedTwice { first, span, .. } if first == span => Diagnostic::error(
+            "E0401",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), "resumed on every iteration"),
+        )
+        .with_note("a continuation of a one-shot handler can only be resumed once")
+        .with_help("write `multi handler` to resume more than once"),
+        EffectError::ResumedTwice { first, span, .. } => Diagnostic::error(
+            "E0401",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), "resumed again here"),
+        )
+        .with_label(Label::new(first.clone(), "first resumed here"))
+        .with_note("a continuation of a one-shot handler can only be resumed once")
+        .with_help("write `multi handler` to resume more than once"),
+    }
+}
+
+fn codegen_diagnostic(source: SourceId, error: &CodegenError) -> Diagnostic {
+    match error {
+        CodegenError::Unsupported { span, .. } => Diagnostic::error(
+            "E0500",
+            error.to_string(),
+            source,
+            Label::new(span.clone(), "not supported yet"),
+        ),
+    }
+}
@@ -1,5 +1,5 @@
-use std::any::Any;
 use crate::types::Type;
+use std::any::Any;
 
 pub struct Effect {
     pub name: String,
@@ -17,7 +17,9 @@ pub struct EffectHandler {
     pub implementations: Vec<EffectImplementation>,
 }
 
+pub type Implementation = Box<dyn Fn(Box<dyn Any>) -> Box<dyn Any>>;
+
 pub struct EffectImplementation {
     pub operation: String,
-    pub implementation: Box<dyn Fn(Box<dyn Any>) -> Box<dyn Any>>,
-}
\ No newline at end of file
+    pub implementation: Implementation,
+}
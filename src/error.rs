@@ -0,0 +1,710 @@
+use crate::lexing::token::{Span, Token};
+use chumsky::error::{Simple, SimpleReason};
+use std::fmt;
+
+/// Identifies the source a diagnostic points into, usually a file path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceId(String);
+
+impl SourceId {
+    pub fn new(id: impl Into<String>) -> Self {
+        SourceId(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for SourceId {
+    fn default() -> Self {
+        SourceId::new("<input>")
+    }
+}
+
+impl fmt::Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An error from any phase of compilation.
+///
+/// Compile errors own all of their data. Tokens and AST nodes borrow from the
+/// source text, so anything taken from them is rendered to a `String` when the
+/// error is built, which lets errors outlive the source they describe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    Lex {
+        source: SourceId,
+        error: LexError,
+    },
+    Parse {
+        source: SourceId,
+        error: ParseError,
+    },
+    Resolve {
+        source: SourceId,
+        error: ResolveError,
+    },
+    Type {
+        source: SourceId,
+        error: TypeError,
+    },
+    Effect {
+        source: SourceId,
+        error: EffectError,
+    },
+    Codegen {
+        source: SourceId,
+        error: CodegenError,
+    },
+}
+
+impl CompileError {
+    pub fn source(&self) -> &SourceId {
+        match self {
+            CompileError::Lex { source, .. }
+            | CompileError::Parse { source, .. }
+            | CompileError::Resolve { source, .. }
+            | CompileError::Type { source, .. }
+            | CompileError::Effect { source, .. }
+            | CompileError::Codegen { source, .. } => source,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::Lex { error, .. } => error.span(),
+            CompileError::Parse { error, .. } => error.span(),
+            CompileError::Resolve { error, .. } => error.span(),
+            CompileError::Type { error, .. } => error.span(),
+            CompileError::Effect { error, .. } => error.span(),
+            CompileError::Codegen { error, .. } => error.span(),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{}:{}..{}: ", self.source(), span.start, span.end)?;
+
+        match self {
+            CompileError::Lex { error, .. } => write!(f, "{}", error),
+            CompileError::Parse { error, .. } => write!(f, "{}", error),
+            CompileError::Resolve { error, .. } => write!(f, "{}", error),
+            CompileError::Type { error, .. } => write!(f, "{}", error),
+            CompileError::Effect { error, .. } => write!(f, "{}", error),
+            CompileError::Codegen { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Something suspicious that does not stop compilation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileWarning {
+    UnreachablePattern {
+        source: SourceId,
+        span: Span,
+    },
+    // In `left ++ right`, `right` also handles `effect`, which `left`
+    // handles first.
+    ShadowedHandler {
+        source: SourceId,
+        effect: String,
+        shadowed_by: Span,
+        span: Span,
+    },
+}
+
+impl CompileWarning {
+    pub fn source(&self) -> &SourceId {
+        match self {
+            CompileWarning::UnreachablePattern { source, .. }
+            | CompileWarning::ShadowedHandler { source, .. } => source,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            CompileWarning::UnreachablePattern { span, .. }
+            | CompileWarning::ShadowedHandler { span, .. } => span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for CompileWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{}:{}..{}: ", self.source(), span.start, span.end)?;
+
+        match self {
+            CompileWarning::UnreachablePattern { .. } => write!(f, "unreachable pattern"),
+            CompileWarning::ShadowedHandler { effect, .. } => {
+                write!(f, "handler of `{}` is shadowed", effect)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    InvalidToken { text: String, span: Span },
+    // A template string with no closing backtick. `span` is its opening one.
+    UnterminatedTemplate { span: Span },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::InvalidToken { span, .. } | LexError::UnterminatedTemplate { span } => {
+                span.clone()
+            }
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::InvalidToken { text, .. } => write!(f, "invalid token `{}`", text),
+            LexError::UnterminatedTemplate { .. } => write!(f, "unterminated template string"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Unexpected {
+        expected: Vec<String>,
+        found: Option<String>,
+        span: Span,
+    },
+    Unclosed {
+        delimiter: String,
+        delimiter_span: Span,
+        found: Option<String>,
+        span: Span,
+    },
+    Custom {
+        message: String,
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Unexpected { span, .. }
+            | ParseError::Unclosed { span, .. }
+            | ParseError::Custom { span, .. } => span.clone(),
+        }
+    }
+}
+
+impl<'a> From<Simple<Token<'a>>> for ParseError {
+    fn from(error: Simple<Token<'a>>) -> Self {
+        let span = error.span();
+        let found = error.found().map(ToString::to_string);
+
+        match error.reason() {
+            SimpleReason::Unexpected => {
+                // `None` in the expected set stands for the end of input, and
+                // a label for what a parser that failed at its start parses.
+                let mut expected: Vec<String> = error
+                    .expected()
+                    .map(|token| match token {
+                        Some(token) => format!("`{}`", token),
+                        None => "end of input".to_string(),
+                    })
+                    .chain(error.label().map(str::to_string))
+                    .collect();
+                expected.sort();
+                expected.dedup();
+
+                ParseError::Unexpected {
+                    expected,
+                    found,
+                    span,
+                }
+            }
+            SimpleReason::Unclosed {
+                span: delimiter_span,
+                delimiter,
+            } => ParseError::Unclosed {
+                delimiter: delimiter.to_string(),
+                delimiter_span: delimiter_span.clone(),
+                found,
+                span,
+            },
+            SimpleReason::Custom(message) => ParseError::Custom {
+                message: message.clone(),
+                span,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Unexpected {
+                expected, found, ..
+            } => {
+                match found {
+                    Some(found) => write!(f, "unexpected `{}`", found)?,
+                    None => write!(f, "unexpected end of input")?,
+                }
+                if !expected.is_empty() {
+                    write!(f, ", expected {}", expected.join(", "))?;
+                }
+                Ok(())
+            }
+            ParseError::Unclosed { delimiter, .. } => {
+                write!(f, "unclosed delimiter `{}`", delimiter)
+            }
+            ParseError::Custom { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    UnboundVariable {
+        name: String,
+        span: Span,
+    },
+    UnboundType {
+        name: String,
+        span: Span,
+    },
+    UnknownModule {
+        module: String,
+        span: Span,
+    },
+    UnknownExport {
+        module: String,
+        name: String,
+        span: Span,
+    },
+    DuplicateDefinition {
+        name: String,
+        previous: Span,
+        span: Span,
+    },
+    AssignToImmutable {
+        name: String,
+        declared: Span,
+        span: Span,
+    },
+    InvalidAssignmentTarget {
+        span: Span,
+    },
+    ReturnOutsideFunction {
+        span: Span,
+    },
+    UnknownConstructor {
+        ty: String,
+        name: String,
+        span: Span,
+    },
+    UnboundConstructor {
+        name: String,
+        span: Span,
+    },
+    AmbiguousConstructor {
+        name: String,
+        owners: Vec<String>,
+        span: Span,
+    },
+    UnknownOperation {
+        effect: String,
+        name: String,
+        span: Span,
+    },
+    MissingOperation {
+        effect: String,
+        name: String,
+        span: Span,
+    },
+    HandlerOutsideImpl {
+        span: Span,
+    },
+    ResumeOutsideHandler {
+        span: Span,
+    },
+    NoDefaultHandler {
+        effect: String,
+        span: Span,
+    },
+}
+
+impl ResolveError {
+    pub fn span(&self) -> Span {
+        match self {
+            ResolveError::UnboundVariable { span, .. }
+            | ResolveError::UnboundType { span, .. }
+            | ResolveError::UnknownModule { span, .. }
+            | ResolveError::UnknownExport { span, .. }
+            | ResolveError::DuplicateDefinition { span, .. }
+            | ResolveError::AssignToImmutable { span, .. }
+            | ResolveError::UnknownConstructor { span, .. }
+            | ResolveError::UnboundConstructor { span, .. }
+            | ResolveError::AmbiguousConstructor { span, .. }
+            | ResolveError::UnknownOperation { span, .. }
+            | ResolveError::MissingOperation { span, .. }
+            | ResolveError::HandlerOutsideImpl { span }
+            | ResolveError::ResumeOutsideHandler { span }
+            | ResolveError::NoDefaultHandler { span, .. }
+            | ResolveError::InvalidAssignmentTarget { span }
+            | ResolveError::ReturnOutsideFunction { span } => span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::UnboundVariable { name, .. } => {
+                write!(f, "cannot find value `{}` in this scope", name)
+            }
+            ResolveError::UnboundType { name, .. } => {
+                write!(f, "cannot find type `{}` in this scope", name)
+            }
+            ResolveError::UnknownModule { module, .. } => {
+                write!(f, "cannot find module `{}`", module)
+            }
+            ResolveError::UnknownExport { module, name, .. } => {
+                write!(f, "module `{}` has no export named `{}`", module, name)
+            }
+            ResolveError::DuplicateDefinition { name, .. } => {
+                write!(f, "`{}` is defined more than once", name)
+            }
+            ResolveError::AssignToImmutable { name, .. } => {
+                write!(f, "cannot assign twice to immutable binding `{}`", name)
+            }
+            ResolveError::InvalidAssignmentTarget { .. } => {
+                write!(f, "invalid left-hand side of assignment")
+            }
+            ResolveError::ReturnOutsideFunction { .. } => {
+                write!(f, "`return` outside of a function")
+            }
+            ResolveError::UnknownConstructor { ty, name, .. } => {
+                write!(f, "type `{}` has no constructor named `{}`", ty, name)
+            }
+            ResolveError::UnboundConstructor { name, .. } => {
+                write!(f, "cannot find constructor `{}` in this scope", name)
+            }
+            ResolveError::AmbiguousConstructor { name, .. } => {
+                write!(f, "constructor `{}` is ambiguous", name)
+            }
+            ResolveError::UnknownOperation { effect, name, .. } => {
+                write!(f, "effect `{}` has no operation named `{}`", effect, name)
+            }
+            ResolveError::MissingOperation { effect, name, .. } => {
+                write!(f, "handler of `{}` has no clause for `{}`", effect, name)
+            }
+            ResolveError::HandlerOutsideImpl { .. } => {
+                write!(f, "`handler` outside of an `impl`")
+            }
+            ResolveError::ResumeOutsideHandler { .. } => {
+                write!(f, "`resume` outside of an operation clause")
+            }
+            ResolveError::NoDefaultHandler { effect, .. } => {
+                write!(f, "effect `{}` has no default handler", effect)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeError {
+    Mismatch {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    NotCallable {
+        found: String,
+        span: Span,
+    },
+    UnknownField {
+        field: String,
+        ty: String,
+        span: Span,
+    },
+    UnsupportedOperator {
+        operator: String,
+        ty: String,
+        span: Span,
+    },
+    InfiniteType {
+        variable: String,
+        ty: String,
+        span: Span,
+    },
+    WrongTypeArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    // `missing` holds example values the match does not cover, as patterns.
+    NonExhaustiveMatch {
+        missing: Vec<String>,
+        span: Span,
+    },
+    // A value checked against a polymorphic type let its generic `generic`
+    // leak into a type from outside.
+    EscapedSkolem {
+        generic: String,
+        span: Span,
+    },
+    // A type was used where a type of another kind belongs, e.g. `Option`
+    // where a type with values is needed.
+    KindMismatch {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    // A type parameter is given more type arguments than its kind, `kind`,
+    // takes, as `F` in `fun f<F, A>(x: F<A>)`.
+    ParameterKindMismatch {
+        parameter: String,
+        kind: String,
+        found: usize,
+        span: Span,
+    },
+    // A type parameter declared `in` or `out` is used the other way in the
+    // definition of its type. `declared` is the keyword.
+    VarianceMismatch {
+        parameter: String,
+        declared: String,
+        span: Span,
+    },
+    // The type `ty` chosen for `generic` does not meet its bound.
+    UnsatisfiedBound {
+        generic: String,
+        ty: String,
+        bound: String,
+        span: Span,
+    },
+    // A value of type `ty` is spread into a record but is not a record, or
+    // its fields are not all known while an earlier spread could override
+    // ones it might have.
+    InvalidSpread {
+        ty: String,
+        span: Span,
+    },
+    // A type used where an effect belongs, as in `Effect<Int, A>` or
+    // `handle Int { ... }`.
+    NotAnEffect {
+        ty: String,
+        span: Span,
+    },
+}
+
+impl TypeError {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::Mismatch { span, .. }
+            | TypeError::ArityMismatch { span, .. }
+            | TypeError::NotCallable { span, .. }
+            | TypeError::UnknownField { span, .. }
+            | TypeError::UnsupportedOperator { span, .. }
+            | TypeError::InfiniteType { span, .. }
+            | TypeError::WrongTypeArgumentCount { span, .. }
+            | TypeError::NonExhaustiveMatch { span, .. }
+            | TypeError::EscapedSkolem { span, .. }
+            | TypeError::KindMismatch { span, .. }
+            | TypeError::ParameterKindMismatch { span, .. }
+            | TypeError::VarianceMismatch { span, .. }
+            | TypeError::UnsatisfiedBound { span, .. }
+            | TypeError::InvalidSpread { span, .. }
+            | TypeError::NotAnEffect { span, .. } => span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::Mismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            TypeError::ArityMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "this function takes {} argument{} but {} {} supplied",
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found,
+                if *found == 1 { "was" } else { "were" }
+            ),
+            TypeError::NotCallable { found, .. } => {
+                write!(f, "expected a function, found `{}`", found)
+            }
+            TypeError::UnknownField { field, ty, .. } => {
+                write!(f, "no field `{}` on type `{}`", field, ty)
+            }
+            TypeError::UnsupportedOperator { operator, ty, .. } => {
+                write!(f, "operator `{}` cannot be applied to `{}`", operator, ty)
+            }
+            TypeError::InfiniteType { variable, ty, .. } => {
+                write!(f, "infinite type: `{}` occurs in `{}`", variable, ty)
+            }
+            TypeError::WrongTypeArgumentCount {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "`{}` expects {} type argument{} but {} {} supplied",
+                name,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found,
+                if *found == 1 { "was" } else { "were" }
+            ),
+            TypeError::NonExhaustiveMatch { missing, .. } => {
+                write!(f, "non-exhaustive match: {} not covered", patterns(missing))
+            }
+            TypeError::EscapedSkolem { generic, .. } => {
+                write!(f, "type variable `{}` would escape its scope", generic)
+            }
+            TypeError::KindMismatch {
+                expected, found, ..
+            } => write!(f, "expected kind `{}`, found `{}`", expected, found),
+            TypeError::ParameterKindMismatch {
+                parameter,
+                kind,
+                found,
+                ..
+            } => write!(
+                f,
+                "type parameter `{}` has kind `{}` but is given {} type argument{}",
+                parameter,
+                kind,
+                found,
+                if *found == 1 { "" } else { "s" }
+            ),
+            TypeError::VarianceMismatch {
+                parameter,
+                declared,
+                ..
+            } => write!(
+                f,
+                "`{}` is declared `{}` but is used as {}",
+                parameter,
+                declared,
+                if declared == "out" {
+                    "an input"
+                } else {
+                    "an output"
+                }
+            ),
+            TypeError::UnsatisfiedBound {
+                generic, ty, bound, ..
+            } => write!(f, "`{}` does not satisfy `{} {}`", ty, generic, bound),
+            TypeError::InvalidSpread { ty, .. } => {
+                write!(f, "cannot spread `{}` into a record", ty)
+            }
+            TypeError::NotAnEffect { ty, .. } => write!(f, "`{}` is not an effect", ty),
+        }
+    }
+}
+
+/// Lists patterns as "`A`", "`A` and `B`" or "`A`, `B` and 2 more".
+pub(crate) fn patterns(patterns: &[String]) -> String {
+    const SHOWN: usize = 3;
+    let quoted: Vec<String> = patterns
+        .iter()
+        .take(SHOWN)
+        .map(|pattern| format!("`{}`", pattern))
+        .collect();
+
+    match (&quoted[..], patterns.len()) {
+        ([], _) => String::new(),
+        ([only], 1) => only.clone(),
+        (_, count) if count > SHOWN => {
+            format!("{} and {} more", quoted.join(", "), count - SHOWN)
+        }
+        ([init @ .., last], _) => format!("{} and {}", init.join(", "), last),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EffectError {
+    Unhandled {
+        effect: String,
+        declared: Span,
+        span: Span,
+    },
+    // The clause of `operation` in a one-shot handler can resume at `first`
+    // and again at `span`.
+    ResumedTwice {
+        operation: String,
+        first: Span,
+        span: Span,
+    },
+}
+
+impl EffectError {
+    pub fn span(&self) -> Span {
+        match self {
+            EffectError::Unhandled { span, .. } | EffectError::ResumedTwice { span, .. } => {
+                span.clone()
+            }
+        }
+    }
+}
+
+impl fmt::Display for EffectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EffectError::Unhandled { effect, .. } => {
+                write!(f, "effect `{}` is not handled", effect)
+            }
+            EffectError::ResumedTwice { operation, .. } => {
+                write!(f, "the clause of `{}` may resume more than once", operation)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenError {
+    Unsupported { feature: String, span: Span },
+}
+
+impl CodegenError {
+    pub fn span(&self) -> Span {
+        match self {
+            CodegenError::Unsupported { span, .. } => span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodegenError::Unsupported { feature, .. } => {
+                write!(f, "{} is not supported by this backend yet", feature)
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::borrow::Cow;
+
+/// Decodes the escape sequences shared by quoted and template strings.
+///
+/// Returns the input unchanged when it contains no escapes. On failure the
+/// error holds the byte offset of the offending backslash within `raw`.
+pub fn unescape(raw: &str) -> Result<Cow<'_, str>, (usize, String)> {
+    if !raw.contains('\\') {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    let mut decoded = String::with_capacity(raw.len());
+    let mut chars = raw.char_indices();
+
+    while let Some((offset, c)) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some((_, escaped)) => escaped,
+            None => return Err((offset, "unterminated escape sequence".to_string())),
+        };
+
+        match escaped {
+            'n' => decoded.push('\n'),
+            't' => decoded.push('\t'),
+            'r' => decoded.push('\r'),
+            '0' => decoded.push('\0'),
+            '\\' | '"' | '\'' | '`' | '$' => decoded.push(escaped),
+            'u' => decoded.push(unicode_escape(&mut chars).ok_or_else(|| {
+                (
+                    offset,
+                    "invalid unicode escape, expected `\\u{XXXX}`".to_string(),
+                )
+            })?),
+            other => return Err((offset, format!("unknown escape sequence `\\{}`", other))),
+        }
+    }
+
+    Ok(Cow::Owned(decoded))
+}
+
+fn unicode_escape(chars: &mut std::str::CharIndices) -> Option<char> {
+    if chars.next()?.1 != '{' {
+        return None;
+    }
+
+    let mut digits = String::new();
+    loop {
+        match chars.next()?.1 {
+            '}' => break,
+            digit if digit.is_ascii_hexdigit() && digits.len() < 6 => digits.push(digit),
+            _ => return None,
+        }
+    }
+
+    char::from_u32(u32::from_str_radix(&digits, 16).ok()?)
+}
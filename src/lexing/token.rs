@@ -1,15 +1,7 @@
 use logos::Logos;
+use std::fmt;
 use std::ops::Range;
 
-/**
- * TODO:
- * Effect
- * Handlers
- * Match
- * Perform
- * Resume
- * Yield
- */
 pub type Span = Range<usize>;
 
 pub fn span(start: usize, end: usize) -> Span {
@@ -18,16 +10,19 @@ pub fn span(start: usize, end: usize) -> Span {
 
 #[derive(Logos, Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Token<'a> {
-    // Literals
+    // Literals. Numbers are unsigned; a leading `-` is the negation operator.
     #[regex(r#""([^"\\]|\\.)*""#)]
+    #[regex(r#"'([^'\\]|\\.)*'"#)]
     StringLiteral(&'a str),
-    #[regex(r"-?[0-9]+", priority = 3)]
+    #[token("`", template)]
+    Template(Vec<TemplateChunk<'a>>),
+    #[regex(r"[0-9]+", priority = 3)]
     IntegerLiteral(&'a str),
-    #[regex(r"-?[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?", priority = 2)]
+    #[regex(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?", priority = 2)]
     DecimalLiteral(&'a str),
-    #[regex(r"-?[0-9]+n", priority = 1)]
+    #[regex(r"[0-9]+n", priority = 1)]
     BigIntegerLiteral(&'a str),
-    #[regex(r"-?[0-9]+(\.[0-9]+)?n", priority = 0)]
+    #[regex(r"[0-9]+(\.[0-9]+)?n", priority = 0)]
     BigDecimalLiteral(&'a str),
     #[regex(r"true|false")]
     BooleanLiteral(&'a str),
@@ -37,8 +32,12 @@ pub enum Token<'a> {
     SymbolLiteral(&'a str),
 
     // Keywords
+    #[token("alias")]
+    Alias,
     #[token("brand")]
     Brand,
+    #[token("break")]
+    Break,
     #[token("continue")]
     Continue,
     #[token("data")]
@@ -51,12 +50,18 @@ pub enum Token<'a> {
     Export,
     #[token("for")]
     For,
+    #[token("fun")]
+    Fun,
     #[token("function")]
     Function,
     #[token("handle")]
     Handle,
+    #[token("handler")]
+    Handler,
     #[token("if")]
     If,
+    #[token("impl")]
+    Impl,
     #[token("import")]
     Import,
     #[token("in")]
@@ -79,6 +84,8 @@ pub enum Token<'a> {
     Type,
     #[token("while")]
     While,
+    #[token("with")]
+    With,
     #[token("yield")]
     Yield,
 
@@ -131,8 +138,9 @@ pub enum Token<'a> {
     Minus,
     #[token("<<")]
     LeftShift,
-    #[token(">>")]
-    RightShift,
+    // There is no `>>` token: it would be ambiguous with the end of nested type
+    // arguments such as `Option<Option<A>>`, so the parser recognises two
+    // adjacent `>` tokens as a right shift instead.
     #[token("<")]
     LessThan,
     #[token("<=")]
@@ -163,6 +171,8 @@ pub enum Token<'a> {
     Colon,
     #[token("=")]
     Equals,
+    #[token(":=")]
+    ColonEquals,
     #[token("+=")]
     PlusEquals,
     #[token("-=")]
@@ -177,8 +187,6 @@ pub enum Token<'a> {
     ExponentEquals,
     #[token("<<=")]
     LeftShiftEquals,
-    #[token(">>=")]
-    RightShiftEquals,
     #[token("&=")]
     AmpersandEquals,
     #[token("^=")]
@@ -206,22 +214,290 @@ pub enum Token<'a> {
     #[token("#")]
     Hash,
 
+    // Trivia
+    #[regex(r"//[^\n]*")]
+    LineComment(&'a str),
+    // `/**/` and comments opened with `/***`, like divider lines of `////`,
+    // are not doc comments.
+    #[token("/*", block_comment)]
+    #[token("/**/", |lexer| lexer.slice())]
+    #[token("/***", block_comment)]
+    BlockComment(&'a str),
+    #[regex(r"///([^/\n][^\n]*)?")]
+    #[token("/**", block_comment)]
+    DocComment(&'a str),
+
     // Error handling
     #[error]
-    #[regex(r"[ \t\n\f]+", logos::skip)]
+    #[regex(r"[ \t\r\n\f]+", logos::skip)]
     Error,
 }
 
+/// A piece of a backtick template string. Literal chunks keep their raw, still
+/// escaped text; interpolations carry the tokens lexed from inside `${}`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum TemplateChunk<'a> {
+    Literal(&'a str, Span),
+    Interpolation(Vec<(Token<'a>, Span)>, Span),
+}
+
+/// Lexes a template string after its opening backtick. Interpolations are
+/// lexed with a nested lexer over the same source so that their spans stay
+/// absolute, and templates nested inside them are handled by recursion.
+///
+/// An unterminated template takes the rest of the source with it, so that it
+/// is reported once, as a single error token starting at its backtick.
+fn template<'a>(lexer: &mut logos::Lexer<'a, Token<'a>>) -> Option<Vec<TemplateChunk<'a>>> {
+    let chunks = template_chunks(lexer);
+    if chunks.is_none() {
+        lexer.bump(lexer.remainder().len());
+    }
+    chunks
+}
+
+fn template_chunks<'a>(lexer: &mut logos::Lexer<'a, Token<'a>>) -> Option<Vec<TemplateChunk<'a>>> {
+    let source = lexer.source();
+    let start = lexer.span().end;
+    let mut chunks = Vec::new();
+    let mut literal_start = start;
+    let mut position = start;
+
+    loop {
+        let rest = &source[position..];
+        let mut chars = rest.chars();
+
+        match chars.next()? {
+            '\\' => {
+                let escaped = chars.next()?;
+                position += 1 + escaped.len_utf8();
+            }
+            '`' => {
+                if literal_start < position {
+                    chunks.push(TemplateChunk::Literal(
+                        &source[literal_start..position],
+                        span(literal_start, position),
+                    ));
+                }
+                position += 1;
+                break;
+            }
+            '$' if rest[1..].starts_with('{') => {
+                if literal_start < position {
+                    chunks.push(TemplateChunk::Literal(
+                        &source[literal_start..position],
+                        span(literal_start, position),
+                    ));
+                }
+                let (tokens, end) = interpolation(source, position + 2)?;
+                chunks.push(TemplateChunk::Interpolation(tokens, span(position, end)));
+                position = end;
+                literal_start = end;
+            }
+            other => position += other.len_utf8(),
+        }
+    }
+
+    lexer.bump(position - start);
+    Some(chunks)
+}
+
+/// Lexes the body of a `${}` interpolation starting at `start`, returning its
+/// tokens and the offset just past the closing brace.
+fn interpolation<'a>(source: &'a str, start: usize) -> Option<(Vec<(Token<'a>, Span)>, usize)> {
+    let mut lexer = Token::lexer(source);
+    lexer.bump(start);
+
+    let mut depth = 0usize;
+    let mut tokens = Vec::new();
+
+    while let Some(token) = lexer.next() {
+        let token_span = lexer.span();
+
+        match token {
+            Token::LeftBrace => depth += 1,
+            Token::RightBrace if depth == 0 => return Some((tokens, token_span.end)),
+            Token::RightBrace => depth -= 1,
+            _ => {}
+        }
+
+        if !token.is_trivia() {
+            tokens.push((token, token_span));
+        }
+    }
+
+    None
+}
+
+/// Consumes the rest of a `/* */` comment. Block comments do not nest, and an
+/// unterminated comment is reported as an error token.
+fn block_comment<'a>(lexer: &mut logos::Lexer<'a, Token<'a>>) -> Option<&'a str> {
+    // The last `*` of `/***` also closes the comment in `/***/`.
+    if lexer.slice() == "/***" && lexer.remainder().starts_with('/') {
+        lexer.bump(1);
+        return Some(lexer.slice());
+    }
+
+    let end = lexer.remainder().find("*/")?;
+    lexer.bump(end + 2);
+    Some(lexer.slice())
+}
+
+impl<'a> Token<'a> {
+    /// Comments are kept out of the token stream handed to the parser, but the
+    /// lexer still records them so tooling can recover doc comments.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            Token::LineComment(_) | Token::BlockComment(_) | Token::DocComment(_)
+        )
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::StringLiteral(raw)
+            | Token::IntegerLiteral(raw)
+            | Token::DecimalLiteral(raw)
+            | Token::BigIntegerLiteral(raw)
+            | Token::BigDecimalLiteral(raw)
+            | Token::BooleanLiteral(raw)
+            | Token::Identifier(raw)
+            | Token::SymbolLiteral(raw) => raw,
+            Token::Template(_) => "template string",
+            Token::LineComment(_) | Token::BlockComment(_) | Token::DocComment(_) => "comment",
+            Token::Error => "invalid token",
+            Token::Alias => "alias",
+            Token::Brand => "brand",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Data => "data",
+            Token::Effect => "effect",
+            Token::Else => "else",
+            Token::Export => "export",
+            Token::For => "for",
+            Token::Fun => "fun",
+            Token::Function => "function",
+            Token::Handle => "handle",
+            Token::Handler => "handler",
+            Token::If => "if",
+            Token::Impl => "impl",
+            Token::Import => "import",
+            Token::In => "in",
+            Token::Let => "let",
+            Token::Match => "match",
+            Token::Of => "of",
+            Token::Out => "out",
+            Token::Perform => "perform",
+            Token::Resume => "resume",
+            Token::Return => "return",
+            Token::Type => "type",
+            Token::While => "while",
+            Token::With => "with",
+            Token::Yield => "yield",
+            Token::Subtype => "<:",
+            Token::Supertype => ">:",
+            Token::LeftParenthesis => "(",
+            Token::RightParenthesis => ")",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::Spread => "...",
+            Token::Dot => ".",
+            Token::DoubleColon => "::",
+            Token::OptionalChaining => "?.",
+            Token::PlusPlus => "++",
+            Token::MinusMinus => "--",
+            Token::LogicalNot => "!",
+            Token::BitwiseNot => "~",
+            Token::Exponent => "**",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Modulo => "%",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::LeftShift => "<<",
+            Token::LessThan => "<",
+            Token::LessThanOrEqual => "<=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanOrEqual => ">=",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::Ampersand => "&",
+            Token::BitwiseXor => "^",
+            Token::Pipe => "|",
+            Token::LogicalAnd => "&&",
+            Token::LogicalOr => "||",
+            Token::NullishCoalescing => "??",
+            Token::QuestionMark => "?",
+            Token::Colon => ":",
+            Token::Equals => "=",
+            Token::ColonEquals => ":=",
+            Token::PlusEquals => "+=",
+            Token::MinusEquals => "-=",
+            Token::MultiplyEquals => "*=",
+            Token::DivideEquals => "/=",
+            Token::ModuloEquals => "%=",
+            Token::ExponentEquals => "**=",
+            Token::LeftShiftEquals => "<<=",
+            Token::AmpersandEquals => "&=",
+            Token::BitwiseXorEquals => "^=",
+            Token::PipeEquals => "|=",
+            Token::LogicalAndEquals => "&&=",
+            Token::LogicalOrEquals => "||=",
+            Token::NullishCoalescingEquals => "??=",
+            Token::Arrow => "->",
+            Token::FatArrow => "=>",
+            Token::PipeOperator => "|>",
+            Token::Comma => ",",
+            Token::Semicolon => ";",
+            Token::At => "@",
+            Token::Hash => "#",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
 pub struct Lexer<'a> {
     source: &'a str,
     lexer: logos::Lexer<'a, Token<'a>>,
+    trivia: Vec<(Token<'a>, Span)>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Lexer {
+            source,
+            lexer: Token::lexer(source),
+            trivia: Vec::new(),
+        }
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// Comments skipped so far, in source order.
+    pub fn trivia(&self) -> &[(Token<'a>, Span)] {
+        &self.trivia
+    }
 }
 
 impl<'a> Iterator for Lexer<'a> {
     type Item = (Token<'a>, Span);
 
     fn next(&mut self) -> Option<Self::Item> {
-      let token = self.lexer.next()?;
-      Some((token, self.lexer.span()))
+        loop {
+            let token = self.lexer.next()?;
+            let span = self.lexer.span();
+
+            if token.is_trivia() {
+                self.trivia.push((token, span));
+            } else {
+                return Some((token, span));
+            }
+        }
     }
 }
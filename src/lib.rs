@@ -1,15 +1,127 @@
+pub mod bytecode;
 mod codegen;
-mod effects;
-mod lexing;
-mod parsing;
-mod types;
+pub mod diagnostics;
+pub mod effects;
+pub mod error;
+pub mod lexing;
+pub mod parsing;
+pub mod types;
+pub mod vm;
 
 use codegen::wasm::WasmCompiler;
+use error::{CompileError, CompileWarning, LexError, ParseError, SourceId};
+use lexing::token::{Lexer, Span, TemplateChunk, Token};
+use parsing::ast::Program;
 use parsing::parser::parse;
+use types::Checked;
 
-pub fn compile(source: &str) -> Result<Vec<u8>, String> {
-    let ast = parse(source)?;
-    let wasm = WasmCompiler::new().compile(&ast);
-    Ok(wasm)
+/// Everything produced by a successful compilation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileOutput {
+    pub wasm: Vec<u8>,
+    pub warnings: Vec<CompileWarning>,
 }
 
+pub fn compile(source: &str) -> Result<CompileOutput, Vec<CompileError>> {
+    compile_source(SourceId::default(), source)
+}
+
+/// Compiles `source`, naming it `id` in any errors.
+///
+/// Lexing and parsing errors borrow from `source`, so they are converted into
+/// owned `CompileError`s before they leave this function.
+pub fn compile_source(id: SourceId, source: &str) -> Result<CompileOutput, Vec<CompileError>> {
+    let (ast, checked) = analyze(&id, source)?;
+
+    let wasm = WasmCompiler::new()
+        .compile(&ast)
+        .map_err(|error| vec![CompileError::Codegen { source: id, error }])?;
+
+    Ok(CompileOutput {
+        wasm,
+        warnings: checked.warnings,
+    })
+}
+
+/// Type-checks `source` and renders the decision tree of every `match` in
+/// it, in source order. Meant for inspecting how matches are compiled.
+pub fn decision_trees(source: &str) -> Result<Vec<String>, Vec<CompileError>> {
+    let (_, checked) = analyze(&SourceId::default(), source)?;
+    Ok(checked
+        .decisions
+        .iter()
+        .map(|(_, decision)| decision.dump())
+        .collect())
+}
+
+/// Type-checks `source` and compiles it to bytecode for the virtual machine.
+pub fn compile_bytecode(source: &str) -> Result<bytecode::Module, Vec<CompileError>> {
+    let id = SourceId::default();
+    let (ast, checked) = analyze(&id, source)?;
+    bytecode::compile(&ast, &checked)
+        .map_err(|error| vec![CompileError::Codegen { source: id, error }])
+}
+
+/// Lexes, parses and type-checks `source`.
+fn analyze<'a>(
+    id: &SourceId,
+    source: &'a str,
+) -> Result<(Program<'a>, Checked), Vec<CompileError>> {
+    let mut lex_errors = Vec::new();
+    invalid_tokens(Lexer::new(source), source, &mut lex_errors);
+    let lex_errors: Vec<CompileError> = lex_errors
+        .into_iter()
+        .map(|error| CompileError::Lex {
+            source: id.clone(),
+            error,
+        })
+        .collect();
+    if !lex_errors.is_empty() {
+        return Err(lex_errors);
+    }
+
+    let ast = parse(source).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|error| CompileError::Parse {
+                source: id.clone(),
+                error: ParseError::from(error),
+            })
+            .collect::<Vec<_>>()
+    })?;
+
+    let checked = types::check(&ast, id)?;
+    Ok((ast, checked))
+}
+
+/// Collects the tokens of `tokens` that are not valid, including those in the
+/// interpolations of template strings, which are lexed along with them.
+fn invalid_tokens<'a>(
+    tokens: impl IntoIterator<Item = (Token<'a>, Span)>,
+    source: &str,
+    errors: &mut Vec<LexError>,
+) {
+    for (token, span) in tokens {
+        match token {
+            // Only a template starts with a backtick, and one that fails to
+            // lex has no closing backtick.
+            Token::Error if source[span.clone()].starts_with('`') => {
+                errors.push(LexError::UnterminatedTemplate {
+                    span: span.start..span.start + 1,
+                })
+            }
+            Token::Error => errors.push(LexError::InvalidToken {
+                text: source[span.clone()].to_string(),
+                span,
+            }),
+            Token::Template(chunks) => {
+                for chunk in chunks {
+                    if let TemplateChunk::Interpolation(tokens, _) = chunk {
+                        invalid_tokens(tokens, source, errors);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,15 +1,6 @@
-use std::collections::HashMap;
 use crate::lexing::token::Span;
-
-/**
- * TODO:
- * Effect
- * Handlers
- * Match
- * Perform
- * Resume
- * Yield
- */
+use std::borrow::Cow;
+use std::collections::HashMap;
 
 // Basic types and literals
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -23,29 +14,33 @@ pub enum RecordKey<'a> {
 pub enum Expression<'a> {
     // Literals
     String {
-        value: &'a str,
+        value: Cow<'a, str>,
+        span: Span,
+    },
+    Template {
+        parts: Vec<TemplatePart<'a>>,
         span: Span,
     },
     Integer {
-        value: &'a i64,
+        value: i64,
         span: Span,
     },
+    // Decimal literals keep their source text so that no precision is lost
+    // before the checker decides how they should be represented.
     Decimal {
-        integer: &'a i64,
-        fractional: &'a i64,
+        value: &'a str,
         span: Span,
     },
     BigInteger {
-        value: &'a i128,
+        value: i128,
         span: Span,
     },
     BigDecimal {
-        integer: &'a i128,
-        fractional: &'a i128,
+        value: &'a str,
         span: Span,
     },
     Boolean {
-        value: &'a bool,
+        value: bool,
         span: Span,
     },
     Array {
@@ -56,14 +51,33 @@ pub enum Expression<'a> {
         elements: Vec<Expression<'a>>,
         span: Span,
     },
+    // `{ ...base, x: 1 }`: the fields of each spread record in turn, then
+    // the fields written out, each overriding any earlier one of its name.
     Record {
         fields: HashMap<RecordKey<'a>, Expression<'a>>,
+        spreads: Vec<Expression<'a>>,
         span: Span,
     },
     Symbol {
         name: &'a str,
         span: Span,
     },
+    Identifier {
+        name: &'a str,
+        span: Span,
+    },
+
+    // Access
+    Member {
+        object: Box<Expression<'a>>,
+        property: &'a str,
+        span: Span,
+    },
+    Index {
+        object: Box<Expression<'a>>,
+        index: Box<Expression<'a>>,
+        span: Span,
+    },
 
     // Operators
     Binary {
@@ -77,6 +91,30 @@ pub enum Expression<'a> {
         expr: Box<Expression<'a>>,
         span: Span,
     },
+    Conditional {
+        condition: Box<Expression<'a>>,
+        then_branch: Box<Expression<'a>>,
+        else_branch: Box<Expression<'a>>,
+        span: Span,
+    },
+    // `op` is set for compound assignments such as `+=`.
+    Assignment {
+        target: Box<Expression<'a>>,
+        op: Option<BinaryOp>,
+        value: Box<Expression<'a>>,
+        span: Span,
+    },
+
+    Lambda {
+        parameters: Vec<Field<'a>>,
+        body: Box<Expression<'a>>,
+        span: Span,
+    },
+    // The value of a block is the value of its last expression statement.
+    Block {
+        statements: Vec<Statement<'a>>,
+        span: Span,
+    },
 
     FunctionCall {
         function: Box<Expression<'a>>,
@@ -85,11 +123,24 @@ pub enum Expression<'a> {
         span: Span,
     },
 
+    // `match value { pattern -> expression, ... }`
+    Match {
+        scrutinee: Box<Expression<'a>>,
+        arms: Vec<MatchArm<'a>>,
+        span: Span,
+    },
+
     Resume {
         expression: Box<Expression<'a>>,
         span: Span,
     },
 
+    // `resume` on its own: the continuation of the operation clause it is
+    // written in, as a value that can be stored and called later.
+    Continuation {
+        span: Span,
+    },
+
     Yield {
         expression: Box<Expression<'a>>,
         span: Span,
@@ -105,6 +156,34 @@ pub enum Expression<'a> {
         expression: Box<Expression<'a>>,
         span: Span,
     },
+
+    // A handler of the effect of the `impl` it is written in.
+    Handler(Box<Handler<'a>>),
+
+    // `expression with handler` runs `expression` with the effects of
+    // `handler` handled.
+    With {
+        expression: Box<Expression<'a>>,
+        handler: Box<Expression<'a>>,
+        span: Span,
+    },
+
+    // Stands in for an expression that failed to parse.
+    Error {
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplatePart<'a> {
+    Literal {
+        value: Cow<'a, str>,
+        span: Span,
+    },
+    Expression {
+        expression: Box<Expression<'a>>,
+        span: Span,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -131,6 +210,8 @@ pub enum BinaryOp {
     NullishCoalescing(Span),
     PipeOperator(Span),
     OptionalChaining(Span),
+    // `++`, which joins lists, strings and handlers.
+    Concatenation(Span),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -170,30 +251,29 @@ pub enum Type<'a> {
         span: Span,
     },
 
+    // Literal types, holding the one value they are written as.
     StringLiteral {
-        value: &'a str,
+        value: Cow<'a, str>,
         span: Span,
     },
     IntegerLiteral {
-        value: &'a i64,
+        value: i64,
         span: Span,
     },
     DecimalLiteral {
-        integer: &'a i64,
-        fraction: &'a i64,
+        value: Cow<'a, str>,
         span: Span,
     },
     BigIntegerLiteral {
-        value: &'a i128,
+        value: i128,
         span: Span,
     },
     BigDecimalLiteral {
-        integer: &'a i128,
-        fraction: &'a i128,
+        value: Cow<'a, str>,
         span: Span,
     },
     BooleanLiteral {
-        value: &'a bool,
+        value: bool,
         span: Span,
     },
     ArrayLiteral {
@@ -223,8 +303,11 @@ pub enum Type<'a> {
         elements: Vec<Type<'a>>,
         span: Span,
     },
+    // `{ x: Int, ...R }` when open: any record with at least the fields,
+    // whose other fields are the row `R`.
     Record {
         fields: HashMap<RecordKey<'a>, Type<'a>>,
+        rest: Option<Box<Type<'a>>>,
         span: Span,
     },
 
@@ -254,10 +337,19 @@ pub enum Type<'a> {
 
     // Function types
     Function {
-        parameters: Vec<TypeParameter<'a>>,
+        type_parameters: Vec<TypeParameter<'a>>,
+        parameters: Vec<Type<'a>>,
         return_type: Box<Type<'a>>,
         span: Span,
     },
+
+    // A named type that is not a primitive, e.g. `Option<A>`. Whether the name
+    // refers to a declaration or a type variable is decided by the checker.
+    Reference {
+        name: &'a str,
+        arguments: Vec<Type<'a>>,
+        span: Span,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -345,6 +437,10 @@ pub enum Statement<'a> {
         span: Span,
     },
     Declaration(Declaration<'a>),
+    // Stands in for a statement that failed to parse.
+    Error {
+        span: Span,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -374,6 +470,7 @@ pub enum Declaration<'a> {
     },
     Let {
         exported: bool,
+        mutable: bool,
         name: &'a str,
         annotation: Option<Type<'a>>,
         value: Expression<'a>,
@@ -393,6 +490,83 @@ pub enum Declaration<'a> {
         fields: Vec<EffectField<'a>>,
         span: Span,
     },
+    // `impl<A> for State<A> { ... }`: handlers of an effect and functions to
+    // use with it, all reached through its name as in `State.local(10)`.
+    Impl {
+        exported: bool,
+        type_parameters: Vec<TypeParameter<'a>>,
+        effect: Type<'a>,
+        members: Vec<ImplMember<'a>>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImplMember<'a> {
+    // Always a `Declaration::Function`.
+    Function(Declaration<'a>),
+    Handler(Handler<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchArm<'a> {
+    pub pattern: Pattern<'a>,
+    pub guard: Option<Expression<'a>>,
+    pub body: Expression<'a>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern<'a> {
+    // `_`
+    Wildcard {
+        span: Span,
+    },
+    Binding {
+        name: &'a str,
+        span: Span,
+    },
+    Literal {
+        value: Literal<'a>,
+        span: Span,
+    },
+    Tuple {
+        elements: Vec<Pattern<'a>>,
+        span: Span,
+    },
+    // `[first, second, ...rest]`; the rest pattern matches the remaining
+    // elements as an array.
+    Array {
+        elements: Vec<Pattern<'a>>,
+        rest: Option<Box<Pattern<'a>>>,
+        span: Span,
+    },
+    // `{ x, y: pattern, ...rest }`, where `x` is short for `x: x`. The rest
+    // pattern matches the record without the fields named before it.
+    Record {
+        fields: Vec<(&'a str, Pattern<'a>)>,
+        rest: Option<Box<Pattern<'a>>>,
+        span: Span,
+    },
+    // `Some(a)`, `Op.Add(a, b)` or `None`. A bare name is a constructor when
+    // it is capitalised and a binding otherwise.
+    Constructor {
+        owner: Option<&'a str>,
+        name: &'a str,
+        arguments: Vec<Pattern<'a>>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Literal<'a> {
+    Integer(i64),
+    Decimal(Cow<'a, str>),
+    BigInteger(i128),
+    BigDecimal(Cow<'a, str>),
+    Boolean(bool),
+    String(Cow<'a, str>),
+    Symbol(&'a str),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -427,9 +601,9 @@ pub enum ImportDeclaration<'a> {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NamedImport<'a> {
-    name: &'a str,
-    alias: Option<String>,
-    span: Span,
+    pub name: &'a str,
+    pub alias: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -448,10 +622,49 @@ pub enum Field<'a> {
     },
 }
 
+// An operation of an effect, such as `modify<B>(A -> (B, A)) -> B`, which
+// may have type parameters of its own besides the effect's.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EffectField<'a> {
     pub name: &'a str,
-    pub declaration: Type<'a>,
+    pub type_parameters: Vec<TypeParameter<'a>>,
+    pub parameters: Vec<Type<'a>>,
+    pub return_type: Type<'a>,
+    pub span: Span,
+}
+
+// `handler name(parameters) { ... }`, without a name for the default
+// handler of an effect or one written as an expression. For an effect with
+// a single operation, the parameters and body are that operation's clause,
+// as in `handler(error: E) { f(error) }`, and `operations` is empty.
+// Otherwise the parameters are the handler's own and the body ends by
+// returning a clause for each operation, `return { get() { current } }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handler<'a> {
+    pub name: Option<&'a str>,
+    // Written `multi handler`: its clauses may resume more than once.
+    pub multishot: bool,
+    pub parameters: Vec<Field<'a>>,
+    pub body: Vec<Statement<'a>>,
+    pub operations: Vec<OperationClause<'a>>,
+    // `{ return(a) { ... } }` after the body, which turns the value of the
+    // computation handled into the value of handling it.
+    pub return_clause: Option<ReturnClause<'a>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationClause<'a> {
+    pub name: &'a str,
+    pub parameters: Vec<Field<'a>>,
+    pub body: Vec<Statement<'a>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReturnClause<'a> {
+    pub parameter: Field<'a>,
+    pub body: Vec<Statement<'a>>,
     pub span: Span,
 }
 
@@ -467,7 +680,15 @@ pub trait Spanned {
 
 impl<T: Spanned> Spanned for Option<T> {
     fn span(&self) -> Span {
-        self.as_ref().map(|t| t.span()).unwrap_or(Span::default())
+        self.as_ref().map(|t| t.span()).unwrap_or_default()
+    }
+}
+
+impl Spanned for RecordKey<'_> {
+    fn span(&self) -> Span {
+        match self {
+            RecordKey::String(_, span) | RecordKey::Symbol(_, span) => span.clone(),
+        }
     }
 }
 
@@ -475,6 +696,7 @@ impl Spanned for Expression<'_> {
     fn span(&self) -> Span {
         match self {
             Expression::String { span, .. } => span.clone(),
+            Expression::Template { span, .. } => span.clone(),
             Expression::Integer { span, .. } => span.clone(),
             Expression::Decimal { span, .. } => span.clone(),
             Expression::BigInteger { span, .. } => span.clone(),
@@ -484,13 +706,34 @@ impl Spanned for Expression<'_> {
             Expression::Tuple { span, .. } => span.clone(),
             Expression::Record { span, .. } => span.clone(),
             Expression::Symbol { span, .. } => span.clone(),
+            Expression::Identifier { span, .. } => span.clone(),
+            Expression::Member { span, .. } => span.clone(),
+            Expression::Index { span, .. } => span.clone(),
             Expression::Binary { span, .. } => span.clone(),
             Expression::Unary { span, .. } => span.clone(),
+            Expression::Conditional { span, .. } => span.clone(),
+            Expression::Assignment { span, .. } => span.clone(),
+            Expression::Lambda { span, .. } => span.clone(),
+            Expression::Block { span, .. } => span.clone(),
             Expression::FunctionCall { span, .. } => span.clone(),
+            Expression::Match { span, .. } => span.clone(),
             Expression::Resume { span, .. } => span.clone(),
             Expression::Yield { span, .. } => span.clone(),
             Expression::Perform { span, .. } => span.clone(),
             Expression::Handle { span, .. } => span.clone(),
+            Expression::Continuation { span } => span.clone(),
+            Expression::With { span, .. } => span.clone(),
+            Expression::Handler(handler) => handler.span.clone(),
+            Expression::Error { span } => span.clone(),
+        }
+    }
+}
+
+impl Spanned for TemplatePart<'_> {
+    fn span(&self) -> Span {
+        match self {
+            TemplatePart::Literal { span, .. } => span.clone(),
+            TemplatePart::Expression { span, .. } => span.clone(),
         }
     }
 }
@@ -509,6 +752,7 @@ impl Spanned for Statement<'_> {
             Statement::ForIn { span, .. } => span.clone(),
             Statement::Import { span, .. } => span.clone(),
             Statement::Declaration(decl) => decl.span(),
+            Statement::Error { span } => span.clone(),
         }
     }
 }
@@ -522,6 +766,21 @@ impl Spanned for Declaration<'_> {
             Declaration::Let { span, .. } => span.clone(),
             Declaration::TypeAlias { span, .. } => span.clone(),
             Declaration::Effect { span, .. } => span.clone(),
+            Declaration::Impl { span, .. } => span.clone(),
+        }
+    }
+}
+
+impl Spanned for Pattern<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard { span } => span.clone(),
+            Pattern::Binding { span, .. } => span.clone(),
+            Pattern::Literal { span, .. } => span.clone(),
+            Pattern::Tuple { span, .. } => span.clone(),
+            Pattern::Array { span, .. } => span.clone(),
+            Pattern::Record { span, .. } => span.clone(),
+            Pattern::Constructor { span, .. } => span.clone(),
         }
     }
 }
@@ -597,6 +856,7 @@ impl Spanned for Type<'_> {
             Type::Union { span, .. } => span.clone(),
             Type::Intersection { span, .. } => span.clone(),
             Type::Function { span, .. } => span.clone(),
+            Type::Reference { span, .. } => span.clone(),
         }
     }
 }
@@ -1,2 +1,2 @@
 pub mod ast;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
@@ -1,40 +1,372 @@
-use crate::lexing::token::{span, Span, Token};
-use crate::parsing::ast::{Declaration, Expression, Program, Spanned, Statement};
+// chumsky passes errors around by value, and `Simple<Token>` is large.
+#![allow(clippy::result_large_err)]
+
+use crate::lexing::escape::unescape;
+use crate::lexing::token::{span, Lexer, Span, TemplateChunk, Token};
+use crate::parsing::ast::{
+    BinaryOp, DataConstructor, Declaration, EffectField, Expression, Field, Handler, ImplMember,
+    ImportDeclaration, Literal, MatchArm, NamedImport, OperationClause, Pattern, Program,
+    RecordKey, ReturnClause, Spanned, Statement, TemplatePart, Type, TypeConstraint, TypeParameter,
+    UnaryOp, Variance,
+};
+use chumsky::error::SimpleReason;
 use chumsky::prelude::*;
-use logos::Logos;
+use chumsky::recovery::NestedDelimiters;
+use chumsky::recursive::Recursive;
+use chumsky::Stream;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+pub fn parse<'a>(source: &'a str) -> Result<Program<'a>, Vec<Simple<Token<'a>>>> {
+    let (tokens, lines) = token_stream(source);
+    program(span(0, source.len()), lines)
+        .parse(tokens)
+        .map_err(distinct_errors)
+}
+
+/// Parses as much of `source` as possible. Anything that fails to parse is
+/// replaced by an `Error` node, so a program is returned alongside every
+/// error unless recovery itself failed.
+pub fn parse_partial<'a>(source: &'a str) -> (Option<Program<'a>>, Vec<Simple<Token<'a>>>) {
+    let (tokens, lines) = token_stream(source);
+    let (program, errors) = program(span(0, source.len()), lines).parse_recovery(tokens);
+    (program, distinct_errors(errors))
+}
+
+// Recovery at several levels can report the same failure more than once; the
+// first report is the most specific.
+fn distinct_errors<'a>(mut errors: Vec<Simple<Token<'a>>>) -> Vec<Simple<Token<'a>>> {
+    let mut seen = HashSet::new();
+    errors.retain(|error| seen.insert(error.span()));
+    errors
+}
+
+pub fn parse_expression<'a>(source: &'a str) -> Result<Expression<'a>, Vec<Simple<Token<'a>>>> {
+    let (tokens, lines) = token_stream(source);
+    expression(lines).then_ignore(end()).parse(tokens)
+}
+
+type TokenStream<'a> = Stream<'a, Token<'a>, Span, std::vec::IntoIter<(Token<'a>, Span)>>;
+
+fn token_stream<'a>(source: &'a str) -> (TokenStream<'a>, LineStarts) {
+    let tokens: Vec<_> = Lexer::new(source).collect();
+    let mut lines = HashSet::new();
+    line_starts(source, &tokens, 0, &mut lines);
+    let eoi = span(source.len(), source.len());
+    (Stream::from_iter(eoi, tokens.into_iter()), Rc::new(lines))
+}
+
+/// The offsets of the tokens that start a line. A call, an index or a postfix
+/// operator never starts one, so that the line begins a new statement
+/// instead, and neither does the value of a `resume`.
+type LineStarts = Rc<HashSet<usize>>;
+
+/// Adds the tokens with a line break between them and the token before, or
+/// `previous` for the first, including those in template interpolations.
+fn line_starts(
+    source: &str,
+    tokens: &[(Token, Span)],
+    mut previous: usize,
+    lines: &mut HashSet<usize>,
+) {
+    for (token, span) in tokens {
+        if source[previous..span.start].contains('\n') {
+            lines.insert(span.start);
+        }
+        if let Token::Template(chunks) = token {
+            for chunk in chunks {
+                if let TemplateChunk::Interpolation(tokens, chunk) = chunk {
+                    line_starts(source, tokens, chunk.start + 2, lines);
+                }
+            }
+        }
+        previous = span.end;
+    }
+}
+
+/// Checks, without consuming anything, that the next token is on the same
+/// line as the one before it.
+fn same_line<'a>(
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, (), Error = Simple<Token<'a>>> + Clone {
+    any()
+        .try_map(move |token, span: Span| match lines.contains(&span.start) {
+            true => Err(Simple::expected_input_found(span, None, Some(token))),
+            false => Ok(()),
+        })
+        .rewind()
+}
+
+/// Checks, without consuming anything, that no operand starts on the same
+/// line next. Prefix operators do not count, so `i++ - 1` is a subtraction.
+fn no_operand<'a>(
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, (), Error = Simple<Token<'a>>> + Clone {
+    any()
+        .or_not()
+        .try_map(move |token, span: Span| match token {
+            Some(token) if starts_operand(&token) && !lines.contains(&span.start) => {
+                Err(Simple::expected_input_found(span, None, Some(token)))
+            }
+            _ => Ok(()),
+        })
+        .rewind()
+}
+
+fn starts_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Identifier(_)
+            | Token::StringLiteral(_)
+            | Token::Template(_)
+            | Token::IntegerLiteral(_)
+            | Token::DecimalLiteral(_)
+            | Token::BigIntegerLiteral(_)
+            | Token::BigDecimalLiteral(_)
+            | Token::BooleanLiteral(_)
+            | Token::SymbolLiteral(_)
+            | Token::LeftParenthesis
+            | Token::LeftBracket
+            | Token::LeftBrace
+            | Token::Resume
+            | Token::Match
+            | Token::Handle
+    )
+}
+
+fn program<'a>(
+    span: Span,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Program<'a>, Error = Simple<Token<'a>>> {
+    // Statements stop at `}` so that blocks can end; at the top level there
+    // is no block to end, so the brace is reported and skipped.
+    let stray_brace = just(Token::RightBrace).validate(|token, span: Span, emit| {
+        emit(Simple::expected_input_found(
+            span.clone(),
+            None,
+            Some(token),
+        ));
+        Statement::Error { span }
+    });
+
+    statement(lines)
+        .or(stray_brace)
+        .repeated()
+        .then_ignore(end())
+        .map(move |statements| Program {
+            statements,
+            span: span.clone(),
+        })
+}
+
+type StatementParser<'a> = Recursive<'a, Token<'a>, Statement<'a>, Simple<Token<'a>>>;
+type ExpressionParser<'a> = Recursive<'a, Token<'a>, Expression<'a>, Simple<Token<'a>>>;
+
+/// Statements and expressions are mutually recursive through blocks, so both
+/// parsers are declared up front and defined in terms of each other.
+fn grammar<'a>(lines: LineStarts) -> (StatementParser<'a>, ExpressionParser<'a>) {
+    let mut statement = Recursive::declare();
+    let mut expression = Recursive::declare();
+
+    expression.define(
+        expecting(
+            operator_expression(expression.clone(), statement.clone(), lines.clone()),
+            "an expression",
+        )
+        .boxed(),
+    );
+    statement.define(
+        statement_start()
+            .ignore_then(
+                expecting(
+                    statement_kind(statement.clone(), expression.clone(), lines.clone()),
+                    "a statement",
+                )
+                .recover_with(
+                    skip_until(STATEMENT_BOUNDARIES, |span| Statement::Error { span }).skip_start(),
+                ),
+            )
+            .then(statement_end(lines))
+            .validate(|(statement, stray), _, emit| {
+                // A statement that failed to parse has been reported already.
+                match (stray, &statement) {
+                    (_, Statement::Error { .. }) | (None, _) => {}
+                    (Some((token, span)), _) => emit(Simple::expected_input_found(
+                        span,
+                        [Some(Token::Semicolon)],
+                        Some(token),
+                    )),
+                }
+                statement
+            })
+            .boxed(),
+    );
+
+    (statement, expression)
+}
+
+/// What follows a statement: a `;`, which is skipped, or a line break, the
+/// `}` closing its block, an `else` or the end of input. Anything else is
+/// returned, without being consumed, so that `x = 1 y = 2` can be reported.
+fn statement_end<'a>(
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Option<(Token<'a>, Span)>, Error = Simple<Token<'a>>> + Clone {
+    let next = any()
+        .map_with_span(|token, span: Span| (token, span))
+        .or_not()
+        .rewind()
+        .map(move |next| {
+            next.filter(|(token, span): &(Token, Span)| {
+                !lines.contains(&span.start) && !matches!(token, Token::RightBrace | Token::Else)
+            })
+        });
+
+    just(Token::Semicolon).to(None).or(next)
+}
+
+/// Where a statement that failed to parse is assumed to end: at a `;`, at the
+/// `}` closing its block or before anything that starts a new statement.
+const STATEMENT_BOUNDARIES: [Token<'static>; 18] = [
+    Token::Semicolon,
+    Token::RightBrace,
+    Token::Alias,
+    Token::Brand,
+    Token::Data,
+    Token::Effect,
+    Token::Export,
+    Token::Fun,
+    Token::Function,
+    Token::Impl,
+    Token::Import,
+    Token::Let,
+    Token::Type,
+    Token::Return,
+    Token::If,
+    Token::While,
+    Token::For,
+    Token::Break,
+];
 
-pub fn parse<'a>(source: &str) -> Result<Program, Vec<Simple<Token<'a>>>> {
-    let mut lexer = Token::lexer(source).spanned();
-    let span = span(0, source.len());
-    let parser = program(span);
+/// Where to resume when the operand after an operator fails to parse: the
+/// statement boundaries, plus the brackets that close a surrounding call,
+/// tuple or array.
+const OPERAND_BOUNDARIES: [Token<'static>; 20] = [
+    Token::Semicolon,
+    Token::RightParenthesis,
+    Token::RightBracket,
+    Token::RightBrace,
+    Token::Alias,
+    Token::Brand,
+    Token::Data,
+    Token::Effect,
+    Token::Export,
+    Token::Fun,
+    Token::Function,
+    Token::Impl,
+    Token::Import,
+    Token::Let,
+    Token::Type,
+    Token::Return,
+    Token::If,
+    Token::While,
+    Token::For,
+    Token::Break,
+];
 
-    parser.parse(lexer)
+/// An operand that must follow an operator which has already been parsed.
+/// Failing here leaves an `Error` in its place, so the operator is reported
+/// rather than the parser backtracking and tripping over it later.
+fn operand<'a, P>(
+    operand: P,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone
+where
+    P: Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone,
+{
+    expecting(operand, "an expression").recover_with(skip_until(OPERAND_BOUNDARIES, |span| {
+        Expression::Error { span }
+    }))
 }
 
-fn program<'a>(span: Span) -> impl Parser<Token<'a>, Program<'a>, Error = Simple<Token<'a>>> {
-    statement().repeated().map(move |statements| Program {
-        statements,
-        span: span.clone(),
+/// Reports a failure on the first token of `parser` as expecting what it
+/// parses, "an expression" say, rather than every token it could start with.
+/// Past its first token, the tokens that could have come next say more.
+fn expecting<'a, O, P>(
+    parser: P,
+    what: &'static str,
+) -> impl Parser<Token<'a>, O, Error = Simple<Token<'a>>> + Clone
+where
+    P: Parser<Token<'a>, O, Error = Simple<Token<'a>>> + Clone,
+{
+    parser.map_err_with_span(move |error: Simple<Token<'a>>, span: Span| {
+        let unexpected = matches!(error.reason(), SimpleReason::Unexpected);
+        match unexpected && error.span().start == span.start {
+            true => Simple::expected_input_found(error.span(), None, error.found().cloned())
+                .with_label(what),
+            false => error,
+        }
     })
 }
 
-fn statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    expression_statement()
-        .or(return_statement())
+/// Checks, without consuming anything, that a statement could start here.
+/// A `}` ends the enclosing block rather than being a broken statement.
+fn statement_start<'a>() -> impl Parser<Token<'a>, Token<'a>, Error = Simple<Token<'a>>> + Clone {
+    filter(|token| *token != Token::RightBrace).rewind()
+}
+
+/// Recovers from an error between `open` and its matching `close` by skipping
+/// to the `close`, keeping track of any other delimiters nested in between.
+fn nested<'a, O>(
+    open: Token<'a>,
+    close: Token<'a>,
+    fallback: fn(Span) -> O,
+) -> NestedDelimiters<Token<'a>, fn(Span) -> O, 3> {
+    nested_delimiters(
+        open,
+        close,
+        [
+            (Token::LeftParenthesis, Token::RightParenthesis),
+            (Token::LeftBracket, Token::RightBracket),
+            (Token::LeftBrace, Token::RightBrace),
+        ],
+        fallback,
+    )
+}
+
+fn error_expression(span: Span) -> Expression<'static> {
+    Expression::Error { span }
+}
+
+fn statement<'a>(lines: LineStarts) -> StatementParser<'a> {
+    grammar(lines).0
+}
+
+fn expression<'a>(lines: LineStarts) -> ExpressionParser<'a> {
+    grammar(lines).1
+}
+
+fn statement_kind<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    return_statement(expression.clone(), lines)
         .or(break_statement())
         .or(continue_statement())
-        .or(block_statement())
-        .or(if_statement())
-        .or(while_statement())
-        .or(for_statement())
-        .or(for_of_statement())
-        .or(for_in_statement())
+        .or(if_statement(statement.clone(), expression.clone()))
+        .or(while_statement(statement.clone(), expression.clone()))
+        .or(for_statement(statement.clone(), expression.clone()))
+        .or(for_of_statement(statement.clone(), expression.clone()))
+        .or(for_in_statement(statement.clone(), expression.clone()))
         .or(import_declaration())
-        .or(declaration_statement())
+        .or(declaration_statement(statement.clone(), expression.clone()))
+        .or(block_statement(statement))
+        .or(expression_statement(expression))
 }
 
-fn expression_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    expression().map(|expression| {
+fn expression_statement<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    expression.map(|expression| {
         let span = expression.span();
         Statement::Expression {
             expr: Box::new(expression),
@@ -43,177 +375,2112 @@ fn expression_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = S
     })
 }
 
-fn expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
+fn primary_expression<'a>(
+    expression: ExpressionParser<'a>,
+    statement: StatementParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
     string_expression()
+        .or(template_expression(lines))
         .or(integer_expression())
         .or(decimal_expression())
         .or(big_integer_expression())
         .or(big_decimal_expression())
         .or(boolean_expression())
-        .or(array_expression())
-        .or(tuple_expression())
-        .or(record_expression())
         .or(symbol_expression())
-        .or(operator_expression())
-        .or(function_call_expression())
-        .or(resume_expression())
-        .or(yield_expression())
-        .or(perform_expression())
-        .or(handle_expression())
+        // Before identifiers, which `multi handler` starts with.
+        .or(handler_expression(statement.clone(), expression.clone()))
+        .or(identifier_expression())
+        .or(just(Token::Resume).map_with_span(|_, span| Expression::Continuation { span }))
+        .or(array_expression(expression.clone()))
+        .or(lambda_expression(expression.clone()))
+        .or(tuple_expression(expression.clone()))
+        .or(record_expression(expression.clone()))
+        .or(block_expression(statement.clone()))
+        .or(match_expression(expression.clone()))
+        .or(handle_expression(expression))
+        .boxed()
 }
 
-fn string_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn string_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    // A string with a bad escape is still a string, so the escape is reported
+    // where it is rather than the parser trying something else.
+    select! { Token::StringLiteral(raw) => raw }.validate(|raw, span: Span, emit| {
+        // Strip the surrounding quotes, which may be either `"` or `'`.
+        let contents = &raw[1..raw.len() - 1];
+        match unescape(contents) {
+            Ok(value) => Expression::String { value, span },
+            Err((offset, message)) => {
+                let start = span.start + 1 + offset;
+                emit(Simple::custom(start..start + 2, message));
+                Expression::Error { span }
+            }
+        }
+    })
 }
 
-fn integer_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn template_expression<'a>(
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    select! { Token::Template(chunks) => chunks }.validate(move |chunks, span: Span, emit| {
+        let mut parts = Vec::with_capacity(chunks.len());
+        let mut failed = false;
+        for chunk in chunks {
+            match template_part(chunk, lines.clone()) {
+                Ok(part) => parts.push(part),
+                Err(errors) => {
+                    failed = true;
+                    errors.into_iter().for_each(&mut *emit);
+                }
+            }
+        }
+        match failed {
+            true => Expression::Error { span },
+            false => Expression::Template { parts, span },
+        }
+    })
 }
 
-fn decimal_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn template_part<'a>(
+    chunk: TemplateChunk<'a>,
+    lines: LineStarts,
+) -> Result<TemplatePart<'a>, Vec<Simple<Token<'a>>>> {
+    match chunk {
+        TemplateChunk::Literal(raw, span) => {
+            let value = unescape(raw).map_err(|(offset, message)| {
+                let start = span.start + offset;
+                vec![Simple::custom(start..start + 2, message)]
+            })?;
+            Ok(TemplatePart::Literal { value, span })
+        }
+        TemplateChunk::Interpolation(mut tokens, span) => {
+            // Interpolations were lexed ahead of time, so each one is parsed as
+            // its own token stream, up to and including its closing brace.
+            let close = span.end - 1;
+            tokens.push((Token::RightBrace, close..span.end));
+            let stream = Stream::from_iter(span.end..span.end, tokens.into_iter());
+            let expression = expression(lines)
+                .then_ignore(just(Token::RightBrace))
+                .then_ignore(end())
+                .parse(stream)?;
+            Ok(TemplatePart::Expression {
+                expression: Box::new(expression),
+                span,
+            })
+        }
+    }
 }
 
-fn big_integer_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>>
-{
-    todo!("TODO: IMPLEMENT")
+// A literal that does not fit is still a literal, so it is reported as too
+// large rather than the parser trying something else.
+fn integer_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    select! { Token::IntegerLiteral(raw) => raw }.validate(|raw, span: Span, emit| {
+        match raw.parse() {
+            Ok(value) => Expression::Integer { value, span },
+            Err(_) => {
+                emit(Simple::custom(span.clone(), "integer literal is too large"));
+                Expression::Error { span }
+            }
+        }
+    })
 }
 
-fn big_decimal_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>>
-{
-    todo!("TODO: IMPLEMENT")
+/// `-9223372036854775808`, the smallest `Int`, whose literal only fits once
+/// it is negated.
+fn minimum_integer_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Minus)
+        .ignore_then(select! { Token::IntegerLiteral(raw) if is_minimum_magnitude(raw) => () })
+        .map_with_span(|(), span| Expression::Integer {
+            value: i64::MIN,
+            span,
+        })
 }
 
-fn boolean_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn is_minimum_magnitude(raw: &str) -> bool {
+    raw.parse::<u64>() == Ok(i64::MIN.unsigned_abs())
 }
 
-fn array_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn decimal_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    filter_map(|span: Span, token| match token {
+        Token::DecimalLiteral(value) => Ok(Expression::Decimal { value, span }),
+        _ => Err(Simple::expected_input_found(span, None, Some(token))),
+    })
 }
 
-fn tuple_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn big_integer_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    select! { Token::BigIntegerLiteral(raw) => raw }.validate(|raw, span: Span, emit| {
+        match raw[..raw.len() - 1].parse() {
+            Ok(value) => Expression::BigInteger { value, span },
+            Err(_) => {
+                emit(Simple::custom(
+                    span.clone(),
+                    "big integer literal is too large",
+                ));
+                Expression::Error { span }
+            }
+        }
+    })
 }
 
-fn record_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn big_decimal_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    select! { Token::BigDecimalLiteral(raw) => raw }.validate(|raw, span: Span, emit| {
+        let value = &raw[..raw.len() - 1];
+        // Its digits, without the point, are held in 128 bits.
+        match value.replace('.', "").parse::<i128>() {
+            Ok(_) => Expression::BigDecimal { value, span },
+            Err(_) => {
+                emit(Simple::custom(
+                    span.clone(),
+                    "big decimal literal is too large",
+                ));
+                Expression::Error { span }
+            }
+        }
+    })
 }
 
-fn symbol_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn boolean_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    filter_map(|span: Span, token| match token {
+        Token::BooleanLiteral(raw) => Ok(Expression::Boolean {
+            value: raw == "true",
+            span,
+        }),
+        _ => Err(Simple::expected_input_found(span, None, Some(token))),
+    })
 }
 
-fn operator_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    unary_operator_expression().or(binary_operator_expression())
+fn symbol_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    filter_map(|span: Span, token| match token {
+        Token::SymbolLiteral(raw) => Ok(Expression::Symbol {
+            name: symbol_name(raw),
+            span,
+        }),
+        _ => Err(Simple::expected_input_found(span, None, Some(token))),
+    })
 }
 
-fn unary_operator_expression<'a>(
-) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// Strips the `Symbol(` prefix and closing parenthesis from a symbol literal.
+fn symbol_name(raw: &str) -> &str {
+    &raw["Symbol(".len()..raw.len() - 1]
+}
+
+fn identifier_expression<'a>(
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    identifier().map_with_span(|name, span| Expression::Identifier { name, span })
+}
+
+fn identifier<'a>() -> impl Parser<Token<'a>, &'a str, Error = Simple<Token<'a>>> + Clone {
+    select! { Token::Identifier(name) => name }
+}
+
+fn array_expression<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    expression
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftBracket), just(Token::RightBracket))
+        .map_with_span(|elements, span| Expression::Array { elements, span })
+        .recover_with(nested(
+            Token::LeftBracket,
+            Token::RightBracket,
+            error_expression,
+        ))
+}
+
+fn tuple_expression<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    // `(a)` is a parenthesised expression while `(a,)` is a one-element tuple,
+    // so the trailing comma is parsed separately instead of allowed.
+    expression
+        .separated_by(just(Token::Comma))
+        .then(just(Token::Comma).or_not())
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+        .map_with_span(|(mut elements, trailing), span| {
+            if elements.len() == 1 && trailing.is_none() {
+                elements.remove(0)
+            } else {
+                Expression::Tuple { elements, span }
+            }
+        })
+        .recover_with(nested(
+            Token::LeftParenthesis,
+            Token::RightParenthesis,
+            error_expression,
+        ))
+}
+
+fn record_expression<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    let spread = just(Token::Spread)
+        .ignore_then(expression.clone())
+        .map(RecordEntry::Spread);
+    let field = record_key()
+        .then_ignore(just(Token::Colon))
+        .then(expression)
+        .map(RecordEntry::Field);
+
+    spread
+        .or(field)
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace))
+        .validate(|entries, span, emit| {
+            // Fields override what is spread, so spreads come first.
+            let mut spreads = Vec::new();
+            let mut fields = Vec::with_capacity(entries.len());
+            for entry in entries {
+                match entry {
+                    RecordEntry::Spread(spread) if !fields.is_empty() => emit(Simple::custom(
+                        spread.span(),
+                        "a spread must come before the fields of a record",
+                    )),
+                    RecordEntry::Spread(spread) => spreads.push(spread),
+                    RecordEntry::Field(field) => fields.push(field),
+                }
+            }
+            Expression::Record {
+                fields: record_fields(fields, emit),
+                spreads,
+                span,
+            }
+        })
+        .recover_with(nested(
+            Token::LeftBrace,
+            Token::RightBrace,
+            error_expression,
+        ))
+}
+
+fn record_key<'a>() -> impl Parser<Token<'a>, RecordKey<'a>, Error = Simple<Token<'a>>> + Clone {
+    filter_map(|span: Span, token| match token {
+        Token::Identifier(name) => Ok(RecordKey::String(name, span)),
+        Token::StringLiteral(raw) => Ok(RecordKey::String(&raw[1..raw.len() - 1], span)),
+        Token::SymbolLiteral(raw) => Ok(RecordKey::Symbol(symbol_name(raw), span)),
+        _ => Err(Simple::expected_input_found(span, None, Some(token))),
+    })
+}
+
+// An entry of a record expression, pattern or type: a field, or what is
+// spread into the record or gathers the rest of it after `...`.
+enum RecordEntry<F, T> {
+    Field(F),
+    Spread(T),
+}
+
+/// Collects record entries, reporting keys that appear more than once. Keys
+/// carry their spans, so duplicates are detected by name.
+fn record_fields<'a, T>(
+    entries: Vec<(RecordKey<'a>, T)>,
+    mut emit: impl FnMut(Simple<Token<'a>>),
+) -> HashMap<RecordKey<'a>, T> {
+    let mut fields = HashMap::with_capacity(entries.len());
+    let mut seen = HashSet::with_capacity(entries.len());
+
+    for (key, value) in entries {
+        let (name, span) = match &key {
+            RecordKey::String(name, span) => (*name, span.clone()),
+            RecordKey::Symbol(name, span) => (*name, span.clone()),
+        };
+
+        if seen.insert(name) {
+            fields.insert(key, value);
+        } else {
+            emit(Simple::custom(
+                span,
+                format!("duplicate record field `{}`", name),
+            ));
+        }
+    }
+
+    fields
+}
+
+fn lambda_expression<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    field(expression.clone())
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+        .then_ignore(just(Token::Arrow))
+        .then(expression)
+        .map_with_span(|(parameters, body), span| Expression::Lambda {
+            parameters,
+            body: Box::new(body),
+            span,
+        })
+}
+
+/// A named parameter or field: `name`, `name: Type` or `name: Type = default`.
+fn field<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Field<'a>, Error = Simple<Token<'a>>> + Clone {
+    identifier()
+        .then(just(Token::Colon).ignore_then(type_annotation()).or_not())
+        .then(just(Token::Equals).ignore_then(expression).or_not())
+        .map_with_span(|((name, annotation), default), span| Field::Named {
+            name,
+            annotation,
+            default,
+            span,
+        })
+}
+
+/// Assignment, the conditional operator and everything that binds tighter.
+/// Assignment is right-associative and binds looser than every other operator.
+fn operator_expression<'a>(
+    expression: ExpressionParser<'a>,
+    statement: StatementParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    let conditional = with_expression(expression.clone(), statement, lines)
+        .then(
+            just(Token::QuestionMark)
+                .ignore_then(operand(expression.clone()))
+                .then_ignore(just(Token::Colon))
+                .then(operand(expression.clone()))
+                .or_not(),
+        )
+        .map(|(condition, branches)| match branches {
+            Some((then_branch, else_branch)) => {
+                let span = condition.span().start..else_branch.span().end;
+                Expression::Conditional {
+                    condition: Box::new(condition),
+                    then_branch: Box::new(then_branch),
+                    else_branch: Box::new(else_branch),
+                    span,
+                }
+            }
+            None => condition,
+        });
+
+    conditional
+        .then(assignment_operator().then(operand(expression)).or_not())
+        .map(|(target, assignment)| match assignment {
+            Some((op, value)) => {
+                let span = target.span().start..value.span().end;
+                Expression::Assignment {
+                    target: Box::new(target),
+                    op,
+                    value: Box::new(value),
+                    span,
+                }
+            }
+            None => target,
+        })
+}
+
+/// `expression with handler`, which binds looser than every binary operator
+/// so that `main() with Calc ++ Fail.try()` installs both handlers.
+fn with_expression<'a>(
+    expression: ExpressionParser<'a>,
+    statement: StatementParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    let binary = binary_operator_expression(expression, statement, lines).boxed();
+
+    binary
+        .clone()
+        .then(just(Token::With).ignore_then(operand(binary)).repeated())
+        .foldl(|expression, handler| {
+            let span = expression.span().start..handler.span().end;
+            Expression::With {
+                expression: Box::new(expression),
+                handler: Box::new(handler),
+                span,
+            }
+        })
+}
+
+/// `=` yields `None`; compound assignments yield the operator they apply.
+fn assignment_operator<'a>(
+) -> impl Parser<Token<'a>, Option<BinaryOp>, Error = Simple<Token<'a>>> + Clone {
+    // `>>=` is lexed as `>` followed by `>=`, see `Token`.
+    let right_shift_equals = adjacent(Token::GreaterThan, Token::GreaterThanOrEqual)
+        .map(|span| Some(BinaryOp::RightShift(span)));
+
+    let operator = filter_map(|span: Span, token| match token {
+        Token::Equals => Ok(None),
+        Token::PlusEquals => Ok(Some(BinaryOp::Addition(span))),
+        Token::MinusEquals => Ok(Some(BinaryOp::Subtraction(span))),
+        Token::MultiplyEquals => Ok(Some(BinaryOp::Multiplication(span))),
+        Token::DivideEquals => Ok(Some(BinaryOp::Division(span))),
+        Token::ModuloEquals => Ok(Some(BinaryOp::Modulus(span))),
+        Token::ExponentEquals => Ok(Some(BinaryOp::Exponentiation(span))),
+        Token::LeftShiftEquals => Ok(Some(BinaryOp::LeftShift(span))),
+        Token::AmpersandEquals => Ok(Some(BinaryOp::BitwiseAnd(span))),
+        Token::BitwiseXorEquals => Ok(Some(BinaryOp::BitwiseXor(span))),
+        Token::PipeEquals => Ok(Some(BinaryOp::BitwiseOr(span))),
+        Token::LogicalAndEquals => Ok(Some(BinaryOp::LogicalAnd(span))),
+        Token::LogicalOrEquals => Ok(Some(BinaryOp::LogicalOr(span))),
+        Token::NullishCoalescingEquals => Ok(Some(BinaryOp::NullishCoalescing(span))),
+        _ => Err(Simple::expected_input_found(span, None, Some(token))),
+    });
+
+    right_shift_equals.or(operator)
+}
+
+/// Two tokens with no whitespace between them, yielding their combined span.
+fn adjacent<'a>(
+    first: Token<'a>,
+    second: Token<'a>,
+) -> impl Parser<Token<'a>, Span, Error = Simple<Token<'a>>> + Clone {
+    just(first)
+        .map_with_span(|_, span: Span| span)
+        .then(just(second).map_with_span(|_, span: Span| span))
+        .try_map(|(first, second), span| {
+            if first.end == second.start {
+                Ok(first.start..second.end)
+            } else {
+                Err(Simple::custom(
+                    span,
+                    "unexpected whitespace inside operator",
+                ))
+            }
+        })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// The binary operator precedence table, loosest first. It follows the order
+/// of the operator tokens in `Token`, with `|>` binding loosest of all and
+/// `**` as the only right-associative operator.
+fn binding_power(op: &BinaryOp) -> (u8, Associativity) {
+    match op {
+        BinaryOp::PipeOperator(_) => (1, Associativity::Left),
+        BinaryOp::NullishCoalescing(_) => (2, Associativity::Left),
+        BinaryOp::LogicalOr(_) => (3, Associativity::Left),
+        BinaryOp::LogicalAnd(_) => (4, Associativity::Left),
+        BinaryOp::BitwiseOr(_) => (5, Associativity::Left),
+        BinaryOp::BitwiseXor(_) => (6, Associativity::Left),
+        BinaryOp::BitwiseAnd(_) => (7, Associativity::Left),
+        BinaryOp::Equal(_) | BinaryOp::NotEqual(_) => (8, Associativity::Left),
+        BinaryOp::LessThan(_)
+        | BinaryOp::LessThanOrEqual(_)
+        | BinaryOp::GreaterThan(_)
+        | BinaryOp::GreaterThanOrEqual(_) => (9, Associativity::Left),
+        BinaryOp::LeftShift(_) | BinaryOp::RightShift(_) => (10, Associativity::Left),
+        BinaryOp::Addition(_) | BinaryOp::Subtraction(_) | BinaryOp::Concatenation(_) => {
+            (11, Associativity::Left)
+        }
+        BinaryOp::Multiplication(_) | BinaryOp::Division(_) | BinaryOp::Modulus(_) => {
+            (12, Associativity::Left)
+        }
+        BinaryOp::Exponentiation(_) => (13, Associativity::Right),
+        // `?.` is parsed as a postfix operator and never reaches the table.
+        BinaryOp::OptionalChaining(_) => (14, Associativity::Left),
+    }
+}
+
+fn binary_operator<'a>() -> impl Parser<Token<'a>, BinaryOp, Error = Simple<Token<'a>>> + Clone {
+    let right_shift = adjacent(Token::GreaterThan, Token::GreaterThan).map(BinaryOp::RightShift);
+
+    let operator = filter_map(|span: Span, token| match token {
+        Token::PipeOperator => Ok(BinaryOp::PipeOperator(span)),
+        Token::NullishCoalescing => Ok(BinaryOp::NullishCoalescing(span)),
+        Token::LogicalOr => Ok(BinaryOp::LogicalOr(span)),
+        Token::LogicalAnd => Ok(BinaryOp::LogicalAnd(span)),
+        Token::Pipe => Ok(BinaryOp::BitwiseOr(span)),
+        Token::BitwiseXor => Ok(BinaryOp::BitwiseXor(span)),
+        Token::Ampersand => Ok(BinaryOp::BitwiseAnd(span)),
+        Token::Equal => Ok(BinaryOp::Equal(span)),
+        Token::NotEqual => Ok(BinaryOp::NotEqual(span)),
+        Token::LessThan => Ok(BinaryOp::LessThan(span)),
+        Token::LessThanOrEqual => Ok(BinaryOp::LessThanOrEqual(span)),
+        Token::GreaterThan => Ok(BinaryOp::GreaterThan(span)),
+        Token::GreaterThanOrEqual => Ok(BinaryOp::GreaterThanOrEqual(span)),
+        Token::LeftShift => Ok(BinaryOp::LeftShift(span)),
+        Token::Plus => Ok(BinaryOp::Addition(span)),
+        Token::PlusPlus => Ok(BinaryOp::Concatenation(span)),
+        Token::Minus => Ok(BinaryOp::Subtraction(span)),
+        Token::Multiply => Ok(BinaryOp::Multiplication(span)),
+        Token::Divide => Ok(BinaryOp::Division(span)),
+        Token::Modulo => Ok(BinaryOp::Modulus(span)),
+        Token::Exponent => Ok(BinaryOp::Exponentiation(span)),
+        _ => Err(Simple::expected_input_found(span, None, Some(token))),
+    });
+
+    // `>` must not be read as a comparison when it starts a `>>=`, which is
+    // left for `assignment_operator`.
+    let not_assignment = adjacent(Token::GreaterThan, Token::GreaterThanOrEqual)
+        .rewind()
+        .or_not()
+        .try_map(|right_shift_equals, span: Span| match right_shift_equals {
+            Some(_) => Err(Simple::expected_input_found(
+                span,
+                None,
+                Some(Token::GreaterThan),
+            )),
+            None => Ok(()),
+        });
+
+    not_assignment.ignore_then(right_shift.or(operator))
 }
 
 fn binary_operator_expression<'a>(
-) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+    expression: ExpressionParser<'a>,
+    statement: StatementParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    let unary = unary_operator_expression(expression, statement, lines).boxed();
+
+    unary
+        .clone()
+        .then(binary_operator().then(operand(unary)).repeated())
+        .map(|(first, rest)| fold_binary(first, rest))
+}
+
+/// Folds a flat `operand (operator operand)*` sequence into a tree using the
+/// precedence table, Pratt style: an operator on the stack is applied before
+/// pushing the next one when it binds tighter, or equally tight and left
+/// associative.
+fn fold_binary<'a>(first: Expression<'a>, rest: Vec<(BinaryOp, Expression<'a>)>) -> Expression<'a> {
+    fn reduce<'a>(operands: &mut Vec<Expression<'a>>, operators: &mut Vec<BinaryOp>) {
+        let op = operators.pop().expect("operator stack underflow");
+        let right = operands.pop().expect("operand stack underflow");
+        let left = operands.pop().expect("operand stack underflow");
+        let span = left.span().start..right.span().end;
+        operands.push(Expression::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+            span,
+        });
+    }
+
+    let mut operands = vec![first];
+    let mut operators: Vec<BinaryOp> = Vec::new();
+
+    for (op, right) in rest {
+        let (power, associativity) = binding_power(&op);
+
+        while let Some(top) = operators.last() {
+            let top_power = binding_power(top).0;
+            if top_power > power || (top_power == power && associativity == Associativity::Left) {
+                reduce(&mut operands, &mut operators);
+            } else {
+                break;
+            }
+        }
+
+        operators.push(op);
+        operands.push(right);
+    }
+
+    while !operators.is_empty() {
+        reduce(&mut operands, &mut operators);
+    }
+
+    operands.pop().expect("operand stack underflow")
+}
+
+enum Prefix {
+    Operator(UnaryOp),
+    Perform(Span),
+    Yield(Span),
+    Resume(Span),
+}
+
+fn unary_operator_expression<'a>(
+    expression: ExpressionParser<'a>,
+    statement: StatementParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    // A `-` right before the literal of the smallest `Int` is part of it.
+    let negation = just(Token::Minus)
+        .map_with_span(|_, span| Prefix::Operator(UnaryOp::Negation(span)))
+        .then_ignore(
+            filter(|token: &Token| {
+                !matches!(token, Token::IntegerLiteral(raw) if is_minimum_magnitude(raw))
+            })
+            .rewind(),
+        );
+
+    let prefix = negation
+        .or(filter_map(|span: Span, token| match token {
+            Token::LogicalNot => Ok(Prefix::Operator(UnaryOp::LogicalNot(span))),
+            Token::BitwiseNot => Ok(Prefix::Operator(UnaryOp::BitwiseNot(span))),
+            Token::PlusPlus => Ok(Prefix::Operator(UnaryOp::PreIncrement(span))),
+            Token::MinusMinus => Ok(Prefix::Operator(UnaryOp::PreDecrement(span))),
+            Token::Perform => Ok(Prefix::Perform(span)),
+            Token::Yield => Ok(Prefix::Yield(span)),
+            _ => Err(Simple::expected_input_found(span, None, Some(token))),
+        }))
+        .or(just(Token::Resume)
+            .map_with_span(|_, span| Prefix::Resume(span))
+            .then_ignore(same_line(lines.clone())));
+
+    let operand = minimum_integer_expression()
+        .or(function_call_expression(
+            expression,
+            statement,
+            lines.clone(),
+        ))
+        .boxed();
+
+    // Without an operand on the same line, `resume` is the continuation
+    // itself, as in `[resume]`, which is parsed as a primary expression.
+    prefix
+        .repeated()
+        .at_least(1)
+        .then(operand.clone())
+        .foldr(|prefix, expression| {
+            let end = expression.span().end;
+            let expression = Box::new(expression);
+            match prefix {
+                Prefix::Operator(op) => {
+                    let span = unary_op_span(&op).start..end;
+                    Expression::Unary {
+                        op,
+                        expr: expression,
+                        span,
+                    }
+                }
+                Prefix::Perform(keyword) => Expression::Perform {
+                    expression,
+                    span: keyword.start..end,
+                },
+                Prefix::Yield(keyword) => Expression::Yield {
+                    expression,
+                    span: keyword.start..end,
+                },
+                Prefix::Resume(keyword) => Expression::Resume {
+                    expression,
+                    span: keyword.start..end,
+                },
+            }
+        })
+        .or(operand)
 }
 
+fn unary_op_span(op: &UnaryOp) -> Span {
+    match op {
+        UnaryOp::Negation(span)
+        | UnaryOp::LogicalNot(span)
+        | UnaryOp::BitwiseNot(span)
+        | UnaryOp::PreIncrement(span)
+        | UnaryOp::PostIncrement(span)
+        | UnaryOp::PreDecrement(span)
+        | UnaryOp::PostDecrement(span) => span.clone(),
+    }
+}
+
+enum Postfix<'a> {
+    Call {
+        type_arguments: Vec<Type<'a>>,
+        arguments: Vec<Expression<'a>>,
+        span: Span,
+    },
+    Member {
+        property: &'a str,
+        span: Span,
+    },
+    OptionalMember {
+        operator: Span,
+        property: &'a str,
+        span: Span,
+    },
+    Index {
+        index: Expression<'a>,
+        span: Span,
+    },
+    Operator(UnaryOp),
+}
+
+/// Calls, member access, indexing and postfix operators, all of which bind
+/// tighter than any prefix operator. Explicit type arguments are written
+/// between the callee and its arguments, as in `f<Int>(x)`.
 fn function_call_expression<'a>(
-) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+    expression: ExpressionParser<'a>,
+    statement: StatementParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    let type_arguments = type_annotation()
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LessThan), just(Token::GreaterThan));
+
+    let arguments = expression
+        .clone()
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+        .recover_with(nested(
+            Token::LeftParenthesis,
+            Token::RightParenthesis,
+            |span| vec![Expression::Error { span }],
+        ));
+
+    let call = type_arguments.or_not().then(arguments).map_with_span(
+        |(type_arguments, arguments), span| Postfix::Call {
+            type_arguments: type_arguments.unwrap_or_default(),
+            arguments,
+            span,
+        },
+    );
+
+    let member = just(Token::Dot)
+        .ignore_then(identifier())
+        .map_with_span(|property, span| Postfix::Member { property, span });
+
+    let optional_member = just(Token::OptionalChaining)
+        .map_with_span(|_, span: Span| span)
+        .then(identifier())
+        .map_with_span(|(operator, property), span| Postfix::OptionalMember {
+            operator,
+            property,
+            span,
+        });
+
+    let index = expression
+        .clone()
+        .delimited_by(just(Token::LeftBracket), just(Token::RightBracket))
+        .recover_with(nested(
+            Token::LeftBracket,
+            Token::RightBracket,
+            error_expression,
+        ))
+        .map_with_span(|index, span| Postfix::Index { index, span });
+
+    // A `++` with an operand after it concatenates the two, as in `xs ++ [x]`
+    // or `a++b`, and is left for `binary_operator`.
+    let operator = filter_map(|span: Span, token| match token {
+        Token::PlusPlus => Ok(Postfix::Operator(UnaryOp::PostIncrement(span))),
+        Token::MinusMinus => Ok(Postfix::Operator(UnaryOp::PostDecrement(span))),
+        _ => Err(Simple::expected_input_found(span, None, Some(token))),
+    })
+    .then_ignore(no_operand(lines.clone()));
+
+    // A call, an index or a postfix operator on a line of its own starts a new
+    // statement instead, so that one can start with a tuple or an array.
+    let continued = same_line(lines.clone())
+        .ignore_then(call.or(index).or(operator))
+        .or(member)
+        .or(optional_member);
+
+    primary_expression(expression, statement, lines)
+        .then(continued.repeated())
+        .foldl(|expression, postfix| {
+            let start = expression.span().start;
+            let expression = Box::new(expression);
+            match postfix {
+                Postfix::Call {
+                    type_arguments,
+                    arguments,
+                    span,
+                } => Expression::FunctionCall {
+                    function: expression,
+                    type_arguments,
+                    arguments,
+                    span: start..span.end,
+                },
+                Postfix::Member { property, span } => Expression::Member {
+                    object: expression,
+                    property,
+                    span: start..span.end,
+                },
+                Postfix::OptionalMember {
+                    operator,
+                    property,
+                    span,
+                } => {
+                    let property_span = operator.end..span.end;
+                    Expression::Binary {
+                        left: expression,
+                        op: BinaryOp::OptionalChaining(operator),
+                        right: Box::new(Expression::Identifier {
+                            name: property,
+                            span: property_span,
+                        }),
+                        span: start..span.end,
+                    }
+                }
+                Postfix::Index { index, span } => Expression::Index {
+                    object: expression,
+                    index: Box::new(index),
+                    span: start..span.end,
+                },
+                Postfix::Operator(op) => {
+                    let end = unary_op_span(&op).end;
+                    Expression::Unary {
+                        op,
+                        expr: expression,
+                        span: start..end,
+                    }
+                }
+            }
+        })
+}
+
+fn block_expression<'a>(
+    statement: StatementParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    block(statement).map_with_span(|statements, span| Expression::Block { statements, span })
+}
+
+fn block<'a>(
+    statement: StatementParser<'a>,
+) -> impl Parser<Token<'a>, Vec<Statement<'a>>, Error = Simple<Token<'a>>> + Clone {
+    statement
+        .repeated()
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace))
+        .recover_with(nested(Token::LeftBrace, Token::RightBrace, |span| {
+            vec![Statement::Error { span }]
+        }))
+}
+
+/// `handle effect { expression }` runs `expression` with `effect` handled.
+fn handle_expression<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Handle)
+        .ignore_then(identifier_expression())
+        .then(expression.delimited_by(just(Token::LeftBrace), just(Token::RightBrace)))
+        .map_with_span(|(effect, expression), span| Expression::Handle {
+            effect: Box::new(effect),
+            expression: Box::new(expression),
+            span,
+        })
+}
+
+fn match_expression<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    let arms = match_arm(expression.clone())
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace))
+        .recover_with(nested(Token::LeftBrace, Token::RightBrace, |_| Vec::new()));
+
+    just(Token::Match)
+        .ignore_then(expression)
+        .then(arms)
+        .map_with_span(|(scrutinee, arms), span| Expression::Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+            span,
+        })
+}
+
+/// `pattern -> body` or `pattern if guard -> body`. An arm may also start
+/// with a guard on a name, as in `n < 2 -> n`, which is short for
+/// `n if n < 2 -> n`.
+fn match_arm<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, MatchArm<'a>, Error = Simple<Token<'a>>> + Clone {
+    let guarded = pattern()
+        .then(just(Token::If).ignore_then(expression.clone()).or_not())
+        .then_ignore(just(Token::Arrow));
+
+    let guard_on_name = identifier()
+        .map_with_span(|name, span| Pattern::Binding { name, span })
+        .rewind()
+        .then(expression.clone().map(Some))
+        .then_ignore(just(Token::Arrow));
+
+    guarded
+        .or(guard_on_name)
+        .then(expression)
+        .map_with_span(|((pattern, guard), body), span| MatchArm {
+            pattern,
+            guard,
+            body,
+            span,
+        })
+}
+
+fn pattern<'a>() -> impl Parser<Token<'a>, Pattern<'a>, Error = Simple<Token<'a>>> + Clone {
+    recursive(|pattern| {
+        let name = identifier().map_with_span(|name, span| match name {
+            "_" => Pattern::Wildcard { span },
+            _ if name.starts_with(char::is_uppercase) => Pattern::Constructor {
+                owner: None,
+                name,
+                arguments: Vec::new(),
+                span,
+            },
+            _ => Pattern::Binding { name, span },
+        });
+
+        let arguments = pattern
+            .clone()
+            .separated_by(just(Token::Comma))
+            .allow_trailing()
+            .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis));
+
+        let constructor = identifier()
+            .then_ignore(just(Token::Dot))
+            .or_not()
+            .then(identifier())
+            .then(arguments.or_not())
+            .try_map(|((owner, name), arguments), span: Span| {
+                if owner.is_none() && arguments.is_none() {
+                    // Left to `name`, which decides between a binding and a
+                    // constructor.
+                    return Err(Simple::expected_input_found(span, None, None));
+                }
+                Ok(Pattern::Constructor {
+                    owner,
+                    name,
+                    arguments: arguments.unwrap_or_default(),
+                    span,
+                })
+            });
+
+        let tuple = pattern
+            .clone()
+            .separated_by(just(Token::Comma))
+            .then(just(Token::Comma).or_not())
+            .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+            .map_with_span(|(mut elements, trailing), span| {
+                if elements.len() == 1 && trailing.is_none() {
+                    elements.remove(0)
+                } else {
+                    Pattern::Tuple { elements, span }
+                }
+            });
+
+        let rest = just(Token::Spread)
+            .map_with_span(|_, span: Span| span)
+            .then(pattern.clone().or_not())
+            .map(|(spread, rest)| rest.unwrap_or(Pattern::Wildcard { span: spread }));
+
+        let array = rest
+            .clone()
+            .map(ArrayElement::Rest)
+            .or(pattern.clone().map(ArrayElement::Pattern))
+            .separated_by(just(Token::Comma))
+            .allow_trailing()
+            .delimited_by(just(Token::LeftBracket), just(Token::RightBracket))
+            .validate(|entries, span, emit| {
+                let mut elements = Vec::with_capacity(entries.len());
+                let mut rest: Option<Box<Pattern>> = None;
+                for entry in entries {
+                    match entry {
+                        ArrayElement::Pattern(element) if rest.is_some() => emit(Simple::custom(
+                            element.span(),
+                            "no element can follow the rest of an array pattern",
+                        )),
+                        ArrayElement::Pattern(element) => elements.push(element),
+                        ArrayElement::Rest(element) if rest.is_some() => emit(Simple::custom(
+                            element.span(),
+                            "an array pattern can only have one rest",
+                        )),
+                        ArrayElement::Rest(element) => rest = Some(Box::new(element)),
+                    }
+                }
+                Pattern::Array {
+                    elements,
+                    rest,
+                    span,
+                }
+            });
+
+        let field = identifier()
+            .map_with_span(|name, span| (name, span))
+            .then(just(Token::Colon).ignore_then(pattern.clone()).or_not())
+            .map(|((name, span), pattern)| {
+                let pattern = pattern.unwrap_or(Pattern::Binding {
+                    name,
+                    span: span.clone(),
+                });
+                (name, span, pattern)
+            });
+
+        let record = rest
+            .map(RecordEntry::Spread)
+            .or(field.map(RecordEntry::Field))
+            .separated_by(just(Token::Comma))
+            .allow_trailing()
+            .delimited_by(just(Token::LeftBrace), just(Token::RightBrace))
+            .validate(|entries, span, emit| {
+                let mut seen = HashSet::with_capacity(entries.len());
+                let mut fields = Vec::with_capacity(entries.len());
+                let mut rest: Option<Box<Pattern>> = None;
+                for entry in entries {
+                    match entry {
+                        RecordEntry::Field((_, name_span, _)) if rest.is_some() => {
+                            emit(Simple::custom(
+                                name_span,
+                                "no field can follow the rest of a record pattern",
+                            ))
+                        }
+                        RecordEntry::Field((name, name_span, pattern)) => {
+                            if seen.insert(name) {
+                                fields.push((name, pattern));
+                            } else {
+                                emit(Simple::custom(
+                                    name_span,
+                                    format!("duplicate record field `{}`", name),
+                                ));
+                            }
+                        }
+                        RecordEntry::Spread(pattern) if rest.is_some() => emit(Simple::custom(
+                            pattern.span(),
+                            "a record pattern can only have one rest",
+                        )),
+                        RecordEntry::Spread(pattern) => rest = Some(Box::new(pattern)),
+                    }
+                }
+                Pattern::Record { fields, rest, span }
+            });
+
+        expecting(
+            literal_pattern()
+                .or(constructor)
+                .or(name)
+                .or(tuple)
+                .or(array)
+                .or(record),
+            "a pattern",
+        )
+    })
+}
+
+enum ArrayElement<'a> {
+    Pattern(Pattern<'a>),
+    Rest(Pattern<'a>),
 }
 
-fn resume_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn literal_pattern<'a>() -> impl Parser<Token<'a>, Pattern<'a>, Error = Simple<Token<'a>>> + Clone {
+    literal().map_with_span(|value, span| Pattern::Literal { value, span })
+}
+
+/// A literal, which may be negated when it is a number.
+fn literal<'a>() -> impl Parser<Token<'a>, Literal<'a>, Error = Simple<Token<'a>>> + Clone {
+    let literal = integer_expression()
+        .or(decimal_expression())
+        .or(big_integer_expression())
+        .or(big_decimal_expression())
+        .or(boolean_expression())
+        .or(string_expression())
+        .or(symbol_expression());
+
+    let minimum = minimum_integer_expression().map(|_| Literal::Integer(i64::MIN));
+
+    minimum.or(just(Token::Minus)
+        .or_not()
+        .then(literal)
+        .try_map(|(minus, literal), span: Span| {
+            Ok(match (minus.is_some(), literal) {
+                (false, Expression::Integer { value, .. }) => Literal::Integer(value),
+                (true, Expression::Integer { value, .. }) => Literal::Integer(-value),
+                (false, Expression::Decimal { value, .. }) => Literal::Decimal(value.into()),
+                (true, Expression::Decimal { value, .. }) => {
+                    Literal::Decimal(format!("-{}", value).into())
+                }
+                (false, Expression::BigInteger { value, .. }) => Literal::BigInteger(value),
+                (true, Expression::BigInteger { value, .. }) => Literal::BigInteger(-value),
+                (false, Expression::BigDecimal { value, .. }) => Literal::BigDecimal(value.into()),
+                (true, Expression::BigDecimal { value, .. }) => {
+                    Literal::BigDecimal(format!("-{}", value).into())
+                }
+                (false, Expression::Boolean { value, .. }) => Literal::Boolean(value),
+                (false, Expression::String { value, .. }) => Literal::String(value),
+                (false, Expression::Symbol { name, .. }) => Literal::Symbol(name),
+                // A literal that was reported as invalid, which stands in for
+                // nothing since the program fails to parse.
+                (_, Expression::Error { .. }) => Literal::Integer(0),
+                (true, _) => return Err(Simple::custom(span, "only numbers can be negated")),
+                (false, _) => unreachable!("only literals are parsed"),
+            })
+        }))
+}
+
+fn type_annotation<'a>() -> impl Parser<Token<'a>, Type<'a>, Error = Simple<Token<'a>>> + Clone {
+    recursive(|ty| {
+        let arguments = ty
+            .clone()
+            .separated_by(just(Token::Comma))
+            .allow_trailing()
+            .delimited_by(just(Token::LessThan), just(Token::GreaterThan));
+
+        let named =
+            identifier()
+                .then(arguments.or_not())
+                .map_with_span(|(name, arguments), span| {
+                    named_type(name, arguments.unwrap_or_default(), span)
+                });
+
+        let list = ty
+            .clone()
+            .separated_by(just(Token::Comma))
+            .then(just(Token::Comma).or_not())
+            .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+            .map_with_span(|list, span| (list, span));
+
+        let function = list
+            .clone()
+            .then_ignore(just(Token::Arrow))
+            .then(ty.clone())
+            .map_with_span(|(((parameters, _), _), return_type), span| Type::Function {
+                type_parameters: Vec::new(),
+                parameters,
+                return_type: Box::new(return_type),
+                span,
+            });
+
+        let tuple = list.clone().map(|((mut elements, trailing), span)| {
+            if elements.len() == 1 && trailing.is_none() {
+                elements.remove(0)
+            } else {
+                Type::Tuple { elements, span }
+            }
+        });
+
+        let array = ty
+            .clone()
+            .delimited_by(just(Token::LeftBracket), just(Token::RightBracket))
+            .map_with_span(|element_type, span| Type::Array {
+                element_type: Box::new(element_type),
+                span,
+            });
+
+        // `{ x: Int, ...R }` is any record with at least an `x`, the rest of
+        // its fields being the row `R`.
+        let record = just(Token::Spread)
+            .ignore_then(ty.clone())
+            .map(RecordEntry::Spread)
+            .or(record_key()
+                .then_ignore(just(Token::Colon))
+                .then(ty.clone())
+                .map(RecordEntry::Field))
+            .separated_by(just(Token::Comma))
+            .allow_trailing()
+            .delimited_by(just(Token::LeftBrace), just(Token::RightBrace))
+            .validate(|entries, span, emit| {
+                let mut fields = Vec::with_capacity(entries.len());
+                let mut rest: Option<Box<Type>> = None;
+                for entry in entries {
+                    match entry {
+                        RecordEntry::Field((key, _)) if rest.is_some() => emit(Simple::custom(
+                            key.span(),
+                            "no field can follow the rest of a record type",
+                        )),
+                        RecordEntry::Field(field) => fields.push(field),
+                        RecordEntry::Spread(ty) if rest.is_some() => emit(Simple::custom(
+                            ty.span(),
+                            "a record type can only have one rest",
+                        )),
+                        RecordEntry::Spread(ty) => rest = Some(Box::new(ty)),
+                    }
+                }
+                Type::Record {
+                    fields: record_fields(fields, emit),
+                    rest,
+                    span,
+                }
+            });
+
+        let literal = literal().map_with_span(|value, span| match value {
+            Literal::Integer(value) => Type::IntegerLiteral { value, span },
+            Literal::Decimal(value) => Type::DecimalLiteral { value, span },
+            Literal::BigInteger(value) => Type::BigIntegerLiteral { value, span },
+            Literal::BigDecimal(value) => Type::BigDecimalLiteral { value, span },
+            Literal::Boolean(value) => Type::BooleanLiteral { value, span },
+            Literal::String(value) => Type::StringLiteral { value, span },
+            Literal::Symbol(name) => Type::SymbolLiteral { name, span },
+        });
+
+        let atom = named.or(tuple).or(array).or(record).or(literal);
+
+        // A polymorphic function type, as in `<A>(A) -> A` or
+        // `<A>A -> A`.
+        let polymorphic = identifier()
+            .map_with_span(|name, span| TypeParameter::Generic {
+                name,
+                constraint: None,
+                variance: None,
+                span,
+            })
+            .separated_by(just(Token::Comma))
+            .at_least(1)
+            .allow_trailing()
+            .delimited_by(just(Token::LessThan), just(Token::GreaterThan))
+            .then(
+                list.clone()
+                    .map(|((parameters, _), _)| parameters)
+                    .or(atom.clone().map(|parameter| vec![parameter])),
+            )
+            .then_ignore(just(Token::Arrow))
+            .then(ty.clone())
+            .map_with_span(
+                |((type_parameters, parameters), return_type), span| Type::Function {
+                    type_parameters,
+                    parameters,
+                    return_type: Box::new(return_type),
+                    span,
+                },
+            );
+
+        // A single unparenthesised parameter, as in `A -> B`.
+        let single_parameter = atom
+            .clone()
+            .then(just(Token::Arrow).ignore_then(ty).or_not())
+            .map_with_span(|(parameter, return_type), span| match return_type {
+                Some(return_type) => Type::Function {
+                    type_parameters: Vec::new(),
+                    parameters: vec![parameter],
+                    return_type: Box::new(return_type),
+                    span,
+                },
+                None => parameter,
+            });
+
+        // `&` binds tighter than `|`, and both looser than `->`, so
+        // `A & B | C` is `(A & B) | C` and `() -> A | B` returns either.
+        let intersection = polymorphic
+            .or(function)
+            .or(single_parameter)
+            .separated_by(just(Token::Ampersand))
+            .at_least(1)
+            .map_with_span(|mut types, span| {
+                if types.len() == 1 {
+                    types.remove(0)
+                } else {
+                    Type::Intersection { types, span }
+                }
+            });
+
+        intersection
+            .separated_by(just(Token::Pipe))
+            .at_least(1)
+            .map_with_span(|mut types, span| {
+                if types.len() == 1 {
+                    types.remove(0)
+                } else {
+                    Type::Union { types, span }
+                }
+            })
+    })
+}
+
+fn named_type<'a>(name: &'a str, arguments: Vec<Type<'a>>, span: Span) -> Type<'a> {
+    if !arguments.is_empty() {
+        return Type::Reference {
+            name,
+            arguments,
+            span,
+        };
+    }
+
+    match name {
+        "Int" => Type::Integer { span },
+        "Float" => Type::Float { span },
+        "BigInt" => Type::BigInteger { span },
+        "BigDecimal" => Type::BigDecimal { span },
+        "Boolean" => Type::Boolean { span },
+        "String" => Type::String { span },
+        _ => Type::Reference {
+            name,
+            arguments,
+            span,
+        },
+    }
 }
 
-fn yield_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// `return`, with a value that starts on the same line, so that a bare
+/// `return` does not take the statement after it.
+fn return_statement<'a>(
+    expression: ExpressionParser<'a>,
+    lines: LineStarts,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Return)
+        .map_with_span(|_, span: Span| span)
+        .then(same_line(lines).ignore_then(expression).or_not())
+        .map_with_span(|(keyword, expression), span| {
+            // A bare `return` returns unit.
+            let expression = expression.unwrap_or(Expression::Tuple {
+                elements: Vec::new(),
+                span: keyword,
+            });
+            Statement::Return {
+                expr: Box::new(expression),
+                span,
+            }
+        })
 }
 
-fn perform_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn break_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone
+{
+    just(Token::Break).map_with_span(|_, span| Statement::Break { span })
 }
 
-fn handle_expression<'a>() -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn continue_statement<'a>(
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Continue).map_with_span(|_, span| Statement::Continue { span })
 }
 
-fn return_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn block_statement<'a>(
+    statement: StatementParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    block_expression(statement).map(|block| {
+        let span = block.span();
+        Statement::Expression {
+            expr: Box::new(block),
+            span,
+        }
+    })
 }
 
-fn break_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// The body of a control-flow statement: a block, or a single statement as in
+/// `if (done) return x`.
+fn body<'a>(
+    statement: StatementParser<'a>,
+) -> impl Parser<Token<'a>, Vec<Statement<'a>>, Error = Simple<Token<'a>>> + Clone {
+    block(statement.clone()).or(statement.map(|statement| vec![statement]))
 }
 
-fn continue_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn if_statement<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    let branch = expression.map(Box::new).then(body(statement.clone()));
+
+    just(Token::If)
+        .ignore_then(branch.clone())
+        .then(
+            just(Token::Else)
+                .ignore_then(just(Token::If))
+                .ignore_then(branch)
+                .repeated(),
+        )
+        .then(just(Token::Else).ignore_then(body(statement)).or_not())
+        .map_with_span(
+            |(((condition, then_branch), else_if_branches), else_branch), span| Statement::If {
+                condition,
+                then_branch,
+                else_if_branches,
+                else_branch,
+                span,
+            },
+        )
 }
 
-fn block_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// `while (condition) body`, or `while (Some(value) = next()) body`, which
+/// runs the body for as long as the value matches the constructor pattern.
+/// The latter is short for
+/// `while (true) { match next() { Some(value) -> { body }, _ -> { break } } }`.
+fn while_statement<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    let matching = pattern()
+        .try_map(|pattern, span| match pattern {
+            Pattern::Constructor { .. } => Ok(pattern),
+            _ => Err(Simple::expected_input_found(span, None, None)),
+        })
+        .then_ignore(just(Token::Equals))
+        .then(expression.clone())
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+        .map_with_span(|matching, span| (matching, span))
+        .then(body(statement.clone()).map_with_span(|body, span| (body, span)))
+        .map(|(((pattern, value), span), (body, body_span))| {
+            let arms = vec![
+                MatchArm {
+                    pattern,
+                    guard: None,
+                    body: Expression::Block {
+                        statements: body,
+                        span: body_span.clone(),
+                    },
+                    span: body_span,
+                },
+                MatchArm {
+                    pattern: Pattern::Wildcard { span: span.clone() },
+                    guard: None,
+                    body: Expression::Block {
+                        statements: vec![Statement::Break { span: span.clone() }],
+                        span: span.clone(),
+                    },
+                    span: span.clone(),
+                },
+            ];
+            let matched = Expression::Match {
+                scrutinee: Box::new(value),
+                arms,
+                span: span.clone(),
+            };
+            let condition = Expression::Boolean {
+                value: true,
+                span: span.clone(),
+            };
+            let body = vec![Statement::Expression {
+                expr: Box::new(matched),
+                span,
+            }];
+            (condition, body)
+        });
+
+    just(Token::While)
+        .ignore_then(matching.or(expression.then(body(statement))))
+        .map_with_span(|(condition, body), span| Statement::While {
+            condition: Box::new(condition),
+            body,
+            span,
+        })
 }
 
-fn if_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn for_statement<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    let header = expression
+        .clone()
+        .then_ignore(just(Token::Semicolon))
+        .then(expression.clone())
+        .then_ignore(just(Token::Semicolon))
+        .then(expression)
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis));
+
+    just(Token::For)
+        .ignore_then(header)
+        .then(body(statement))
+        .map_with_span(
+            |(((initializer, condition), increment), body), span| Statement::For {
+                initializer: Box::new(initializer),
+                condition: Box::new(condition),
+                increment: Box::new(increment),
+                body,
+                span,
+            },
+        )
 }
 
-fn while_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// The `variable of iterable` or `variable in iterable` header of a loop, with
+/// or without surrounding parentheses.
+fn iteration_header<'a>(
+    keyword: Token<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, (Expression<'a>, Expression<'a>), Error = Simple<Token<'a>>> + Clone {
+    let header = expression
+        .clone()
+        .then_ignore(just(keyword))
+        .then(expression);
+
+    header
+        .clone()
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+        .or(header)
 }
 
-fn for_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn for_of_statement<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::For)
+        .ignore_then(iteration_header(Token::Of, expression))
+        .then(body(statement))
+        .map_with_span(|((variable, iterable), body), span| Statement::ForOf {
+            variable: Box::new(variable),
+            iterable: Box::new(iterable),
+            body,
+            span,
+        })
 }
 
-fn for_of_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn for_in_statement<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::For)
+        .ignore_then(iteration_header(Token::In, expression))
+        .then(body(statement))
+        .map_with_span(|((variable, iterable), body), span| Statement::ForIn {
+            variable: Box::new(variable),
+            iterable: Box::new(iterable),
+            body,
+            span,
+        })
 }
 
-fn for_in_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// `import Name from 'module'` or `import { a, b as c } from 'module'`.
+fn import_declaration<'a>(
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    let alias = contextual_keyword("as").ignore_then(identifier()).or_not();
+
+    let named_imports = identifier()
+        .then(alias)
+        .map_with_span(|(name, alias), span| NamedImport {
+            name,
+            alias: alias.map(str::to_string),
+            span,
+        })
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace))
+        .map_with_span(|imports, span| ImportDeclaration::NamedImports { imports, span });
+
+    let namespace_import =
+        identifier().map_with_span(|name, span| ImportDeclaration::NamespaceImport { name, span });
+
+    let module = select! { Token::StringLiteral(raw) => &raw[1..raw.len() - 1] };
+
+    just(Token::Import)
+        .ignore_then(named_imports.or(namespace_import))
+        .then_ignore(contextual_keyword("from"))
+        .then(module)
+        .map_with_span(|(declaration, module), span| Statement::Import {
+            module,
+            declaration,
+            span,
+        })
 }
 
-fn import_declaration<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// Matches an identifier that acts as a keyword only in certain positions.
+fn contextual_keyword<'a>(
+    keyword: &'static str,
+) -> impl Parser<Token<'a>, (), Error = Simple<Token<'a>>> + Clone {
+    just(Token::Identifier(keyword)).ignored()
 }
 
-fn declaration_statement<'a>() -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> {
-    declaration().map(|declaration| Statement::Declaration(declaration))
+fn declaration_statement<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Statement<'a>, Error = Simple<Token<'a>>> + Clone {
+    declaration(statement, expression).map(Statement::Declaration)
 }
 
-fn declaration<'a>() -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> {
-    function_declaration()
+fn declaration<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    let declaration = function_declaration(statement.clone(), expression.clone())
         .or(brand_declaration())
+        .or(sum_type_declaration())
         .or(type_alias_declaration())
         .or(data_declaration())
         .or(effect_declaration())
-        .or(let_declaration())
+        .or(impl_declaration(statement, expression.clone()))
+        .or(let_declaration(expression));
+
+    just(Token::Export)
+        .map_with_span(|_, span: Span| span)
+        .or_not()
+        .then(declaration)
+        .map(|(export, mut declaration)| {
+            if let Some(export) = export {
+                mark_exported(&mut declaration, export.start);
+            }
+            declaration
+        })
 }
 
-fn function_declaration<'a>() -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>>
-{
-    todo!("TODO: IMPLEMENT")
+/// Flags a declaration as exported and widens its span to cover `export`.
+fn mark_exported(declaration: &mut Declaration, start: usize) {
+    let (exported, span) = match declaration {
+        Declaration::Function { exported, span, .. }
+        | Declaration::Brand { exported, span, .. }
+        | Declaration::Data { exported, span, .. }
+        | Declaration::Let { exported, span, .. }
+        | Declaration::TypeAlias { exported, span, .. }
+        | Declaration::Effect { exported, span, .. }
+        | Declaration::Impl { exported, span, .. } => (exported, span),
+    };
+
+    *exported = true;
+    span.start = start;
 }
 
-fn brand_declaration<'a>() -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// `<A, out B, in C <: D, F<_>>`, or nothing at all.
+fn type_parameters<'a>(
+) -> impl Parser<Token<'a>, Vec<TypeParameter<'a>>, Error = Simple<Token<'a>>> + Clone {
+    type_parameter()
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LessThan), just(Token::GreaterThan))
+        .or_not()
+        .map(Option::unwrap_or_default)
 }
 
-fn type_alias_declaration<'a>() -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>>
-{
-    todo!("TODO: IMPLEMENT")
+fn type_parameter<'a>(
+) -> impl Parser<Token<'a>, TypeParameter<'a>, Error = Simple<Token<'a>>> + Clone {
+    recursive(|type_parameter| {
+        let placeholder = just(Token::Identifier("_"))
+            .map_with_span(|_, span| TypeParameter::Placeholder { span });
+
+        let higher_kinded = identifier()
+            .then(
+                type_parameter
+                    .separated_by(just(Token::Comma))
+                    .at_least(1)
+                    .delimited_by(just(Token::LessThan), just(Token::GreaterThan)),
+            )
+            .map_with_span(|(name, parameters), span| TypeParameter::HigherKinded {
+                name,
+                parameters,
+                span,
+            });
+
+        let variance = select! {
+            Token::In => Variance::In,
+            Token::Out => Variance::Out,
+        };
+
+        let constraint = just(Token::Subtype)
+            .ignore_then(type_annotation())
+            .map_with_span(|ty, span| TypeConstraint::Subtype {
+                ty: Box::new(ty),
+                span,
+            })
+            .or(just(Token::Supertype)
+                .ignore_then(type_annotation())
+                .map_with_span(|ty, span| TypeConstraint::Supertype {
+                    ty: Box::new(ty),
+                    span,
+                }));
+
+        let generic = variance
+            .or_not()
+            .then(identifier())
+            .then(constraint.or_not())
+            .map_with_span(
+                |((variance, name), constraint), span| TypeParameter::Generic {
+                    name,
+                    constraint,
+                    variance,
+                    span,
+                },
+            );
+
+        placeholder.or(higher_kinded).or(generic)
+    })
+}
+
+/// Parameters in parentheses; `->` may be used in place of `:` before the
+/// return type.
+fn function_declaration<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    let parameters = field(expression)
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis));
+
+    let return_type = just(Token::Colon)
+        .or(just(Token::Arrow))
+        .ignore_then(type_annotation())
+        .or_not();
+
+    just(Token::Fun)
+        .or(just(Token::Function))
+        .ignore_then(identifier())
+        .then(type_parameters())
+        .then(parameters)
+        .then(return_type)
+        .then(block(statement))
+        .map_with_span(
+            |((((name, type_parameters), parameters), return_type), body), span| {
+                Declaration::Function {
+                    exported: false,
+                    name,
+                    type_parameters,
+                    parameters,
+                    return_type,
+                    body,
+                    span,
+                }
+            },
+        )
+}
+
+/// `brand Name<T> = Constructor(T)`, using the same constructors as `data`.
+fn brand_declaration<'a>(
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Brand)
+        .ignore_then(identifier())
+        .then(type_parameters())
+        .then_ignore(just(Token::Equals))
+        .then(data_constructors())
+        .map_with_span(
+            |((name, type_parameters), data_constructors), span| Declaration::Brand {
+                exported: false,
+                name,
+                type_parameters,
+                data_constructors,
+                span,
+            },
+        )
+}
+
+/// `type Name<T> = | A | B(T) | C { value: T }`. The leading `|` is what sets
+/// it apart from an alias; it is the same declaration as `data`.
+fn sum_type_declaration<'a>(
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Type)
+        .ignore_then(identifier())
+        .then(type_parameters())
+        .then_ignore(just(Token::Equals))
+        .then_ignore(just(Token::Pipe).rewind())
+        .then(data_constructors())
+        .map_with_span(
+            |((name, type_parameters), data_constructors), span| Declaration::Data {
+                exported: false,
+                name,
+                type_parameters,
+                data_constructors,
+                span,
+            },
+        )
+}
+
+/// `type Name<T> = Type`, or `alias Name<T> = Type`.
+fn type_alias_declaration<'a>(
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Type)
+        .or(just(Token::Alias))
+        .ignore_then(identifier())
+        .then(type_parameters())
+        .then_ignore(just(Token::Equals))
+        .then(type_annotation())
+        .map_with_span(
+            |((name, type_parameters), alias), span| Declaration::TypeAlias {
+                exported: false,
+                name,
+                type_parameters,
+                alias,
+                span,
+            },
+        )
 }
 
-fn data_declaration<'a>() -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// `data Name<T> = A | B(T) | C { value: T }`, with an optional leading `|`.
+fn data_declaration<'a>(
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Data)
+        .ignore_then(identifier())
+        .then(type_parameters())
+        .then_ignore(just(Token::Equals))
+        .then(data_constructors())
+        .map_with_span(
+            |((name, type_parameters), data_constructors), span| Declaration::Data {
+                exported: false,
+                name,
+                type_parameters,
+                data_constructors,
+                span,
+            },
+        )
 }
 
-fn effect_declaration<'a>() -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+fn data_constructors<'a>(
+) -> impl Parser<Token<'a>, Vec<DataConstructor<'a>>, Error = Simple<Token<'a>>> + Clone {
+    just(Token::Pipe).or_not().ignore_then(
+        data_constructor()
+            .separated_by(just(Token::Pipe))
+            .at_least(1),
+    )
+}
+
+fn data_constructor<'a>(
+) -> impl Parser<Token<'a>, DataConstructor<'a>, Error = Simple<Token<'a>>> + Clone {
+    let tuple_fields = type_annotation()
+        .map_with_span(|annotation, span| (annotation, span))
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis))
+        .map(|fields| {
+            fields
+                .into_iter()
+                .enumerate()
+                .map(|(index, (annotation, span))| Field::Typed {
+                    index: index as u32,
+                    annotation,
+                    default: None,
+                    span,
+                })
+                .collect::<Vec<_>>()
+        });
+
+    let named_fields = record_key()
+        .then_ignore(just(Token::Colon))
+        .then(type_annotation())
+        .map_with_span(|(key, annotation), span| {
+            let name = match &key {
+                RecordKey::String(name, _) | RecordKey::Symbol(name, _) => *name,
+            };
+            let field = Field::Named {
+                name,
+                annotation: Some(annotation),
+                default: None,
+                span,
+            };
+            (key, field)
+        })
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace));
+
+    let tuple = identifier()
+        .then(tuple_fields)
+        .map_with_span(|(name, fields), span| DataConstructor::Tuple { name, fields, span });
+
+    let record = identifier()
+        .then(named_fields)
+        .validate(|(name, entries), span, emit| DataConstructor::Record {
+            name,
+            fields: record_fields(entries, emit),
+            span,
+        });
+
+    let void = identifier().map_with_span(|name, span| DataConstructor::Void { name, span });
+
+    tuple.or(record).or(void)
 }
 
-fn let_declaration<'a>() -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> {
-    todo!("TODO: IMPLEMENT")
+/// Either the struct-like form with one operation per entry,
+/// `effect State<A> { get(): A, modify<B>(A -> (B, A)): B }`, or the
+/// single-operation form `effect Fail<E>(E) -> Never`, whose operation shares
+/// the effect's name.
+fn effect_declaration<'a>(
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    let signature = type_parameters()
+        .then(
+            type_annotation()
+                .separated_by(just(Token::Comma))
+                .allow_trailing()
+                .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis)),
+        )
+        .then_ignore(just(Token::Colon).or(just(Token::Arrow)))
+        .then(type_annotation());
+
+    let operation = identifier().then(signature.clone()).map_with_span(
+        |(name, ((type_parameters, parameters), return_type)), span| EffectField {
+            name,
+            type_parameters,
+            parameters,
+            return_type,
+            span,
+        },
+    );
+
+    let operations = operation
+        .then_ignore(just(Token::Comma).or(just(Token::Semicolon)).or_not())
+        .repeated()
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace));
+
+    just(Token::Effect)
+        .ignore_then(identifier().map_with_span(|name, span: Span| (name, span)))
+        .then(type_parameters())
+        .then(operations.map(Ok).or(signature.map(Err)))
+        .map_with_span(|(((name, name_span), type_parameters), fields), span| {
+            let fields = match fields {
+                Ok(fields) => fields,
+                Err(((operation_parameters, parameters), return_type)) => {
+                    let span = name_span.start..return_type.span().end;
+                    vec![EffectField {
+                        name,
+                        type_parameters: operation_parameters,
+                        parameters,
+                        return_type,
+                        span,
+                    }]
+                }
+            };
+            Declaration::Effect {
+                exported: false,
+                name,
+                type_parameters,
+                fields,
+                span,
+            }
+        })
+}
+
+/// `impl<A> for State<A> { ... }`, holding functions and handlers.
+fn impl_declaration<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    let function =
+        function_declaration(statement.clone(), expression.clone()).map(ImplMember::Function);
+    let handler = multishot()
+        .then_ignore(just(Token::Handler))
+        .then(identifier().or_not())
+        .then(handler_clauses(statement, expression))
+        .map_with_span(|((multishot, name), handler), span| {
+            ImplMember::Handler(Handler {
+                name,
+                multishot,
+                span,
+                ..handler
+            })
+        });
+
+    just(Token::Impl)
+        .ignore_then(type_parameters())
+        .then_ignore(just(Token::For))
+        .then(type_annotation())
+        .then(
+            function
+                .or(handler)
+                .then_ignore(just(Token::Semicolon).or_not())
+                .repeated()
+                .delimited_by(just(Token::LeftBrace), just(Token::RightBrace)),
+        )
+        .map_with_span(
+            |((type_parameters, effect), members), span| Declaration::Impl {
+                exported: false,
+                type_parameters,
+                effect,
+                members,
+                span,
+            },
+        )
+}
+
+/// `handler(error: E) { f(error) }`, a handler of the effect of the
+/// surrounding `impl`.
+fn handler_expression<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Expression<'a>, Error = Simple<Token<'a>>> + Clone {
+    multishot()
+        .then_ignore(just(Token::Handler))
+        .then(handler_clauses(statement, expression))
+        .map_with_span(|(multishot, handler), span| {
+            Expression::Handler(Box::new(Handler {
+                multishot,
+                span,
+                ..handler
+            }))
+        })
+}
+
+/// Whether a handler is written `multi handler`. `multi` is only a keyword
+/// there, so it remains a valid name elsewhere, and is left out of what a
+/// handler is expected to start with.
+fn multishot<'a>() -> impl Parser<Token<'a>, bool, Error = Simple<Token<'a>>> + Clone {
+    contextual_keyword("multi")
+        .map_err(|error: Simple<Token<'a>>| {
+            Simple::expected_input_found(error.span(), None, error.found().cloned())
+        })
+        .then(just(Token::Handler).rewind())
+        .or_not()
+        .map(|multi| multi.is_some())
+}
+
+/// Everything in a handler after its name: the parameters, the body that may
+/// end by returning a clause for each operation, and a return clause.
+fn handler_clauses<'a>(
+    statement: StatementParser<'a>,
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Handler<'a>, Error = Simple<Token<'a>>> + Clone {
+    let parameters = field(expression.clone())
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis));
+
+    let operation = identifier()
+        .then(parameters.clone())
+        .then(block(statement.clone()))
+        .map_with_span(|((name, parameters), body), span| OperationClause {
+            name,
+            parameters,
+            body,
+            span,
+        });
+    // Tried before a statement, which `return {` would otherwise start.
+    let operations = just(Token::Return).ignore_then(
+        operation
+            .separated_by(just(Token::Comma))
+            .allow_trailing()
+            .delimited_by(just(Token::LeftBrace), just(Token::RightBrace)),
+    );
+    let body = operations
+        .map(Err)
+        .or(statement.clone().map(Ok))
+        .repeated()
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace))
+        .try_map(|items, span| {
+            let mut body = Vec::new();
+            let mut operations = None;
+            for item in items {
+                match (item, &operations) {
+                    (Ok(statement), None) => body.push(statement),
+                    (Err(clauses), None) => operations = Some(clauses),
+                    (_, Some(_)) => {
+                        return Err(Simple::custom(
+                            span,
+                            "the clauses of a handler must come last",
+                        ))
+                    }
+                }
+            }
+            Ok((body, operations.unwrap_or_default()))
+        });
+
+    let return_clause = just(Token::Return)
+        .ignore_then(
+            field(expression)
+                .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis)),
+        )
+        .then(block(statement))
+        .map_with_span(|(parameter, body), span| ReturnClause {
+            parameter,
+            body,
+            span,
+        })
+        .delimited_by(just(Token::LeftBrace), just(Token::RightBrace));
+
+    parameters
+        .then(body)
+        .then(return_clause.or_not())
+        .map_with_span(
+            |((parameters, (body, operations)), return_clause), span| Handler {
+                name: None,
+                multishot: false,
+                parameters,
+                body,
+                operations,
+                return_clause,
+                span,
+            },
+        )
+}
+
+/// `let name: Type = value`, or `name := value` for a mutable binding.
+fn let_declaration<'a>(
+    expression: ExpressionParser<'a>,
+) -> impl Parser<Token<'a>, Declaration<'a>, Error = Simple<Token<'a>>> + Clone {
+    let immutable = just(Token::Let)
+        .ignore_then(identifier())
+        .then(just(Token::Colon).ignore_then(type_annotation()).or_not())
+        .then_ignore(just(Token::Equals))
+        .then(expression.clone())
+        .map(|((name, annotation), value)| (false, name, annotation, value));
+
+    let mutable = identifier()
+        .then_ignore(just(Token::ColonEquals))
+        .then(expression)
+        .map(|(name, value)| (true, name, None, value));
+
+    immutable
+        .or(mutable)
+        .map_with_span(
+            |(mutable, name, annotation, value), span| Declaration::Let {
+                exported: false,
+                mutable,
+                name,
+                annotation,
+                value,
+                span,
+            },
+        )
 }
@@ -0,0 +1,4360 @@
+use super::decision::{self, Decision};
+use super::exhaustiveness::{self, Arm};
+use super::kind::{Kind, Kinds};
+use super::linearity;
+use super::subtype::Subtyping;
+use super::unify::{Substitution, UnifyError};
+use super::variance::{self, Occurrences};
+use super::{
+    Bound, Constructor, ConstructorFields, Operation, Scheme, Type, TypeDefinition, TypeEnv,
+    TypeVar,
+};
+use crate::error::{CompileError, CompileWarning, EffectError, ResolveError, SourceId, TypeError};
+use crate::lexing::token::Span;
+use crate::parsing::ast::{
+    self, BinaryOp, DataConstructor, Declaration, EffectField, Expression, Field, Handler,
+    ImplMember, ImportDeclaration, Literal, MatchArm, Pattern, Program, RecordKey, Spanned,
+    Statement, TemplatePart, TypeConstraint, TypeParameter, UnaryOp, Variance,
+};
+use std::collections::HashMap;
+
+/// What checking a well-typed program produces.
+#[derive(Debug, Clone, Default)]
+pub struct Checked {
+    pub warnings: Vec<CompileWarning>,
+    /// The decision tree of every `match`, by the span of the whole `match`
+    /// and in source order.
+    pub decisions: Vec<(Span, Decision)>,
+}
+
+/// Resolves names and type-checks a whole program.
+pub fn check(program: &Program, source: &SourceId) -> Result<Checked, Vec<CompileError>> {
+    let mut checker = Checker::new(source.clone());
+    checker.check_program(program);
+
+    if checker.errors.is_empty() {
+        let mut decisions = checker.decisions;
+        decisions.sort_by_key(|(span, _)| span.start);
+        Ok(Checked {
+            warnings: checker.warnings,
+            decisions,
+        })
+    } else {
+        Err(checker.errors)
+    }
+}
+
+/// Whether `handler` is written as the clause of the only operation of its
+/// effect, rather than ending with a clause for each.
+fn is_shorthand(handler: &Handler, operations: &[Operation]) -> bool {
+    handler.operations.is_empty() && operations.len() == 1
+}
+
+/// Closes the effects of the function `ty` when they end in a variable
+/// nothing else mentions. Calling it adds its effects to the caller's
+/// whatever they end in, so the variable would only be quantified over.
+fn close_effects(ty: Type, in_scope: &[TypeVar]) -> Type {
+    let (parameters, return_type, effects) = match ty {
+        Type::Function(parameters, return_type, effects) => (parameters, *return_type, *effects),
+        ty => return ty,
+    };
+    let (listed, rest) = match effects {
+        Type::Effects(listed, rest) => (listed, rest.map(|rest| *rest)),
+        rest => (Vec::new(), Some(rest)),
+    };
+    let elsewhere = Type::Tuple(
+        parameters
+            .iter()
+            .chain([&return_type])
+            .chain(&listed)
+            .cloned()
+            .collect(),
+    )
+    .free_vars();
+    let rest = match rest {
+        Some(Type::Var(var)) if !in_scope.contains(&var) && !elsewhere.contains(&var) => None,
+        rest => rest,
+    };
+    Type::effectful(parameters, return_type, Type::effects(listed, rest))
+}
+
+/// The values exported by the built-in modules.
+fn module_exports(module: &str) -> Option<Vec<(String, Type)>> {
+    match module {
+        "std:Console" => {
+            let console = Type::effects(vec![Type::HKT("Console".to_string(), Vec::new())], None);
+            let value = Type::Generic("A".to_string());
+            Some(vec![
+                (
+                    "log".to_string(),
+                    Type::effectful(vec![Type::String], Type::unit(), console.clone()),
+                ),
+                // Handles `Console` with the platform's standard streams.
+                (
+                    "Platform".to_string(),
+                    Type::Forall(
+                        vec!["A".to_string()],
+                        Box::new(Type::HKT(
+                            "Handler".to_string(),
+                            vec![console, value.clone(), value, Type::pure()],
+                        )),
+                    ),
+                ),
+            ])
+        }
+        _ => None,
+    }
+}
+
+/// The effects performed by the values of a built-in module, which the host
+/// running the program handles.
+fn module_effects(module: &str) -> Vec<&'static str> {
+    match module {
+        "std:Console" => vec!["Console"],
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    scheme: Scheme,
+    mutable: bool,
+    span: Span,
+}
+
+// Where an effect was declared, and whether the host running the program
+// handles it, as it does the effects of the built-in modules.
+struct EffectDeclaration {
+    span: Span,
+    host: bool,
+}
+
+// What the clauses of a handler share: the `answer` of handling a
+// computation, the effects they may perform themselves, and whether they
+// may resume it more than once.
+struct Clauses<'t> {
+    answer: &'t Type,
+    performed: &'t Type,
+    multishot: bool,
+}
+
+// What `resume` does in the clause of an operation: it passes back `value`,
+// which the operation returns, and gives `answer`, the value of handling
+// the rest of the computation, which may perform `performed`.
+struct Resumption {
+    value: Type,
+    answer: Type,
+    performed: Type,
+    resumed: bool,
+}
+
+// An operator applied to an operand whose type was not yet known, checked
+// once the whole program has been.
+struct Overload {
+    operator: &'static str,
+    operand: Type,
+    supports: fn(&Type) -> bool,
+    span: Span,
+}
+
+// A data type or effect and the types it is built from, each with whether
+// values of it flow out of the type, for working out its variances.
+struct VarianceDefinition<'p, 'a> {
+    name: &'a str,
+    type_parameters: &'p [TypeParameter<'a>],
+    parts: Vec<(Type, bool)>,
+}
+
+// A type that must meet the bound on the generic it was chosen for, which is
+// checked once the whole program has been.
+struct BoundCheck {
+    generic: String,
+    ty: Type,
+    bound: Bound,
+    span: Span,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    bindings: HashMap<String, Binding>,
+    // Type parameters that are in scope, which are rigid inside this scope.
+    generics: Vec<String>,
+    // The kinds of those of `generics` that are not `*`.
+    kinds: HashMap<String, Kind>,
+    // The bounds on those of `generics` that have one.
+    bounds: HashMap<String, Bound>,
+    // Narrower types that names bound outside this scope are known to have
+    // inside it, such as after `if (x == "a")`.
+    narrowed: HashMap<String, Type>,
+    // Whether this scope is the outermost scope of a function body.
+    function: bool,
+}
+
+struct Checker {
+    source: SourceId,
+    substitution: Substitution,
+    kinds: Kinds,
+    types: TypeEnv,
+    scopes: Vec<Scope>,
+    // The return type of every function being checked, innermost last.
+    returns: Vec<Type>,
+    // The effects the code being checked may perform, innermost last.
+    effects: Vec<Type>,
+    effect_declarations: HashMap<String, EffectDeclaration>,
+    // The effects performed by the top level of the program, which runs
+    // with only what the host handles.
+    performed: Vec<(Type, Span)>,
+    // Where a function called `main` is referred to, with where the one
+    // referred to is declared.
+    main_references: Vec<(Span, Span)>,
+    // The functions and named handlers written for each effect, by name.
+    members: HashMap<String, HashMap<String, Binding>>,
+    // The handler each effect is handled by when it is named on its own.
+    default_handlers: HashMap<String, Binding>,
+    // The effect of each `impl`, by its span.
+    implemented: HashMap<Span, Type>,
+    // The effects of the `impl`s being checked, innermost last.
+    impls: Vec<Type>,
+    // The operation clauses being checked, innermost last.
+    resumptions: Vec<Resumption>,
+    overloads: Vec<Overload>,
+    bound_checks: Vec<BoundCheck>,
+    errors: Vec<CompileError>,
+    warnings: Vec<CompileWarning>,
+    decisions: Vec<(Span, Decision)>,
+}
+
+impl Checker {
+    fn new(source: SourceId) -> Self {
+        let mut types = TypeEnv::new();
+        types.add_type(
+            "Unit".to_string(),
+            TypeDefinition::Alias {
+                parameters: Vec::new(),
+                ty: Type::unit(),
+            },
+        );
+        types.add_type(
+            "Never".to_string(),
+            TypeDefinition::Alias {
+                parameters: Vec::new(),
+                ty: Type::Never,
+            },
+        );
+        types.add_type(
+            "Option".to_string(),
+            TypeDefinition::Data {
+                parameters: vec!["A".to_string()],
+                constructors: vec![
+                    Constructor {
+                        name: "None".to_string(),
+                        fields: ConstructorFields::Void,
+                    },
+                    Constructor {
+                        name: "Some".to_string(),
+                        fields: ConstructorFields::Tuple(vec![Type::Generic("A".to_string())]),
+                    },
+                ],
+            },
+        );
+        types.set_parameter_variances("Option".to_string(), vec![Variance::Out]);
+        // A computation running alongside the one that forked it, which
+        // gives `A` once it is joined.
+        types.add_type(
+            "Fiber".to_string(),
+            TypeDefinition::Nominal {
+                parameters: vec!["A".to_string()],
+            },
+        );
+        types.set_parameter_variances("Fiber".to_string(), vec![Variance::Out]);
+        // What handles the effect `E` in a computation giving `A`, giving `R`
+        // instead and performing `F` itself.
+        types.add_type(
+            "Handler".to_string(),
+            TypeDefinition::Nominal {
+                parameters: ["E", "A", "R", "F"].map(String::from).to_vec(),
+            },
+        );
+        types.set_parameter_variances(
+            "Handler".to_string(),
+            vec![
+                Variance::Invariant,
+                Variance::In,
+                Variance::Out,
+                Variance::Out,
+            ],
+        );
+        // The effects of `Effect<E, A>` belong to the function that returns
+        // it, so anywhere else it is just `A`.
+        types.add_type(
+            "Effect".to_string(),
+            TypeDefinition::Alias {
+                parameters: vec!["E".to_string(), "A".to_string()],
+                ty: Type::Generic("A".to_string()),
+            },
+        );
+
+        let mut prelude = Scope::default();
+        // The constructors of `Option` need not be written `Option.Some`.
+        if let Some(TypeDefinition::Data {
+            parameters,
+            constructors,
+        }) = types.get_type("Option")
+        {
+            for constructor in constructors {
+                prelude.bindings.insert(
+                    constructor.name.clone(),
+                    Binding {
+                        scheme: constructor.scheme("Option", parameters),
+                        mutable: false,
+                        span: Span::default(),
+                    },
+                );
+            }
+        }
+        prelude.bindings.insert(
+            "assert_equal".to_string(),
+            Binding {
+                scheme: Scheme {
+                    generics: vec!["A".to_string()],
+                    ty: Type::function(
+                        vec![
+                            Type::Generic("A".to_string()),
+                            Type::Generic("A".to_string()),
+                        ],
+                        Type::unit(),
+                    ),
+                    bounds: Vec::new(),
+                },
+                mutable: false,
+                span: Span::default(),
+            },
+        );
+
+        Checker {
+            source,
+            substitution: Substitution::new(),
+            kinds: Kinds::new(),
+            types,
+            scopes: vec![prelude],
+            returns: Vec::new(),
+            effects: Vec::new(),
+            effect_declarations: HashMap::new(),
+            performed: Vec::new(),
+            main_references: Vec::new(),
+            members: HashMap::new(),
+            default_handlers: HashMap::new(),
+            implemented: HashMap::new(),
+            impls: Vec::new(),
+            resumptions: Vec::new(),
+            overloads: Vec::new(),
+            bound_checks: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            decisions: Vec::new(),
+        }
+    }
+
+    fn check_program(&mut self, program: &Program) {
+        self.push_scope(Vec::new(), false);
+        self.check_statements(&program.statements);
+        // `main` is run by the host unless the program runs it itself, so
+        // then it may perform only what the host handles too.
+        if let Some(binding) = self.current_scope().bindings.get("main").cloned() {
+            let declared = binding.span;
+            let run = self.main_references.iter().any(|(span, main)| {
+                *main == declared && (span.start < declared.start || span.end > declared.end)
+            });
+            if let (false, Type::Function(_, _, effects)) = (run, binding.scheme.ty) {
+                self.performed.push((*effects, declared));
+            }
+        }
+        self.pop_scope();
+
+        let mut reported = Vec::new();
+        for (effects, span) in std::mem::take(&mut self.performed) {
+            for effect in self.substitution.row(&effects).0 {
+                let name = effect.effect_name();
+                let host = self
+                    .effect_declarations
+                    .get(&name)
+                    .is_some_and(|declaration| declaration.host);
+                if !host && !reported.contains(&name) {
+                    self.unhandled(&effect, span.clone());
+                    reported.push(name);
+                }
+            }
+        }
+
+        for overload in std::mem::take(&mut self.overloads) {
+            let operand = self.substitution.resolve(&overload.operand);
+            if !matches!(operand, Type::Var(_)) && !(overload.supports)(&operand) {
+                self.type_error(TypeError::UnsupportedOperator {
+                    operator: overload.operator.to_string(),
+                    ty: operand.to_string(),
+                    span: overload.span,
+                });
+            }
+        }
+
+        for check in std::mem::take(&mut self.bound_checks) {
+            let ty = self.substitution.resolve(&check.ty);
+            if matches!(ty, Type::Var(_)) {
+                continue;
+            }
+            let (found, expected) = match &check.bound {
+                Bound::Upper(bound) => (ty.clone(), bound.clone()),
+                Bound::Lower(bound) => (bound.clone(), ty.clone()),
+            };
+            let bounds = HashMap::new();
+            let context = Subtyping {
+                types: &self.types,
+                bounds: &bounds,
+            };
+            if self
+                .substitution
+                .subtype(&context, &found, &expected)
+                .is_err()
+            {
+                self.type_error(TypeError::UnsatisfiedBound {
+                    generic: check.generic,
+                    ty: ty.to_string(),
+                    bound: match check.bound {
+                        Bound::Upper(bound) => Bound::Upper(self.substitution.resolve(&bound)),
+                        Bound::Lower(bound) => Bound::Lower(self.substitution.resolve(&bound)),
+                    }
+                    .to_string(),
+                    span: check.span,
+                });
+            }
+        }
+    }
+
+    // Errors
+
+    fn resolve_error(&mut self, error: ResolveError) {
+        self.errors.push(CompileError::Resolve {
+            source: self.source.clone(),
+            error,
+        });
+    }
+
+    fn type_error(&mut self, error: TypeError) {
+        self.errors.push(CompileError::Type {
+            source: self.source.clone(),
+            error,
+        });
+    }
+
+    /// Unifies two types, reporting an error at `span` if they differ.
+    /// Returns whether unification succeeded.
+    fn unify(&mut self, expected: &Type, found: &Type, span: Span) -> bool {
+        let result = self.substitution.unify(expected, found);
+        self.report(result, span)
+    }
+
+    /// Makes `found` a subtype of `expected`, reporting an error at `span`
+    /// if it cannot be. Returns whether it could.
+    fn subtype(&mut self, expected: &Type, found: &Type, span: Span) -> bool {
+        let mut bounds = HashMap::new();
+        for scope in &self.scopes {
+            bounds.extend(scope.bounds.clone());
+        }
+        let context = Subtyping {
+            types: &self.types,
+            bounds: &bounds,
+        };
+        let result = self.substitution.subtype(&context, found, expected);
+        self.report(result, span)
+    }
+
+    fn effect_error(&mut self, error: EffectError) {
+        self.errors.push(CompileError::Effect {
+            source: self.source.clone(),
+            error,
+        });
+    }
+
+    /// Performs the effects of the row `effects` at `span`, which the code
+    /// being checked must be allowed to.
+    fn perform(&mut self, effects: &Type, span: Span) {
+        let allowed = match self.effects.last() {
+            Some(allowed) => allowed.clone(),
+            None => return self.performed.push((effects.clone(), span)),
+        };
+        let result = self.substitution.include(effects, &allowed);
+        if result.is_ok() {
+            return;
+        }
+
+        let (performed, _) = self.substitution.row(effects);
+        let (listed, rest) = self.substitution.row(&allowed);
+        let unhandled: Vec<Type> = performed
+            .into_iter()
+            .filter(|effect| {
+                !listed
+                    .iter()
+                    .any(|other| other.effect_name() == effect.effect_name())
+            })
+            .collect();
+        if unhandled.is_empty() || matches!(rest, Some(Type::Var(_))) {
+            self.report(result, span);
+            return;
+        }
+        for effect in unhandled {
+            self.unhandled(&effect, span.clone());
+        }
+    }
+
+    fn unhandled(&mut self, effect: &Type, span: Span) {
+        let declared = self
+            .effect_declarations
+            .get(&effect.effect_name())
+            .map(|declaration| declaration.span.clone())
+            .unwrap_or_default();
+        self.effect_error(EffectError::Unhandled {
+            effect: effect.to_string(),
+            declared,
+            span,
+        });
+    }
+
+    fn report(&mut self, result: Result<(), UnifyError>, span: Span) -> bool {
+        match result {
+            Ok(()) => return true,
+            Err(UnifyError::Mismatch { expected, found }) => self.type_error(TypeError::Mismatch {
+                expected: expected.to_string(),
+                found: found.to_string(),
+                span,
+            }),
+            Err(UnifyError::InfiniteType { variable, ty }) => {
+                self.type_error(TypeError::InfiniteType {
+                    variable: Type::Var(variable).to_string(),
+                    ty: ty.to_string(),
+                    span,
+                })
+            }
+        }
+        false
+    }
+
+    // Scopes
+
+    fn push_scope(&mut self, generics: Vec<String>, function: bool) {
+        self.scopes.push(Scope {
+            bindings: HashMap::new(),
+            generics,
+            kinds: HashMap::new(),
+            bounds: HashMap::new(),
+            narrowed: HashMap::new(),
+            function,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn current_scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("there is always a scope")
+    }
+
+    /// Binds `name` in the current scope, shadowing any outer binding.
+    fn bind(&mut self, name: &str, binding: Binding) {
+        let scope = self.current_scope();
+        scope.narrowed.remove(name);
+        scope.bindings.insert(name.to_string(), binding);
+    }
+
+    /// Binds `name` in the current scope, reporting an earlier declaration of
+    /// the same name in that scope.
+    fn declare(&mut self, name: &str, binding: Binding) {
+        if let Some(previous) = self.current_scope().bindings.get(name) {
+            let previous = previous.span.clone();
+            self.resolve_error(ResolveError::DuplicateDefinition {
+                name: name.to_string(),
+                previous,
+                span: binding.span,
+            });
+            return;
+        }
+
+        self.bind(name, binding);
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.bindings.get(name))
+    }
+
+    /// Opens a scope in which each name has the narrower type given for it.
+    fn push_narrowed(&mut self, narrowings: Vec<(String, Type)>) {
+        self.push_scope(Vec::new(), false);
+        self.current_scope().narrowed.extend(narrowings);
+    }
+
+    /// The type `name` has been narrowed to, if it has been since it was
+    /// bound.
+    fn narrowed(&self, name: &str) -> Option<Type> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(ty) = scope.narrowed.get(name) {
+                return Some(ty.clone());
+            }
+            if scope.bindings.contains_key(name) {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Forgets what `name` was narrowed to, once it has been assigned.
+    fn widen(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            scope.narrowed.remove(name);
+            if scope.bindings.contains_key(name) {
+                return;
+            }
+        }
+    }
+
+    /// The bound on the type parameter `name`, if it has one.
+    fn bound(&self, name: &str) -> Option<Bound> {
+        self.scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.generics.iter().any(|generic| generic == name))
+            .and_then(|scope| scope.bounds.get(name).cloned())
+    }
+
+    /// Opens a scope in which `generics` have the given kinds.
+    fn push_generics(&mut self, generics: Vec<String>, kinds: Vec<Kind>) {
+        self.push_scope(generics.clone(), false);
+        self.current_scope().kinds = generics.into_iter().zip(kinds).collect();
+    }
+
+    /// The kind of the type parameter `name`, if one is in scope.
+    fn generic_kind(&self, name: &str) -> Option<Kind> {
+        self.scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.generics.iter().any(|generic| generic == name))
+            .map(|scope| scope.kinds.get(name).cloned().unwrap_or(Kind::Star))
+    }
+
+    /// The kinds of the type parameters of a type declaration, with a kind
+    /// variable for each of those left for its body to decide.
+    fn inferred_kinds(&mut self, type_parameters: &[TypeParameter]) -> Vec<Kind> {
+        type_parameters
+            .iter()
+            .map(|parameter| match parameter {
+                TypeParameter::Generic { .. } => self.kinds.fresh(),
+                parameter => Kind::of_parameter(parameter),
+            })
+            .collect()
+    }
+
+    /// Quantifies over the variables in `ty` that nothing else in scope
+    /// mentions, so that every use of the binding can pick its own types for
+    /// them. `own` names a binding in the current scope to leave out, which
+    /// is the one being generalized.
+    fn generalize(&self, ty: &Type, mut generics: Vec<String>, own: Option<&str>) -> Scheme {
+        let in_scope = self.environment_vars(own);
+        let ty = close_effects(self.substitution.resolve(ty), &in_scope);
+        let vars: Vec<TypeVar> = ty
+            .free_vars()
+            .into_iter()
+            .filter(|var| !in_scope.contains(var))
+            .collect();
+        let names: Vec<Type> = (0..vars.len())
+            .map(|index| Type::Generic(Scheme::inferred_generic(index)))
+            .collect();
+        generics.extend(names.iter().map(ToString::to_string));
+
+        Scheme {
+            generics,
+            ty: ty.replace_vars(&vars, &names),
+            bounds: Vec::new(),
+        }
+    }
+
+    /// The variables mentioned by anything in scope, except the binding
+    /// `own` in the current scope.
+    fn environment_vars(&self, own: Option<&str>) -> Vec<TypeVar> {
+        let current = self.scopes.len() - 1;
+        let mut vars: Vec<TypeVar> = Vec::new();
+        for (depth, scope) in self.scopes.iter().enumerate() {
+            for (name, binding) in &scope.bindings {
+                if depth != current || Some(name.as_str()) != own {
+                    vars.extend(self.substitution.resolve(&binding.scheme.ty).free_vars());
+                }
+            }
+        }
+        // Pending return types and operators still constrain their variables.
+        for ty in self
+            .returns
+            .iter()
+            .chain(&self.effects)
+            .chain(self.overloads.iter().map(|overload| &overload.operand))
+        {
+            vars.extend(self.substitution.resolve(ty).free_vars());
+        }
+        vars
+    }
+
+    /// Replaces the generics of a polymorphic type with fresh variables, so
+    /// that it can be used at one particular type.
+    fn instantiate_type(&mut self, ty: &Type) -> Type {
+        match self.substitution.shallow_resolve(ty) {
+            Type::Forall(generics, body) => {
+                let replacements: Vec<Type> =
+                    generics.iter().map(|_| self.substitution.fresh()).collect();
+                let ty = body.substitute(&generics, &replacements);
+                self.instantiate_type(&ty)
+            }
+            ty => ty,
+        }
+    }
+
+    /// Runs `check` on the body of the polymorphic type `polymorphic` with
+    /// its generics replaced by skolems, then reports any skolem that ended
+    /// up in the environment or in `outer`, which were there before.
+    fn skolemized(
+        &mut self,
+        polymorphic: &Type,
+        outer: &[&Type],
+        span: Span,
+        check: impl FnOnce(&mut Self, &Type) -> bool,
+    ) -> bool {
+        let (generics, body) = match polymorphic {
+            Type::Forall(generics, body) => (generics, body),
+            ty => return check(self, ty),
+        };
+
+        let mut before = self.environment_vars(None);
+        for ty in outer.iter().copied().chain([polymorphic]) {
+            before.extend(self.substitution.resolve(ty).free_vars());
+        }
+        let skolems: Vec<Type> = generics
+            .iter()
+            .map(|generic| self.substitution.skolem(generic))
+            .collect();
+        let body = body.substitute(generics, &skolems);
+
+        if !check(self, &body) {
+            return false;
+        }
+
+        for var in before {
+            let ty = self.substitution.resolve(&Type::Var(var));
+            if let Some(Type::Skolem(_, generic)) =
+                skolems.iter().find(|skolem| ty.contains(skolem))
+            {
+                self.type_error(TypeError::EscapedSkolem {
+                    generic: generic.clone(),
+                    span,
+                });
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Checks that a value of type `found` can be used where `expected` is
+    /// wanted, which it can if it is a subtype at least as polymorphic.
+    fn subsume(&mut self, expected: &Type, found: &Type, span: Span) -> bool {
+        let expected = self.substitution.shallow_resolve(expected);
+        let found = self.substitution.shallow_resolve(found);
+
+        match (&expected, &found) {
+            (Type::Forall(..), _) => {
+                self.skolemized(&expected, &[&found], span.clone(), |checker, expected| {
+                    checker.subsume(expected, &found, span)
+                })
+            }
+            (_, Type::Forall(..)) => {
+                let found = self.instantiate_type(&found);
+                self.subsume(&expected, &found, span)
+            }
+            // Functions are compared part by part when a part is polymorphic.
+            (
+                Type::Function(expected_parameters, expected_return, expected_effects),
+                Type::Function(found_parameters, found_return, found_effects),
+            ) if expected_parameters.len() == found_parameters.len()
+                && (self.is_polymorphic(&expected) || self.is_polymorphic(&found)) =>
+            {
+                let parameters = expected_parameters
+                    .iter()
+                    .zip(found_parameters)
+                    .all(|(expected, found)| self.subsume(found, expected, span.clone()));
+                if !parameters || !self.subsume(expected_return, found_return, span.clone()) {
+                    return false;
+                }
+                let result = self.substitution.include(found_effects, expected_effects);
+                self.report(result, span)
+            }
+            _ => self.subtype(&expected, &found, span),
+        }
+    }
+
+    fn is_polymorphic(&self, ty: &Type) -> bool {
+        fn has_forall(ty: &Type) -> bool {
+            match ty {
+                Type::Forall(..) => true,
+                Type::Function(parameters, return_type, _) => {
+                    parameters.iter().any(has_forall) || has_forall(return_type)
+                }
+                _ => false,
+            }
+        }
+        has_forall(&self.substitution.resolve(ty))
+    }
+
+    /// Instantiates `scheme` for a use at `span`.
+    fn instantiate(&mut self, scheme: &Scheme, span: Span) -> Type {
+        let replacements: Vec<Type> = scheme
+            .generics
+            .iter()
+            .map(|_| self.substitution.fresh())
+            .collect();
+        self.require_bounds(scheme, &replacements, span);
+        scheme.ty.substitute(&scheme.generics, &replacements)
+    }
+
+    /// Records that the types chosen for the generics of `scheme` must meet
+    /// their bounds.
+    fn require_bounds(&mut self, scheme: &Scheme, replacements: &[Type], span: Span) {
+        for (generic, bound) in &scheme.bounds {
+            let index = match scheme.generics.iter().position(|name| name == generic) {
+                Some(index) => index,
+                None => continue,
+            };
+            let bound = match bound {
+                Bound::Upper(ty) => Bound::Upper(ty.substitute(&scheme.generics, replacements)),
+                Bound::Lower(ty) => Bound::Lower(ty.substitute(&scheme.generics, replacements)),
+            };
+            self.bound_checks.push(BoundCheck {
+                generic: generic.clone(),
+                ty: replacements[index].clone(),
+                bound,
+                span: span.clone(),
+            });
+        }
+    }
+
+    // Declarations are visible throughout the block that contains them, so
+    // types and function signatures are collected before anything is checked.
+    // The kinds of type parameters are inferred from every type declared in
+    // the block before any function signature uses them.
+    fn hoist(&mut self, statements: &[Statement]) {
+        let mut declared = Vec::new();
+        for statement in statements {
+            match statement {
+                // The constructors are filled in once every type name is known.
+                Statement::Declaration(Declaration::Data {
+                    name,
+                    type_parameters,
+                    ..
+                }) => {
+                    self.types.add_type(
+                        name.to_string(),
+                        TypeDefinition::Data {
+                            parameters: type_parameter_names(type_parameters),
+                            constructors: Vec::new(),
+                        },
+                    );
+                    let kinds = self.inferred_kinds(type_parameters);
+                    self.types.set_parameter_kinds(name.to_string(), kinds);
+                    declared.push(*name);
+                }
+                // The effects of a built-in module are handled by the host.
+                Statement::Import { module, span, .. } => {
+                    for effect in module_effects(module) {
+                        if self.effect_declarations.contains_key(effect) {
+                            continue;
+                        }
+                        self.types.add_type(
+                            effect.to_string(),
+                            TypeDefinition::Effect {
+                                parameters: Vec::new(),
+                                operations: Vec::new(),
+                            },
+                        );
+                        self.effect_declarations.insert(
+                            effect.to_string(),
+                            EffectDeclaration {
+                                span: span.clone(),
+                                host: true,
+                            },
+                        );
+                    }
+                }
+                Statement::Declaration(Declaration::Brand {
+                    name,
+                    type_parameters,
+                    ..
+                }) => {
+                    self.types.add_type(
+                        name.to_string(),
+                        TypeDefinition::Nominal {
+                            parameters: type_parameter_names(type_parameters),
+                        },
+                    );
+                    let kinds = self.inferred_kinds(type_parameters);
+                    self.types.set_parameter_kinds(name.to_string(), kinds);
+                    declared.push(*name);
+                }
+                Statement::Declaration(Declaration::Effect {
+                    name,
+                    type_parameters,
+                    span,
+                    ..
+                }) => {
+                    // The operations are filled in once every type name is
+                    // known.
+                    self.types.add_type(
+                        name.to_string(),
+                        TypeDefinition::Effect {
+                            parameters: type_parameter_names(type_parameters),
+                            operations: Vec::new(),
+                        },
+                    );
+                    self.effect_declarations.insert(
+                        name.to_string(),
+                        EffectDeclaration {
+                            span: span.clone(),
+                            host: false,
+                        },
+                    );
+                    let kinds = self.inferred_kinds(type_parameters);
+                    self.types.set_parameter_kinds(name.to_string(), kinds);
+                    declared.push(*name);
+                }
+                _ => {}
+            }
+        }
+
+        for statement in statements {
+            if let Statement::Declaration(Declaration::TypeAlias {
+                name,
+                type_parameters,
+                alias,
+                ..
+            }) = statement
+            {
+                let parameters = type_parameter_names(type_parameters);
+                let kinds = self.inferred_kinds(type_parameters);
+                self.push_generics(parameters.clone(), kinds.clone());
+                let ty = self.resolve_annotation(alias);
+                self.pop_scope();
+
+                self.types
+                    .add_type(name.to_string(), TypeDefinition::Alias { parameters, ty });
+                self.types.set_parameter_kinds(name.to_string(), kinds);
+                declared.push(*name);
+            }
+        }
+
+        let mut definitions = Vec::new();
+        for statement in statements {
+            match statement {
+                Statement::Declaration(Declaration::Data {
+                    name,
+                    type_parameters,
+                    data_constructors,
+                    ..
+                }) => {
+                    let parameters = type_parameter_names(type_parameters);
+                    let kinds = self.types.parameter_kinds(name);
+                    self.push_generics(parameters.clone(), kinds);
+                    let constructors = self.constructors(data_constructors);
+                    self.pop_scope();
+
+                    let parts = constructors
+                        .iter()
+                        .flat_map(|constructor| match &constructor.fields {
+                            ConstructorFields::Void => Vec::new(),
+                            ConstructorFields::Tuple(fields) => fields.clone(),
+                            ConstructorFields::Record(fields) => {
+                                fields.iter().map(|(_, ty)| ty.clone()).collect()
+                            }
+                        })
+                        .map(|ty| (ty, true))
+                        .collect();
+                    definitions.push(VarianceDefinition {
+                        name,
+                        type_parameters,
+                        parts,
+                    });
+
+                    self.types.add_type(
+                        name.to_string(),
+                        TypeDefinition::Data {
+                            parameters,
+                            constructors,
+                        },
+                    );
+                }
+                // What an operation is given comes from whoever performs it,
+                // and what it returns goes to them.
+                Statement::Declaration(Declaration::Effect {
+                    name,
+                    type_parameters,
+                    fields,
+                    ..
+                }) => {
+                    let parameters = type_parameter_names(type_parameters);
+                    let kinds = self.types.parameter_kinds(name);
+                    self.push_generics(parameters.clone(), kinds);
+                    let operations = self.operations(fields);
+                    self.pop_scope();
+
+                    let parts = operations
+                        .iter()
+                        .map(|operation| {
+                            let ty = Type::function(
+                                operation.parameters.clone(),
+                                operation.return_type.clone(),
+                            );
+                            match &operation.generics[..] {
+                                [] => (ty, false),
+                                generics => (Type::Forall(generics.to_vec(), Box::new(ty)), false),
+                            }
+                        })
+                        .collect();
+                    definitions.push(VarianceDefinition {
+                        name,
+                        type_parameters,
+                        parts,
+                    });
+
+                    self.types.add_type(
+                        name.to_string(),
+                        TypeDefinition::Effect {
+                            parameters,
+                            operations,
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        // A parameter nothing applies holds ordinary types.
+        for name in declared {
+            let kinds = self
+                .types
+                .parameter_kinds(name)
+                .iter()
+                .map(|kind| self.kinds.default(kind))
+                .collect();
+            self.types.set_parameter_kinds(name.to_string(), kinds);
+        }
+        self.infer_variances(&definitions);
+
+        for statement in statements {
+            match statement {
+                Statement::Declaration(Declaration::Function {
+                    name,
+                    type_parameters,
+                    parameters,
+                    return_type,
+                    span,
+                    ..
+                }) => {
+                    let scheme = self.signature(type_parameters, parameters, return_type.as_ref());
+                    self.declare(
+                        name,
+                        Binding {
+                            scheme,
+                            mutable: false,
+                            span: span.clone(),
+                        },
+                    );
+                }
+                Statement::Declaration(Declaration::Impl {
+                    type_parameters,
+                    effect,
+                    members,
+                    span,
+                    ..
+                }) => self.hoist_impl(type_parameters, effect, members, span),
+                _ => {}
+            }
+        }
+    }
+
+    /// The type of a function declared with `type_parameters`, `parameters`
+    /// and `return_type`, with a variable for anything not written.
+    fn signature(
+        &mut self,
+        type_parameters: &[TypeParameter],
+        parameters: &[Field],
+        return_type: Option<&ast::Type>,
+    ) -> Scheme {
+        let generics = type_parameter_names(type_parameters);
+        self.push_generics(generics.clone(), declared_kinds(type_parameters));
+        let bounds = self.bounds(type_parameters);
+        self.current_scope().bounds = bounds.iter().cloned().collect();
+        let parameters = parameters
+            .iter()
+            .map(|parameter| self.parameter_type(parameter))
+            .collect();
+        // Without an annotation, the effects are inferred from the body.
+        let (return_type, effects) = match return_type {
+            Some(annotation) => self.return_annotation(annotation),
+            None => (self.substitution.fresh(), self.substitution.fresh()),
+        };
+        self.pop_scope();
+
+        Scheme {
+            generics,
+            ty: Type::effectful(parameters, return_type, effects),
+            bounds,
+        }
+    }
+
+    /// Records the effect an `impl` is for and the types of its members,
+    /// which are reached through the effect's name. A handler's type is
+    /// only known once its clauses have been checked.
+    fn hoist_impl(
+        &mut self,
+        type_parameters: &[TypeParameter],
+        effect: &ast::Type,
+        members: &[ImplMember],
+        span: &Span,
+    ) {
+        let generics = type_parameter_names(type_parameters);
+        self.push_generics(generics.clone(), declared_kinds(type_parameters));
+        let effect = match self.resolve_annotation(effect) {
+            ty @ Type::HKT(..) if self.is_effect(&ty.effect_name()) => ty,
+            Type::Var(_) => return self.pop_scope(),
+            ty => {
+                self.type_error(TypeError::NotAnEffect {
+                    ty: ty.to_string(),
+                    span: effect.span(),
+                });
+                return self.pop_scope();
+            }
+        };
+        let owner = effect.effect_name();
+        let operations = match self.types.get_type(&owner) {
+            Some(TypeDefinition::Effect { operations, .. }) => operations.clone(),
+            _ => Vec::new(),
+        };
+
+        for member in members {
+            let (name, ty, span) = match member {
+                ImplMember::Function(Declaration::Function {
+                    name,
+                    type_parameters,
+                    parameters,
+                    return_type,
+                    span,
+                    ..
+                }) => {
+                    let mut scheme =
+                        self.signature(type_parameters, parameters, return_type.as_ref());
+                    // A function written for an effect may perform it without
+                    // saying so.
+                    if let (Some(_), Type::Function(parameters, return_type, effects)) =
+                        (return_type, &scheme.ty)
+                    {
+                        let effects =
+                            Type::effects(vec![effect.clone()], Some((**effects).clone()));
+                        scheme.ty =
+                            Type::effectful(parameters.clone(), (**return_type).clone(), effects);
+                    }
+                    scheme.generics = generics.iter().chain(&scheme.generics).cloned().collect();
+                    self.declare_member(&owner, name, scheme, span.clone());
+                    continue;
+                }
+                ImplMember::Function(_) => continue,
+                ImplMember::Handler(handler) => {
+                    let handled = self.handler_type(&effect);
+                    let ty = match handler.name {
+                        None => handled,
+                        // A handler of a single operation takes that
+                        // operation's parameters, so nothing to be built.
+                        Some(_) if is_shorthand(handler, &operations) => {
+                            let effects = self.substitution.fresh();
+                            Type::effectful(Vec::new(), handled, effects)
+                        }
+                        Some(_) => {
+                            let parameters = handler
+                                .parameters
+                                .iter()
+                                .map(|parameter| self.parameter_type(parameter))
+                                .collect();
+                            let effects = self.substitution.fresh();
+                            Type::effectful(parameters, handled, effects)
+                        }
+                    };
+                    (handler.name, ty, handler.span.clone())
+                }
+            };
+            let scheme = Scheme {
+                generics: generics.clone(),
+                ty,
+                bounds: Vec::new(),
+            };
+            match name {
+                Some(name) => self.declare_member(&owner, name, scheme, span),
+                None => match self.default_handlers.get(&owner) {
+                    Some(previous) => {
+                        let previous = previous.span.clone();
+                        self.resolve_error(ResolveError::DuplicateDefinition {
+                            name: owner.clone(),
+                            previous,
+                            span,
+                        });
+                    }
+                    None => {
+                        let binding = Binding {
+                            scheme,
+                            mutable: false,
+                            span,
+                        };
+                        self.default_handlers.insert(owner.clone(), binding);
+                    }
+                },
+            }
+        }
+        self.pop_scope();
+        self.implemented.insert(span.clone(), effect);
+    }
+
+    /// Makes `name` a member of the effect `owner`, as in `State.local`,
+    /// reporting one that is already taken by another or an operation.
+    fn declare_member(&mut self, owner: &str, name: &str, scheme: Scheme, span: Span) {
+        let previous = match self
+            .members
+            .get(owner)
+            .and_then(|members| members.get(name))
+        {
+            Some(previous) => Some(previous.span.clone()),
+            None if self.operation_scheme(owner, name).is_some() => self
+                .effect_declarations
+                .get(owner)
+                .map(|declaration| declaration.span.clone()),
+            None => None,
+        };
+        if let Some(previous) = previous {
+            return self.resolve_error(ResolveError::DuplicateDefinition {
+                name: name.to_string(),
+                previous,
+                span,
+            });
+        }
+        self.members.entry(owner.to_string()).or_default().insert(
+            name.to_string(),
+            Binding {
+                scheme,
+                mutable: false,
+                span,
+            },
+        );
+    }
+
+    /// A handler of `effect` with variables for everything else about it.
+    fn handler_type(&mut self, effect: &Type) -> Type {
+        Type::HKT(
+            "Handler".to_string(),
+            vec![
+                Type::effects(vec![effect.clone()], None),
+                self.substitution.fresh(),
+                self.substitution.fresh(),
+                self.substitution.fresh(),
+            ],
+        )
+    }
+
+    /// The bounds written on `type_parameters`, which must be in scope.
+    fn bounds(&mut self, type_parameters: &[TypeParameter]) -> Vec<(String, Bound)> {
+        let mut bounds = Vec::new();
+        for parameter in type_parameters {
+            if let TypeParameter::Generic {
+                name,
+                constraint: Some(constraint),
+                ..
+            } = parameter
+            {
+                let bound = match constraint {
+                    TypeConstraint::Subtype { ty, .. } => Bound::Upper(self.resolve_annotation(ty)),
+                    TypeConstraint::Supertype { ty, .. } => {
+                        Bound::Lower(self.resolve_annotation(ty))
+                    }
+                    // Not something the parser produces.
+                    TypeConstraint::Invariant { .. } => continue,
+                };
+                bounds.push((name.to_string(), bound));
+            }
+        }
+        bounds
+    }
+
+    /// Works out the variances of the parameters of `definitions`, which may
+    /// refer to each other, and checks them against those declared. Every
+    /// round assumes the variances the last one found, until none change.
+    fn infer_variances(&mut self, definitions: &[VarianceDefinition]) {
+        let mut found: Vec<Vec<Occurrences>> = definitions
+            .iter()
+            .map(|definition| vec![Occurrences::default(); definition.type_parameters.len()])
+            .collect();
+        loop {
+            for (definition, found) in definitions.iter().zip(&found) {
+                let variances = definition
+                    .type_parameters
+                    .iter()
+                    .zip(found)
+                    .map(|(parameter, found)| match parameter {
+                        TypeParameter::Generic {
+                            variance: Some(variance),
+                            ..
+                        } => variance.clone(),
+                        _ => found.variance(),
+                    })
+                    .collect();
+                self.types
+                    .set_parameter_variances(definition.name.to_string(), variances);
+            }
+
+            let mut changed = false;
+            for (definition, found) in definitions.iter().zip(&mut found) {
+                let parameters = type_parameter_names(definition.type_parameters);
+                let mut next = found.clone();
+                for (ty, positive) in &definition.parts {
+                    variance::occurrences(&self.types, &parameters, ty, *positive, &mut next);
+                }
+                changed |= next != *found;
+                *found = next;
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        for (definition, found) in definitions.iter().zip(&found) {
+            for (parameter, found) in definition.type_parameters.iter().zip(found) {
+                if let TypeParameter::Generic {
+                    name,
+                    variance: Some(variance),
+                    span,
+                    ..
+                } = parameter
+                {
+                    if !found.allow(variance) {
+                        self.type_error(TypeError::VarianceMismatch {
+                            parameter: name.to_string(),
+                            declared: match variance {
+                                Variance::In => "in",
+                                Variance::Out => "out",
+                                Variance::Invariant => "invariant",
+                            }
+                            .to_string(),
+                            span: span.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Resolves the operations of an effect, whose own type parameters are
+    /// in scope in their signatures besides the effect's.
+    fn operations(&mut self, fields: &[EffectField]) -> Vec<Operation> {
+        let mut operations: Vec<Operation> = Vec::new();
+        let mut spans: HashMap<&str, Span> = HashMap::new();
+
+        for field in fields {
+            let generics = type_parameter_names(&field.type_parameters);
+            self.push_generics(generics.clone(), declared_kinds(&field.type_parameters));
+            let parameters = field
+                .parameters
+                .iter()
+                .map(|parameter| self.resolve_annotation(parameter))
+                .collect();
+            let return_type = self.resolve_annotation(&field.return_type);
+            self.pop_scope();
+
+            if let Some(previous) = spans.get(field.name) {
+                self.resolve_error(ResolveError::DuplicateDefinition {
+                    name: field.name.to_string(),
+                    previous: previous.clone(),
+                    span: field.span.clone(),
+                });
+                continue;
+            }
+            spans.insert(field.name, field.span.clone());
+            operations.push(Operation {
+                name: field.name.to_string(),
+                generics,
+                parameters,
+                return_type,
+            });
+        }
+        operations
+    }
+
+    fn constructors(&mut self, declarations: &[DataConstructor]) -> Vec<Constructor> {
+        let mut constructors: Vec<Constructor> = Vec::new();
+        let mut spans: HashMap<&str, Span> = HashMap::new();
+
+        for declaration in declarations {
+            let (name, fields) = match declaration {
+                DataConstructor::Void { name, .. } => (*name, ConstructorFields::Void),
+                DataConstructor::Tuple { name, fields, .. } => (
+                    *name,
+                    ConstructorFields::Tuple(
+                        fields
+                            .iter()
+                            .map(|field| self.parameter_type(field))
+                            .collect(),
+                    ),
+                ),
+                DataConstructor::Record { name, fields, .. } => {
+                    let mut fields: Vec<(String, Type)> = fields
+                        .iter()
+                        .map(|(key, field)| (record_key_name(key), self.parameter_type(field)))
+                        .collect();
+                    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    (*name, ConstructorFields::Record(fields))
+                }
+            };
+
+            if let Some(previous) = spans.get(name) {
+                self.resolve_error(ResolveError::DuplicateDefinition {
+                    name: name.to_string(),
+                    previous: previous.clone(),
+                    span: declaration.span(),
+                });
+                continue;
+            }
+            spans.insert(name, declaration.span());
+            constructors.push(Constructor {
+                name: name.to_string(),
+                fields,
+            });
+        }
+
+        constructors
+    }
+
+    // Statements
+
+    /// Checks a sequence of statements in the current scope and returns the
+    /// value of the last one.
+    fn check_statements(&mut self, statements: &[Statement]) -> Type {
+        self.hoist(statements);
+
+        let mut value = Type::unit();
+        for statement in statements {
+            value = self.check_statement(statement);
+        }
+        value
+    }
+
+    /// Checks a sequence of statements in the current scope, checking the
+    /// value of the last one against `expected`. `span` is where an empty
+    /// sequence is reported.
+    fn check_tail(&mut self, statements: &[Statement], expected: &Type, span: Span) {
+        self.hoist(statements);
+
+        let (last, rest) = match statements.split_last() {
+            Some(split) => split,
+            None => {
+                self.subsume(expected, &Type::unit(), span);
+                return;
+            }
+        };
+        for statement in rest {
+            self.check_statement(statement);
+        }
+        match last {
+            Statement::Expression { expr, .. } => self.check_expression(expr, expected),
+            statement => {
+                let value = self.check_statement(statement);
+                self.subsume(expected, &value, statement.span());
+            }
+        }
+    }
+
+    fn check_body(&mut self, statements: &[Statement]) -> Type {
+        self.push_scope(Vec::new(), false);
+        let value = self.check_statements(statements);
+        self.pop_scope();
+        value
+    }
+
+    fn check_statement(&mut self, statement: &Statement) -> Type {
+        match statement {
+            Statement::Expression { expr, .. } => return self.infer(expr),
+            Statement::Return { expr, span } => {
+                match self.returns.last().cloned() {
+                    Some(return_type) => self.check_expression(expr, &return_type),
+                    None => {
+                        self.infer(expr);
+                        self.resolve_error(ResolveError::ReturnOutsideFunction {
+                            span: span.clone(),
+                        })
+                    }
+                }
+                return Type::Never;
+            }
+            // The parser has already reported whatever went wrong here.
+            Statement::Break { .. } | Statement::Continue { .. } | Statement::Error { .. } => {}
+            Statement::If {
+                condition,
+                then_branch,
+                else_if_branches,
+                else_branch,
+                ..
+            } => {
+                // Each branch knows its own condition held and the earlier
+                // ones did not. When none of them carries on past the `if`,
+                // none held for whatever follows it, and when the `else`
+                // does not either, nothing follows it.
+                let branches = std::iter::once((condition, then_branch)).chain(
+                    else_if_branches
+                        .iter()
+                        .map(|(condition, branch)| (condition, branch)),
+                );
+                let mut failed = Vec::new();
+                let mut diverges = true;
+                for (condition, branch) in branches {
+                    self.check_condition(condition);
+                    let narrowings = self.narrowings(condition, true);
+                    self.push_narrowed(narrowings);
+                    let value = self.check_statements(branch);
+                    diverges &= self.substitution.resolve(&value) == Type::Never;
+                    self.pop_scope();
+
+                    let narrowings = self.narrowings(condition, false);
+                    failed.extend(narrowings.clone());
+                    self.push_narrowed(narrowings);
+                }
+                let otherwise = else_branch.as_ref().map(|branch| self.check_body(branch));
+                for _ in 0..=else_if_branches.len() {
+                    self.pop_scope();
+                }
+                if diverges {
+                    match otherwise {
+                        Some(value) if self.substitution.resolve(&value) == Type::Never => {
+                            return Type::Never
+                        }
+                        _ => self.current_scope().narrowed.extend(failed),
+                    }
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.check_condition(condition);
+                let narrowings = self.narrowings(condition, true);
+                self.push_narrowed(narrowings);
+                self.check_statements(body);
+                self.pop_scope();
+            }
+            Statement::For {
+                initializer,
+                condition,
+                increment,
+                body,
+                ..
+            } => {
+                self.push_scope(Vec::new(), false);
+                self.infer(initializer);
+                self.check_condition(condition);
+                self.infer(increment);
+                self.check_body(body);
+                self.pop_scope();
+            }
+            Statement::ForOf {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                let element = self.substitution.fresh();
+                let iterable_type = self.infer(iterable);
+                self.unify(
+                    &Type::Array(Box::new(element.clone())),
+                    &iterable_type,
+                    iterable.span(),
+                );
+
+                self.push_scope(Vec::new(), false);
+                self.bind_target(variable, element);
+                self.check_body(body);
+                self.pop_scope();
+            }
+            Statement::ForIn {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                let iterable_type = self.infer(iterable);
+                let key = match self.substitution.resolve(&iterable_type) {
+                    Type::Array(_) | Type::Tuple(_) => Type::Integer,
+                    _ => Type::String,
+                };
+
+                self.push_scope(Vec::new(), false);
+                self.bind_target(variable, key);
+                self.check_body(body);
+                self.pop_scope();
+            }
+            Statement::Import {
+                module,
+                declaration,
+                span,
+            } => self.check_import(module, declaration, span),
+            Statement::Declaration(declaration) => self.check_declaration(declaration),
+        }
+
+        Type::unit()
+    }
+
+    fn check_condition(&mut self, condition: &Expression) {
+        let ty = self.infer(condition);
+        self.unify(&Type::Boolean, &ty, condition.span());
+    }
+
+    /// The names whose unions `condition` narrows when it evaluates to
+    /// `holds`, with what they narrow to. Comparisons with a literal or
+    /// `()` narrow the name compared, or the union of records whose field is
+    /// compared; `!`, `&&` and `||` combine them.
+    fn narrowings(&mut self, condition: &Expression, holds: bool) -> Vec<(String, Type)> {
+        match condition {
+            Expression::Unary {
+                op: UnaryOp::LogicalNot(_),
+                expr,
+                ..
+            } => self.narrowings(expr, !holds),
+            Expression::Binary {
+                left, op, right, ..
+            } => match op {
+                // Only when both sides hold, or both fail, is either known.
+                BinaryOp::LogicalAnd(_) if holds => self.both_narrowings(left, right, holds),
+                BinaryOp::LogicalOr(_) if !holds => self.both_narrowings(left, right, holds),
+                BinaryOp::Equal(_) => self.comparison(left, right, holds).into_iter().collect(),
+                BinaryOp::NotEqual(_) => self.comparison(left, right, !holds).into_iter().collect(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // The right operand is only evaluated once the left one has decided
+    // nothing, so it is narrowed by it.
+    fn both_narrowings(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        holds: bool,
+    ) -> Vec<(String, Type)> {
+        let mut narrowings = self.narrowings(left, holds);
+        self.push_narrowed(narrowings.clone());
+        narrowings.extend(self.narrowings(right, holds));
+        self.pop_scope();
+        narrowings
+    }
+
+    /// What comparing `left` and `right` for equality narrows, given whether
+    /// they are `equal`.
+    fn comparison(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        equal: bool,
+    ) -> Option<(String, Type)> {
+        let (subject, value) = match (singleton_type(left), singleton_type(right)) {
+            (_, Some(value)) => (left, value),
+            (Some(value), _) => (right, value),
+            _ => return None,
+        };
+        let (name, field) = match subject {
+            Expression::Identifier { name, .. } => (*name, None),
+            Expression::Member {
+                object, property, ..
+            } => match &**object {
+                Expression::Identifier { name, .. } => (*name, Some(*property)),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let ty = match self.narrowed(name) {
+            Some(ty) => ty,
+            None => match self.lookup(name) {
+                Some(binding) if binding.scheme.generics.is_empty() => binding.scheme.ty.clone(),
+                _ => return None,
+            },
+        };
+        let members = match self.substitution.resolve(&ty) {
+            Type::Union(members) => members,
+            _ => return None,
+        };
+
+        let narrowed = members
+            .into_iter()
+            .filter(|member| {
+                let part = match field {
+                    None => Some(member),
+                    Some(field) => match member {
+                        Type::Struct(fields) | Type::Extend(fields, _) => fields
+                            .iter()
+                            .find(|(name, _)| name == field)
+                            .map(|(_, ty)| ty),
+                        _ => None,
+                    },
+                };
+                match part {
+                    Some(part) if equal => may_be(part, &value),
+                    Some(part) => *part != value,
+                    None => true,
+                }
+            })
+            .collect();
+        Some((name.to_string(), Type::union(narrowed)))
+    }
+
+    fn check_import(&mut self, module: &str, declaration: &ImportDeclaration, span: &Span) {
+        let exports = match module_exports(module) {
+            Some(exports) => exports,
+            None => {
+                return self.resolve_error(ResolveError::UnknownModule {
+                    module: module.to_string(),
+                    span: span.clone(),
+                })
+            }
+        };
+
+        match declaration {
+            ImportDeclaration::NamespaceImport { name, span } => self.declare(
+                name,
+                Binding {
+                    scheme: Scheme::monomorphic(Type::structure(exports)),
+                    mutable: false,
+                    span: span.clone(),
+                },
+            ),
+            ImportDeclaration::NamedImports { imports, .. } => {
+                for import in imports {
+                    let export = exports.iter().find(|(name, _)| name == import.name);
+                    match export {
+                        Some((_, ty)) => {
+                            let name = import.alias.as_deref().unwrap_or(import.name);
+                            self.declare(
+                                name,
+                                Binding {
+                                    scheme: Scheme::monomorphic(ty.clone()),
+                                    mutable: false,
+                                    span: import.span.clone(),
+                                },
+                            );
+                        }
+                        None => self.resolve_error(ResolveError::UnknownExport {
+                            module: module.to_string(),
+                            name: import.name.to_string(),
+                            span: import.span.clone(),
+                        }),
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_declaration(&mut self, declaration: &Declaration) {
+        match declaration {
+            Declaration::Function {
+                name,
+                type_parameters,
+                parameters,
+                body,
+                span,
+                ..
+            } => {
+                // The signature was recorded when the declaration was hoisted.
+                let scheme = match self.current_scope().bindings.get(*name) {
+                    Some(binding) => binding.scheme.clone(),
+                    None => return,
+                };
+                let (parameter_types, return_type, effects) = match scheme.ty {
+                    Type::Function(parameters, return_type, effects) => {
+                        (parameters, *return_type, *effects)
+                    }
+                    _ => return,
+                };
+
+                self.push_scope(scheme.generics.clone(), true);
+                self.current_scope().kinds = type_parameter_names(type_parameters)
+                    .into_iter()
+                    .zip(declared_kinds(type_parameters))
+                    .collect();
+                self.current_scope().bounds = scheme.bounds.iter().cloned().collect();
+                self.bind_parameters(parameters, &parameter_types);
+                self.returns.push(return_type.clone());
+                self.effects.push(effects.clone());
+
+                self.check_tail(body, &return_type, span.clone());
+
+                self.effects.pop();
+                self.returns.pop();
+                self.pop_scope();
+
+                // Uses checked from here on may instantiate whatever the body
+                // left unconstrained.
+                let ty = Type::effectful(parameter_types, return_type, effects);
+                let scheme = Scheme {
+                    bounds: scheme.bounds,
+                    ..self.generalize(&ty, scheme.generics, Some(name))
+                };
+                if let Some(binding) = self.current_scope().bindings.get_mut(*name) {
+                    binding.scheme = scheme;
+                }
+            }
+            Declaration::Let {
+                mutable,
+                name,
+                annotation,
+                value,
+                span,
+                ..
+            } => {
+                let ty = match annotation {
+                    Some(annotation) => {
+                        let ty = self.resolve_annotation(annotation);
+                        self.check_expression(value, &ty);
+                        ty
+                    }
+                    None => self.infer(value),
+                };
+                let scheme = if !*mutable && (is_value(value) || self.is_handler(&ty)) {
+                    self.generalize(&ty, Vec::new(), None)
+                } else {
+                    Scheme::monomorphic(ty)
+                };
+
+                self.declare(
+                    name,
+                    Binding {
+                        scheme,
+                        mutable: *mutable,
+                        span: span.clone(),
+                    },
+                );
+            }
+            Declaration::Impl {
+                type_parameters,
+                members,
+                span,
+                ..
+            } => {
+                let effect = match self.implemented.get(span) {
+                    Some(effect) => effect.clone(),
+                    None => return,
+                };
+                let owner = effect.effect_name();
+                let generics = type_parameter_names(type_parameters);
+                self.push_generics(generics.clone(), declared_kinds(type_parameters));
+                self.impls.push(effect.clone());
+                for member in members {
+                    match member {
+                        ImplMember::Function(function @ Declaration::Function { name, .. }) => {
+                            let member = self
+                                .members
+                                .get(&owner)
+                                .and_then(|members| members.get(*name));
+                            if let Some(member) = member.cloned() {
+                                // Checked like any function, then reached through
+                                // the effect.
+                                self.bind(name, member);
+                                self.check_declaration(function);
+                                let checked = self.current_scope().bindings.get(*name).cloned();
+                                if let (Some(checked), Some(members)) =
+                                    (checked, self.members.get_mut(&owner))
+                                {
+                                    members.insert(name.to_string(), checked);
+                                }
+                            }
+                        }
+                        ImplMember::Function(_) => {}
+                        ImplMember::Handler(handler) => {
+                            let member = match handler.name {
+                                Some(name) => self
+                                    .members
+                                    .get(&owner)
+                                    .and_then(|members| members.get(name)),
+                                None => self.default_handlers.get(&owner),
+                            };
+                            let mut member = match member {
+                                Some(member) => member.clone(),
+                                None => continue,
+                            };
+                            let ty = self.check_handler(handler, &effect, handler.name.is_some());
+                            self.unify(&member.scheme.ty, &ty, handler.span.clone());
+                            member.scheme =
+                                self.generalize(&member.scheme.ty, generics.clone(), None);
+                            match handler.name {
+                                Some(name) => {
+                                    if let Some(members) = self.members.get_mut(&owner) {
+                                        members.insert(name.to_string(), member);
+                                    }
+                                }
+                                None => {
+                                    self.default_handlers.insert(owner.clone(), member);
+                                }
+                            }
+                        }
+                    }
+                }
+                self.impls.pop();
+                self.pop_scope();
+            }
+            // Named types were registered when the block was hoisted.
+            Declaration::Brand { .. }
+            | Declaration::Data { .. }
+            | Declaration::TypeAlias { .. }
+            | Declaration::Effect { .. } => {}
+        }
+    }
+
+    /// Checks a handler of `effect` and returns its type: the handler itself,
+    /// or when it is `built` with arguments, a function from them to it.
+    fn check_handler(&mut self, handler: &Handler, effect: &Type, built: bool) -> Type {
+        let (owner, arguments) = match effect {
+            Type::HKT(owner, arguments) => (owner.clone(), arguments.clone()),
+            _ => return self.substitution.fresh(),
+        };
+        let (parameters, operations) = match self.types.get_type(&owner) {
+            Some(TypeDefinition::Effect {
+                parameters,
+                operations,
+            }) => (parameters.clone(), operations.clone()),
+            _ => return self.substitution.fresh(),
+        };
+        let operations: Vec<Operation> = operations
+            .into_iter()
+            .map(|operation| Operation {
+                parameters: operation
+                    .parameters
+                    .iter()
+                    .map(|ty| ty.substitute(&parameters, &arguments))
+                    .collect(),
+                return_type: operation.return_type.substitute(&parameters, &arguments),
+                ..operation
+            })
+            .collect();
+
+        let input = self.substitution.fresh();
+        let answer = self.substitution.fresh();
+        let performed = self.substitution.fresh();
+        let handled = Type::HKT(
+            "Handler".to_string(),
+            vec![
+                Type::effects(vec![effect.clone()], None),
+                input.clone(),
+                answer.clone(),
+                performed.clone(),
+            ],
+        );
+        let clauses = Clauses {
+            answer: &answer,
+            performed: &performed,
+            multishot: handler.multishot,
+        };
+
+        if is_shorthand(handler, &operations) {
+            self.operation_clause(
+                &operations[0],
+                &handler.parameters,
+                &handler.body,
+                handler.span.clone(),
+                &clauses,
+            );
+            self.return_clause(handler, &input, &clauses);
+            return if built {
+                Type::function(Vec::new(), handled)
+            } else {
+                handled
+            };
+        }
+
+        // The body sets up what the clauses share, such as the state they
+        // keep, each time the handler is built.
+        if !built && !handler.parameters.is_empty() {
+            self.type_error(TypeError::ArityMismatch {
+                expected: 0,
+                found: handler.parameters.len(),
+                span: handler.span.clone(),
+            });
+        }
+        let parameter_types: Vec<Type> = handler
+            .parameters
+            .iter()
+            .map(|parameter| self.parameter_type(parameter))
+            .collect();
+        let setup = self.substitution.fresh();
+        self.push_scope(Vec::new(), built);
+        self.bind_parameters(&handler.parameters, &parameter_types);
+        if built {
+            self.returns.push(handled.clone());
+            self.effects.push(setup.clone());
+        }
+        self.check_statements(&handler.body);
+
+        let mut spans: HashMap<&str, Span> = HashMap::new();
+        for clause in &handler.operations {
+            let operation = operations
+                .iter()
+                .find(|operation| operation.name == clause.name);
+            match (operation, spans.get(clause.name)) {
+                (None, _) => self.resolve_error(ResolveError::UnknownOperation {
+                    effect: owner.clone(),
+                    name: clause.name.to_string(),
+                    span: clause.span.clone(),
+                }),
+                (Some(_), Some(previous)) => {
+                    self.resolve_error(ResolveError::DuplicateDefinition {
+                        name: clause.name.to_string(),
+                        previous: previous.clone(),
+                        span: clause.span.clone(),
+                    })
+                }
+                (Some(operation), None) => {
+                    spans.insert(clause.name, clause.span.clone());
+                    self.operation_clause(
+                        operation,
+                        &clause.parameters,
+                        &clause.body,
+                        clause.span.clone(),
+                        &clauses,
+                    );
+                }
+            }
+        }
+        for operation in &operations {
+            if !spans.contains_key(operation.name.as_str()) {
+                self.resolve_error(ResolveError::MissingOperation {
+                    effect: owner.clone(),
+                    name: operation.name.clone(),
+                    span: handler.span.clone(),
+                });
+            }
+        }
+
+        if built {
+            self.effects.pop();
+            self.returns.pop();
+        }
+        self.pop_scope();
+        self.return_clause(handler, &input, &clauses);
+
+        if built {
+            Type::effectful(parameter_types, handled, setup)
+        } else {
+            handled
+        }
+    }
+
+    /// Checks the clause of `operation` in a handler. Its value is the
+    /// answer of the handler when the clause resumes the computation or the
+    /// operation cannot return; otherwise it is what the operation returns.
+    fn operation_clause(
+        &mut self,
+        operation: &Operation,
+        parameters: &[Field],
+        body: &[Statement],
+        span: Span,
+        clauses: &Clauses,
+    ) {
+        if parameters.len() != operation.parameters.len() {
+            return self.type_error(TypeError::ArityMismatch {
+                expected: operation.parameters.len(),
+                found: parameters.len(),
+                span,
+            });
+        }
+
+        // The clause must work whatever the operation's own type parameters
+        // are chosen to be where it is performed.
+        self.push_generics(operation.generics.clone(), Vec::new());
+        let parameter_types: Vec<Type> = parameters
+            .iter()
+            .zip(&operation.parameters)
+            .map(|(parameter, expected)| {
+                if parameter_annotation(parameter).is_none() {
+                    return expected.clone();
+                }
+                let ty = self.parameter_type(parameter);
+                self.subsume(&ty, expected, parameter.span());
+                ty
+            })
+            .collect();
+        let value = self.substitution.fresh();
+        self.push_scope(Vec::new(), true);
+        self.bind_parameters(parameters, &parameter_types);
+        self.returns.push(value.clone());
+        self.effects.push(clauses.performed.clone());
+        self.resumptions.push(Resumption {
+            value: operation.return_type.clone(),
+            answer: clauses.answer.clone(),
+            performed: clauses.performed.clone(),
+            resumed: false,
+        });
+
+        self.check_tail(body, &value, span.clone());
+
+        let resumption = self.resumptions.pop().expect("pushed above");
+        self.effects.pop();
+        self.returns.pop();
+        self.pop_scope();
+        self.pop_scope();
+
+        if !clauses.multishot {
+            if let Some((first, second)) = linearity::resumed_twice(body) {
+                self.effect_error(EffectError::ResumedTwice {
+                    operation: operation.name.clone(),
+                    first,
+                    span: second,
+                });
+            }
+        }
+
+        if resumption.resumed || operation.return_type == Type::Never {
+            self.subtype(clauses.answer, &value, span);
+        } else {
+            self.subtype(&operation.return_type, &value, span);
+        }
+    }
+
+    /// Checks the return clause of `handler`, which turns the `input` given
+    /// by the computation handled into the answer. Without one, the answer
+    /// is that input.
+    fn return_clause(&mut self, handler: &Handler, input: &Type, clauses: &Clauses) {
+        let clause = match &handler.return_clause {
+            Some(clause) => clause,
+            None => {
+                self.unify(clauses.answer, input, handler.span.clone());
+                return;
+            }
+        };
+
+        let ty = match parameter_annotation(&clause.parameter) {
+            Some(_) => {
+                let ty = self.parameter_type(&clause.parameter);
+                self.subsume(&ty, input, clause.parameter.span());
+                ty
+            }
+            None => input.clone(),
+        };
+        self.push_scope(Vec::new(), true);
+        self.bind_parameters(std::slice::from_ref(&clause.parameter), &[ty]);
+        self.returns.push(clauses.answer.clone());
+        self.effects.push(clauses.performed.clone());
+        self.check_tail(&clause.body, clauses.answer, clause.span.clone());
+        self.effects.pop();
+        self.returns.pop();
+        self.pop_scope();
+    }
+
+    fn parameter_type(&mut self, parameter: &Field) -> Type {
+        match parameter_annotation(parameter) {
+            Some(annotation) => self.resolve_annotation(annotation),
+            None => self.substitution.fresh(),
+        }
+    }
+
+    fn bind_parameters(&mut self, parameters: &[Field], types: &[Type]) {
+        for (parameter, ty) in parameters.iter().zip(types) {
+            if let Field::Named {
+                name,
+                default,
+                span,
+                ..
+            } = parameter
+            {
+                if let Some(default) = default {
+                    let default_type = self.infer(default);
+                    self.unify(ty, &default_type, default.span());
+                }
+
+                self.bind(
+                    name,
+                    Binding {
+                        scheme: Scheme::monomorphic(ty.clone()),
+                        mutable: false,
+                        span: span.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Binds the names in a destructuring target such as `(a, b)` to the
+    /// matching parts of `ty`.
+    fn bind_target(&mut self, target: &Expression, ty: Type) {
+        match target {
+            Expression::Identifier { name, span } => self.bind(
+                name,
+                Binding {
+                    scheme: Scheme::monomorphic(ty),
+                    mutable: false,
+                    span: span.clone(),
+                },
+            ),
+            Expression::Tuple { elements, span } => {
+                let parts: Vec<Type> = elements.iter().map(|_| self.substitution.fresh()).collect();
+                self.unify(&Type::Tuple(parts.clone()), &ty, span.clone());
+                for (element, part) in elements.iter().zip(parts) {
+                    self.bind_target(element, part);
+                }
+            }
+            _ => self.resolve_error(ResolveError::InvalidAssignmentTarget {
+                span: target.span(),
+            }),
+        }
+    }
+
+    // Expressions
+
+    fn infer(&mut self, expression: &Expression) -> Type {
+        match expression {
+            Expression::String { .. } => Type::String,
+            Expression::Template { parts, .. } => {
+                for part in parts {
+                    if let TemplatePart::Expression { expression, .. } = part {
+                        self.infer(expression);
+                    }
+                }
+                Type::String
+            }
+            Expression::Integer { .. } => Type::Integer,
+            Expression::Decimal { .. } => Type::Float,
+            Expression::BigInteger { .. } => Type::BigInteger,
+            Expression::BigDecimal { .. } => Type::BigDecimal,
+            Expression::Boolean { .. } => Type::Boolean,
+            Expression::Symbol { .. } => Type::Symbol,
+            Expression::Array { elements, .. } => {
+                let element_type = self.substitution.fresh();
+                for element in elements {
+                    let ty = self.infer(element);
+                    self.unify(&element_type, &ty, element.span());
+                }
+                Type::Array(Box::new(element_type))
+            }
+            Expression::Tuple { elements, .. } => {
+                Type::Tuple(elements.iter().map(|element| self.infer(element)).collect())
+            }
+            Expression::Record {
+                fields, spreads, ..
+            } => {
+                let record = spreads
+                    .iter()
+                    .fold(Type::Struct(Vec::new()), |record, spread| {
+                        self.spread(record, spread)
+                    });
+                let fields = fields
+                    .iter()
+                    .map(|(key, value)| (record_key_name(key), self.infer(value)))
+                    .collect();
+                Type::extend(fields, record)
+            }
+            Expression::Identifier { name, span } => match (self.narrowed(name), self.lookup(name))
+            {
+                (Some(narrowed), _) => narrowed,
+                (None, Some(binding)) => {
+                    let (scheme, declared) = (binding.scheme.clone(), binding.span.clone());
+                    if *name == "main" {
+                        self.main_references.push((span.clone(), declared));
+                    }
+                    let ty = self.instantiate(&scheme, span.clone());
+                    self.instantiate_type(&ty)
+                }
+                // The operation of a single-operation effect has its name.
+                (None, None) if self.is_effect(name) => self.operation(name, name, span.clone()),
+                (None, None) => {
+                    self.resolve_error(ResolveError::UnboundVariable {
+                        name: name.to_string(),
+                        span: span.clone(),
+                    });
+                    self.substitution.fresh()
+                }
+            },
+            Expression::Member {
+                object,
+                property,
+                span,
+            } => match (self.data_type_name(object), object.as_ref()) {
+                (Some(owner), _) => self.constructor(owner, property, span.clone()),
+                (None, Expression::Identifier { name, .. })
+                    if self.lookup(name).is_none() && self.is_effect(name) =>
+                {
+                    self.operation(name, property, span.clone())
+                }
+                (None, Expression::Identifier { name, .. }) if self.lookup(name).is_none() => {
+                    match self.effect_alias(name) {
+                        Some((effect, arguments)) => {
+                            self.aliased_operation(&effect, &arguments, property, span.clone())
+                        }
+                        None => {
+                            let object_type = self.infer(object);
+                            self.member(&object_type, property, span.clone())
+                        }
+                    }
+                }
+                (None, _) => {
+                    let object_type = self.infer(object);
+                    self.member(&object_type, property, span.clone())
+                }
+            },
+            Expression::Index {
+                object,
+                index,
+                span,
+            } => self.index(object, index, span.clone()),
+            Expression::Binary {
+                left, op, right, ..
+            } => self.binary(left, op, right),
+            Expression::Unary { op, expr, .. } => self.unary(op, expr),
+            Expression::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.check_condition(condition);
+                let then_type =
+                    self.narrowed_branch(condition, true, |checker| checker.infer(then_branch));
+                let else_type =
+                    self.narrowed_branch(condition, false, |checker| checker.infer(else_branch));
+                // A branch that cannot finish fits whatever the other gives.
+                match self.substitution.resolve(&then_type) {
+                    Type::Never => else_type,
+                    _ => {
+                        if self.substitution.resolve(&else_type) != Type::Never {
+                            self.unify(&then_type, &else_type, else_branch.span());
+                        }
+                        then_type
+                    }
+                }
+            }
+            Expression::Assignment {
+                target,
+                op,
+                value,
+                span,
+            } => {
+                let value_type = match op {
+                    Some(_) => self.infer(value),
+                    None => self.assigned_type(target, value),
+                };
+                match op {
+                    Some(op) => {
+                        let target_type = self.place(target);
+                        let result = self.binary_types(op, &target_type, &value_type, span.clone());
+                        self.unify(&target_type, &result, span.clone());
+                    }
+                    None => {
+                        let value_like = is_value(value) || self.is_handler(&value_type);
+                        self.assign(target, value_type.clone(), value_like, value.span())
+                    }
+                }
+                if let Expression::Identifier { name, .. } = &**target {
+                    self.widen(name);
+                }
+                value_type
+            }
+            Expression::Lambda {
+                parameters, body, ..
+            } => {
+                let parameter_types: Vec<Type> = parameters
+                    .iter()
+                    .map(|parameter| self.parameter_type(parameter))
+                    .collect();
+                let return_type = self.substitution.fresh();
+                let effects = self.substitution.fresh();
+
+                self.push_scope(Vec::new(), true);
+                self.bind_parameters(parameters, &parameter_types);
+                self.returns.push(return_type.clone());
+                self.effects.push(effects.clone());
+
+                let value = self.infer(body);
+                self.unify(&return_type, &value, body.span());
+
+                self.effects.pop();
+                self.returns.pop();
+                self.pop_scope();
+
+                Type::effectful(parameter_types, return_type, effects)
+            }
+            Expression::Block { statements, .. } => self.check_body(statements),
+            Expression::FunctionCall {
+                function,
+                type_arguments,
+                arguments,
+                span,
+            } => self.call(function, type_arguments, arguments, span.clone()),
+            Expression::Match {
+                scrutinee,
+                arms,
+                span,
+            } => self.match_expression(scrutinee, arms, None, span.clone()),
+            // Inside, the effect is handled; whatever else is performed
+            // there is performed by the `handle` itself.
+            Expression::Handle {
+                effect,
+                expression,
+                span,
+            } => {
+                let handled = self.handled_effect(effect).into_iter().collect();
+                let rest = self.substitution.fresh();
+                self.effects
+                    .push(Type::effects(handled, Some(rest.clone())));
+                let ty = self.infer(expression);
+                self.effects.pop();
+                self.perform(&rest, span.clone());
+                ty
+            }
+            // Inside, the effects of the handler are handled; the rest, and
+            // whatever its clauses perform, are performed by the `with`.
+            Expression::With {
+                expression,
+                handler,
+                span,
+            } => {
+                let handler_type = self.handler_operand(handler);
+                let [handled, value, answer, performed] =
+                    self.handler_parts(&handler_type, handler.span());
+                let (handled, _) = self.substitution.row(&handled);
+                let rest = self.substitution.fresh();
+                self.effects
+                    .push(Type::effects(handled, Some(rest.clone())));
+                let ty = self.infer(expression);
+                self.effects.pop();
+                self.subtype(&value, &ty, expression.span());
+                self.perform(&rest, span.clone());
+                self.perform(&performed, span.clone());
+                answer
+            }
+            Expression::Handler(handler) => match self.impls.last().cloned() {
+                Some(effect) => self.check_handler(handler, &effect, false),
+                None => {
+                    self.resolve_error(ResolveError::HandlerOutsideImpl {
+                        span: handler.span.clone(),
+                    });
+                    self.substitution.fresh()
+                }
+            },
+            Expression::Resume { expression, span } => match self.resumptions.len() {
+                0 => {
+                    self.resolve_error(ResolveError::ResumeOutsideHandler { span: span.clone() });
+                    self.infer(expression);
+                    self.substitution.fresh()
+                }
+                count => {
+                    let value = self.resumptions[count - 1].value.clone();
+                    self.check_expression(expression, &value);
+                    self.resumptions[count - 1].resumed = true;
+                    self.resumptions[count - 1].answer.clone()
+                }
+            },
+            // Calling the continuation resumes the computation, which can
+            // happen after the clause has finished.
+            Expression::Continuation { span } => match self.resumptions.last_mut() {
+                None => {
+                    self.resolve_error(ResolveError::ResumeOutsideHandler { span: span.clone() });
+                    self.substitution.fresh()
+                }
+                Some(resumption) => {
+                    resumption.resumed = true;
+                    Type::effectful(
+                        vec![resumption.value.clone()],
+                        resumption.answer.clone(),
+                        resumption.performed.clone(),
+                    )
+                }
+            },
+            // Only the operands are checked for now.
+            Expression::Yield { expression, .. } | Expression::Perform { expression, .. } => {
+                self.infer(expression);
+                self.substitution.fresh()
+            }
+            Expression::Error { .. } => self.substitution.fresh(),
+        }
+    }
+
+    /// Infers an operand of `with` or `++`, where an effect's name stands
+    /// for its default handler unless a value has that name.
+    fn handler_operand(&mut self, expression: &Expression) -> Type {
+        if let Expression::Identifier { name, span } = expression {
+            if self.narrowed(name).is_none() && self.lookup(name).is_none() && self.is_effect(name)
+            {
+                return match self.default_handlers.get(*name).cloned() {
+                    Some(binding) => {
+                        let ty = self.instantiate(&binding.scheme, span.clone());
+                        self.instantiate_type(&ty)
+                    }
+                    None => {
+                        self.resolve_error(ResolveError::NoDefaultHandler {
+                            effect: name.to_string(),
+                            span: span.clone(),
+                        });
+                        self.substitution.fresh()
+                    }
+                };
+            }
+        }
+        let ty = self.infer(expression);
+        self.instantiate_type(&ty)
+    }
+
+    /// Whether `ty` is a handler. A binding of one is generalized even when
+    /// it is not initialized by a value, so that each `with` or `++` using
+    /// it can pick the types of the computation it handles, as a handler
+    /// written out there could.
+    fn is_handler(&self, ty: &Type) -> bool {
+        matches!(self.substitution.resolve(ty), Type::HKT(name, _) if name == "Handler")
+    }
+
+    /// The arguments of the handler type `handler`: the effects it handles,
+    /// the value of the computation it handles, its answer and the effects
+    /// its clauses perform.
+    fn handler_parts(&mut self, handler: &Type, span: Span) -> [Type; 4] {
+        let parts = [(); 4].map(|_| self.substitution.fresh());
+        self.unify(
+            &Type::HKT("Handler".to_string(), parts.to_vec()),
+            handler,
+            span,
+        );
+        parts.map(|part| self.substitution.resolve(&part))
+    }
+
+    /// `left ++ right` handles a computation with `left` inside `right`:
+    /// `right` handles the answer of `left` and what its clauses perform.
+    /// Operations reach `left` first, so where both handle an effect,
+    /// `left` wins and the handler of `right` is reported as shadowed.
+    fn merge_handlers(&mut self, left: (&Type, Span), right: (&Type, Span), span: Span) -> Type {
+        let [inner, value, inner_answer, inner_performed] =
+            self.handler_parts(left.0, left.1.clone());
+        let [outer, outer_value, answer, outer_performed] =
+            self.handler_parts(right.0, right.1.clone());
+        let (inner, _) = self.substitution.row(&inner);
+        let (outer, _) = self.substitution.row(&outer);
+
+        for effect in &outer {
+            let name = effect.effect_name();
+            if inner.iter().any(|other| other.effect_name() == name) {
+                self.warnings.push(CompileWarning::ShadowedHandler {
+                    source: self.source.clone(),
+                    effect: name,
+                    shadowed_by: left.1.clone(),
+                    span: right.1.clone(),
+                });
+            }
+        }
+
+        self.subtype(&outer_value, &inner_answer, left.1.clone());
+        let performed = self.substitution.fresh();
+        let result = self.substitution.include(
+            &inner_performed,
+            &Type::effects(outer.clone(), Some(performed.clone())),
+        );
+        self.report(result, left.1);
+        let result = self.substitution.include(&outer_performed, &performed);
+        self.report(result, span);
+
+        Type::HKT(
+            "Handler".to_string(),
+            vec![
+                Type::effects(inner.into_iter().chain(outer).collect(), None),
+                value,
+                answer,
+                performed,
+            ],
+        )
+    }
+
+    /// The effect `handle` names, with fresh variables for its parameters.
+    fn handled_effect(&mut self, effect: &Expression) -> Option<Type> {
+        let (name, span) = match effect {
+            Expression::Identifier { name, span } => (*name, span.clone()),
+            effect => {
+                self.infer(effect);
+                return None;
+            }
+        };
+        match self.types.get_type(name) {
+            Some(TypeDefinition::Effect { parameters, .. }) => {
+                let count = parameters.len();
+                let arguments = (0..count).map(|_| self.substitution.fresh()).collect();
+                Some(Type::HKT(name.to_string(), arguments))
+            }
+            Some(_) => {
+                self.type_error(TypeError::NotAnEffect {
+                    ty: name.to_string(),
+                    span,
+                });
+                None
+            }
+            None => {
+                self.resolve_error(ResolveError::UnboundType {
+                    name: name.to_string(),
+                    span,
+                });
+                None
+            }
+        }
+    }
+
+    /// Checks `expression` against a type known from its context. Unlike
+    /// inferring a type and comparing, this lets lambdas take polymorphic
+    /// parameters and be checked against polymorphic types.
+    fn check_expression(&mut self, expression: &Expression, expected: &Type) {
+        let expected = self.substitution.shallow_resolve(expected);
+        match (expression, &expected) {
+            (_, Type::Forall(..)) => {
+                self.skolemized(&expected, &[], expression.span(), |checker, expected| {
+                    let errors = checker.errors.len();
+                    checker.check_expression(expression, expected);
+                    checker.errors.len() == errors
+                });
+            }
+            (
+                Expression::Lambda {
+                    parameters, body, ..
+                },
+                Type::Function(expected_parameters, expected_return, expected_effects),
+            ) if parameters.len() == expected_parameters.len() => {
+                let errors = self.errors.len();
+                let parameter_types: Vec<Type> = parameters
+                    .iter()
+                    .zip(expected_parameters)
+                    .map(|(parameter, expected)| {
+                        if parameter_annotation(parameter).is_none() {
+                            return expected.clone();
+                        }
+                        // An annotated parameter must accept whatever the
+                        // context passes to it.
+                        let ty = self.parameter_type(parameter);
+                        self.subsume(&ty, expected, parameter.span());
+                        ty
+                    })
+                    .collect();
+
+                self.push_scope(Vec::new(), true);
+                self.bind_parameters(parameters, &parameter_types);
+                self.returns.push((**expected_return).clone());
+                self.effects.push((**expected_effects).clone());
+                // Once a parameter is wrong the body would only repeat it.
+                if self.errors.len() == errors {
+                    self.check_expression(body, expected_return);
+                } else {
+                    self.infer(body);
+                }
+                self.effects.pop();
+                self.returns.pop();
+                self.pop_scope();
+            }
+            (
+                Expression::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                    ..
+                },
+                _,
+            ) => {
+                self.check_condition(condition);
+                self.narrowed_branch(condition, true, |checker| {
+                    checker.check_expression(then_branch, &expected)
+                });
+                self.narrowed_branch(condition, false, |checker| {
+                    checker.check_expression(else_branch, &expected)
+                });
+            }
+            (Expression::Block { statements, span }, _) => {
+                self.push_scope(Vec::new(), false);
+                self.check_tail(statements, &expected, span.clone());
+                self.pop_scope();
+            }
+            (
+                Expression::Match {
+                    scrutinee,
+                    arms,
+                    span,
+                },
+                _,
+            ) => {
+                self.match_expression(scrutinee, arms, Some(&expected), span.clone());
+            }
+            // Parts are checked against their own types, so that they can be
+            // polymorphic or literal.
+            (Expression::Array { elements, .. }, Type::Array(element)) => {
+                for value in elements {
+                    self.check_expression(value, element);
+                }
+            }
+            (Expression::Tuple { elements, .. }, Type::Tuple(expected_elements))
+                if elements.len() == expected_elements.len() =>
+            {
+                for (element, expected) in elements.iter().zip(expected_elements) {
+                    self.check_expression(element, expected);
+                }
+            }
+            (
+                Expression::Record {
+                    fields, spreads, ..
+                },
+                Type::Struct(expected_fields),
+            ) if spreads.is_empty() && fields.len() == expected_fields.len() => {
+                let parts: Option<Vec<(&Expression, &Type)>> = fields
+                    .iter()
+                    .map(|(key, value)| {
+                        let name = record_key_name(key);
+                        expected_fields
+                            .iter()
+                            .find(|(field, _)| *field == name)
+                            .map(|(_, ty)| (value, ty))
+                    })
+                    .collect();
+                match parts {
+                    // In source order, so that errors are too.
+                    Some(mut parts) => {
+                        parts.sort_by_key(|(value, _)| value.span().start);
+                        for (value, expected) in parts {
+                            self.check_expression(value, expected);
+                        }
+                    }
+                    None => {
+                        let found = self.infer(expression);
+                        self.subsume(
+                            &Type::Struct(expected_fields.clone()),
+                            &found,
+                            expression.span(),
+                        );
+                    }
+                }
+            }
+            _ => {
+                // A literal is only given its own type where one is wanted,
+                // so that it is otherwise inferred as its primitive.
+                let found = match literal_type(expression) {
+                    Some(literal) if has_literal(&self.substitution.resolve(&expected)) => literal,
+                    _ => self.infer(expression),
+                };
+                // An expression that cannot finish fits anything, and says
+                // nothing about what is expected.
+                if self.substitution.resolve(&found) != Type::Never {
+                    self.subsume(&expected, &found, expression.span());
+                }
+            }
+        }
+    }
+
+    /// Runs `check` in a scope narrowed by `condition` evaluating to `holds`.
+    fn narrowed_branch<T>(
+        &mut self,
+        condition: &Expression,
+        holds: bool,
+        check: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let narrowings = self.narrowings(condition, holds);
+        self.push_narrowed(narrowings);
+        let result = check(self);
+        self.pop_scope();
+        result
+    }
+
+    /// Every arm must produce the same type, or the `expected` one. A
+    /// `match` without arms never produces a value.
+    ///
+    /// When the scrutinee is a union, each arm only sees the members its
+    /// pattern may match that no earlier arm matched entirely, and so does
+    /// the scrutinee itself in the arm if it is a name.
+    fn match_expression(
+        &mut self,
+        scrutinee: &Expression,
+        arms: &[MatchArm],
+        expected: Option<&Type>,
+        span: Span,
+    ) -> Type {
+        let errors = self.errors.len();
+        let scrutinee_type = self.infer(scrutinee);
+        let result = match expected {
+            Some(expected) => expected.clone(),
+            None if arms.is_empty() => Type::Never,
+            None => self.substitution.fresh(),
+        };
+
+        let mut remaining = self.substitution.resolve(&scrutinee_type);
+        let union = matches!(remaining, Type::Union(_));
+        for arm in arms {
+            let mut narrowings = Vec::new();
+            let arm_type = if union {
+                let members = remaining
+                    .members()
+                    .into_iter()
+                    .filter(|member| may_match(&arm.pattern, member))
+                    .collect();
+                match Type::union(members) {
+                    // An arm that cannot match is checked against the whole
+                    // union, and reported as unreachable.
+                    Type::Never => scrutinee_type.clone(),
+                    narrowed => narrowed,
+                }
+            } else {
+                scrutinee_type.clone()
+            };
+            if let (true, Expression::Identifier { name, .. }) = (union, scrutinee) {
+                narrowings.push((name.to_string(), arm_type.clone()));
+            }
+
+            self.push_narrowed(narrowings);
+            self.check_pattern(&arm.pattern, &arm_type, &mut HashMap::new());
+            if let Some(guard) = &arm.guard {
+                let guard_type = self.infer(guard);
+                self.unify(&Type::Boolean, &guard_type, guard.span());
+            }
+            match expected {
+                Some(expected) => self.check_expression(&arm.body, expected),
+                // An arm that cannot finish fits whatever the others give.
+                None => {
+                    let body = self.infer(&arm.body);
+                    if self.substitution.resolve(&body) != Type::Never {
+                        self.unify(&result, &body, arm.body.span());
+                    }
+                }
+            }
+            self.pop_scope();
+
+            if union && arm.guard.is_none() {
+                let members = remaining
+                    .members()
+                    .into_iter()
+                    .filter(|member| !covers(&arm.pattern, member))
+                    .collect();
+                remaining = Type::union(members);
+            }
+        }
+
+        // Coverage is only meaningful for patterns that fit the scrutinee.
+        if self.errors.len() == errors {
+            self.check_coverage(scrutinee, &scrutinee_type, arms, span);
+        }
+
+        result
+    }
+
+    /// Reports missing and unreachable arms, and compiles an exhaustive
+    /// match to a decision tree.
+    fn check_coverage(
+        &mut self,
+        scrutinee: &Expression,
+        scrutinee_type: &Type,
+        arms: &[MatchArm],
+        span: Span,
+    ) {
+        let ty = self.substitution.resolve(scrutinee_type);
+        let lowered: Vec<Arm> = arms
+            .iter()
+            .map(|arm| Arm {
+                pattern: exhaustiveness::lower(&self.types, &arm.pattern, &ty),
+                guarded: arm.guard.is_some(),
+            })
+            .collect();
+        let coverage = exhaustiveness::check(&self.types, &ty, &lowered);
+
+        for index in coverage.unreachable {
+            self.warnings.push(CompileWarning::UnreachablePattern {
+                source: self.source.clone(),
+                span: arms[index].pattern.span(),
+            });
+        }
+        if !coverage.missing.is_empty() {
+            self.type_error(TypeError::NonExhaustiveMatch {
+                missing: coverage.missing.iter().map(ToString::to_string).collect(),
+                span: scrutinee.span(),
+            });
+        } else {
+            self.decisions
+                .push((span, decision::compile(&self.types, &ty, arms)));
+        }
+    }
+
+    /// Checks that `pattern` can match a value of type `expected` and binds
+    /// the names it introduces in the current scope. `bound` holds the names
+    /// bound so far by the whole pattern, which must be distinct.
+    fn check_pattern<'a>(
+        &mut self,
+        pattern: &Pattern<'a>,
+        expected: &Type,
+        bound: &mut HashMap<&'a str, Span>,
+    ) {
+        match pattern {
+            Pattern::Wildcard { .. } => {}
+            Pattern::Binding { name, span } => {
+                if let Some(previous) = bound.insert(name, span.clone()) {
+                    self.resolve_error(ResolveError::DuplicateDefinition {
+                        name: name.to_string(),
+                        previous,
+                        span: span.clone(),
+                    });
+                    return;
+                }
+                self.bind(
+                    name,
+                    Binding {
+                        scheme: Scheme::monomorphic(expected.clone()),
+                        mutable: false,
+                        span: span.clone(),
+                    },
+                );
+            }
+            // A literal picks out members of a union.
+            Pattern::Literal { value, span }
+                if matches!(
+                    self.substitution.resolve(expected),
+                    Type::Union(_) | Type::Literal(..)
+                ) =>
+            {
+                self.subtype(expected, &Type::literal(value), span.clone());
+            }
+            Pattern::Literal { value, span } => {
+                let ty = match value {
+                    Literal::Integer(_) => Type::Integer,
+                    Literal::Decimal(_) => Type::Float,
+                    Literal::BigInteger(_) => Type::BigInteger,
+                    Literal::BigDecimal(_) => Type::BigDecimal,
+                    Literal::Boolean(_) => Type::Boolean,
+                    Literal::String(_) => Type::String,
+                    Literal::Symbol(_) => Type::Symbol,
+                };
+                self.unify(expected, &ty, span.clone());
+            }
+            Pattern::Tuple { elements, span } => {
+                let types: Vec<Type> = elements.iter().map(|_| self.substitution.fresh()).collect();
+                self.unify(expected, &Type::Tuple(types.clone()), span.clone());
+                for (element, ty) in elements.iter().zip(&types) {
+                    self.check_pattern(element, ty, bound);
+                }
+            }
+            Pattern::Array {
+                elements,
+                rest,
+                span,
+            } => {
+                let element_type = self.substitution.fresh();
+                let array = Type::Array(Box::new(element_type.clone()));
+                self.unify(expected, &array, span.clone());
+                for element in elements {
+                    self.check_pattern(element, &element_type, bound);
+                }
+                if let Some(rest) = rest {
+                    self.check_pattern(rest, &array, bound);
+                }
+            }
+            Pattern::Record { fields, rest, span } => {
+                // A record whose type is not yet known has at least the
+                // fields named.
+                let record = match self.substitution.resolve(expected) {
+                    record @ (Type::Struct(_) | Type::Extend(..) | Type::Union(_)) => record,
+                    _ => {
+                        let record = Type::extend(
+                            fields
+                                .iter()
+                                .map(|(name, _)| (name.to_string(), self.substitution.fresh()))
+                                .collect(),
+                            self.substitution.fresh(),
+                        );
+                        self.unify(expected, &record, span.clone());
+                        record
+                    }
+                };
+                for (name, field) in fields {
+                    let ty = self.member(&record, name, field.span());
+                    self.check_pattern(field, &ty, bound);
+                }
+                if let Some(rest) = rest {
+                    let names: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+                    let remaining = restrict(&self.substitution.resolve(&record), &names);
+                    self.check_pattern(rest, &remaining, bound);
+                }
+            }
+            Pattern::Constructor {
+                owner,
+                name,
+                arguments,
+                span,
+            } => {
+                // A constant is the data type itself; anything else is a
+                // function from its fields to it.
+                let (parameters, matches) =
+                    match self.constructor_owner(*owner, name, expected, span) {
+                        Some(owner) => match self.constructor(&owner, name, span.clone()) {
+                            Type::Function(parameters, result, _) => {
+                                let matches = self.unify(expected, &result, span.clone());
+                                (parameters, matches)
+                            }
+                            result => (Vec::new(), self.unify(expected, &result, span.clone())),
+                        },
+                        None => (Vec::new(), false),
+                    };
+                if matches && parameters.len() != arguments.len() {
+                    self.type_error(TypeError::ArityMismatch {
+                        expected: parameters.len(),
+                        found: arguments.len(),
+                        span: span.clone(),
+                    });
+                }
+
+                // The arguments are still checked when the constructor is
+                // wrong, so that their bindings exist for the arm's body.
+                for (index, argument) in arguments.iter().enumerate() {
+                    let ty = match parameters.get(index) {
+                        Some(parameter) if matches => parameter.clone(),
+                        _ => self.substitution.fresh(),
+                    };
+                    self.check_pattern(argument, &ty, bound);
+                }
+            }
+        }
+    }
+
+    /// Works out which data type a constructor pattern refers to: the one it
+    /// is qualified with, else the type being matched, else the only type
+    /// with a constructor of that name.
+    fn constructor_owner(
+        &mut self,
+        owner: Option<&str>,
+        name: &str,
+        expected: &Type,
+        span: &Span,
+    ) -> Option<String> {
+        if let Some(owner) = owner {
+            return match self.types.get_type(owner) {
+                Some(TypeDefinition::Data { .. }) => Some(owner.to_string()),
+                _ => {
+                    self.resolve_error(ResolveError::UnboundType {
+                        name: owner.to_string(),
+                        span: span.clone(),
+                    });
+                    None
+                }
+            };
+        }
+
+        if let Type::HKT(expected, _) = self.substitution.resolve(expected) {
+            if self
+                .types
+                .constructor_owners(name)
+                .contains(&expected.as_str())
+            {
+                return Some(expected);
+            }
+        }
+
+        let owners: Vec<String> = self
+            .types
+            .constructor_owners(name)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        match &owners[..] {
+            [owner] => Some(owner.clone()),
+            [] => {
+                self.resolve_error(ResolveError::UnboundConstructor {
+                    name: name.to_string(),
+                    span: span.clone(),
+                });
+                None
+            }
+            _ => {
+                self.resolve_error(ResolveError::AmbiguousConstructor {
+                    name: name.to_string(),
+                    owners,
+                    span: span.clone(),
+                });
+                None
+            }
+        }
+    }
+
+    /// The name of the data type that `object` refers to, when it names one
+    /// and is not shadowed by a value, as in `Either.Left`.
+    fn data_type_name<'a>(&self, object: &Expression<'a>) -> Option<&'a str> {
+        match object {
+            Expression::Identifier { name, .. }
+                if self.lookup(name).is_none()
+                    && matches!(self.types.get_type(name), Some(TypeDefinition::Data { .. })) =>
+            {
+                Some(*name)
+            }
+            _ => None,
+        }
+    }
+
+    fn constructor(&mut self, owner: &str, name: &str, span: Span) -> Type {
+        let scheme = match self.types.get_type(owner) {
+            Some(TypeDefinition::Data {
+                parameters,
+                constructors,
+            }) => constructors
+                .iter()
+                .find(|constructor| constructor.name == name)
+                .map(|constructor| constructor.scheme(owner, parameters)),
+            _ => None,
+        };
+
+        match scheme {
+            Some(scheme) => self.instantiate(&scheme, span),
+            None => {
+                self.resolve_error(ResolveError::UnknownConstructor {
+                    ty: owner.to_string(),
+                    name: name.to_string(),
+                    span,
+                });
+                self.substitution.fresh()
+            }
+        }
+    }
+
+    fn operation_scheme(&self, owner: &str, name: &str) -> Option<Scheme> {
+        match self.types.get_type(owner) {
+            Some(TypeDefinition::Effect {
+                parameters,
+                operations,
+            }) => operations
+                .iter()
+                .find(|operation| operation.name == name)
+                .map(|operation| operation.scheme(owner, parameters)),
+            _ => None,
+        }
+    }
+
+    fn is_effect(&self, name: &str) -> bool {
+        matches!(
+            self.types.get_type(name),
+            Some(TypeDefinition::Effect { .. })
+        )
+    }
+
+    /// The effect an alias such as `alias IntQueue = Queue<Int>` names, with
+    /// its arguments.
+    fn effect_alias(&self, name: &str) -> Option<(String, Vec<Type>)> {
+        match self.types.get_type(name) {
+            Some(TypeDefinition::Alias { parameters, ty }) if parameters.is_empty() => match ty {
+                Type::HKT(effect, arguments) if self.is_effect(effect) => {
+                    Some((effect.clone(), arguments.clone()))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Like `operation`, for an effect applied to `arguments` through an
+    /// alias, as in `IntQueue.dequeue`.
+    fn aliased_operation(
+        &mut self,
+        effect: &str,
+        arguments: &[Type],
+        name: &str,
+        span: Span,
+    ) -> Type {
+        let parameters = match self.types.get_type(effect) {
+            Some(TypeDefinition::Effect { parameters, .. }) => parameters.clone(),
+            _ => Vec::new(),
+        };
+        match self.operation_scheme(effect, name) {
+            Some(scheme) if parameters.len() == arguments.len() => {
+                let scheme = Scheme {
+                    generics: scheme.generics[parameters.len()..].to_vec(),
+                    ty: scheme.ty.substitute(&parameters, arguments),
+                    bounds: scheme.bounds,
+                };
+                self.instantiate(&scheme, span)
+            }
+            _ => self.operation(effect, name, span),
+        }
+    }
+
+    /// The type of the operation or member `name` of the effect `owner`, as
+    /// in `State.get`, `Fail` or `State.local`.
+    fn operation(&mut self, owner: &str, name: &str, span: Span) -> Type {
+        let scheme = self.operation_scheme(owner, name).or_else(|| {
+            self.members
+                .get(owner)
+                .and_then(|members| members.get(name))
+                .map(|member| member.scheme.clone())
+        });
+
+        match scheme {
+            Some(scheme) => self.instantiate(&scheme, span),
+            None => {
+                self.resolve_error(ResolveError::UnknownOperation {
+                    effect: owner.to_string(),
+                    name: name.to_string(),
+                    span,
+                });
+                self.substitution.fresh()
+            }
+        }
+    }
+
+    fn member(&mut self, object: &Type, property: &str, span: Span) -> Type {
+        let object = match self.substitution.resolve(object) {
+            // A parameter has the fields of its upper bound.
+            Type::Generic(name) => match self.bound(&name) {
+                Some(Bound::Upper(bound)) => bound,
+                _ => Type::Generic(name),
+            },
+            object => object,
+        };
+        match self.substitution.resolve(&object) {
+            Type::Struct(fields) => match fields.into_iter().find(|(name, _)| name == property) {
+                Some((_, ty)) => ty,
+                None => {
+                    self.type_error(TypeError::UnknownField {
+                        field: property.to_string(),
+                        ty: self.substitution.resolve(&object).to_string(),
+                        span,
+                    });
+                    self.substitution.fresh()
+                }
+            },
+            // A field that every member of a union of records has is of the
+            // union of its types.
+            Type::Union(members) => {
+                let types: Option<Vec<Type>> = members
+                    .iter()
+                    .map(|member| match member {
+                        Type::Struct(fields) | Type::Extend(fields, _) => fields
+                            .iter()
+                            .find(|(name, _)| name == property)
+                            .map(|(_, ty)| ty.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                match types {
+                    Some(types) => Type::union(types),
+                    None => {
+                        self.type_error(TypeError::UnknownField {
+                            field: property.to_string(),
+                            ty: Type::Union(members).to_string(),
+                            span,
+                        });
+                        self.substitution.fresh()
+                    }
+                }
+            }
+            // A field of any member of an intersection is a field of it.
+            Type::Intersection(members) => {
+                let types: Vec<Type> = members
+                    .iter()
+                    .filter_map(|member| match member {
+                        Type::Struct(fields) | Type::Extend(fields, _) => fields
+                            .iter()
+                            .find(|(name, _)| name == property)
+                            .map(|(_, ty)| ty.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                if types.is_empty() {
+                    self.type_error(TypeError::UnknownField {
+                        field: property.to_string(),
+                        ty: Type::Intersection(members).to_string(),
+                        span,
+                    });
+                    return self.substitution.fresh();
+                }
+                Type::intersection(types)
+            }
+            // An unknown type must be a record with at least this field, as
+            // must the unknown rest of an open record without it.
+            Type::Var(_) => self.require_field(&object, property, span),
+            Type::Extend(fields, row) => {
+                match fields.into_iter().find(|(name, _)| name == property) {
+                    Some((_, ty)) => ty,
+                    None if matches!(*row, Type::Var(_)) => {
+                        self.require_field(&row, property, span)
+                    }
+                    None => {
+                        self.type_error(TypeError::UnknownField {
+                            field: property.to_string(),
+                            ty: self.substitution.resolve(&object).to_string(),
+                            span,
+                        });
+                        self.substitution.fresh()
+                    }
+                }
+            }
+            Type::Never => self.substitution.fresh(),
+            // Any function of no arguments can be forked into a fiber, which
+            // runs with the handlers of the computation that forks it.
+            Type::Function(parameters, result, effects)
+                if parameters.is_empty() && property == "fork" =>
+            {
+                Type::effectful(
+                    Vec::new(),
+                    Type::HKT("Fiber".to_string(), vec![*result]),
+                    *effects,
+                )
+            }
+            Type::HKT(name, mut arguments)
+                if name == "Fiber" && arguments.len() == 1 && property == "join" =>
+            {
+                Type::function(Vec::new(), arguments.remove(0))
+            }
+
+            other => {
+                self.type_error(TypeError::UnknownField {
+                    field: property.to_string(),
+                    ty: other.to_string(),
+                    span,
+                });
+                self.substitution.fresh()
+            }
+        }
+    }
+
+    // Makes `record` a record with at least the field `name`, returning its
+    // type.
+    fn require_field(&mut self, record: &Type, name: &str, span: Span) -> Type {
+        let ty = self.substitution.fresh();
+        let row = self.substitution.fresh();
+        self.unify(
+            record,
+            &Type::Extend(vec![(name.to_string(), ty.clone())], Box::new(row)),
+            span,
+        );
+        ty
+    }
+
+    // The record `record` with the fields of `spread` added, overriding any
+    // of the same names.
+    fn spread(&mut self, record: Type, spread: &Expression) -> Type {
+        let ty = self.infer(spread);
+        match self.substitution.resolve(&ty) {
+            Type::Struct(fields) => Type::extend(fields, record),
+            // Fields that are not known could override any before them, so
+            // such a record can only be spread first.
+            ty @ (Type::Extend(..) | Type::Var(_)) if record == Type::Struct(Vec::new()) => ty,
+            Type::Never => record,
+            ty => {
+                self.type_error(TypeError::InvalidSpread {
+                    ty: ty.to_string(),
+                    span: spread.span(),
+                });
+                record
+            }
+        }
+    }
+
+    fn index(&mut self, object: &Expression, index: &Expression, span: Span) -> Type {
+        let object_type = self.infer(object);
+        let index_type = self.infer(index);
+
+        match self.substitution.resolve(&object_type) {
+            Type::Tuple(elements) => {
+                self.unify(&Type::Integer, &index_type, index.span());
+                match index {
+                    Expression::Integer { value, .. } => {
+                        match usize::try_from(*value).ok().and_then(|i| elements.get(i)) {
+                            Some(element) => element.clone(),
+                            None => {
+                                self.type_error(TypeError::UnknownField {
+                                    field: value.to_string(),
+                                    ty: Type::Tuple(elements).to_string(),
+                                    span,
+                                });
+                                self.substitution.fresh()
+                            }
+                        }
+                    }
+                    _ => {
+                        self.type_error(TypeError::UnsupportedOperator {
+                            operator: "[]".to_string(),
+                            ty: Type::Tuple(elements).to_string(),
+                            span,
+                        });
+                        self.substitution.fresh()
+                    }
+                }
+            }
+            Type::String => {
+                self.unify(&Type::Integer, &index_type, index.span());
+                Type::String
+            }
+            Type::Array(_) | Type::Var(_) | Type::Never => {
+                let element = self.substitution.fresh();
+                self.unify(
+                    &Type::Array(Box::new(element.clone())),
+                    &object_type,
+                    object.span(),
+                );
+                self.unify(&Type::Integer, &index_type, index.span());
+                element
+            }
+            other => {
+                self.type_error(TypeError::UnsupportedOperator {
+                    operator: "[]".to_string(),
+                    ty: other.to_string(),
+                    span,
+                });
+                self.substitution.fresh()
+            }
+        }
+    }
+
+    fn binary(&mut self, left: &Expression, op: &BinaryOp, right: &Expression) -> Type {
+        match op {
+            // `x |> f` is `f(x)`.
+            BinaryOp::PipeOperator(span) => {
+                let argument = self.infer(left);
+                let function = self.infer(right);
+                let result = self.substitution.fresh();
+                let effects = self.substitution.fresh();
+                if self.unify(
+                    &Type::effectful(vec![argument], result.clone(), effects.clone()),
+                    &function,
+                    span.clone(),
+                ) {
+                    self.perform(&effects, span.clone());
+                }
+                result
+            }
+            BinaryOp::OptionalChaining(span) => {
+                let object = self.infer(left);
+                match right {
+                    Expression::Identifier { name, .. } => self.member(&object, name, span.clone()),
+                    _ => self.infer(right),
+                }
+            }
+            // `++` merges handlers as well as joining lists and strings.
+            BinaryOp::Concatenation(_) => {
+                let left_type = self.handler_operand(left);
+                let right_type = self.handler_operand(right);
+                let span = left.span().start..right.span().end;
+                if is_handler(&self.substitution.resolve(&left_type))
+                    || is_handler(&self.substitution.resolve(&right_type))
+                {
+                    return self.merge_handlers(
+                        (&left_type, left.span()),
+                        (&right_type, right.span()),
+                        span,
+                    );
+                }
+                self.binary_types(op, &left_type, &right_type, span)
+            }
+            // The right operand is only evaluated when the left one did not
+            // decide the result, and is narrowed by that.
+            BinaryOp::LogicalAnd(_) | BinaryOp::LogicalOr(_) => {
+                let left_type = self.infer(left);
+                let holds = matches!(op, BinaryOp::LogicalAnd(_));
+                let right_type = self.narrowed_branch(left, holds, |checker| checker.infer(right));
+                let span = left.span().start..right.span().end;
+                self.binary_types(op, &left_type, &right_type, span)
+            }
+            // A literal compared with something that has literal types must
+            // be one of them, rather than be widened to its primitive.
+            BinaryOp::Equal(_) | BinaryOp::NotEqual(_)
+                if singleton_type(left).is_some() || singleton_type(right).is_some() =>
+            {
+                let swapped = singleton_type(right).is_none();
+                let (subject, value) = if swapped {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                let subject_type = self.infer(subject);
+                if has_literal(&self.substitution.resolve(&subject_type)) {
+                    self.check_expression(value, &subject_type);
+                    return Type::Boolean;
+                }
+
+                let value_type = self.infer(value);
+                let (left_type, right_type) = if swapped {
+                    (value_type, subject_type)
+                } else {
+                    (subject_type, value_type)
+                };
+                let span = left.span().start..right.span().end;
+                self.binary_types(op, &left_type, &right_type, span)
+            }
+            _ => {
+                let left_type = self.infer(left);
+                let right_type = self.infer(right);
+                let span = left.span().start..right.span().end;
+                self.binary_types(op, &left_type, &right_type, span)
+            }
+        }
+    }
+
+    fn binary_types(&mut self, op: &BinaryOp, left: &Type, right: &Type, span: Span) -> Type {
+        let (symbol, _) = binary_operator(op);
+
+        match op {
+            BinaryOp::LogicalAnd(_) | BinaryOp::LogicalOr(_) => {
+                self.unify(&Type::Boolean, left, span.clone());
+                self.unify(&Type::Boolean, right, span);
+                return Type::Boolean;
+            }
+            BinaryOp::PipeOperator(_) | BinaryOp::OptionalChaining(_) => {
+                return self.substitution.fresh()
+            }
+            // `()` stands for a missing value, which the right operand
+            // replaces. Only a union can hold it: `??` is compiled as a
+            // comparison with `()`, so an `Option` is matched on instead.
+            BinaryOp::NullishCoalescing(_) => match self.substitution.resolve(left) {
+                Type::Union(members) if members.contains(&Type::unit()) => {
+                    let present = members
+                        .into_iter()
+                        .filter(|member| *member != Type::unit())
+                        .chain(std::iter::once(right.clone()))
+                        .collect();
+                    return Type::union(present);
+                }
+                Type::HKT(name, _) if name == "Option" => {
+                    self.type_error(TypeError::UnsupportedOperator {
+                        operator: symbol.to_string(),
+                        ty: self.substitution.resolve(left).to_string(),
+                        span,
+                    });
+                    return right.clone();
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        // A mismatch has already been reported, so the operator is not checked.
+        let unified = self.unify(left, right, span.clone());
+        let operand = self.substitution.resolve(left);
+        let supports: fn(&Type) -> bool = match op {
+            BinaryOp::Addition(_)
+            | BinaryOp::LessThan(_)
+            | BinaryOp::LessThanOrEqual(_)
+            | BinaryOp::GreaterThan(_)
+            | BinaryOp::GreaterThanOrEqual(_) => |ty| ty.is_numeric() || *ty == Type::String,
+            BinaryOp::Concatenation(_) => |ty| matches!(ty, Type::Array(_)) || *ty == Type::String,
+            BinaryOp::Subtraction(_)
+            | BinaryOp::Multiplication(_)
+            | BinaryOp::Division(_)
+            | BinaryOp::Modulus(_)
+            | BinaryOp::Exponentiation(_) => Type::is_numeric,
+            BinaryOp::BitwiseAnd(_)
+            | BinaryOp::BitwiseOr(_)
+            | BinaryOp::BitwiseXor(_)
+            | BinaryOp::LeftShift(_)
+            | BinaryOp::RightShift(_) => Type::is_integral,
+            _ => |_| true,
+        };
+        if unified {
+            self.overload(symbol, &operand, supports, span);
+        }
+
+        match op {
+            BinaryOp::Equal(_)
+            | BinaryOp::NotEqual(_)
+            | BinaryOp::LessThan(_)
+            | BinaryOp::LessThanOrEqual(_)
+            | BinaryOp::GreaterThan(_)
+            | BinaryOp::GreaterThanOrEqual(_) => Type::Boolean,
+            _ => operand,
+        }
+    }
+
+    fn unary(&mut self, op: &UnaryOp, expr: &Expression) -> Type {
+        let (symbol, span) = unary_operator(op);
+        let span = span.start.min(expr.span().start)..span.end.max(expr.span().end);
+
+        let ty = match op {
+            UnaryOp::PreIncrement(_)
+            | UnaryOp::PostIncrement(_)
+            | UnaryOp::PreDecrement(_)
+            | UnaryOp::PostDecrement(_) => self.place(expr),
+            _ => self.infer(expr),
+        };
+
+        let operand = self.substitution.resolve(&ty);
+        let supports: fn(&Type) -> bool = match op {
+            UnaryOp::LogicalNot(_) => {
+                self.unify(&Type::Boolean, &ty, expr.span());
+                return Type::Boolean;
+            }
+            UnaryOp::BitwiseNot(_) => Type::is_integral,
+            _ => Type::is_numeric,
+        };
+        self.overload(symbol, &operand, supports, span);
+
+        ty
+    }
+
+    /// Checks that `operator` is defined for `operand`, or once the program
+    /// has been checked if its type is not known yet.
+    fn overload(
+        &mut self,
+        operator: &'static str,
+        operand: &Type,
+        supports: fn(&Type) -> bool,
+        span: Span,
+    ) {
+        match operand {
+            Type::Var(_) => self.overloads.push(Overload {
+                operator,
+                operand: operand.clone(),
+                supports,
+                span,
+            }),
+            _ if !supports(operand) => self.type_error(TypeError::UnsupportedOperator {
+                operator: operator.to_string(),
+                ty: operand.to_string(),
+                span,
+            }),
+            _ => {}
+        }
+    }
+
+    fn call(
+        &mut self,
+        function: &Expression,
+        type_arguments: &[ast::Type],
+        arguments: &[Expression],
+        span: Span,
+    ) -> Type {
+        let callee = match function {
+            Expression::Identifier { name, span } if !type_arguments.is_empty() => {
+                self.explicit_instantiation(name, type_arguments, span.clone())
+            }
+            _ => self.infer(function),
+        };
+        let callee = self.instantiate_type(&callee);
+
+        // Arguments are checked against known parameter types, so that they
+        // can be polymorphic.
+        if let Type::Function(parameters, return_type, effects) = self.substitution.resolve(&callee)
+        {
+            if parameters.len() == arguments.len() {
+                for (parameter, argument) in parameters.iter().zip(arguments) {
+                    self.check_expression(argument, parameter);
+                }
+                self.perform(&effects, span);
+                return *return_type;
+            }
+        }
+
+        let argument_types: Vec<Type> = arguments
+            .iter()
+            .map(|argument| self.infer(argument))
+            .collect();
+
+        match self.substitution.resolve(&callee) {
+            // Inferring the arguments can tell what the callee is.
+            Type::Function(parameters, return_type, effects)
+                if parameters.len() == arguments.len() =>
+            {
+                for ((parameter, argument), expression) in
+                    parameters.iter().zip(&argument_types).zip(arguments)
+                {
+                    self.subsume(parameter, argument, expression.span());
+                }
+                self.perform(&effects, span);
+                *return_type
+            }
+            Type::Function(parameters, return_type, _) => {
+                self.type_error(TypeError::ArityMismatch {
+                    expected: parameters.len(),
+                    found: argument_types.len(),
+                    span,
+                });
+                *return_type
+            }
+            Type::Var(_) => {
+                let return_type = self.substitution.fresh();
+                let effects = self.substitution.fresh();
+                if self.unify(
+                    &callee,
+                    &Type::effectful(argument_types, return_type.clone(), effects.clone()),
+                    span.clone(),
+                ) {
+                    self.perform(&effects, span);
+                }
+                return_type
+            }
+            Type::Never => Type::Never,
+            other => {
+                self.type_error(TypeError::NotCallable {
+                    found: other.to_string(),
+                    span: function.span(),
+                });
+                self.substitution.fresh()
+            }
+        }
+    }
+
+    fn explicit_instantiation(
+        &mut self,
+        name: &str,
+        type_arguments: &[ast::Type],
+        span: Span,
+    ) -> Type {
+        let scheme = match self.lookup(name) {
+            Some(binding) => binding.scheme.clone(),
+            None => {
+                self.resolve_error(ResolveError::UnboundVariable {
+                    name: name.to_string(),
+                    span,
+                });
+                return self.substitution.fresh();
+            }
+        };
+
+        // Only the type parameters written in the source can be given.
+        let explicit = scheme
+            .generics
+            .iter()
+            .filter(|generic| !Scheme::is_inferred(generic))
+            .count();
+        if explicit != type_arguments.len() {
+            self.type_error(TypeError::WrongTypeArgumentCount {
+                name: name.to_string(),
+                expected: explicit,
+                found: type_arguments.len(),
+                span: span.clone(),
+            });
+            return self.instantiate(&scheme, span);
+        }
+
+        let mut type_arguments = type_arguments.iter();
+        let arguments: Vec<Type> = scheme
+            .generics
+            .iter()
+            .map(|generic| match type_arguments.next() {
+                // Schemes do not record the kinds of their generics, so a
+                // type argument may be of any kind.
+                Some(argument) if !Scheme::is_inferred(generic) => {
+                    let kind = self.kinds.fresh();
+                    self.type_argument(argument, &kind)
+                }
+                _ => self.substitution.fresh(),
+            })
+            .collect();
+        self.require_bounds(&scheme, &arguments, span);
+        scheme.ty.substitute(&scheme.generics, &arguments)
+    }
+
+    /// Infers the type of something that is about to be updated in place and
+    /// checks that it can be.
+    fn place(&mut self, target: &Expression) -> Type {
+        if let Expression::Identifier { name, span } = target {
+            if let Some(binding) = self.lookup(name) {
+                if !binding.mutable {
+                    let declared = binding.span.clone();
+                    self.resolve_error(ResolveError::AssignToImmutable {
+                        name: name.to_string(),
+                        declared,
+                        span: span.clone(),
+                    });
+                }
+            }
+        }
+
+        match target {
+            Expression::Identifier { .. }
+            | Expression::Member { .. }
+            | Expression::Index { .. } => self.infer(target),
+            _ => {
+                self.resolve_error(ResolveError::InvalidAssignmentTarget {
+                    span: target.span(),
+                });
+                self.substitution.fresh()
+            }
+        }
+    }
+
+    /// Plain assignment. Assigning to a name that is not in scope declares a
+    /// new constant, generalized when `value` says the assigned expression
+    /// is a value; assigning to a mutable binding updates it. A value of the
+    /// wrong type is reported at `value_span`, where the assigned expression is.
+    fn assign(&mut self, target: &Expression, ty: Type, value: bool, value_span: Span) {
+        match target {
+            Expression::Identifier { name, span } => match self.lookup_assignable(name) {
+                Some(binding) if binding.mutable => {
+                    let declared = self.instantiate(&binding.scheme, span.clone());
+                    self.subsume(&declared, &ty, value_span);
+                }
+                Some(binding) => self.resolve_error(ResolveError::AssignToImmutable {
+                    name: name.to_string(),
+                    declared: binding.span,
+                    span: span.clone(),
+                }),
+                None => {
+                    let scheme = if value {
+                        self.generalize(&ty, Vec::new(), None)
+                    } else {
+                        Scheme::monomorphic(ty)
+                    };
+                    self.bind(
+                        name,
+                        Binding {
+                            scheme,
+                            mutable: false,
+                            span: span.clone(),
+                        },
+                    )
+                }
+            },
+            Expression::Tuple { elements, span } => {
+                let parts: Vec<Type> = elements.iter().map(|_| self.substitution.fresh()).collect();
+                self.unify(&Type::Tuple(parts.clone()), &ty, span.clone());
+                for (element, part) in elements.iter().zip(parts) {
+                    self.assign(element, part, false, value_span.clone());
+                }
+            }
+            Expression::Member { .. } | Expression::Index { .. } => {
+                let target_type = self.infer(target);
+                self.unify(&target_type, &ty, value_span);
+            }
+            _ => self.resolve_error(ResolveError::InvalidAssignmentTarget {
+                span: target.span(),
+            }),
+        }
+    }
+
+    /// The type of `value` when it is assigned to `target`. A literal keeps
+    /// its own type when assigned to a name that has literal types.
+    fn assigned_type(&mut self, target: &Expression, value: &Expression) -> Type {
+        if let (Expression::Identifier { name, .. }, Some(literal)) = (target, literal_type(value))
+        {
+            if let Some(binding) = self.lookup_assignable(name) {
+                if has_literal(&self.substitution.resolve(&binding.scheme.ty)) {
+                    return literal;
+                }
+            }
+        }
+        self.infer(value)
+    }
+
+    // Mutable bindings can be updated from anywhere they are visible, but a
+    // constant from an enclosing function may be shadowed by a new one.
+    fn lookup_assignable(&self, name: &str) -> Option<Binding> {
+        let mut crossed_function = false;
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.bindings.get(name) {
+                if binding.mutable || !crossed_function {
+                    return Some(binding.clone());
+                }
+                return None;
+            }
+            crossed_function |= scope.function;
+        }
+        None
+    }
+
+    // Type annotations
+
+    fn resolve_annotation(&mut self, annotation: &ast::Type) -> Type {
+        match annotation {
+            ast::Type::Integer { .. } => Type::Integer,
+            ast::Type::Float { .. } => Type::Float,
+            ast::Type::BigInteger { .. } => Type::BigInteger,
+            ast::Type::BigDecimal { .. } => Type::BigDecimal,
+            ast::Type::Boolean { .. } => Type::Boolean,
+            ast::Type::String { .. } => Type::String,
+            ast::Type::Symbol { .. } => Type::Symbol,
+
+            ast::Type::StringLiteral { value, .. } => {
+                Type::literal(&Literal::String(value.clone()))
+            }
+            ast::Type::IntegerLiteral { value, .. } => Type::literal(&Literal::Integer(*value)),
+            ast::Type::DecimalLiteral { value, .. } => {
+                Type::literal(&Literal::Decimal(value.clone()))
+            }
+            ast::Type::BigIntegerLiteral { value, .. } => {
+                Type::literal(&Literal::BigInteger(*value))
+            }
+            ast::Type::BigDecimalLiteral { value, .. } => {
+                Type::literal(&Literal::BigDecimal(value.clone()))
+            }
+            ast::Type::BooleanLiteral { value, .. } => Type::literal(&Literal::Boolean(*value)),
+            ast::Type::SymbolLiteral { name, .. } => Type::literal(&Literal::Symbol(name)),
+            ast::Type::ArrayLiteral { element_type, .. }
+            | ast::Type::Array { element_type, .. } => {
+                Type::Array(Box::new(self.resolve_annotation(element_type)))
+            }
+            ast::Type::TupleLiteral { elements, .. } | ast::Type::Tuple { elements, .. } => {
+                Type::Tuple(
+                    elements
+                        .iter()
+                        .map(|element| self.resolve_annotation(element))
+                        .collect(),
+                )
+            }
+            ast::Type::RecordLiteral { fields, .. } => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, ty)| (record_key_name(key), self.resolve_annotation(ty)))
+                    .collect();
+                Type::structure(fields)
+            }
+            ast::Type::Record { fields, rest, .. } => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, ty)| (record_key_name(key), self.resolve_annotation(ty)))
+                    .collect();
+                let row = match rest {
+                    Some(rest) => self.resolve_annotation(rest),
+                    None => Type::Struct(Vec::new()),
+                };
+                Type::extend(fields, row)
+            }
+
+            ast::Type::TypeVariable { .. }
+            | ast::Type::HigherKindedType { .. }
+            | ast::Type::Reference { .. } => self.type_argument(annotation, &Kind::Star),
+
+            ast::Type::Union { types, .. } => {
+                Type::union(types.iter().map(|ty| self.resolve_annotation(ty)).collect())
+            }
+            ast::Type::Intersection { types, .. } => {
+                Type::intersection(types.iter().map(|ty| self.resolve_annotation(ty)).collect())
+            }
+
+            ast::Type::Function {
+                type_parameters,
+                parameters,
+                return_type,
+                ..
+            } => {
+                let generics = type_parameter_names(type_parameters);
+                self.push_generics(generics.clone(), declared_kinds(type_parameters));
+                let parameters = parameters
+                    .iter()
+                    .map(|parameter| self.resolve_annotation(parameter))
+                    .collect();
+                let (return_type, effects) = self.return_annotation(return_type);
+                self.pop_scope();
+
+                let ty = Type::effectful(parameters, return_type, effects);
+                if generics.is_empty() {
+                    ty
+                } else {
+                    Type::Forall(generics, Box::new(ty))
+                }
+            }
+        }
+    }
+
+    /// Resolves the return type of a function, which also gives the effects
+    /// it performs: those of `E` when it returns `Effect<E, A>`, and none
+    /// otherwise.
+    fn return_annotation(&mut self, annotation: &ast::Type) -> (Type, Type) {
+        match annotation {
+            ast::Type::Reference {
+                name: "Effect",
+                arguments,
+                ..
+            } if arguments.len() == 2 => (
+                self.resolve_annotation(&arguments[1]),
+                self.effect_row(&arguments[0]),
+            ),
+            annotation => (self.resolve_annotation(annotation), Type::pure()),
+        }
+    }
+
+    /// Resolves the `E` of `Effect<E, A>`: an effect, several joined by `|`,
+    /// `Unit` for none, and at most one effect parameter standing for any
+    /// others.
+    fn effect_row(&mut self, annotation: &ast::Type) -> Type {
+        let members = match annotation {
+            ast::Type::Union { types, .. } => types.iter().collect(),
+            annotation => vec![annotation],
+        };
+        let mut effects = Vec::new();
+        let mut rest = None;
+        for member in members {
+            let ty = self.resolve_annotation(member);
+            match &ty {
+                Type::HKT(name, _)
+                    if matches!(
+                        self.types.get_type(name),
+                        Some(TypeDefinition::Effect { .. })
+                    ) =>
+                {
+                    effects.push(ty)
+                }
+                Type::Generic(_) if rest.is_none() => rest = Some(ty),
+                // A name that did not resolve has been reported already.
+                Type::Var(_) => {}
+                ty if *ty == Type::unit() => {}
+                _ => self.type_error(TypeError::NotAnEffect {
+                    ty: ty.to_string(),
+                    span: member.span(),
+                }),
+            }
+        }
+        Type::effects(effects, rest)
+    }
+
+    /// Resolves `annotation` where a type of kind `expected` belongs, which
+    /// for anything but a name is `*`.
+    fn type_argument(&mut self, annotation: &ast::Type, expected: &Kind) -> Type {
+        match annotation {
+            ast::Type::TypeVariable { name, span, .. }
+            | ast::Type::HigherKindedType { name, span, .. } => {
+                self.named_type(name, &[], expected, span)
+            }
+            ast::Type::Reference {
+                name,
+                arguments,
+                span,
+            } => self.named_type(name, arguments, expected, span),
+            annotation => {
+                if !self.kinds.unify(expected, &Kind::Star) {
+                    self.kind_mismatch(expected, &Kind::Star, annotation.span());
+                }
+                self.resolve_annotation(annotation)
+            }
+        }
+    }
+
+    /// Resolves the type `name` applied to `arguments`, which may leave holes
+    /// for the arguments it still needs.
+    fn named_type(
+        &mut self,
+        name: &str,
+        arguments: &[ast::Type],
+        expected: &Kind,
+        span: &Span,
+    ) -> Type {
+        let generic = self.generic_kind(name);
+        let definition = self.types.get_type(name).cloned();
+        let kind = match (&generic, &definition) {
+            (Some(kind), _) => kind.clone(),
+            (None, Some(_)) => Kind::arrow(self.types.parameter_kinds(name), Kind::Star),
+            (None, None) => {
+                self.resolve_error(ResolveError::UnboundType {
+                    name: name.to_string(),
+                    span: span.clone(),
+                });
+                return self.substitution.fresh();
+            }
+        };
+
+        // Each argument takes off one parameter of the constructor's kind.
+        let mut result = kind.clone();
+        let mut parameters = Vec::new();
+        for _ in arguments {
+            match self.kinds.shallow_resolve(&result) {
+                Kind::Arrow(parameter, rest) => {
+                    parameters.push(*parameter);
+                    result = *rest;
+                }
+                Kind::Var(_) => {
+                    let (parameter, rest) = (self.kinds.fresh(), self.kinds.fresh());
+                    let arrow = Kind::arrow(vec![parameter.clone()], rest.clone());
+                    self.kinds.unify(&result, &arrow);
+                    parameters.push(parameter);
+                    result = rest;
+                }
+                // A type parameter's arguments are declared with it, as
+                // `F<_>`, so without them it is the parameter that is wrong.
+                Kind::Star if generic.is_some() => {
+                    self.type_error(TypeError::ParameterKindMismatch {
+                        parameter: name.to_string(),
+                        kind: self.kinds.default(&kind).to_string(),
+                        found: arguments.len(),
+                        span: span.clone(),
+                    });
+                    return self.substitution.fresh();
+                }
+                Kind::Star => {
+                    self.type_error(TypeError::WrongTypeArgumentCount {
+                        name: name.to_string(),
+                        expected: parameters.len(),
+                        found: arguments.len(),
+                        span: span.clone(),
+                    });
+                    return self.substitution.fresh();
+                }
+            }
+        }
+
+        let errors = self.errors.len();
+        let mut holes = Vec::new();
+        let arguments: Vec<Type> = arguments
+            .iter()
+            .zip(&parameters)
+            .map(|(argument, kind)| match argument {
+                ast::Type::Reference {
+                    name: "_",
+                    arguments,
+                    ..
+                } if arguments.is_empty() => {
+                    holes.push(kind.clone());
+                    Type::hole()
+                }
+                argument => self.type_argument(argument, kind),
+            })
+            .collect();
+
+        // A type built from a wrong argument would only cause more errors.
+        if self.errors.len() > errors {
+            return self.substitution.fresh();
+        }
+
+        let found = Kind::arrow(holes, result);
+        if !self.kinds.unify(expected, &found) {
+            self.kind_mismatch(expected, &found, span.clone());
+            return self.substitution.fresh();
+        }
+
+        match (generic, definition) {
+            (Some(_), _) if arguments.is_empty() => Type::Generic(name.to_string()),
+            (Some(_), _) => Type::HKT(name.to_string(), arguments),
+            // Aliases are expanded, so they have to be given every argument.
+            (None, Some(TypeDefinition::Alias { parameters, ty })) => {
+                if parameters.len() != arguments.len() || arguments.contains(&Type::hole()) {
+                    self.type_error(TypeError::WrongTypeArgumentCount {
+                        name: name.to_string(),
+                        expected: parameters.len(),
+                        found: arguments.len(),
+                        span: span.clone(),
+                    });
+                    return self.substitution.fresh();
+                }
+                ty.substitute(&parameters, &arguments)
+            }
+            (None, _) => Type::HKT(name.to_string(), arguments),
+        }
+    }
+
+    fn kind_mismatch(&mut self, expected: &Kind, found: &Kind, span: Span) {
+        self.type_error(TypeError::KindMismatch {
+            expected: self.kinds.default(expected).to_string(),
+            found: self.kinds.default(found).to_string(),
+            span,
+        });
+    }
+}
+
+/// Whether evaluating `expression` cannot create anything mutable, so that
+/// the binding it initializes can be generalized. `[]` is not a value: every
+/// use of the array must agree on its elements.
+fn is_value(expression: &Expression) -> bool {
+    match expression {
+        Expression::Lambda { .. }
+        | Expression::Identifier { .. }
+        | Expression::String { .. }
+        | Expression::Integer { .. }
+        | Expression::Decimal { .. }
+        | Expression::BigInteger { .. }
+        | Expression::BigDecimal { .. }
+        | Expression::Boolean { .. }
+        | Expression::Symbol { .. } => true,
+        Expression::Tuple { elements, .. } => elements.iter().all(is_value),
+        Expression::Record {
+            fields, spreads, ..
+        } => fields.iter().all(|(_, value)| is_value(value)) && spreads.iter().all(is_value),
+        _ => false,
+    }
+}
+
+/// The literal type of `expression`, if it is a literal.
+fn literal_type(expression: &Expression) -> Option<Type> {
+    let literal = match expression {
+        Expression::Integer { value, .. } => Literal::Integer(*value),
+        Expression::Decimal { value, .. } => Literal::Decimal((*value).into()),
+        Expression::BigInteger { value, .. } => Literal::BigInteger(*value),
+        Expression::BigDecimal { value, .. } => Literal::BigDecimal((*value).into()),
+        Expression::Boolean { value, .. } => Literal::Boolean(*value),
+        Expression::String { value, .. } => Literal::String(value.clone()),
+        Expression::Symbol { name, .. } => Literal::Symbol(name),
+        _ => return None,
+    };
+    Some(Type::literal(&literal))
+}
+
+/// The type of an expression with only one possible value: a literal or
+/// `()`.
+fn singleton_type(expression: &Expression) -> Option<Type> {
+    match expression {
+        Expression::Tuple { elements, .. } if elements.is_empty() => Some(Type::unit()),
+        expression => literal_type(expression),
+    }
+}
+
+/// Whether `ty` is a literal type or has one among its members.
+fn has_literal(ty: &Type) -> bool {
+    match ty {
+        Type::Literal(..) => true,
+        Type::Union(members) | Type::Intersection(members) => members.iter().any(has_literal),
+        _ => false,
+    }
+}
+
+/// Whether a value of `ty` may be `value`, the only value of its type.
+fn may_be(ty: &Type, value: &Type) -> bool {
+    match ty {
+        Type::Literal(..) => ty == value,
+        Type::Union(members) => members.iter().any(|member| may_be(member, value)),
+        Type::Var(_) | Type::Generic(_) | Type::Skolem(..) | Type::Intersection(_) => true,
+        ty => match value {
+            Type::Literal(primitive, _) => **primitive == *ty,
+            value => ty == value,
+        },
+    }
+}
+
+/// Whether `pattern` may match some value of `ty`, judging by its shape.
+fn may_match(pattern: &Pattern, ty: &Type) -> bool {
+    match (pattern, ty) {
+        (_, Type::Union(members)) => members.iter().any(|member| may_match(pattern, member)),
+        (Pattern::Wildcard { .. } | Pattern::Binding { .. }, _) | (_, Type::Var(_)) => true,
+        (Pattern::Literal { value, .. }, _) => may_be(ty, &Type::literal(value)),
+        (Pattern::Record { fields, .. }, Type::Struct(types)) => {
+            fields.iter().all(|(name, field)| {
+                types
+                    .iter()
+                    .find(|(field_name, _)| field_name == name)
+                    .is_some_and(|(_, ty)| may_match(field, ty))
+            })
+        }
+        (Pattern::Tuple { elements, .. }, Type::Tuple(types)) => {
+            elements.len() == types.len()
+                && elements
+                    .iter()
+                    .zip(types)
+                    .all(|(element, ty)| may_match(element, ty))
+        }
+        (Pattern::Array { .. }, Type::Array(_)) | (Pattern::Constructor { .. }, Type::HKT(..)) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Whether `pattern` matches every value of `ty`.
+fn covers(pattern: &Pattern, ty: &Type) -> bool {
+    match (pattern, ty) {
+        (Pattern::Wildcard { .. } | Pattern::Binding { .. }, _) => true,
+        (_, Type::Union(members)) => members.iter().all(|member| covers(pattern, member)),
+        (Pattern::Literal { value, .. }, _) => *ty == Type::literal(value),
+        (Pattern::Record { fields, .. }, Type::Struct(types)) => {
+            fields.iter().all(|(name, field)| {
+                types
+                    .iter()
+                    .find(|(field_name, _)| field_name == name)
+                    .is_some_and(|(_, ty)| covers(field, ty))
+            })
+        }
+        (Pattern::Tuple { elements, .. }, Type::Tuple(types)) => {
+            elements.len() == types.len()
+                && elements
+                    .iter()
+                    .zip(types)
+                    .all(|(element, ty)| covers(element, ty))
+        }
+        _ => false,
+    }
+}
+
+fn parameter_annotation<'p, 'a>(parameter: &'p Field<'a>) -> Option<&'p ast::Type<'a>> {
+    match parameter {
+        Field::Named { annotation, .. } => annotation.as_ref(),
+        Field::Typed { annotation, .. } => Some(annotation),
+    }
+}
+
+fn type_parameter_names(type_parameters: &[TypeParameter]) -> Vec<String> {
+    type_parameters
+        .iter()
+        .enumerate()
+        .map(|(index, parameter)| match parameter {
+            TypeParameter::Generic { name, .. } | TypeParameter::HigherKinded { name, .. } => {
+                name.to_string()
+            }
+            TypeParameter::Placeholder { .. } => format!("_{}", index),
+        })
+        .collect()
+}
+
+/// The kinds written for `type_parameters`.
+fn declared_kinds(type_parameters: &[TypeParameter]) -> Vec<Kind> {
+    type_parameters.iter().map(Kind::of_parameter).collect()
+}
+
+/// The record `record` without the fields in `names`.
+fn restrict(record: &Type, names: &[&str]) -> Type {
+    let keep = |fields: &[(String, Type)]| {
+        fields
+            .iter()
+            .filter(|(name, _)| !names.contains(&name.as_str()))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    match record {
+        Type::Struct(fields) => Type::Struct(keep(fields)),
+        Type::Extend(fields, row) => Type::extend(keep(fields), (**row).clone()),
+        Type::Union(members) => Type::union(
+            members
+                .iter()
+                .map(|member| restrict(member, names))
+                .collect(),
+        ),
+        record => record.clone(),
+    }
+}
+
+fn is_handler(ty: &Type) -> bool {
+    matches!(ty, Type::HKT(name, _) if name == "Handler")
+}
+
+fn record_key_name(key: &RecordKey) -> String {
+    match key {
+        RecordKey::String(name, _) | RecordKey::Symbol(name, _) => name.to_string(),
+    }
+}
+
+fn binary_operator(op: &BinaryOp) -> (&'static str, Span) {
+    match op {
+        BinaryOp::Addition(span) => ("+", span.clone()),
+        BinaryOp::Subtraction(span) => ("-", span.clone()),
+        BinaryOp::Multiplication(span) => ("*", span.clone()),
+        BinaryOp::Division(span) => ("/", span.clone()),
+        BinaryOp::Modulus(span) => ("%", span.clone()),
+        BinaryOp::Exponentiation(span) => ("**", span.clone()),
+        BinaryOp::Equal(span) => ("==", span.clone()),
+        BinaryOp::NotEqual(span) => ("!=", span.clone()),
+        BinaryOp::LessThan(span) => ("<", span.clone()),
+        BinaryOp::LessThanOrEqual(span) => ("<=", span.clone()),
+        BinaryOp::GreaterThan(span) => (">", span.clone()),
+        BinaryOp::GreaterThanOrEqual(span) => (">=", span.clone()),
+        BinaryOp::LogicalAnd(span) => ("&&", span.clone()),
+        BinaryOp::LogicalOr(span) => ("||", span.clone()),
+        BinaryOp::BitwiseAnd(span) => ("&", span.clone()),
+        BinaryOp::BitwiseOr(span) => ("|", span.clone()),
+        BinaryOp::BitwiseXor(span) => ("^", span.clone()),
+        BinaryOp::LeftShift(span) => ("<<", span.clone()),
+        BinaryOp::RightShift(span) => (">>", span.clone()),
+        BinaryOp::NullishCoalescing(span) => ("??", span.clone()),
+        BinaryOp::PipeOperator(span) => ("|>", span.clone()),
+        BinaryOp::OptionalChaining(span) => ("?.", span.clone()),
+        BinaryOp::Concatenation(span) => ("++", span.clone()),
+    }
+}
+
+fn unary_operator(op: &UnaryOp) -> (&'static str, Span) {
+    match op {
+        UnaryOp::Negation(span) => ("-", span.clone()),
+        UnaryOp::LogicalNot(span) => ("!", span.clone()),
+        UnaryOp::BitwiseNot(span) => ("~", span.clone()),
+        UnaryOp::PreIncrement(span) | UnaryOp::PostIncrement(span) => ("++", span.clone()),
+        UnaryOp::PreDecrement(span) | UnaryOp::PostDecrement(span) => ("--", span.clone()),
+    }
+}
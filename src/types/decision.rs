@@ -0,0 +1,418 @@
+//! Compiles `match` into decision trees, after Maranget's "Compiling pattern
+//! matching to good decision trees". Every node tests one part of the
+//! scrutinee once, and the arms that survive a test share everything below
+//! it, so no part of a value is ever examined twice on the way to an arm.
+
+use super::exhaustiveness::{self, Ctor, Pat};
+use super::{Type, TypeEnv};
+use crate::parsing::ast::{MatchArm, Pattern};
+use std::fmt;
+
+/// One step from a value to a part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    // A tuple element or a constructor argument.
+    Field(usize),
+    // A record field.
+    Key(String),
+    Index(usize),
+    // The elements of an array from an index on.
+    Slice(usize),
+}
+
+/// Where a value sits inside the scrutinee.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path(pub Vec<Step>);
+
+impl Path {
+    fn then(&self, step: Step) -> Path {
+        let mut steps = self.0.clone();
+        steps.push(step);
+        Path(steps)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// No arm matches. Only reachable when a guard fails in a match that
+    /// relies on it, which exhaustiveness checking already rejects.
+    Fail,
+    /// Runs `arm` with the names its pattern binds.
+    Leaf {
+        arm: usize,
+        bindings: Vec<(String, Path)>,
+    },
+    /// Runs `arm` if its guard holds, and otherwise carries on.
+    Guard {
+        arm: usize,
+        bindings: Vec<(String, Path)>,
+        otherwise: Box<Decision>,
+    },
+    /// Branches on how the value at `path` was built. A `default` is only
+    /// needed for types with too many values to list, or for a field that
+    /// only some members of a union of records have, which takes it when
+    /// the field is missing.
+    Switch {
+        path: Path,
+        cases: Vec<(Ctor, Decision)>,
+        default: Option<Box<Decision>>,
+    },
+}
+
+/// Builds the decision tree for `arms` matching a value of type `ty`, which
+/// must be fully resolved and fit every pattern.
+pub fn compile(types: &TypeEnv, ty: &Type, arms: &[MatchArm]) -> Decision {
+    let rows = arms
+        .iter()
+        .enumerate()
+        .map(|(arm, MatchArm { pattern, guard, .. })| Row {
+            tests: vec![Test {
+                path: Path::default(),
+                ty: ty.clone(),
+                pattern,
+                optional: false,
+            }],
+            bindings: Vec::new(),
+            arm,
+            guarded: guard.is_some(),
+        })
+        .collect();
+    decide(types, rows)
+}
+
+#[derive(Clone)]
+struct Test<'p, 'a> {
+    path: Path,
+    ty: Type,
+    pattern: &'p Pattern<'a>,
+    // Whether the value may be missing.
+    optional: bool,
+}
+
+// The tests an arm still needs before it can run.
+#[derive(Clone)]
+struct Row<'p, 'a> {
+    tests: Vec<Test<'p, 'a>>,
+    bindings: Vec<(String, Path)>,
+    arm: usize,
+    guarded: bool,
+}
+
+impl<'p, 'a> Row<'p, 'a> {
+    /// Removes the tests that always succeed: names are bound, and tuples
+    /// and records are replaced by tests on their parts.
+    fn simplify(mut self) -> Self {
+        let mut pending = std::mem::take(&mut self.tests);
+        pending.reverse();
+        while let Some(test) = pending.pop() {
+            match (test.pattern, &test.ty) {
+                (Pattern::Wildcard { .. }, _) => {}
+                (Pattern::Binding { name, .. }, _) => {
+                    self.bindings.push((name.to_string(), test.path));
+                }
+                (Pattern::Tuple { elements, .. }, Type::Tuple(tys)) => {
+                    for (index, (pattern, ty)) in elements.iter().zip(tys).enumerate().rev() {
+                        pending.push(Test {
+                            path: test.path.then(Step::Field(index)),
+                            ty: ty.clone(),
+                            pattern,
+                            optional: test.optional,
+                        });
+                    }
+                }
+                (Pattern::Record { fields, .. }, ty) => match exhaustiveness::record_fields(ty) {
+                    Some(field_types) => {
+                        for (name, pattern) in fields.iter().rev() {
+                            if let Some((_, ty, everywhere)) =
+                                field_types.iter().find(|(field, ..)| field == name)
+                            {
+                                pending.push(Test {
+                                    path: test.path.then(Step::Key(name.to_string())),
+                                    ty: ty.clone(),
+                                    pattern,
+                                    optional: test.optional || !everywhere,
+                                });
+                            }
+                        }
+                    }
+                    None => self.tests.push(test),
+                },
+                _ => self.tests.push(test),
+            }
+        }
+        self
+    }
+
+    fn test_at(&self, path: &Path) -> Option<&Test<'p, 'a>> {
+        self.tests.iter().find(|test| test.path == *path)
+    }
+}
+
+fn decide(types: &TypeEnv, rows: Vec<Row>) -> Decision {
+    let mut rows: Vec<Row> = rows.into_iter().map(Row::simplify).collect();
+    if rows.is_empty() {
+        return Decision::Fail;
+    }
+
+    if rows[0].tests.is_empty() {
+        let first = rows.remove(0);
+        return if first.guarded {
+            Decision::Guard {
+                arm: first.arm,
+                bindings: first.bindings,
+                otherwise: Box::new(decide(types, rows)),
+            }
+        } else {
+            Decision::Leaf {
+                arm: first.arm,
+                bindings: first.bindings,
+            }
+        };
+    }
+
+    // Test whatever the first arm needs that the most arms care about,
+    // preferring its earliest test on ties.
+    let users = |path: &Path| {
+        rows.iter()
+            .filter(|row| row.test_at(path).is_some())
+            .count()
+    };
+    let mut test = &rows[0].tests[0];
+    for candidate in &rows[0].tests[1..] {
+        if users(&candidate.path) > users(&test.path) {
+            test = candidate;
+        }
+    }
+    let test = test.clone();
+
+    let column: Vec<Pat> = rows
+        .iter()
+        .filter_map(|row| row.test_at(&test.path))
+        .map(|test| exhaustiveness::lower(types, test.pattern, &test.ty))
+        .collect();
+    let column_refs: Vec<&Pat> = column.iter().collect();
+    let all = if test.optional {
+        None
+    } else {
+        exhaustiveness::constructors(types, &test.ty, &column_refs)
+    };
+
+    let ctors = match &all {
+        Some(all) => all.clone(),
+        // Only the values named by some arm need their own case.
+        None => column.iter().fold(Vec::new(), |mut ctors, pattern| {
+            if let Pat::Ctor(ctor, _) = pattern {
+                if !ctors.contains(ctor) {
+                    ctors.push(ctor.clone());
+                }
+            }
+            ctors
+        }),
+    };
+
+    let mut cases: Vec<(Ctor, Decision)> = ctors
+        .into_iter()
+        .map(|ctor| {
+            let rows = rows
+                .iter()
+                .filter_map(|row| specialize(types, row, &test, &ctor))
+                .collect();
+            let decision = decide(types, rows);
+            (ctor, decision)
+        })
+        .collect();
+
+    match all {
+        // A type with a single constructor needs no test.
+        Some(all) if all.len() == 1 => cases.remove(0).1,
+        Some(_) => Decision::Switch {
+            path: test.path,
+            cases,
+            default: None,
+        },
+        None => {
+            let rows = rows
+                .into_iter()
+                .filter(|row| row.test_at(&test.path).is_none())
+                .collect();
+            Decision::Switch {
+                path: test.path,
+                cases,
+                default: Some(Box::new(decide(types, rows))),
+            }
+        }
+    }
+}
+
+/// `row` once the value at `test.path` is known to be built with `ctor`, or
+/// `None` if the row cannot match such a value.
+fn specialize<'p, 'a>(
+    types: &TypeEnv,
+    row: &Row<'p, 'a>,
+    test: &Test,
+    ctor: &Ctor,
+) -> Option<Row<'p, 'a>> {
+    let own = match row.test_at(&test.path) {
+        Some(own) => own,
+        None => return Some(row.clone()),
+    };
+    if !exhaustiveness::matches(&exhaustiveness::lower(types, own.pattern, &own.ty), ctor) {
+        return None;
+    }
+
+    let mut row = row.clone();
+    row.tests.retain(|other| other.path != test.path);
+    let fields = exhaustiveness::fields(types, ctor, &test.ty);
+
+    match parts(own.pattern) {
+        Parts::Fields(patterns) => {
+            for (index, (pattern, ty)) in patterns.into_iter().zip(fields).enumerate() {
+                row.tests.push(Test {
+                    path: test.path.then(Step::Field(index)),
+                    ty,
+                    pattern,
+                    optional: test.optional,
+                });
+            }
+        }
+        Parts::Array(prefix, rest) => {
+            let length = prefix.len();
+            for (index, (pattern, ty)) in prefix.into_iter().zip(fields).enumerate() {
+                row.tests.push(Test {
+                    path: test.path.then(Step::Index(index)),
+                    ty,
+                    pattern,
+                    optional: test.optional,
+                });
+            }
+            if let Some(pattern) = rest {
+                row.tests.push(Test {
+                    path: test.path.then(Step::Slice(length)),
+                    ty: test.ty.clone(),
+                    pattern,
+                    optional: test.optional,
+                });
+            }
+        }
+    }
+    Some(row)
+}
+
+enum Parts<'p, 'a> {
+    Fields(Vec<&'p Pattern<'a>>),
+    // The elements before the rest, and the rest.
+    Array(Vec<&'p Pattern<'a>>, Option<&'p Pattern<'a>>),
+}
+
+/// The patterns for the parts of a value matched by `pattern`. A rest that
+/// is itself an array pattern continues the outer one.
+fn parts<'p, 'a>(pattern: &'p Pattern<'a>) -> Parts<'p, 'a> {
+    match pattern {
+        Pattern::Constructor { arguments, .. } => Parts::Fields(arguments.iter().collect()),
+        Pattern::Array { elements, rest, .. } => {
+            let mut prefix: Vec<&Pattern> = elements.iter().collect();
+            let mut rest = rest.as_deref();
+            while let Some(Pattern::Array {
+                elements,
+                rest: inner,
+                ..
+            }) = rest
+            {
+                prefix.extend(elements.iter());
+                rest = inner.as_deref();
+            }
+            Parts::Array(prefix, rest)
+        }
+        _ => Parts::Fields(Vec::new()),
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$")?;
+        for step in &self.0 {
+            match step {
+                Step::Field(index) => write!(f, ".{}", index)?,
+                Step::Key(name) => write!(f, ".{}", name)?,
+                Step::Index(index) => write!(f, "[{}]", index)?,
+                Step::Slice(start) => write!(f, "[{}..]", start)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Decision {
+    /// A readable rendering of the tree, one node per line.
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        self.write(&mut output, 0);
+        output
+    }
+
+    fn write(&self, output: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        match self {
+            Decision::Fail => output.push_str(&format!("{}fail\n", indent)),
+            Decision::Leaf { arm, bindings } => {
+                output.push_str(&format!(
+                    "{}arm {}{}\n",
+                    indent,
+                    arm,
+                    show_bindings(bindings)
+                ));
+            }
+            Decision::Guard {
+                arm,
+                bindings,
+                otherwise,
+            } => {
+                output.push_str(&format!(
+                    "{}guard arm {}{}\n{}else\n",
+                    indent,
+                    arm,
+                    show_bindings(bindings),
+                    indent
+                ));
+                otherwise.write(output, depth + 1);
+            }
+            Decision::Switch {
+                path,
+                cases,
+                default,
+            } => {
+                output.push_str(&format!("{}switch {}\n", indent, path));
+                for (ctor, decision) in cases {
+                    output.push_str(&format!("{}  {}\n", indent, show_case(ctor)));
+                    decision.write(output, depth + 2);
+                }
+                if let Some(default) = default {
+                    output.push_str(&format!("{}  _\n", indent));
+                    default.write(output, depth + 2);
+                }
+            }
+        }
+    }
+}
+
+fn show_bindings(bindings: &[(String, Path)]) -> String {
+    if bindings.is_empty() {
+        return String::new();
+    }
+    let bindings: Vec<String> = bindings
+        .iter()
+        .map(|(name, path)| format!("{} = {}", name, path))
+        .collect();
+    format!(" ({})", bindings.join(", "))
+}
+
+fn show_case(ctor: &Ctor) -> String {
+    match ctor {
+        Ctor::Array {
+            length,
+            rest: false,
+        } => format!("length {}", length),
+        Ctor::Array { length, rest: true } => format!("length >= {}", length),
+        ctor => Pat::Ctor(ctor.clone(), Vec::new()).to_string(),
+    }
+}
@@ -0,0 +1,507 @@
+//! Exhaustiveness and redundancy checking for `match`, after Maranget's
+//! "Warnings for pattern matching". A pattern is *useful* after some others
+//! when there is a value it matches that none of them do: an arm that is not
+//! useful is unreachable, and a match is exhaustive when a wildcard would not
+//! be useful after all of its arms.
+
+use super::{ConstructorFields, Type, TypeDefinition, TypeEnv};
+use crate::parsing::ast;
+use std::fmt;
+
+// How many uncovered patterns are worth collecting for one match.
+const WITNESS_LIMIT: usize = 8;
+
+/// A pattern reduced to what matters for coverage: bindings are wildcards,
+/// constructors are resolved and record patterns name every field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ctor {
+    Variant(String),
+    Tuple,
+    // Field names, sorted like `Type::Struct`.
+    Record(Vec<String>),
+    Boolean(bool),
+    // Any other literal, by its source text. Their types have too many values
+    // for a match to list them all.
+    Literal(String),
+    // Arrays of exactly `length` elements, or of at least `length` when
+    // `rest` is set.
+    Array { length: usize, rest: bool },
+}
+
+pub struct Arm {
+    pub pattern: Pat,
+    pub guarded: bool,
+}
+
+pub struct Coverage {
+    /// Patterns for values that no arm matches.
+    pub missing: Vec<Pat>,
+    /// The indices of arms that can never match.
+    pub unreachable: Vec<usize>,
+}
+
+/// Checks the arms of a match on a value of type `ty`. A guarded arm may
+/// still be unreachable, but never makes a later arm unreachable or covers
+/// anything, since its guard may fail.
+pub fn check(types: &TypeEnv, ty: &Type, arms: &[Arm]) -> Coverage {
+    let tys = [ty.clone()];
+    let mut matrix: Vec<Vec<Pat>> = Vec::new();
+    let mut unreachable = Vec::new();
+
+    for (index, arm) in arms.iter().enumerate() {
+        let row = vec![arm.pattern.clone()];
+        if !useful(types, &matrix, &row, &tys) {
+            unreachable.push(index);
+        }
+        if !arm.guarded {
+            matrix.push(row);
+        }
+    }
+
+    let missing = witnesses(types, &matrix, &tys)
+        .into_iter()
+        .map(|mut witness| witness.remove(0))
+        .collect();
+
+    Coverage {
+        missing,
+        unreachable,
+    }
+}
+
+/// Lowers a pattern that has been checked against `ty`, which must be fully
+/// resolved. Anything that does not fit the type is treated as a wildcard,
+/// since it has already been reported.
+pub fn lower(types: &TypeEnv, pattern: &ast::Pattern, ty: &Type) -> Pat {
+    match (pattern, ty) {
+        (ast::Pattern::Wildcard { .. } | ast::Pattern::Binding { .. }, _) => Pat::Wild,
+        (ast::Pattern::Literal { value, .. }, _) => {
+            Pat::Ctor(literal_ctor(&Type::literal(value)), Vec::new())
+        }
+        (ast::Pattern::Tuple { elements, .. }, Type::Tuple(tys)) if elements.len() == tys.len() => {
+            Pat::Ctor(Ctor::Tuple, lower_all(types, elements, tys))
+        }
+        (ast::Pattern::Array { elements, rest, .. }, Type::Array(element)) => {
+            // A rest that is itself an array pattern continues the outer one,
+            // so `[a, ...[b, ...c]]` is `[a, b, ...c]`.
+            let mut prefix: Vec<Pat> = Vec::new();
+            let (mut elements, mut rest) = (elements, rest);
+            loop {
+                prefix.extend(
+                    elements
+                        .iter()
+                        .map(|pattern| lower(types, pattern, element)),
+                );
+                match rest.as_deref() {
+                    Some(ast::Pattern::Array {
+                        elements: inner,
+                        rest: inner_rest,
+                        ..
+                    }) => {
+                        elements = inner;
+                        rest = inner_rest;
+                    }
+                    rest => {
+                        let ctor = Ctor::Array {
+                            length: prefix.len(),
+                            rest: rest.is_some(),
+                        };
+                        break Pat::Ctor(ctor, prefix);
+                    }
+                }
+            }
+        }
+        (ast::Pattern::Record { fields, .. }, _) => match record_fields(ty) {
+            Some(field_types) => {
+                let names = field_types.iter().map(|(name, ..)| name.clone()).collect();
+                let arguments = field_types
+                    .iter()
+                    .map(
+                        |(name, ty, _)| match fields.iter().find(|(field, _)| field == name) {
+                            Some((_, pattern)) => lower(types, pattern, ty),
+                            None => Pat::Wild,
+                        },
+                    )
+                    .collect();
+                Pat::Ctor(Ctor::Record(names), arguments)
+            }
+            None => Pat::Wild,
+        },
+        (
+            ast::Pattern::Constructor {
+                name, arguments, ..
+            },
+            _,
+        ) => match variant_fields(types, ty, name) {
+            Some(fields) if fields.len() == arguments.len() => Pat::Ctor(
+                Ctor::Variant(name.to_string()),
+                lower_all(types, arguments, &fields),
+            ),
+            _ => Pat::Wild,
+        },
+        _ => Pat::Wild,
+    }
+}
+
+fn lower_all(types: &TypeEnv, patterns: &[ast::Pattern], tys: &[Type]) -> Vec<Pat> {
+    patterns
+        .iter()
+        .zip(tys)
+        .map(|(pattern, ty)| lower(types, pattern, ty))
+        .collect()
+}
+
+/// The field types of the constructor `name` of the data type `ty`.
+fn variant_fields(types: &TypeEnv, ty: &Type, name: &str) -> Option<Vec<Type>> {
+    let (owner, arguments) = match ty {
+        Type::HKT(owner, arguments) => (owner, arguments),
+        _ => return None,
+    };
+    let (parameters, constructors) = match types.get_type(owner)? {
+        TypeDefinition::Data {
+            parameters,
+            constructors,
+        } => (parameters, constructors),
+        _ => return None,
+    };
+    let constructor = constructors
+        .iter()
+        .find(|constructor| constructor.name == name)?;
+
+    let fields = match &constructor.fields {
+        ConstructorFields::Void => Vec::new(),
+        ConstructorFields::Tuple(fields) => fields.clone(),
+        ConstructorFields::Record(fields) => vec![Type::Struct(fields.clone())],
+    };
+    Some(
+        fields
+            .iter()
+            .map(|field| field.substitute(parameters, arguments))
+            .collect(),
+    )
+}
+
+/// The fields of a record type, with whether every value of it has them. A
+/// union of records is taken to have every field of any of its members, of
+/// the union of their types there: coverage then tells apart the members of
+/// a union discriminated by a literal field, and can only err towards
+/// asking for more arms. An open record has the fields it is known to.
+pub(super) fn record_fields(ty: &Type) -> Option<Vec<(String, Type, bool)>> {
+    let members = match ty {
+        Type::Struct(fields) | Type::Extend(fields, _) => {
+            return Some(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), ty.clone(), true))
+                    .collect(),
+            )
+        }
+        Type::Union(members) => members,
+        _ => return None,
+    };
+
+    let mut merged: Vec<(String, Vec<Type>)> = Vec::new();
+    for member in members {
+        let fields = match member {
+            Type::Struct(fields) | Type::Extend(fields, _) => fields,
+            _ => return None,
+        };
+        for (name, ty) in fields {
+            match merged.iter_mut().find(|(field, _)| field == name) {
+                Some((_, types)) => types.push(ty.clone()),
+                None => merged.push((name.clone(), vec![ty.clone()])),
+            }
+        }
+    }
+    merged.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Some(
+        merged
+            .into_iter()
+            .map(|(name, types)| {
+                let everywhere = types.len() == members.len();
+                (name, Type::union(types), everywhere)
+            })
+            .collect(),
+    )
+}
+
+/// The constructor for the value of the literal type `ty`.
+fn literal_ctor(ty: &Type) -> Ctor {
+    match ty {
+        Type::Literal(primitive, text) if **primitive == Type::Boolean => {
+            Ctor::Boolean(text == "true")
+        }
+        Type::Literal(_, text) => Ctor::Literal(text.clone()),
+        _ => unreachable!("only literal types have a single value"),
+    }
+}
+
+/// Every constructor of `ty`, or `None` when there are too many to list.
+/// Arrays are split by length: each length up to the longest one in
+/// `column`, then every longer array together.
+pub(super) fn constructors(types: &TypeEnv, ty: &Type, column: &[&Pat]) -> Option<Vec<Ctor>> {
+    match ty {
+        Type::Boolean => Some(vec![Ctor::Boolean(true), Ctor::Boolean(false)]),
+        Type::Tuple(_) => Some(vec![Ctor::Tuple]),
+        Type::Literal(..) => Some(vec![literal_ctor(ty)]),
+        Type::Union(members)
+            if members
+                .iter()
+                .all(|member| matches!(member, Type::Literal(..))) =>
+        {
+            Some(members.iter().map(literal_ctor).collect())
+        }
+        Type::Struct(_) | Type::Extend(..) | Type::Union(_) => record_fields(ty).map(|fields| {
+            vec![Ctor::Record(
+                fields.into_iter().map(|(name, ..)| name).collect(),
+            )]
+        }),
+        Type::HKT(owner, _) => match types.get_type(owner) {
+            Some(TypeDefinition::Data { constructors, .. }) => Some(
+                constructors
+                    .iter()
+                    .map(|constructor| Ctor::Variant(constructor.name.clone()))
+                    .collect(),
+            ),
+            _ => None,
+        },
+        Type::Array(_) => {
+            let longest = column
+                .iter()
+                .filter_map(|pattern| match pattern {
+                    Pat::Ctor(Ctor::Array { length, .. }, _) => Some(*length),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
+            let mut ctors: Vec<Ctor> = (0..=longest)
+                .map(|length| Ctor::Array {
+                    length,
+                    rest: false,
+                })
+                .collect();
+            ctors.push(Ctor::Array {
+                length: longest + 1,
+                rest: true,
+            });
+            Some(ctors)
+        }
+        // Nothing has type `Never`, so no constructor is needed to cover it.
+        Type::Never => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+/// The types of the values inside a `ctor` of type `ty`.
+pub(super) fn fields(types: &TypeEnv, ctor: &Ctor, ty: &Type) -> Vec<Type> {
+    match (ctor, ty) {
+        (Ctor::Variant(name), _) => variant_fields(types, ty, name).unwrap_or_default(),
+        (Ctor::Tuple, Type::Tuple(elements)) => elements.clone(),
+        (Ctor::Record(_), _) => record_fields(ty)
+            .map(|fields| fields.into_iter().map(|(_, ty, _)| ty).collect())
+            .unwrap_or_default(),
+        (Ctor::Array { length, .. }, Type::Array(element)) => vec![(**element).clone(); *length],
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `pattern` matches values built with `ctor`.
+pub(super) fn matches(pattern: &Pat, ctor: &Ctor) -> bool {
+    match (pattern, ctor) {
+        (Pat::Wild, _) => true,
+        // An array pattern with a rest matches every array at least as long
+        // as its prefix.
+        (
+            Pat::Ctor(Ctor::Array { length, rest: true }, _),
+            Ctor::Array {
+                length: ctor_length,
+                ..
+            },
+        ) => ctor_length >= length,
+        (Pat::Ctor(pattern, _), ctor) => pattern == ctor,
+    }
+}
+
+/// The rows of `matrix` that match `ctor`, with their first pattern replaced
+/// by the `arity` patterns inside it.
+fn specialize(matrix: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter(|row| matches(&row[0], ctor))
+        .map(|row| {
+            let mut specialized = match &row[0] {
+                Pat::Wild => vec![Pat::Wild; arity],
+                Pat::Ctor(_, arguments) => {
+                    let mut arguments = arguments.clone();
+                    arguments.resize(arity, Pat::Wild);
+                    arguments
+                }
+            };
+            specialized.extend_from_slice(&row[1..]);
+            specialized
+        })
+        .collect()
+}
+
+/// The rows of `matrix` that start with a wildcard, without it.
+fn default(matrix: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter(|row| row[0] == Pat::Wild)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// Whether some row of `matrix` starts with a pattern built from `ctor`.
+fn covers(matrix: &[Vec<Pat>], ctor: &Ctor) -> bool {
+    matrix
+        .iter()
+        .any(|row| row[0] != Pat::Wild && matches(&row[0], ctor))
+}
+
+fn useful(types: &TypeEnv, matrix: &[Vec<Pat>], row: &[Pat], tys: &[Type]) -> bool {
+    if row.is_empty() {
+        return matrix.is_empty();
+    }
+
+    let ty = &tys[0];
+    let column: Vec<&Pat> = matrix
+        .iter()
+        .map(|row| &row[0])
+        .chain(std::iter::once(&row[0]))
+        .collect();
+    let all = constructors(types, ty, &column);
+
+    let useful_for = |ctor: &Ctor| {
+        let mut field_types = fields(types, ctor, ty);
+        let arity = field_types.len();
+        field_types.extend_from_slice(&tys[1..]);
+        let row = &specialize(&[row.to_vec()], ctor, arity)[0];
+        useful(types, &specialize(matrix, ctor, arity), row, &field_types)
+    };
+
+    match (&row[0], all) {
+        (Pat::Wild, Some(all)) if all.iter().all(|ctor| covers(matrix, ctor)) => {
+            all.iter().any(useful_for)
+        }
+        (Pat::Wild, _) => useful(types, &default(matrix), &row[1..], &tys[1..]),
+        (head, Some(all)) => all
+            .iter()
+            .filter(|ctor| matches(head, ctor))
+            .any(useful_for),
+        (Pat::Ctor(ctor, _), None) => useful_for(ctor),
+    }
+}
+
+/// Rows of patterns, one per column of `tys`, that match values no row of
+/// `matrix` does.
+fn witnesses(types: &TypeEnv, matrix: &[Vec<Pat>], tys: &[Type]) -> Vec<Vec<Pat>> {
+    if tys.is_empty() {
+        return if matrix.is_empty() {
+            vec![Vec::new()]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let ty = &tys[0];
+    let column: Vec<&Pat> = matrix.iter().map(|row| &row[0]).collect();
+    let all = constructors(types, ty, &column);
+
+    match all {
+        Some(all) if all.iter().all(|ctor| covers(matrix, ctor)) => {
+            let mut found = Vec::new();
+            for ctor in all {
+                let mut field_types = fields(types, &ctor, ty);
+                let arity = field_types.len();
+                field_types.extend_from_slice(&tys[1..]);
+
+                for mut witness in witnesses(types, &specialize(matrix, &ctor, arity), &field_types)
+                {
+                    let rest = witness.split_off(arity);
+                    let mut row = vec![Pat::Ctor(ctor.clone(), witness)];
+                    row.extend(rest);
+                    found.push(row);
+                }
+                if found.len() >= WITNESS_LIMIT {
+                    break;
+                }
+            }
+            found.truncate(WITNESS_LIMIT);
+            found
+        }
+        all => {
+            let rest = witnesses(types, &default(matrix), &tys[1..]);
+            if rest.is_empty() {
+                return Vec::new();
+            }
+
+            // Name the missing constructors when some are matched, and
+            // otherwise say that anything at all is missing.
+            let heads: Vec<Pat> = match all {
+                Some(all) if column.iter().any(|pattern| **pattern != Pat::Wild) => all
+                    .into_iter()
+                    .filter(|ctor| !covers(matrix, ctor))
+                    .map(|ctor| {
+                        let arity = fields(types, &ctor, ty).len();
+                        Pat::Ctor(ctor, vec![Pat::Wild; arity])
+                    })
+                    .collect(),
+                _ => vec![Pat::Wild],
+            };
+
+            heads
+                .iter()
+                .flat_map(|head| {
+                    rest.iter().map(move |rest| {
+                        let mut row = vec![head.clone()];
+                        row.extend(rest.iter().cloned());
+                        row
+                    })
+                })
+                .take(WITNESS_LIMIT)
+                .collect()
+        }
+    }
+}
+
+impl fmt::Display for Pat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ctor, arguments) = match self {
+            Pat::Wild => return write!(f, "_"),
+            Pat::Ctor(ctor, arguments) => (ctor, arguments),
+        };
+        let list = |separator: &str| {
+            arguments
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(separator)
+        };
+
+        match ctor {
+            Ctor::Variant(name) if arguments.is_empty() => write!(f, "{}", name),
+            Ctor::Variant(name) => write!(f, "{}({})", name, list(", ")),
+            Ctor::Tuple => write!(f, "({})", list(", ")),
+            Ctor::Record(names) => {
+                let fields: Vec<String> = names
+                    .iter()
+                    .zip(arguments)
+                    .map(|(name, argument)| format!("{}: {}", name, argument))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Ctor::Boolean(value) => write!(f, "{}", value),
+            Ctor::Literal(text) => write!(f, "{}", text),
+            Ctor::Array { rest: false, .. } => write!(f, "[{}]", list(", ")),
+            Ctor::Array { rest: true, .. } if arguments.is_empty() => write!(f, "[...]"),
+            Ctor::Array { rest: true, .. } => write!(f, "[{}, ...]", list(", ")),
+        }
+    }
+}
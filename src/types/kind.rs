@@ -0,0 +1,137 @@
+//! Kinds classify types the way types classify values: `*` is the kind of
+//! types that have values, and `* -> *` the kind of constructors such as
+//! `Option` that build one from another. The kinds of a declaration's type
+//! parameters are inferred from how its body uses them.
+
+use crate::parsing::ast::TypeParameter;
+use std::fmt;
+
+/// Identifies a kind variable of a `Kinds`.
+pub type KindVar = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Star,
+    Arrow(Box<Kind>, Box<Kind>),
+    Var(KindVar),
+}
+
+impl Kind {
+    /// The kind of a constructor taking arguments of kinds `parameters` to a
+    /// type of kind `result`.
+    pub fn arrow(parameters: Vec<Kind>, result: Kind) -> Kind {
+        parameters
+            .into_iter()
+            .rev()
+            .fold(result, |result, parameter| {
+                Kind::Arrow(Box::new(parameter), Box::new(result))
+            })
+    }
+
+    /// The kind written for a type parameter: `F<_>` is `* -> *`, and
+    /// anything without parameters is `*`.
+    pub fn of_parameter(parameter: &TypeParameter) -> Kind {
+        match parameter {
+            TypeParameter::HigherKinded { parameters, .. } => Kind::arrow(
+                parameters.iter().map(Kind::of_parameter).collect(),
+                Kind::Star,
+            ),
+            TypeParameter::Generic { .. } | TypeParameter::Placeholder { .. } => Kind::Star,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Star => write!(f, "*"),
+            Kind::Arrow(parameter, result) => match **parameter {
+                Kind::Arrow(..) => write!(f, "({}) -> {}", parameter, result),
+                _ => write!(f, "{} -> {}", parameter, result),
+            },
+            Kind::Var(var) => write!(f, "?k{}", var),
+        }
+    }
+}
+
+/// The solutions found so far for every kind variable.
+#[derive(Debug, Clone, Default)]
+pub struct Kinds {
+    bindings: Vec<Option<Kind>>,
+}
+
+impl Kinds {
+    pub fn new() -> Self {
+        Kinds {
+            bindings: Vec::new(),
+        }
+    }
+
+    pub fn fresh(&mut self) -> Kind {
+        self.bindings.push(None);
+        Kind::Var(self.bindings.len() - 1)
+    }
+
+    /// Follows variable bindings at the top of `kind` only.
+    pub fn shallow_resolve(&self, kind: &Kind) -> Kind {
+        let mut kind = kind.clone();
+        while let Kind::Var(var) = kind {
+            match &self.bindings[var] {
+                Some(bound) => kind = bound.clone(),
+                None => break,
+            }
+        }
+        kind
+    }
+
+    pub fn resolve(&self, kind: &Kind) -> Kind {
+        match self.shallow_resolve(kind) {
+            Kind::Arrow(parameter, result) => Kind::Arrow(
+                Box::new(self.resolve(&parameter)),
+                Box::new(self.resolve(&result)),
+            ),
+            kind => kind,
+        }
+    }
+
+    /// Resolves `kind`, taking `*` for anything still unknown: a parameter
+    /// that is never applied to anything holds ordinary types.
+    pub fn default(&self, kind: &Kind) -> Kind {
+        match self.shallow_resolve(kind) {
+            Kind::Arrow(parameter, result) => Kind::Arrow(
+                Box::new(self.default(&parameter)),
+                Box::new(self.default(&result)),
+            ),
+            Kind::Var(_) | Kind::Star => Kind::Star,
+        }
+    }
+
+    /// Makes `a` and `b` equal, returning whether they could be.
+    pub fn unify(&mut self, a: &Kind, b: &Kind) -> bool {
+        match (self.shallow_resolve(a), self.shallow_resolve(b)) {
+            (Kind::Var(a), Kind::Var(b)) if a == b => true,
+            (Kind::Var(var), kind) | (kind, Kind::Var(var)) => {
+                if self.occurs(var, &kind) {
+                    return false;
+                }
+                self.bindings[var] = Some(kind);
+                true
+            }
+            (Kind::Star, Kind::Star) => true,
+            (Kind::Arrow(a_parameter, a_result), Kind::Arrow(b_parameter, b_result)) => {
+                self.unify(&a_parameter, &b_parameter) && self.unify(&a_result, &b_result)
+            }
+            _ => false,
+        }
+    }
+
+    fn occurs(&self, var: KindVar, kind: &Kind) -> bool {
+        match self.shallow_resolve(kind) {
+            Kind::Var(other) => other == var,
+            Kind::Arrow(parameter, result) => {
+                self.occurs(var, &parameter) || self.occurs(var, &result)
+            }
+            Kind::Star => false,
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Checks that the clauses of one-shot handlers resume the computation they
+//! handle at most once. Every path through a clause is followed, counting
+//! the `resume`s on it, and a `resume` in a loop counts as many unless the
+//! loop is always left after it with a `break` or a `return`. A
+//! continuation that escapes into a lambda or a nested handler is not
+//! followed: resuming it twice is caught when the program runs.
+
+use crate::lexing::token::Span;
+use crate::parsing::ast::{Declaration, Expression, Spanned, Statement, TemplatePart};
+
+/// The first two uses of `resume` in the clause `body` that can both run,
+/// if there are any.
+pub fn resumed_twice(body: &[Statement]) -> Option<(Span, Span)> {
+    let flow = statements(body);
+    match worst(flow.normal, flow.returned)?.as_slice() {
+        [first, second, ..] => Some((first.clone(), second.clone())),
+        _ => None,
+    }
+}
+
+// The uses of `resume` on the path through some code that resumes most
+// often, kept up to the second, for each way of leaving the code.
+#[derive(Debug, Clone)]
+struct Flow {
+    // Paths that carry on after the code, `None` when none can.
+    normal: Option<Vec<Span>>,
+    // Paths that `return` from the clause.
+    returned: Option<Vec<Span>>,
+    // Paths that `break` out of the loop the code is in, and that `continue`
+    // it.
+    broken: Option<Vec<Span>>,
+    continued: Option<Vec<Span>>,
+}
+
+impl Flow {
+    fn straight(path: Vec<Span>) -> Self {
+        Flow {
+            normal: Some(path),
+            returned: None,
+            broken: None,
+            continued: None,
+        }
+    }
+
+    /// `self` followed by `next`.
+    fn then(self, next: Flow) -> Flow {
+        let normal = self.normal.as_ref();
+        let after = |next: Option<Vec<Span>>| normal.zip(next).map(|(a, b)| join(a, &b));
+        Flow {
+            normal: after(next.normal),
+            returned: worst(self.returned, after(next.returned)),
+            broken: worst(self.broken, after(next.broken)),
+            continued: worst(self.continued, after(next.continued)),
+        }
+    }
+
+    /// Either `self` or `other`.
+    fn or(self, other: Flow) -> Flow {
+        Flow {
+            normal: worst(self.normal, other.normal),
+            returned: worst(self.returned, other.returned),
+            broken: worst(self.broken, other.broken),
+            continued: worst(self.continued, other.continued),
+        }
+    }
+
+    /// The body of a loop once it is through: a `continue` goes on to the
+    /// next iteration like the end of the body does.
+    fn iteration(self) -> Flow {
+        Flow {
+            normal: worst(self.normal, self.continued),
+            continued: None,
+            ..self
+        }
+    }
+
+    /// `self`, the iteration of a loop, run any number of times, and then
+    /// left either normally or with a `break`.
+    fn repeated(self) -> Flow {
+        let flow = Flow::straight(Vec::new()).or(self.clone().then(self));
+        Flow {
+            normal: worst(flow.normal, flow.broken),
+            broken: None,
+            ..flow
+        }
+    }
+}
+
+fn join(first: &[Span], second: &[Span]) -> Vec<Span> {
+    first.iter().chain(second).take(2).cloned().collect()
+}
+
+fn worst(a: Option<Vec<Span>>, b: Option<Vec<Span>>) -> Option<Vec<Span>> {
+    match (a, b) {
+        (Some(a), Some(b)) if b.len() > a.len() => Some(b),
+        (Some(a), _) => Some(a),
+        (None, b) => b,
+    }
+}
+
+fn statements(statements: &[Statement]) -> Flow {
+    statements
+        .iter()
+        .fold(Flow::straight(Vec::new()), |flow, next| {
+            flow.then(statement(next))
+        })
+}
+
+fn statement(statement: &Statement) -> Flow {
+    match statement {
+        Statement::Expression { expr, .. } => expression(expr),
+        Statement::Return { expr, .. } => {
+            let flow = expression(expr);
+            Flow {
+                normal: None,
+                returned: worst(flow.returned, flow.normal),
+                ..flow
+            }
+        }
+        Statement::If {
+            condition,
+            then_branch,
+            else_if_branches,
+            else_branch,
+            ..
+        } => {
+            let otherwise = else_if_branches.iter().rev().fold(
+                statements(else_branch.as_deref().unwrap_or_default()),
+                |otherwise, (condition, branch)| {
+                    expression(condition).then(statements(branch).or(otherwise))
+                },
+            );
+            expression(condition).then(statements(then_branch).or(otherwise))
+        }
+        Statement::While {
+            condition, body, ..
+        } => expression(condition).then(
+            statements(body)
+                .iteration()
+                .then(expression(condition))
+                .repeated(),
+        ),
+        Statement::For {
+            initializer,
+            condition,
+            increment,
+            body,
+            ..
+        } => expression(initializer).then(expression(condition)).then(
+            statements(body)
+                .iteration()
+                .then(expression(increment))
+                .then(expression(condition))
+                .repeated(),
+        ),
+        Statement::ForOf { iterable, body, .. } | Statement::ForIn { iterable, body, .. } => {
+            expression(iterable).then(statements(body).iteration().repeated())
+        }
+        Statement::Declaration(Declaration::Let { value, .. }) => expression(value),
+        Statement::Break { .. } => Flow {
+            normal: None,
+            returned: None,
+            broken: Some(Vec::new()),
+            continued: None,
+        },
+        Statement::Continue { .. } => Flow {
+            normal: None,
+            returned: None,
+            broken: None,
+            continued: Some(Vec::new()),
+        },
+        Statement::Import { .. } | Statement::Declaration(_) | Statement::Error { .. } => {
+            Flow::straight(Vec::new())
+        }
+    }
+}
+
+fn sequence<'e, 'a: 'e>(expressions: impl IntoIterator<Item = &'e Expression<'a>>) -> Flow {
+    expressions
+        .into_iter()
+        .fold(Flow::straight(Vec::new()), |flow, next| {
+            flow.then(expression(next))
+        })
+}
+
+fn expression(expression: &Expression) -> Flow {
+    match expression {
+        Expression::Resume { expression, span } => {
+            self::expression(expression).then(Flow::straight(vec![span.clone()]))
+        }
+        Expression::Continuation { span } => Flow::straight(vec![span.clone()]),
+        Expression::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => self::expression(condition)
+            .then(self::expression(then_branch).or(self::expression(else_branch))),
+        Expression::Match {
+            scrutinee, arms, ..
+        } => {
+            let arms = arms
+                .iter()
+                .map(|arm| sequence(arm.guard.iter().chain([&arm.body])))
+                .reduce(Flow::or)
+                .unwrap_or_else(|| Flow::straight(Vec::new()));
+            self::expression(scrutinee).then(arms)
+        }
+        Expression::Template { parts, .. } => {
+            sequence(parts.iter().filter_map(|part| match part {
+                TemplatePart::Expression { expression, .. } => Some(&**expression),
+                TemplatePart::Literal { .. } => None,
+            }))
+        }
+        Expression::Array { elements, .. } | Expression::Tuple { elements, .. } => {
+            sequence(elements)
+        }
+        Expression::Record {
+            fields, spreads, ..
+        } => {
+            let mut values: Vec<&Expression> = fields.values().collect();
+            values.sort_by_key(|value| value.span().start);
+            sequence(spreads.iter().chain(values))
+        }
+        Expression::Member { object, .. } => self::expression(object),
+        Expression::Index { object, index, .. } => sequence([&**object, &**index]),
+        Expression::Binary { left, right, .. } => sequence([&**left, &**right]),
+        Expression::Unary { expr, .. } => self::expression(expr),
+        Expression::Assignment { target, value, .. } => sequence([&**target, &**value]),
+        Expression::Block { statements, .. } => self::statements(statements),
+        Expression::FunctionCall {
+            function,
+            arguments,
+            ..
+        } => sequence(std::iter::once(&**function).chain(arguments)),
+        Expression::Yield { expression, .. } | Expression::Perform { expression, .. } => {
+            self::expression(expression)
+        }
+        Expression::Handle {
+            effect, expression, ..
+        } => sequence([&**effect, &**expression]),
+        Expression::With {
+            expression,
+            handler,
+            ..
+        } => sequence([&**handler, &**expression]),
+        // What these do with a continuation is up to whoever calls them.
+        Expression::Lambda { .. } | Expression::Handler(_) => Flow::straight(Vec::new()),
+        Expression::String { .. }
+        | Expression::Integer { .. }
+        | Expression::Decimal { .. }
+        | Expression::BigInteger { .. }
+        | Expression::BigDecimal { .. }
+        | Expression::Boolean { .. }
+        | Expression::Symbol { .. }
+        | Expression::Identifier { .. }
+        | Expression::Error { .. } => Flow::straight(Vec::new()),
+    }
+}
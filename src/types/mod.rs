@@ -1,30 +1,828 @@
+pub mod checker;
+pub mod decision;
+pub mod exhaustiveness;
+pub mod kind;
+pub mod linearity;
+mod subtype;
+mod unify;
+pub mod variance;
+
+use crate::parsing::ast::{Literal, Variance};
+use kind::Kind;
+use std::collections::HashMap;
+use std::fmt;
+
+pub use checker::{check, Checked};
+
+/// Identifies a unification variable. Variables are only meaningful relative
+/// to the substitution that created them.
+pub type TypeVar = usize;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
-    Number,
+    Integer,
+    Float,
+    BigInteger,
+    BigDecimal,
     Boolean,
     String,
-    Function(Vec<Type>, Box<Type>),
-    Struct(Vec<(String, Type)>),
+    Symbol,
+    // The type of expressions that never produce a value, such as `return`.
+    Never,
+    // The type of a single value of a primitive type, written as in the
+    // source, e.g. `"a"` or `1`. It widens to the primitive.
+    Literal(Box<Type>, String),
+    // The values of any of the types.
+    Union(Vec<Type>),
+    // The values of all of the types at once.
+    Intersection(Vec<Type>),
+    Var(TypeVar),
+    // A rigid type parameter, e.g. the `A` inside `fun id<A>(a: A): A`.
     Generic(String),
+    // A named type applied to its arguments, e.g. `Option<Int>`. Arguments
+    // may be holes, written `_`, that the type is still waiting for, as in
+    // `Result<String, _>`.
     HKT(String, Vec<Type>),
+    // A type constructor not known yet applied to arguments, as `F<A>` is
+    // once `F` is instantiated.
+    Apply(TypeVar, Vec<Type>),
+    // Parameters, return type and the effects a call may perform.
+    Function(Vec<Type>, Box<Type>, Box<Type>),
+    // The empty tuple is `Unit`.
+    Tuple(Vec<Type>),
+    Array(Box<Type>),
+    // Fields are kept sorted by name so that structural equality is order-free.
+    Struct(Vec<(String, Type)>),
+    // A record with at least the fields, sorted likewise, whose other fields
+    // are the row: a variable while they are unknown, or a row parameter
+    // such as the `R` of `{ x: Int, ...R }`.
+    Extend(Vec<(String, Type)>, Box<Type>),
+    // A row of effects, such as `Console | Fail<String>`: named effect types
+    // kept sorted by name with at most one of each, then the rest of the
+    // row. That is a variable while it is unknown, or an effect parameter,
+    // and a row without one has exactly the effects listed.
+    Effects(Vec<Type>, Option<Box<Type>>),
+    // A polymorphic type such as `<A>(A) -> A`: a value of this type works
+    // for every choice of its generics.
+    Forall(Vec<String>, Box<Type>),
+    // Stands for one of the generics of a `Forall` while a value is checked
+    // against it: an unknown type that is only equal to itself. The number
+    // tells apart skolems made for different checks.
+    Skolem(usize, String),
+}
+
+impl Type {
+    pub fn unit() -> Type {
+        Type::Tuple(Vec::new())
+    }
+
+    /// A function that performs no effects.
+    pub fn function(parameters: Vec<Type>, return_type: Type) -> Type {
+        Type::effectful(parameters, return_type, Type::pure())
+    }
+
+    pub fn effectful(parameters: Vec<Type>, return_type: Type, effects: Type) -> Type {
+        Type::Function(parameters, Box::new(return_type), Box::new(effects))
+    }
+
+    /// The row without any effects.
+    pub fn pure() -> Type {
+        Type::Effects(Vec::new(), None)
+    }
+
+    /// The row of `effects` followed by `rest`, normalized: a rest that is
+    /// itself a row is merged in, an effect listed earlier hiding any of the
+    /// same name after it, and a row of nothing but its rest is that rest.
+    pub fn effects(effects: Vec<Type>, rest: Option<Type>) -> Type {
+        let (more, rest) = match rest {
+            Some(Type::Effects(more, rest)) => (more, rest.map(|rest| *rest)),
+            rest => (Vec::new(), rest),
+        };
+        let mut merged: Vec<Type> = Vec::new();
+        for effect in effects.into_iter().chain(more) {
+            if !merged
+                .iter()
+                .any(|other| other.effect_name() == effect.effect_name())
+            {
+                merged.push(effect);
+            }
+        }
+        merged.sort_by_key(Type::effect_name);
+
+        match rest {
+            Some(rest) if merged.is_empty() => rest,
+            rest => Type::Effects(merged, rest.map(Box::new)),
+        }
+    }
+
+    /// The name an effect is known by in a row, e.g. `Fail` for
+    /// `Fail<String>`.
+    pub fn effect_name(&self) -> String {
+        match self {
+            Type::HKT(name, _) => name.clone(),
+            ty => ty.to_string(),
+        }
+    }
+
+    pub fn is_pure(&self) -> bool {
+        matches!(self, Type::Effects(effects, None) if effects.is_empty())
+    }
+
+    pub fn structure(mut fields: Vec<(String, Type)>) -> Type {
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Type::Struct(fields)
+    }
+
+    /// The record with `fields` besides those of `row`. A record row is
+    /// merged in, its fields giving way to `fields`, and no fields at all
+    /// leave just the row.
+    pub fn extend(fields: Vec<(String, Type)>, row: Type) -> Type {
+        let (others, row) = match row {
+            Type::Struct(others) => (others, None),
+            Type::Extend(others, row) => (others, Some(*row)),
+            row if fields.is_empty() => return row,
+            row => (Vec::new(), Some(row)),
+        };
+        let mut merged: Vec<(String, Type)> = others
+            .into_iter()
+            .filter(|(name, _)| !fields.iter().any(|(field, _)| field == name))
+            .collect();
+        merged.extend(fields);
+        merged.sort_by(|(a, _), (b, _)| a.cmp(b));
+        match row {
+            Some(row) => Type::Extend(merged, Box::new(row)),
+            None => Type::Struct(merged),
+        }
+    }
+
+    /// The type whose only value is `literal`.
+    pub fn literal(literal: &Literal) -> Type {
+        let (primitive, text) = match literal {
+            Literal::Integer(value) => (Type::Integer, value.to_string()),
+            Literal::Decimal(value) => (Type::Float, value.to_string()),
+            Literal::BigInteger(value) => (Type::BigInteger, format!("{}n", value)),
+            Literal::BigDecimal(value) => (Type::BigDecimal, format!("{}n", value)),
+            Literal::Boolean(value) => (Type::Boolean, value.to_string()),
+            Literal::String(value) => (Type::String, format!("{:?}", value)),
+            Literal::Symbol(name) => (Type::Symbol, format!("Symbol({})", name)),
+        };
+        Type::Literal(Box::new(primitive), text)
+    }
+
+    /// The union of `members`, normalized: nested unions are flattened,
+    /// repeated members and `Never` are dropped, and a literal is absorbed
+    /// by its primitive when both are members. A single member is returned
+    /// as it is, and no members at all make `Never`.
+    pub fn union(members: Vec<Type>) -> Type {
+        let mut flattened: Vec<Type> = Vec::new();
+        for member in members {
+            let parts = match member {
+                Type::Union(parts) => parts,
+                Type::Never => Vec::new(),
+                member => vec![member],
+            };
+            for part in parts {
+                if !flattened.contains(&part) {
+                    flattened.push(part);
+                }
+            }
+        }
+        let primitives = flattened.clone();
+        flattened.retain(|member| match member {
+            Type::Literal(primitive, _) => !primitives.contains(primitive),
+            _ => true,
+        });
+
+        match flattened.len() {
+            0 => Type::Never,
+            1 => flattened.remove(0),
+            _ => Type::Union(flattened),
+        }
+    }
+
+    /// The intersection of `members`, normalized like a union: nested
+    /// intersections are flattened and repeats dropped. It is `Never` when a
+    /// member is, or when two different literals must hold at once.
+    pub fn intersection(members: Vec<Type>) -> Type {
+        let mut flattened: Vec<Type> = Vec::new();
+        for member in members {
+            let parts = match member {
+                Type::Intersection(parts) => parts,
+                member => vec![member],
+            };
+            for part in parts {
+                if !flattened.contains(&part) {
+                    flattened.push(part);
+                }
+            }
+        }
+        let literals = flattened
+            .iter()
+            .filter(|member| matches!(member, Type::Literal(..)))
+            .count();
+        if flattened.contains(&Type::Never) || literals > 1 {
+            return Type::Never;
+        }
+
+        match flattened.len() {
+            1 => flattened.remove(0),
+            _ => Type::Intersection(flattened),
+        }
+    }
+
+    /// The members of a union, or the type itself when it is not one.
+    pub fn members(&self) -> Vec<Type> {
+        match self {
+            Type::Union(members) => members.clone(),
+            Type::Never => Vec::new(),
+            ty => vec![ty.clone()],
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Type::Integer | Type::Float | Type::BigInteger | Type::BigDecimal
+        )
+    }
+
+    pub fn is_integral(&self) -> bool {
+        matches!(self, Type::Integer | Type::BigInteger)
+    }
+
+    /// The hole in a partially applied type, as in `Result<String, _>`.
+    pub fn hole() -> Type {
+        Type::Generic("_".to_string())
+    }
+
+    /// Applies the type constructor `self` to `arguments`, which first fill
+    /// its holes in order.
+    pub fn apply(&self, arguments: Vec<Type>) -> Type {
+        match self {
+            Type::HKT(name, applied) => {
+                let mut arguments = arguments.into_iter();
+                let mut applied: Vec<Type> = applied
+                    .iter()
+                    .map(|argument| match argument {
+                        Type::Generic(hole) if hole == "_" => {
+                            arguments.next().unwrap_or_else(|| argument.clone())
+                        }
+                        argument => argument.clone(),
+                    })
+                    .collect();
+                applied.extend(arguments);
+                Type::HKT(name.clone(), applied)
+            }
+            Type::Generic(name) => Type::HKT(name.clone(), arguments),
+            Type::Var(var) => Type::Apply(*var, arguments),
+            Type::Apply(var, applied) => {
+                Type::Apply(*var, applied.iter().cloned().chain(arguments).collect())
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Replaces the generics named in `names` with the matching entries of
+    /// `replacements`.
+    pub fn substitute(&self, names: &[String], replacements: &[Type]) -> Type {
+        match self {
+            Type::Generic(name) => names
+                .iter()
+                .position(|n| n == name)
+                .map(|index| replacements[index].clone())
+                .unwrap_or_else(|| self.clone()),
+            Type::HKT(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.substitute(names, replacements))
+                    .collect();
+                match names.iter().position(|n| n == name) {
+                    // A higher-kinded parameter applied to arguments.
+                    Some(index) => replacements[index].apply(arguments),
+                    None => Type::HKT(name.clone(), arguments),
+                }
+            }
+            Type::Apply(var, arguments) => Type::Apply(
+                *var,
+                arguments
+                    .iter()
+                    .map(|argument| argument.substitute(names, replacements))
+                    .collect(),
+            ),
+            Type::Function(parameters, return_type, effects) => Type::effectful(
+                parameters
+                    .iter()
+                    .map(|parameter| parameter.substitute(names, replacements))
+                    .collect(),
+                return_type.substitute(names, replacements),
+                effects.substitute(names, replacements),
+            ),
+            Type::Effects(effects, rest) => Type::effects(
+                effects
+                    .iter()
+                    .map(|effect| effect.substitute(names, replacements))
+                    .collect(),
+                rest.as_ref()
+                    .map(|rest| rest.substitute(names, replacements)),
+            ),
+            Type::Tuple(elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| element.substitute(names, replacements))
+                    .collect(),
+            ),
+            Type::Array(element) => Type::Array(Box::new(element.substitute(names, replacements))),
+            Type::Struct(fields) => Type::Struct(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), ty.substitute(names, replacements)))
+                    .collect(),
+            ),
+            Type::Extend(fields, row) => Type::extend(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), ty.substitute(names, replacements)))
+                    .collect(),
+                row.substitute(names, replacements),
+            ),
+            Type::Union(members) => Type::union(
+                members
+                    .iter()
+                    .map(|member| member.substitute(names, replacements))
+                    .collect(),
+            ),
+            Type::Intersection(members) => Type::intersection(
+                members
+                    .iter()
+                    .map(|member| member.substitute(names, replacements))
+                    .collect(),
+            ),
+            // The generics of a `Forall` shadow any outer ones of the same name.
+            Type::Forall(generics, body) => {
+                let (names, replacements): (Vec<String>, Vec<Type>) = names
+                    .iter()
+                    .zip(replacements)
+                    .filter(|(name, _)| !generics.contains(name))
+                    .map(|(name, replacement)| (name.clone(), replacement.clone()))
+                    .unzip();
+                Type::Forall(
+                    generics.clone(),
+                    Box::new(body.substitute(&names, &replacements)),
+                )
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Whether `part` appears anywhere in `ty`, which must be resolved.
+    pub fn contains(&self, part: &Type) -> bool {
+        self == part
+            || match self {
+                Type::HKT(_, arguments) | Type::Apply(_, arguments) => {
+                    arguments.iter().any(|ty| ty.contains(part))
+                }
+                Type::Function(parameters, return_type, effects) => {
+                    parameters.iter().any(|ty| ty.contains(part))
+                        || return_type.contains(part)
+                        || effects.contains(part)
+                }
+                Type::Effects(effects, rest) => {
+                    effects.iter().any(|ty| ty.contains(part))
+                        || rest.as_ref().is_some_and(|rest| rest.contains(part))
+                }
+                Type::Tuple(elements) => elements.iter().any(|ty| ty.contains(part)),
+                Type::Array(element) => element.contains(part),
+                Type::Struct(fields) => fields.iter().any(|(_, ty)| ty.contains(part)),
+                Type::Extend(fields, row) => {
+                    fields.iter().any(|(_, ty)| ty.contains(part)) || row.contains(part)
+                }
+                Type::Union(members) | Type::Intersection(members) => {
+                    members.iter().any(|ty| ty.contains(part))
+                }
+                Type::Forall(_, body) => body.contains(part),
+                _ => false,
+            }
+    }
+
+    /// The unification variables in `ty`, which must be resolved, in the
+    /// order they first appear.
+    pub fn free_vars(&self) -> Vec<TypeVar> {
+        let mut vars = Vec::new();
+        self.collect_vars(&mut vars);
+        vars
+    }
+
+    fn collect_vars(&self, vars: &mut Vec<TypeVar>) {
+        match self {
+            Type::Var(var) if !vars.contains(var) => vars.push(*var),
+            Type::HKT(_, arguments) => arguments.iter().for_each(|ty| ty.collect_vars(vars)),
+            Type::Apply(var, arguments) => {
+                if !vars.contains(var) {
+                    vars.push(*var);
+                }
+                arguments.iter().for_each(|ty| ty.collect_vars(vars));
+            }
+            Type::Function(parameters, return_type, effects) => {
+                parameters.iter().for_each(|ty| ty.collect_vars(vars));
+                return_type.collect_vars(vars);
+                effects.collect_vars(vars);
+            }
+            Type::Effects(effects, rest) => {
+                effects.iter().for_each(|ty| ty.collect_vars(vars));
+                if let Some(rest) = rest {
+                    rest.collect_vars(vars);
+                }
+            }
+            Type::Tuple(elements) => elements.iter().for_each(|ty| ty.collect_vars(vars)),
+            Type::Array(element) => element.collect_vars(vars),
+            Type::Struct(fields) => fields.iter().for_each(|(_, ty)| ty.collect_vars(vars)),
+            Type::Extend(fields, row) => {
+                fields.iter().for_each(|(_, ty)| ty.collect_vars(vars));
+                row.collect_vars(vars);
+            }
+            Type::Union(members) | Type::Intersection(members) => {
+                members.iter().for_each(|ty| ty.collect_vars(vars))
+            }
+            Type::Forall(_, body) => body.collect_vars(vars),
+            _ => {}
+        }
+    }
+
+    /// Replaces the variables in `vars` with the matching entries of
+    /// `replacements`.
+    pub fn replace_vars(&self, vars: &[TypeVar], replacements: &[Type]) -> Type {
+        let replace = |ty: &Type| ty.replace_vars(vars, replacements);
+        match self {
+            Type::Var(var) => vars
+                .iter()
+                .position(|v| v == var)
+                .map(|index| replacements[index].clone())
+                .unwrap_or_else(|| self.clone()),
+            Type::HKT(name, arguments) => {
+                Type::HKT(name.clone(), arguments.iter().map(replace).collect())
+            }
+            Type::Apply(var, arguments) => Type::Var(*var)
+                .replace_vars(vars, replacements)
+                .apply(arguments.iter().map(replace).collect()),
+            Type::Function(parameters, return_type, effects) => Type::effectful(
+                parameters.iter().map(replace).collect(),
+                replace(return_type),
+                replace(effects),
+            ),
+            Type::Effects(effects, rest) => Type::effects(
+                effects.iter().map(replace).collect(),
+                rest.as_deref().map(replace),
+            ),
+            Type::Tuple(elements) => Type::Tuple(elements.iter().map(replace).collect()),
+            Type::Array(element) => Type::Array(Box::new(replace(element))),
+            Type::Struct(fields) => Type::Struct(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), replace(ty)))
+                    .collect(),
+            ),
+            Type::Extend(fields, row) => Type::extend(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), replace(ty)))
+                    .collect(),
+                replace(row),
+            ),
+            Type::Union(members) => Type::union(members.iter().map(replace).collect()),
+            Type::Intersection(members) => {
+                Type::intersection(members.iter().map(replace).collect())
+            }
+            Type::Forall(generics, body) => Type::Forall(generics.clone(), Box::new(replace(body))),
+            _ => self.clone(),
+        }
+    }
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Integer => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::BigInteger => write!(f, "BigInt"),
+            Type::BigDecimal => write!(f, "BigDecimal"),
+            Type::Boolean => write!(f, "Boolean"),
+            Type::String => write!(f, "String"),
+            Type::Symbol => write!(f, "Symbol"),
+            Type::Never => write!(f, "Never"),
+            Type::Literal(_, value) => write!(f, "{}", value),
+            Type::Union(members) => write!(f, "{}", separated(members, " | ")),
+            Type::Intersection(members) => write!(f, "{}", separated(members, " & ")),
+            Type::Var(var) => write!(f, "?{}", var),
+            Type::Generic(name) => write!(f, "{}", name),
+            Type::HKT(name, arguments) => {
+                write!(f, "{}", name)?;
+                if !arguments.is_empty() {
+                    write!(f, "<{}>", comma_separated(arguments))?;
+                }
+                Ok(())
+            }
+            Type::Apply(var, arguments) => {
+                write!(f, "?{}<{}>", var, comma_separated(arguments))
+            }
+            Type::Function(parameters, return_type, effects) if effects.is_pure() => {
+                write!(f, "({}) -> {}", comma_separated(parameters), return_type)
+            }
+            Type::Function(parameters, return_type, effects) => write!(
+                f,
+                "({}) -> Effect<{}, {}>",
+                comma_separated(parameters),
+                effects,
+                return_type
+            ),
+            Type::Effects(effects, rest) if effects.is_empty() && rest.is_none() => {
+                write!(f, "Unit")
+            }
+            Type::Effects(effects, rest) => {
+                let rest = rest.iter().map(|rest| (**rest).clone());
+                let members: Vec<Type> = effects.iter().cloned().chain(rest).collect();
+                write!(f, "{}", separated(&members, " | "))
+            }
+            Type::Tuple(elements) if elements.is_empty() => write!(f, "Unit"),
+            Type::Tuple(elements) => write!(f, "({})", comma_separated(elements)),
+            Type::Array(element) => write!(f, "[{}]", element),
+            Type::Struct(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Type::Extend(fields, row) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty))
+                    .chain(std::iter::once(format!("...{}", row)))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Type::Forall(generics, body) => write!(f, "<{}>{}", generics.join(", "), body),
+            Type::Skolem(_, name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// Members of a union or intersection, with functions parenthesized so that
+// their return types do not swallow the rest.
+fn separated(members: &[Type], separator: &str) -> String {
+    members
+        .iter()
+        .map(|member| match member {
+            Type::Function(..) | Type::Union(_) | Type::Intersection(_) => format!("({})", member),
+            member => member.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn comma_separated(types: &[Type]) -> String {
+    types
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A type with generics that are instantiated afresh at every use. Generics
+/// found by generalization rather than written in the source are named
+/// `'a`, `'b`, ..., which no type parameter can clash with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheme {
+    pub generics: Vec<String>,
+    pub ty: Type,
+    // Bounds that every instantiation of some of the generics must meet.
+    pub bounds: Vec<(String, Bound)>,
+}
+
+impl Scheme {
+    pub fn monomorphic(ty: Type) -> Self {
+        Scheme {
+            generics: Vec::new(),
+            ty,
+            bounds: Vec::new(),
+        }
+    }
+
+    /// The name of the `index`th generic found by generalization.
+    pub fn inferred_generic(index: usize) -> String {
+        let letter = (b'a' + (index % 26) as u8) as char;
+        match index / 26 {
+            0 => format!("'{}", letter),
+            round => format!("'{}{}", letter, round),
+        }
+    }
+
+    pub fn is_inferred(generic: &str) -> bool {
+        generic.starts_with('\'')
+    }
+}
+
+/// A bound on a type parameter, as in `A <: Animal` or `A >: Cat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bound {
+    // The parameter must be a subtype of this.
+    Upper(Type),
+    // The parameter must be a supertype of this.
+    Lower(Type),
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Upper(ty) => write!(f, "<: {}", ty),
+            Bound::Lower(ty) => write!(f, ">: {}", ty),
+        }
+    }
+}
+
+/// A named type declaration: an alias expands to its body, anything else is
+/// nominal and stays a `Type::HKT`. Algebraic data types also know their
+/// constructors, and effects can be performed and handled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeDefinition {
+    Alias {
+        parameters: Vec<String>,
+        ty: Type,
+    },
+    Nominal {
+        parameters: Vec<String>,
+    },
+    Data {
+        parameters: Vec<String>,
+        constructors: Vec<Constructor>,
+    },
+    Effect {
+        parameters: Vec<String>,
+        operations: Vec<Operation>,
+    },
+}
+
+impl TypeDefinition {
+    pub fn arity(&self) -> usize {
+        self.parameters().len()
+    }
+
+    pub fn parameters(&self) -> &[String] {
+        match self {
+            TypeDefinition::Alias { parameters, .. }
+            | TypeDefinition::Nominal { parameters }
+            | TypeDefinition::Data { parameters, .. }
+            | TypeDefinition::Effect { parameters, .. } => parameters,
+        }
+    }
+}
+
+/// One case of an algebraic data type, with field types written in terms of
+/// the type's parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constructor {
+    pub name: String,
+    pub fields: ConstructorFields,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstructorFields {
+    Void,
+    Tuple(Vec<Type>),
+    // Sorted by name, like `Type::Struct`.
+    Record(Vec<(String, Type)>),
+}
+
+impl Constructor {
+    /// The type of the constructor used as a value in a type named `owner`:
+    /// the data type itself for a constant, otherwise a function building it.
+    /// A record constructor takes its fields as a single record.
+    pub fn scheme(&self, owner: &str, parameters: &[String]) -> Scheme {
+        let result = Type::HKT(
+            owner.to_string(),
+            parameters
+                .iter()
+                .map(|parameter| Type::Generic(parameter.clone()))
+                .collect(),
+        );
+        let ty = match &self.fields {
+            ConstructorFields::Void => result,
+            ConstructorFields::Tuple(fields) => Type::function(fields.clone(), result),
+            ConstructorFields::Record(fields) => {
+                Type::function(vec![Type::Struct(fields.clone())], result)
+            }
+        };
+
+        Scheme {
+            generics: parameters.to_vec(),
+            ty,
+            bounds: Vec::new(),
+        }
+    }
+}
+
+/// An operation of an effect, with its types written in terms of the
+/// effect's parameters and its own `generics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub name: String,
+    pub generics: Vec<String>,
+    pub parameters: Vec<Type>,
+    pub return_type: Type,
+}
+
+impl Operation {
+    /// The type of the operation called as a function of the effect named
+    /// `owner`, which performs that effect.
+    pub fn scheme(&self, owner: &str, parameters: &[String]) -> Scheme {
+        let effect = Type::HKT(
+            owner.to_string(),
+            parameters
+                .iter()
+                .map(|parameter| Type::Generic(parameter.clone()))
+                .collect(),
+        );
+        let ty = Type::effectful(
+            self.parameters.clone(),
+            self.return_type.clone(),
+            Type::effects(vec![effect], None),
+        );
+
+        Scheme {
+            generics: parameters.iter().chain(&self.generics).cloned().collect(),
+            ty,
+            bounds: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct TypeEnv {
-    types: std::collections::HashMap<String, Type>,
+    types: HashMap<String, TypeDefinition>,
+    // The kinds of the parameters of each type, for those that take
+    // anything other than `*`.
+    kinds: HashMap<String, Vec<Kind>>,
+    // How subtyping of each type follows its parameters, for those known
+    // not to be invariant in all of them.
+    variances: HashMap<String, Vec<Variance>>,
 }
 
 impl TypeEnv {
     pub fn new() -> Self {
         TypeEnv {
-            types: std::collections::HashMap::new(),
+            types: HashMap::new(),
+            kinds: HashMap::new(),
+            variances: HashMap::new(),
+        }
+    }
+
+    pub fn set_parameter_variances(&mut self, name: String, variances: Vec<Variance>) {
+        self.variances.insert(name, variances);
+    }
+
+    /// The variances of the parameters of the type `name`.
+    pub fn parameter_variances(&self, name: &str) -> Vec<Variance> {
+        match (self.variances.get(name), self.types.get(name)) {
+            (Some(variances), _) => variances.clone(),
+            (None, Some(definition)) => vec![Variance::Invariant; definition.arity()],
+            (None, None) => Vec::new(),
         }
     }
 
-    pub fn add_type(&mut self, name: String, ty: Type) {
-        self.types.insert(name, ty);
+    pub fn set_parameter_kinds(&mut self, name: String, kinds: Vec<Kind>) {
+        self.kinds.insert(name, kinds);
     }
 
-    pub fn get_type(&self, name: &str) -> Option<&Type> {
+    /// The kinds of the parameters of the type `name`.
+    pub fn parameter_kinds(&self, name: &str) -> Vec<Kind> {
+        match (self.kinds.get(name), self.types.get(name)) {
+            (Some(kinds), _) => kinds.clone(),
+            (None, Some(definition)) => vec![Kind::Star; definition.arity()],
+            (None, None) => Vec::new(),
+        }
+    }
+
+    pub fn add_type(&mut self, name: String, definition: TypeDefinition) {
+        self.types.insert(name, definition);
+    }
+
+    pub fn get_type(&self, name: &str) -> Option<&TypeDefinition> {
         self.types.get(name)
     }
-}
\ No newline at end of file
+
+    /// The names of the data types with a constructor called `name`, sorted.
+    pub fn constructor_owners(&self, name: &str) -> Vec<&str> {
+        let mut owners: Vec<&str> = self
+            .types
+            .iter()
+            .filter(|(_, definition)| match definition {
+                TypeDefinition::Data { constructors, .. } => constructors
+                    .iter()
+                    .any(|constructor| constructor.name == name),
+                _ => false,
+            })
+            .map(|(owner, _)| owner.as_str())
+            .collect();
+        owners.sort_unstable();
+        owners
+    }
+}
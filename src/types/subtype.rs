@@ -0,0 +1,190 @@
+//! The subtyping relation: when a value of one type can be used where
+//! another is expected. Records may have more fields than needed, unions
+//! and intersections are compared member by member, literal types widen to
+//! their primitive, and named types follow the variance of their
+//! parameters. Unknown types are never guessed at: a variable is simply
+//! made equal to the other side.
+
+use super::unify::{Substitution, UnifyError};
+use super::{Bound, Type, TypeEnv};
+use crate::parsing::ast::Variance;
+use std::collections::HashMap;
+
+/// What subtyping needs to know besides the types themselves.
+pub struct Subtyping<'a> {
+    pub types: &'a TypeEnv,
+    // The bounds on the type parameters in scope.
+    pub bounds: &'a HashMap<String, Bound>,
+}
+
+impl Substitution {
+    /// Makes `found` a subtype of `expected`, solving variables on the way.
+    pub fn subtype(
+        &mut self,
+        context: &Subtyping,
+        found: &Type,
+        expected: &Type,
+    ) -> Result<(), UnifyError> {
+        let found = self.shallow_resolve(found);
+        let expected = self.shallow_resolve(expected);
+        if found == expected {
+            return Ok(());
+        }
+        if matches!(found, Type::Var(_)) || matches!(expected, Type::Var(_)) {
+            return self.unify(&expected, &found);
+        }
+
+        // A bounded parameter stands for some subtype of its upper bound, or
+        // some supertype of its lower bound.
+        if let Type::Generic(name) = &found {
+            if let Some(Bound::Upper(upper)) = context.bounds.get(name) {
+                if self.attempt(context, upper, &expected) {
+                    return Ok(());
+                }
+            }
+        }
+        if let Type::Generic(name) = &expected {
+            if let Some(Bound::Lower(lower)) = context.bounds.get(name) {
+                if self.attempt(context, &found, lower) {
+                    return Ok(());
+                }
+            }
+        }
+
+        let result = match (&found, &expected) {
+            (Type::Never, _) => Ok(()),
+            // Nothing else is, so only `Never` itself fits.
+            (_, Type::Never) => Err(self.mismatch(&expected, &found)),
+
+            (Type::Union(members), _) => members
+                .iter()
+                .try_for_each(|member| self.subtype(context, member, &expected)),
+            (_, Type::Intersection(members)) => members
+                .iter()
+                .try_for_each(|member| self.subtype(context, &found, member)),
+            (_, Type::Union(members)) => {
+                if members
+                    .iter()
+                    .any(|member| self.attempt(context, &found, member))
+                {
+                    Ok(())
+                } else {
+                    Err(self.mismatch(&expected, &found))
+                }
+            }
+            (Type::Intersection(members), _) => {
+                if members
+                    .iter()
+                    .any(|member| self.attempt(context, member, &expected))
+                {
+                    Ok(())
+                } else {
+                    Err(self.mismatch(&expected, &found))
+                }
+            }
+
+            (Type::Literal(primitive, _), _) => self.subtype(context, primitive, &expected),
+
+            // A record may have fields beyond those wanted.
+            (Type::Struct(found_fields), Type::Struct(expected_fields)) => {
+                expected_fields.iter().try_for_each(|(name, wanted)| {
+                    match found_fields.iter().find(|(field, _)| field == name) {
+                        Some((_, given)) => self.subtype(context, given, wanted),
+                        None => Err(self.mismatch(&expected, &found)),
+                    }
+                })
+            }
+            // An open record wants at least its fields, and its row takes
+            // whichever others are given.
+            (Type::Struct(found_fields), Type::Extend(expected_fields, row)) => expected_fields
+                .iter()
+                .try_for_each(|(name, wanted)| {
+                    match found_fields.iter().find(|(field, _)| field == name) {
+                        Some((_, given)) => self.subtype(context, given, wanted),
+                        None => Err(self.mismatch(&expected, &found)),
+                    }
+                })
+                .and_then(|_| {
+                    let rest = found_fields
+                        .iter()
+                        .filter(|(name, _)| !expected_fields.iter().any(|(field, _)| field == name))
+                        .cloned()
+                        .collect();
+                    self.unify(row, &Type::Struct(rest))
+                }),
+            // The fields an open record does not name must be in its row.
+            (Type::Extend(found_fields, row), Type::Struct(expected_fields)) => {
+                let mut missing = Vec::new();
+                expected_fields
+                    .iter()
+                    .try_for_each(|(name, wanted)| {
+                        match found_fields.iter().find(|(field, _)| field == name) {
+                            Some((_, given)) => self.subtype(context, given, wanted),
+                            None => {
+                                missing.push((name.clone(), wanted.clone()));
+                                Ok(())
+                            }
+                        }
+                    })
+                    .and_then(|_| {
+                        if missing.is_empty() {
+                            return Ok(());
+                        }
+                        let rest = self.fresh();
+                        self.unify(row, &Type::extend(missing, rest))
+                    })
+            }
+            (Type::Tuple(found_elements), Type::Tuple(expected_elements))
+                if found_elements.len() == expected_elements.len() =>
+            {
+                found_elements
+                    .iter()
+                    .zip(expected_elements)
+                    .try_for_each(|(found, expected)| self.subtype(context, found, expected))
+            }
+            // A function may perform fewer effects than allowed.
+            (
+                Type::Function(found_parameters, found_return, found_effects),
+                Type::Function(expected_parameters, expected_return, expected_effects),
+            ) if found_parameters.len() == expected_parameters.len() => expected_parameters
+                .iter()
+                .zip(found_parameters)
+                .try_for_each(|(expected, found)| self.subtype(context, expected, found))
+                .and_then(|_| self.subtype(context, found_return, expected_return))
+                .and_then(|_| self.include(found_effects, expected_effects)),
+            (
+                Type::HKT(found_name, found_arguments),
+                Type::HKT(expected_name, expected_arguments),
+            ) if found_name == expected_name
+                && found_arguments.len() == expected_arguments.len() =>
+            {
+                let variances = context.types.parameter_variances(found_name);
+                found_arguments
+                    .iter()
+                    .zip(expected_arguments)
+                    .enumerate()
+                    .try_for_each(|(index, (found, expected))| {
+                        match variances.get(index).unwrap_or(&Variance::Invariant) {
+                            Variance::Out => self.subtype(context, found, expected),
+                            Variance::In => self.subtype(context, expected, found),
+                            Variance::Invariant => self.unify(expected, found),
+                        }
+                    })
+            }
+
+            _ => self.unify(&expected, &found),
+        };
+        result.map_err(|error| self.widen(error, &expected, &found))
+    }
+
+    // Whether `found` is a subtype of `expected`, leaving the substitution
+    // as it was if it is not.
+    fn attempt(&mut self, context: &Subtyping, found: &Type, expected: &Type) -> bool {
+        let saved = self.clone();
+        let success = self.subtype(context, found, expected).is_ok();
+        if !success {
+            *self = saved;
+        }
+        success
+    }
+}
@@ -0,0 +1,441 @@
+use super::{Type, TypeVar};
+
+/// Why two types could not be unified. Types are resolved as far as possible
+/// so they can be shown to the user as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnifyError {
+    Mismatch { expected: Type, found: Type },
+    InfiniteType { variable: TypeVar, ty: Type },
+}
+
+/// The solutions found so far for every unification variable.
+#[derive(Debug, Clone, Default)]
+pub struct Substitution {
+    bindings: Vec<Option<Type>>,
+    skolems: usize,
+}
+
+impl Substitution {
+    pub fn new() -> Self {
+        Substitution {
+            bindings: Vec::new(),
+            skolems: 0,
+        }
+    }
+
+    pub fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    /// A new skolem for the generic `name`.
+    pub fn skolem(&mut self, name: &str) -> Type {
+        self.skolems += 1;
+        Type::Skolem(self.skolems, name.to_string())
+    }
+
+    /// Follows variable bindings at the top of `ty` only, applying any
+    /// constructor that has been found.
+    pub fn shallow_resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        loop {
+            ty = match &ty {
+                Type::Var(var) => match &self.bindings[*var] {
+                    Some(bound) => bound.clone(),
+                    None => break,
+                },
+                Type::Apply(var, arguments) => match &self.bindings[*var] {
+                    Some(bound) => bound.apply(arguments.clone()),
+                    None => break,
+                },
+                _ => break,
+            };
+        }
+        ty
+    }
+
+    /// Applies the substitution everywhere inside `ty`.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match self.shallow_resolve(ty) {
+            Type::HKT(name, arguments) => Type::HKT(
+                name,
+                arguments
+                    .iter()
+                    .map(|argument| self.resolve(argument))
+                    .collect(),
+            ),
+            Type::Apply(var, arguments) => Type::Apply(
+                var,
+                arguments
+                    .iter()
+                    .map(|argument| self.resolve(argument))
+                    .collect(),
+            ),
+            Type::Function(parameters, return_type, effects) => Type::effectful(
+                parameters
+                    .iter()
+                    .map(|parameter| self.resolve(parameter))
+                    .collect(),
+                self.resolve(&return_type),
+                self.resolve(&effects),
+            ),
+            Type::Effects(effects, rest) => Type::effects(
+                effects.iter().map(|effect| self.resolve(effect)).collect(),
+                rest.map(|rest| self.resolve(&rest)),
+            ),
+            Type::Tuple(elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.resolve(element))
+                    .collect(),
+            ),
+            Type::Array(element) => Type::Array(Box::new(self.resolve(&element))),
+            Type::Struct(fields) => Type::Struct(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), self.resolve(ty)))
+                    .collect(),
+            ),
+            Type::Extend(fields, row) => Type::extend(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), self.resolve(ty)))
+                    .collect(),
+                self.resolve(&row),
+            ),
+            Type::Union(members) => {
+                Type::union(members.iter().map(|member| self.resolve(member)).collect())
+            }
+            Type::Intersection(members) => {
+                Type::intersection(members.iter().map(|member| self.resolve(member)).collect())
+            }
+            Type::Forall(generics, body) => Type::Forall(generics, Box::new(self.resolve(&body))),
+            ty => ty,
+        }
+    }
+
+    pub fn unify(&mut self, expected: &Type, found: &Type) -> Result<(), UnifyError> {
+        let expected = self.shallow_resolve(expected);
+        let found = self.shallow_resolve(found);
+
+        match (&expected, &found) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => self.bind(*var, ty),
+
+            // `Never` has no values, so it can stand in for any type.
+            (Type::Never, _) | (_, Type::Never) => Ok(()),
+
+            (Type::Integer, Type::Integer)
+            | (Type::Float, Type::Float)
+            | (Type::BigInteger, Type::BigInteger)
+            | (Type::BigDecimal, Type::BigDecimal)
+            | (Type::Boolean, Type::Boolean)
+            | (Type::String, Type::String)
+            | (Type::Symbol, Type::Symbol) => Ok(()),
+
+            (Type::Generic(a), Type::Generic(b)) if a == b => Ok(()),
+            (Type::Literal(a, a_value), Type::Literal(b, b_value))
+                if a == b && a_value == b_value =>
+            {
+                Ok(())
+            }
+            (Type::Skolem(a, _), Type::Skolem(b, _)) if a == b => Ok(()),
+
+            // Two polymorphic types are equal if they are once their generics
+            // are replaced by the same unknown types.
+            (Type::Forall(a_generics, a_body), Type::Forall(b_generics, b_body))
+                if a_generics.len() == b_generics.len() =>
+            {
+                let skolems: Vec<Type> = a_generics.iter().map(|name| self.skolem(name)).collect();
+                let a = a_body.substitute(a_generics, &skolems);
+                let b = b_body.substitute(b_generics, &skolems);
+                self.unify(&a, &b)
+                    .map_err(|error| self.widen(error, &expected, &found))
+            }
+
+            (Type::HKT(a, a_arguments), Type::HKT(b, b_arguments))
+                if a == b && a_arguments.len() == b_arguments.len() =>
+            {
+                self.unify_all(a_arguments, b_arguments)
+                    .map_err(|_| self.mismatch(&expected, &found))
+            }
+
+            (Type::Apply(a, a_arguments), Type::Apply(b, b_arguments))
+                if a_arguments.len() == b_arguments.len() =>
+            {
+                self.unify(&Type::Var(*a), &Type::Var(*b))
+                    .and_then(|_| self.unify_all(a_arguments, b_arguments))
+                    .map_err(|_| self.mismatch(&expected, &found))
+            }
+
+            // `F<A>` is `Result<E, A>` when `F` is `Result<E, _>`, so the
+            // unknown constructor takes the arguments it is not given.
+            (Type::Apply(var, arguments), Type::HKT(name, applied))
+            | (Type::HKT(name, applied), Type::Apply(var, arguments))
+                if applied.len() >= arguments.len() =>
+            {
+                let (given, rest) = applied.split_at(applied.len() - arguments.len());
+                let constructor = Type::HKT(name.clone(), given.to_vec());
+                self.unify(&Type::Var(*var), &constructor)
+                    .and_then(|_| self.unify_all(arguments, rest))
+                    .map_err(|_| self.mismatch(&expected, &found))
+            }
+
+            (
+                Type::Function(a_parameters, a_return, a_effects),
+                Type::Function(b_parameters, b_return, b_effects),
+            ) if a_parameters.len() == b_parameters.len() => self
+                .unify_all(a_parameters, b_parameters)
+                .and_then(|_| self.unify(a_return, b_return))
+                .and_then(|_| self.unify(a_effects, b_effects))
+                .map_err(|error| self.widen(error, &expected, &found)),
+
+            (Type::Effects(..), _) | (_, Type::Effects(..)) => self
+                .unify_rows(&expected, &found)
+                .map_err(|error| self.widen(error, &expected, &found)),
+
+            (Type::Tuple(a), Type::Tuple(b)) if a.len() == b.len() => self
+                .unify_all(a, b)
+                .map_err(|error| self.widen(error, &expected, &found)),
+
+            (Type::Union(a), Type::Union(b)) | (Type::Intersection(a), Type::Intersection(b))
+                if a.len() == b.len() =>
+            {
+                self.unify_all(a, b)
+                    .map_err(|error| self.widen(error, &expected, &found))
+            }
+
+            (Type::Array(a), Type::Array(b)) => self
+                .unify(a, b)
+                .map_err(|error| self.widen(error, &expected, &found)),
+
+            (Type::Struct(a), Type::Struct(b))
+                if a.len() == b.len() && a.iter().zip(b.iter()).all(|((a, _), (b, _))| a == b) =>
+            {
+                let a: Vec<Type> = a.iter().map(|(_, ty)| ty.clone()).collect();
+                let b: Vec<Type> = b.iter().map(|(_, ty)| ty.clone()).collect();
+                self.unify_all(&a, &b)
+                    .map_err(|error| self.widen(error, &expected, &found))
+            }
+
+            // An open record is a closed one when its row holds exactly the
+            // fields it does not name.
+            (Type::Extend(fields, row), Type::Struct(others))
+            | (Type::Struct(others), Type::Extend(fields, row)) => {
+                let mut rest = others.clone();
+                fields
+                    .iter()
+                    .try_for_each(|(name, ty)| {
+                        match rest.iter().position(|(other, _)| other == name) {
+                            Some(index) => {
+                                let (_, other) = rest.remove(index);
+                                self.unify(ty, &other)
+                            }
+                            None => Err(self.mismatch(&expected, &found)),
+                        }
+                    })
+                    .and_then(|_| self.unify(row, &Type::Struct(rest)))
+                    .map_err(|error| self.widen(error, &expected, &found))
+            }
+
+            // Each row takes the fields only the other names, besides some
+            // row both share.
+            (Type::Extend(a, a_row), Type::Extend(b, b_row)) => {
+                let only = |fields: &[(String, Type)], others: &[(String, Type)]| {
+                    fields
+                        .iter()
+                        .filter(|(name, _)| !others.iter().any(|(other, _)| other == name))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                };
+                let (only_a, only_b) = (only(a, b), only(b, a));
+                let same_row = self.shallow_resolve(a_row) == self.shallow_resolve(b_row);
+                if same_row && !(only_a.is_empty() && only_b.is_empty()) {
+                    return Err(self.mismatch(&expected, &found));
+                }
+
+                let common: Vec<(Type, Type)> = a
+                    .iter()
+                    .filter_map(|(name, a)| {
+                        b.iter()
+                            .find(|(other, _)| other == name)
+                            .map(|(_, b)| (a.clone(), b.clone()))
+                    })
+                    .collect();
+                let row = self.fresh();
+                common
+                    .iter()
+                    .try_for_each(|(a, b)| self.unify(a, b))
+                    .and_then(|_| self.unify(a_row, &Type::extend(only_b, row.clone())))
+                    .and_then(|_| self.unify(b_row, &Type::extend(only_a, row)))
+                    .map_err(|error| self.widen(error, &expected, &found))
+            }
+
+            _ => Err(self.mismatch(&expected, &found)),
+        }
+    }
+
+    // Two rows are equal when they have the same effects: each one's rest
+    // takes the effects only the other lists, besides some rest both share.
+    fn unify_rows(&mut self, expected: &Type, found: &Type) -> Result<(), UnifyError> {
+        // A rest on its own, such as an effect parameter, is only equal to
+        // itself.
+        let rows = (self.resolve(expected), self.resolve(found));
+        if !matches!(rows, (Type::Effects(..), Type::Effects(..))) {
+            return Err(self.mismatch(expected, found));
+        }
+
+        let (a, a_rest) = self.row(expected);
+        let (b, b_rest) = self.row(found);
+        let only = |effects: &[Type], others: &[Type]| -> Vec<Type> {
+            effects
+                .iter()
+                .filter(|effect| {
+                    !others
+                        .iter()
+                        .any(|other| other.effect_name() == effect.effect_name())
+                })
+                .cloned()
+                .collect()
+        };
+        let (only_a, only_b) = (only(&a, &b), only(&b, &a));
+
+        for effect in &a {
+            if let Some(other) = b
+                .iter()
+                .find(|other| other.effect_name() == effect.effect_name())
+            {
+                self.unify(effect, other)?;
+            }
+        }
+        match (a_rest, b_rest) {
+            (None, None) if only_a.is_empty() && only_b.is_empty() => Ok(()),
+            (Some(a_rest), None) if only_a.is_empty() => {
+                self.unify(&a_rest, &Type::effects(only_b, None))
+            }
+            (None, Some(b_rest)) if only_b.is_empty() => {
+                self.unify(&b_rest, &Type::effects(only_a, None))
+            }
+            (Some(a_rest), Some(b_rest)) => {
+                if a_rest == b_rest && only_a.is_empty() && only_b.is_empty() {
+                    return Ok(());
+                }
+                if a_rest == b_rest {
+                    return Err(self.mismatch(expected, found));
+                }
+                let rest = self.fresh();
+                self.unify(&a_rest, &Type::effects(only_b, Some(rest.clone())))?;
+                self.unify(&b_rest, &Type::effects(only_a, Some(rest)))
+            }
+            _ => Err(self.mismatch(expected, found)),
+        }
+    }
+
+    /// Makes every effect of the row `found` one of the row `expected`,
+    /// adding them to its rest while that is unknown.
+    pub fn include(&mut self, found: &Type, expected: &Type) -> Result<(), UnifyError> {
+        let (effects, rest) = self.row(found);
+        for effect in &effects {
+            let (listed, expected_rest) = self.row(expected);
+            match listed
+                .iter()
+                .find(|other| other.effect_name() == effect.effect_name())
+            {
+                Some(other) => self.unify(other, effect)?,
+                None => match expected_rest {
+                    Some(Type::Var(var)) => {
+                        let rest = self.fresh();
+                        self.bind(var, &Type::effects(vec![effect.clone()], Some(rest)))?;
+                    }
+                    _ => return Err(self.mismatch(expected, found)),
+                },
+            }
+        }
+
+        // An unknown rest is taken to be all of `expected`, as it can only be
+        // used where `expected` allows.
+        match rest {
+            None => Ok(()),
+            Some(rest) if Some(&rest) == self.row(expected).1.as_ref() => Ok(()),
+            Some(rest) => match self.row(expected).1 {
+                Some(Type::Var(var)) if !matches!(rest, Type::Var(_)) => self.bind(var, &rest),
+                _ => self.unify(&rest, expected),
+            },
+        }
+    }
+
+    /// The effects of a row and the rest of it, resolved.
+    pub fn row(&self, row: &Type) -> (Vec<Type>, Option<Type>) {
+        match self.resolve(row) {
+            Type::Effects(effects, rest) => (effects, rest.map(|rest| *rest)),
+            rest => (Vec::new(), Some(rest)),
+        }
+    }
+
+    fn unify_all(&mut self, expected: &[Type], found: &[Type]) -> Result<(), UnifyError> {
+        expected
+            .iter()
+            .zip(found.iter())
+            .try_for_each(|(expected, found)| self.unify(expected, found))
+    }
+
+    fn bind(&mut self, var: TypeVar, ty: &Type) -> Result<(), UnifyError> {
+        if self.occurs(var, ty) {
+            return Err(UnifyError::InfiniteType {
+                variable: var,
+                ty: self.resolve(ty),
+            });
+        }
+
+        self.bindings[var] = Some(ty.clone());
+        Ok(())
+    }
+
+    fn occurs(&self, var: TypeVar, ty: &Type) -> bool {
+        match self.shallow_resolve(ty) {
+            Type::Var(other) => other == var,
+            Type::HKT(_, arguments) => arguments.iter().any(|argument| self.occurs(var, argument)),
+            Type::Apply(other, arguments) => {
+                other == var || arguments.iter().any(|argument| self.occurs(var, argument))
+            }
+            Type::Function(parameters, return_type, effects) => {
+                parameters
+                    .iter()
+                    .any(|parameter| self.occurs(var, parameter))
+                    || self.occurs(var, &return_type)
+                    || self.occurs(var, &effects)
+            }
+            Type::Effects(effects, rest) => {
+                effects.iter().any(|effect| self.occurs(var, effect))
+                    || rest.is_some_and(|rest| self.occurs(var, &rest))
+            }
+            Type::Tuple(elements) => elements.iter().any(|element| self.occurs(var, element)),
+            Type::Array(element) => self.occurs(var, &element),
+            Type::Struct(fields) => fields.iter().any(|(_, ty)| self.occurs(var, ty)),
+            Type::Extend(fields, row) => {
+                fields.iter().any(|(_, ty)| self.occurs(var, ty)) || self.occurs(var, &row)
+            }
+            Type::Union(members) | Type::Intersection(members) => {
+                members.iter().any(|member| self.occurs(var, member))
+            }
+            Type::Forall(_, body) => self.occurs(var, &body),
+            _ => false,
+        }
+    }
+
+    pub(super) fn mismatch(&self, expected: &Type, found: &Type) -> UnifyError {
+        UnifyError::Mismatch {
+            expected: self.resolve(expected),
+            found: self.resolve(found),
+        }
+    }
+
+    // Reports a mismatch between the outermost types rather than whichever
+    // component failed, which reads better in diagnostics.
+    pub(super) fn widen(&self, error: UnifyError, expected: &Type, found: &Type) -> UnifyError {
+        match error {
+            UnifyError::Mismatch { .. } => self.mismatch(expected, found),
+            error => error,
+        }
+    }
+}
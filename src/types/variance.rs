@@ -0,0 +1,127 @@
+//! Variance says how subtyping of a named type follows its parameters:
+//! `Box<Cat>` is a `Box<Animal>` when `Box` is covariant (`out`) in its
+//! parameter, and the other way round when it is contravariant (`in`). It
+//! follows from where the parameter occurs in the type's definition.
+
+use super::{Type, TypeEnv};
+use crate::parsing::ast::Variance;
+
+/// Where a type parameter occurs: in positive positions values of it are
+/// produced, as in fields and return types, and in negative ones they are
+/// consumed, as in function parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Occurrences {
+    pub positive: bool,
+    pub negative: bool,
+}
+
+impl Occurrences {
+    /// The variance of a parameter that occurs like this. One that does not
+    /// occur at all is taken to be covariant.
+    pub fn variance(self) -> Variance {
+        match (self.positive, self.negative) {
+            (_, false) => Variance::Out,
+            (false, true) => Variance::In,
+            (true, true) => Variance::Invariant,
+        }
+    }
+
+    /// Whether a parameter declared with `variance` may occur like this.
+    pub fn allow(self, variance: &Variance) -> bool {
+        match variance {
+            Variance::Out => !self.negative,
+            Variance::In => !self.positive,
+            Variance::Invariant => true,
+        }
+    }
+
+    fn record(&mut self, positive: bool) {
+        if positive {
+            self.positive = true;
+        } else {
+            self.negative = true;
+        }
+    }
+}
+
+/// Records in `found` where each of `parameters` occurs in `ty`, which is
+/// itself in a positive position if `positive`. Other named types are
+/// looked through using their variances in `types`.
+pub fn occurrences(
+    types: &TypeEnv,
+    parameters: &[String],
+    ty: &Type,
+    positive: bool,
+    found: &mut [Occurrences],
+) {
+    let mut visit = |ty: &Type, positive: bool| occurrences(types, parameters, ty, positive, found);
+    match ty {
+        Type::Generic(name) => {
+            if let Some(index) = parameters.iter().position(|parameter| parameter == name) {
+                found[index].record(positive);
+            }
+        }
+        Type::HKT(name, arguments) => {
+            // The variance of a higher-kinded parameter is not known, so its
+            // arguments could be used either way.
+            let variances = match parameters.iter().position(|parameter| parameter == name) {
+                Some(index) => {
+                    found[index].record(positive);
+                    Vec::new()
+                }
+                None => types.parameter_variances(name),
+            };
+            for (index, argument) in arguments.iter().enumerate() {
+                match variances.get(index).unwrap_or(&Variance::Invariant) {
+                    Variance::Out => occurrences(types, parameters, argument, positive, found),
+                    Variance::In => occurrences(types, parameters, argument, !positive, found),
+                    Variance::Invariant => {
+                        occurrences(types, parameters, argument, positive, found);
+                        occurrences(types, parameters, argument, !positive, found);
+                    }
+                }
+            }
+        }
+        Type::Function(function_parameters, return_type, effects) => {
+            for parameter in function_parameters {
+                visit(parameter, !positive);
+            }
+            visit(return_type, positive);
+            visit(effects, positive);
+        }
+        Type::Effects(effects, rest) => {
+            for effect in effects.iter().chain(rest.as_deref()) {
+                visit(effect, positive);
+            }
+        }
+        Type::Tuple(members) | Type::Union(members) | Type::Intersection(members) => {
+            for member in members {
+                visit(member, positive);
+            }
+        }
+        Type::Struct(fields) => {
+            for (_, ty) in fields {
+                visit(ty, positive);
+            }
+        }
+        Type::Extend(fields, row) => {
+            for (_, ty) in fields {
+                visit(ty, positive);
+            }
+            visit(row, positive);
+        }
+        // Arrays can be written to, so their elements go both ways.
+        Type::Array(element) => {
+            visit(element, positive);
+            visit(element, !positive);
+        }
+        Type::Apply(_, arguments) => {
+            for argument in arguments {
+                visit(argument, positive);
+                visit(argument, !positive);
+            }
+        }
+        Type::Forall(_, body) => visit(body, positive),
+        _ => {}
+    }
+}
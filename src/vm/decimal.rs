@@ -0,0 +1,190 @@
+//! The decimals `BigDecimal` values are: an integer scaled down by a power
+//! of ten, so that `1.50n` is 150 scaled by 2. They keep the digits they
+//! were written with, but are equal and ordered by their value, so `1.50n`
+//! is equal to `1.5n`.
+//!
+//! Arithmetic is exact, save for division: a quotient that does not end is
+//! cut off after `Decimal::DIVISION_SCALE` digits.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    unscaled: i128,
+    // How many of the digits of `unscaled` are after the point.
+    scale: u32,
+}
+
+impl Decimal {
+    /// The most digits after the point a quotient is given.
+    pub const DIVISION_SCALE: u32 = 20;
+
+    /// The decimal written `digits`, as in `12.50`, if it fits.
+    pub fn parse(digits: &str) -> Option<Decimal> {
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let unscaled = format!("{}{}", whole, fraction);
+        if whole.is_empty() || !unscaled.bytes().all(|digit| digit.is_ascii_digit()) {
+            return None;
+        }
+        Some(Decimal {
+            unscaled: unscaled.parse().ok()?,
+            scale: u32::try_from(fraction.len()).ok()?,
+        })
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.unscaled == 0
+    }
+
+    pub fn checked_neg(self) -> Option<Decimal> {
+        Some(Decimal {
+            unscaled: self.unscaled.checked_neg()?,
+            ..self
+        })
+    }
+
+    pub fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other)?;
+        Some(Decimal {
+            unscaled: a.checked_add(b)?,
+            scale,
+        })
+    }
+
+    pub fn checked_sub(self, other: Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other)?;
+        Some(Decimal {
+            unscaled: a.checked_sub(b)?,
+            scale,
+        })
+    }
+
+    pub fn checked_mul(self, other: Decimal) -> Option<Decimal> {
+        Some(Decimal {
+            unscaled: self.unscaled.checked_mul(other.unscaled)?,
+            scale: self.scale.checked_add(other.scale)?,
+        })
+    }
+
+    /// The quotient, worked out a digit at a time as by hand, or `None` when
+    /// `other` is zero or the whole part does not fit.
+    pub fn checked_div(self, other: Decimal) -> Option<Decimal> {
+        let (a, b, _) = self.aligned(other)?;
+        let mut quotient = a.checked_div(b)?;
+        let mut remainder = a % b;
+        let mut scale = 0;
+        while remainder != 0 && scale < Decimal::DIVISION_SCALE {
+            let next = remainder.checked_mul(10).and_then(|shifted| {
+                let next = quotient.checked_mul(10)?.checked_add(shifted / b)?;
+                Some((next, shifted % b))
+            });
+            match next {
+                Some((next, rest)) => {
+                    quotient = next;
+                    remainder = rest;
+                    scale += 1;
+                }
+                // Digits stop once they no longer fit, as they would past
+                // the last one kept.
+                None => break,
+            }
+        }
+        Some(Decimal {
+            unscaled: quotient,
+            scale,
+        })
+    }
+
+    pub fn checked_rem(self, other: Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other)?;
+        Some(Decimal {
+            unscaled: a.checked_rem(b)?,
+            scale,
+        })
+    }
+
+    /// `self` raised to a power that is a whole number.
+    pub fn checked_pow(self, exponent: Decimal) -> Option<Decimal> {
+        let exponent = exponent.normalized();
+        if exponent.scale != 0 {
+            return None;
+        }
+        let exponent = u32::try_from(exponent.unscaled).ok()?;
+        Some(Decimal {
+            unscaled: self.unscaled.checked_pow(exponent)?,
+            scale: self.scale.checked_mul(exponent)?,
+        })
+    }
+
+    /// The same value with no zeros at the end of its digits after the
+    /// point.
+    fn normalized(self) -> Decimal {
+        let mut decimal = self;
+        while decimal.scale > 0 && decimal.unscaled % 10 == 0 {
+            decimal.unscaled /= 10;
+            decimal.scale -= 1;
+        }
+        decimal
+    }
+
+    /// The unscaled values of `self` and `other` at the larger of their
+    /// scales, and that scale.
+    fn aligned(self, other: Decimal) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        Some((self.rescaled(scale)?, other.rescaled(scale)?, scale))
+    }
+
+    /// The unscaled value at a scale at least as large as `self`'s.
+    fn rescaled(self, scale: u32) -> Option<i128> {
+        if self.unscaled == 0 {
+            return Some(0);
+        }
+        10i128
+            .checked_pow(scale - self.scale)?
+            .checked_mul(self.unscaled)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let (a, b) = (self.normalized(), other.normalized());
+        let scale = a.scale.max(b.scale);
+        match (a.rescaled(scale), b.rescaled(scale)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            // What does not fit at the larger scale is further from zero
+            // than what does.
+            (None, _) => a.unscaled.cmp(&0),
+            (_, None) => 0.cmp(&b.unscaled),
+        }
+    }
+}
+
+/// The digits, with as many after the point as the decimal has.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.unscaled.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        let sign = if self.unscaled < 0 { "-" } else { "" };
+        match fraction {
+            "" => write!(f, "{}{}", sign, whole),
+            _ => write!(f, "{}{}.{}", sign, whole, fraction),
+        }
+    }
+}
@@ -0,0 +1,1239 @@
+//! A virtual machine running bytecode modules.
+//!
+//! Each function called gets a frame of its own, with its locals and the
+//! stack its instructions work on, and the frames of a computation make a
+//! stack of their own. Nothing the program does recurses in Rust, so a
+//! computation can be suspended at any point by taking frames off it:
+//!
+//! - `Handle` puts a marker frame for each layer of the handler under the
+//!   computation it handles.
+//! - `Perform` takes every frame from the nearest marker of the effect up
+//!   as the continuation, and calls the clause in the marker's place. The
+//!   marker is part of the continuation, so resuming it puts the frames
+//!   back on top of whoever resumes it with the handler still installed.
+//! - A value returned to a marker goes through the handler's return clause.
+//!
+//! A fiber is another stack of frames, starting with copies of the markers
+//! of the computation that forked it, so it is handled by the same
+//! handlers. Fibers take turns: forking runs the new fiber at once and
+//! queues the one that forked it, and one that finishes or waits for
+//! another to finish lets the next in the queue carry on. Running a program
+//! is always the same sequence of steps.
+
+pub mod decimal;
+pub mod value;
+
+use crate::bytecode::{
+    Capture, ClauseShape, Constant, HandlerShape, Instruction, LoadError, Module,
+};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+pub use decimal::Decimal;
+pub use value::{Closure, Handler, Layer, Native, Value, Variant};
+
+pub struct Vm {
+    module: Rc<Module>,
+    globals: Vec<Option<Value>>,
+    // The fiber running, if any is.
+    thread: Option<Thread>,
+    // Fibers waiting for their turn, with what to carry on with.
+    ready: VecDeque<(Thread, Kind, Value)>,
+    // What the computation started by `load` or `call` finished with.
+    result: Option<Value>,
+    log: Box<dyn FnMut(&str)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    // No function or global of the module has the name.
+    Undefined {
+        name: String,
+    },
+    Unhandled {
+        effect: String,
+        operation: String,
+    },
+    ResumedTwice {
+        operation: String,
+    },
+    NoMatch,
+    AssertionFailed {
+        left: Value,
+        right: Value,
+    },
+    // Every fiber is waiting for another to finish.
+    Deadlock,
+    NotCallable {
+        found: String,
+    },
+    Arity {
+        expected: usize,
+        found: usize,
+    },
+    // The operands of an instruction are not what it works on.
+    Operands {
+        instruction: String,
+        found: Vec<String>,
+    },
+    DivisionByZero,
+    Overflow {
+        instruction: String,
+    },
+    OutOfBounds {
+        index: i64,
+        length: usize,
+    },
+    MissingKey {
+        key: String,
+    },
+    // The module itself is wrong, as a module the compiler made never is.
+    Malformed {
+        reason: String,
+    },
+    // The module was rejected by the verifier before it was run.
+    Invalid {
+        error: LoadError,
+    },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Undefined { name } => write!(f, "`{}` is not defined", name),
+            RuntimeError::Unhandled { effect, operation } => {
+                write!(f, "`{}` of effect `{}` was not handled", operation, effect)
+            }
+            RuntimeError::ResumedTwice { operation } => write!(
+                f,
+                "the continuation of `{}` was resumed twice by a one-shot handler",
+                operation
+            ),
+            RuntimeError::NoMatch => write!(f, "no arm of the match matched"),
+            RuntimeError::AssertionFailed { left, right } => {
+                write!(f, "assertion failed: {:?} is not {:?}", left, right)
+            }
+            RuntimeError::Deadlock => write!(f, "every fiber is waiting for another to finish"),
+            RuntimeError::NotCallable { found } => write!(f, "a {} cannot be called", found),
+            RuntimeError::Arity { expected, found } => write!(
+                f,
+                "expected {} argument(s) but was given {}",
+                expected, found
+            ),
+            RuntimeError::Operands { instruction, found } => write!(
+                f,
+                "`{}` does not work on {}",
+                instruction,
+                found.join(" and ")
+            ),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::Overflow { instruction } => {
+                write!(f, "the result of `{}` overflowed", instruction)
+            }
+            RuntimeError::OutOfBounds { index, length } => write!(
+                f,
+                "index {} is out of bounds for a length of {}",
+                index, length
+            ),
+            RuntimeError::MissingKey { key } => write!(f, "the record has no key `{}`", key),
+            RuntimeError::Malformed { reason } => write!(f, "malformed module: {}", reason),
+            RuntimeError::Invalid { error } => write!(f, "invalid module: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// The frames of a fiber, the first at the bottom.
+#[derive(Debug, Default)]
+struct Thread {
+    frames: Vec<Frame>,
+    // Whether this is the computation started by `load` or `call`, whose
+    // result they give.
+    root: bool,
+}
+
+#[derive(Clone)]
+enum Frame {
+    Code(CodeFrame),
+    // Where a layer of a handler was installed.
+    Handle(Rc<Layer>),
+    // The bottom of the computation of a fiber, which keeps its result.
+    Fiber(Rc<Fiber>),
+}
+
+#[derive(Clone)]
+struct CodeFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    slots: Vec<Value>,
+    stack: Vec<Value>,
+    kind: Kind,
+}
+
+/// What becomes of what a function returns.
+#[derive(Debug, Clone)]
+enum Kind {
+    // It goes to the frame below.
+    Return,
+    // The function is a tail resumptive clause, whose value the
+    // continuation is resumed with.
+    Resume(Rc<Continuation>),
+}
+
+/// The frames of a computation suspended by `Perform`, from the marker of
+/// the handler that handles it up.
+pub struct Continuation {
+    operation: String,
+    multishot: bool,
+    // Taken by the first resumption when the handler is one-shot.
+    frames: RefCell<Option<Vec<Frame>>>,
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Continuation({})", self.operation)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Fiber {
+    result: RefCell<Option<Value>>,
+    // Those waiting for the result.
+    joiners: RefCell<Vec<(Thread, Kind)>>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    /// A machine with an empty module loaded, which logs to standard
+    /// output.
+    pub fn new() -> Self {
+        Vm {
+            module: Rc::new(Module::default()),
+            globals: Vec::new(),
+            thread: None,
+            ready: VecDeque::new(),
+            result: None,
+            log: Box::new(|message| println!("{}", message)),
+        }
+    }
+
+    /// Where what `Console.log` logs goes.
+    pub fn on_log(&mut self, log: impl FnMut(&str) + 'static) {
+        self.log = Box::new(log);
+    }
+
+    /// Verifies `module` and loads it in place of the one loaded before, and
+    /// runs its top-level statements, giving the value of the last one.
+    pub fn load(&mut self, module: Module) -> Result<Value, RuntimeError> {
+        module
+            .verify()
+            .map_err(|error| RuntimeError::Invalid { error })?;
+        self.globals = vec![None; module.globals.len()];
+        self.module = Rc::new(module);
+        let entry = Value::Closure(Rc::new(Closure {
+            function: Module::ENTRY,
+            captures: Vec::new(),
+        }));
+        self.start(entry, Vec::new())
+    }
+
+    /// Calls the global `name` of the loaded module with `arguments`.
+    pub fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let function = self
+            .module
+            .global(name)
+            .and_then(|global| self.globals[global as usize].clone())
+            .ok_or_else(|| RuntimeError::Undefined {
+                name: name.to_string(),
+            })?;
+        self.start(function, arguments)
+    }
+
+    /// The value of the global `name` of the loaded module, once set.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let global = self.module.global(name)?;
+        self.globals[global as usize].clone()
+    }
+
+    fn start(&mut self, function: Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        // The host's handlers go under the computation, so that what it
+        // handles, such as `Console`, is handled without a `with`.
+        self.thread = Some(Thread {
+            frames: host_handlers().into_iter().map(Frame::Handle).collect(),
+            root: true,
+        });
+        self.ready.clear();
+        self.result = None;
+        let result = self
+            .call_value(function, arguments, Kind::Return)
+            .and_then(|()| self.run());
+        self.thread = None;
+        self.ready.clear();
+        result
+    }
+
+    /// Runs fibers until none can carry on, giving the result of the root.
+    fn run(&mut self) -> Result<Value, RuntimeError> {
+        let module = self.module.clone();
+        loop {
+            let frame = match self
+                .thread
+                .as_mut()
+                .and_then(|thread| thread.frames.last_mut())
+            {
+                Some(Frame::Code(frame)) => frame,
+                Some(_) => unreachable!("values are delivered past markers at once"),
+                None => match self.ready.pop_front() {
+                    Some((thread, kind, value)) => {
+                        self.thread = Some(thread);
+                        self.finish(kind, value)?;
+                        continue;
+                    }
+                    None => return self.result.take().ok_or(RuntimeError::Deadlock),
+                },
+            };
+            let function = &module.functions[frame.closure.function as usize];
+            let instruction =
+                *function
+                    .chunk
+                    .code
+                    .get(frame.ip)
+                    .ok_or_else(|| RuntimeError::Malformed {
+                        reason: format!("`{}` runs past its end", function.name),
+                    })?;
+            frame.ip += 1;
+            self.step(&module, instruction)?;
+        }
+    }
+
+    fn thread(&mut self) -> &mut Thread {
+        self.thread.as_mut().expect("a fiber is running")
+    }
+
+    fn frame(&mut self) -> &mut CodeFrame {
+        match self.thread().frames.last_mut() {
+            Some(Frame::Code(frame)) => frame,
+            _ => unreachable!("instructions run in a code frame"),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.frame().stack.push(value);
+    }
+
+    fn pop(&mut self) -> Result<Value, RuntimeError> {
+        self.frame()
+            .stack
+            .pop()
+            .ok_or_else(|| RuntimeError::Malformed {
+                reason: "the stack underflowed".to_string(),
+            })
+    }
+
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Value>, RuntimeError> {
+        let stack = &mut self.frame().stack;
+        if stack.len() < count {
+            return Err(RuntimeError::Malformed {
+                reason: "the stack underflowed".to_string(),
+            });
+        }
+        Ok(stack.split_off(stack.len() - count))
+    }
+
+    fn constant<'m>(
+        &mut self,
+        module: &'m Module,
+        index: u16,
+    ) -> Result<&'m Constant, RuntimeError> {
+        let function = self.frame().closure.function;
+        module.functions[function as usize]
+            .chunk
+            .constants
+            .get(index as usize)
+            .ok_or_else(|| RuntimeError::Malformed {
+                reason: format!("there is no constant {}", index),
+            })
+    }
+
+    fn name<'m>(&mut self, module: &'m Module, index: u16) -> Result<&'m str, RuntimeError> {
+        match self.constant(module, index)? {
+            Constant::String(name) => Ok(name),
+            constant => Err(RuntimeError::Malformed {
+                reason: format!("expected a name, found {:?}", constant),
+            }),
+        }
+    }
+
+    fn step(&mut self, module: &Module, instruction: Instruction) -> Result<(), RuntimeError> {
+        match instruction {
+            Instruction::Constant(index) => {
+                let constant = self.constant(module, index)?;
+                let value = Value::constant(constant).ok_or_else(|| RuntimeError::Malformed {
+                    reason: format!("{:?} is not a value", constant),
+                })?;
+                self.push(value);
+            }
+            Instruction::Unit => self.push(Value::Unit),
+            Instruction::True => self.push(Value::Boolean(true)),
+            Instruction::False => self.push(Value::Boolean(false)),
+            Instruction::Pop => {
+                self.pop()?;
+            }
+            Instruction::Dup => {
+                let value = self.pop()?;
+                self.push(value.clone());
+                self.push(value);
+            }
+
+            Instruction::GetLocal(slot) => {
+                let value = self.slot(slot)?.clone();
+                self.push(value);
+            }
+            Instruction::SetLocal(slot) => {
+                let value = self.pop()?;
+                *self.slot(slot)? = value;
+            }
+            Instruction::GetCapture(index) => {
+                let value = self
+                    .frame()
+                    .closure
+                    .captures
+                    .get(index as usize)
+                    .cloned()
+                    .ok_or_else(|| RuntimeError::Malformed {
+                        reason: format!("there is no capture {}", index),
+                    })?;
+                self.push(value);
+            }
+            Instruction::GetGlobal(index) => {
+                let value = self.get_global(module, index)?;
+                self.push(value);
+            }
+            Instruction::SetGlobal(index) => {
+                let value = self.pop()?;
+                let global = self.globals.get_mut(index as usize).ok_or_else(|| {
+                    RuntimeError::Malformed {
+                        reason: format!("there is no global {}", index),
+                    }
+                })?;
+                *global = Some(value);
+            }
+            Instruction::NewCell => {
+                let value = self.pop()?;
+                self.push(Value::Cell(Rc::new(RefCell::new(value))));
+            }
+            Instruction::Deref => match self.pop()? {
+                Value::Cell(cell) => {
+                    let value = cell.borrow().clone();
+                    self.push(value);
+                }
+                other => return Err(operands(instruction, &[other])),
+            },
+            Instruction::SetCell => {
+                let [value, cell] = self.pop_array()?;
+                match cell {
+                    Value::Cell(cell) => *cell.borrow_mut() = value,
+                    other => return Err(operands(instruction, &[other])),
+                }
+            }
+
+            Instruction::Array(count) => {
+                let elements = self.pop_many(count as usize)?;
+                self.push(Value::array(elements));
+            }
+            Instruction::Tuple(count) => {
+                let elements = self.pop_many(count as usize)?;
+                self.push(Value::tuple(elements));
+            }
+            Instruction::Record(index) => {
+                let keys = match self.constant(module, index)? {
+                    Constant::Keys(keys) => keys,
+                    constant => {
+                        return Err(RuntimeError::Malformed {
+                            reason: format!("expected keys, found {:?}", constant),
+                        })
+                    }
+                };
+                let values = self.pop_many(keys.len())?;
+                self.push(Value::record(keys.iter().cloned().zip(values).collect()));
+            }
+            Instruction::Extend => match self.pop_array()? {
+                [Value::Record(record), Value::Record(spread)] => {
+                    let mut fields = record.borrow().clone();
+                    fields.extend(spread.borrow().clone());
+                    self.push(Value::record(fields));
+                }
+                values => return Err(operands(instruction, &values)),
+            },
+            Instruction::Variant { tag, fields } => {
+                let tag = self.name(module, tag)?;
+                let fields = self.pop_many(fields as usize)?;
+                self.push(Value::variant(tag, fields));
+            }
+            Instruction::Field(index) => {
+                let value = self.pop()?;
+                let field = match &value {
+                    Value::Tuple(elements) => elements.get(index as usize).cloned(),
+                    Value::Variant(variant) => variant.fields.get(index as usize).cloned(),
+                    _ => None,
+                };
+                let field = field.ok_or_else(|| operands(instruction, &[value]))?;
+                self.push(field);
+            }
+            Instruction::GetKey(key) => {
+                let key = self.name(module, key)?;
+                let value = match self.pop()? {
+                    Value::Record(fields) => {
+                        fields.borrow().get(key).cloned().ok_or_else(|| {
+                            RuntimeError::MissingKey {
+                                key: key.to_string(),
+                            }
+                        })?
+                    }
+                    // What every function and fiber has.
+                    Value::Closure(closure) if key == "fork" => {
+                        Value::Native(Native::Fork(closure))
+                    }
+                    Value::Fiber(fiber) if key == "join" => Value::Native(Native::Join(fiber)),
+                    other => return Err(operands(instruction, &[other])),
+                };
+                self.push(value);
+            }
+            Instruction::TryKey(key) => {
+                let key = self.name(module, key)?;
+                let value = match self.pop()? {
+                    Value::Record(fields) => fields.borrow().get(key).cloned(),
+                    _ => None,
+                };
+                self.push(value.unwrap_or(Value::Unit));
+            }
+            Instruction::SetKey(key) => {
+                let key = self.name(module, key)?;
+                match self.pop_array()? {
+                    [value, Value::Record(fields)] => {
+                        fields.borrow_mut().insert(key.to_string(), value);
+                    }
+                    values => return Err(operands(instruction, &values)),
+                }
+            }
+            Instruction::Index => {
+                let [object, index] = self.pop_array()?;
+                let value = index_of(&object, &index)
+                    .unwrap_or_else(|| Err(operands(instruction, &[object.clone(), index])))?;
+                self.push(value);
+            }
+            Instruction::SetIndex => match self.pop_array()? {
+                [value, Value::Array(elements), Value::Integer(index)] => {
+                    let mut elements = elements.borrow_mut();
+                    let length = elements.len();
+                    let element = usize::try_from(index)
+                        .ok()
+                        .and_then(|index| elements.get_mut(index))
+                        .ok_or(RuntimeError::OutOfBounds { index, length })?;
+                    *element = value;
+                }
+                values => return Err(operands(instruction, &values)),
+            },
+            Instruction::Element(index) => match self.pop()? {
+                Value::Array(elements) => {
+                    let element = elements.borrow().get(index as usize).cloned();
+                    let element = element.ok_or_else(|| RuntimeError::OutOfBounds {
+                        index: index as i64,
+                        length: elements.borrow().len(),
+                    })?;
+                    self.push(element);
+                }
+                other => return Err(operands(instruction, &[other])),
+            },
+            Instruction::Slice(start) => match self.pop()? {
+                Value::Array(elements) => {
+                    let rest = elements
+                        .borrow()
+                        .iter()
+                        .skip(start as usize)
+                        .cloned()
+                        .collect();
+                    self.push(Value::array(rest));
+                }
+                other => return Err(operands(instruction, &[other])),
+            },
+            Instruction::Length => {
+                let value = self.pop()?;
+                let length = match &value {
+                    Value::Array(elements) => elements.borrow().len(),
+                    Value::Tuple(elements) => elements.len(),
+                    Value::Unit => 0,
+                    Value::String(string) => string.chars().count(),
+                    _ => return Err(operands(instruction, &[value])),
+                };
+                self.push(Value::Integer(length as i64));
+            }
+            Instruction::Keys => {
+                let value = self.pop()?;
+                let keys = match &value {
+                    Value::Array(elements) => indices(elements.borrow().len()),
+                    Value::Tuple(elements) => indices(elements.len()),
+                    Value::Record(fields) => fields
+                        .borrow()
+                        .keys()
+                        .map(|key| Value::string(key))
+                        .collect(),
+                    _ => return Err(operands(instruction, &[value])),
+                };
+                self.push(Value::array(keys));
+            }
+
+            Instruction::IsVariant(tag) => {
+                let tag = self.name(module, tag)?;
+                let is = match self.pop()? {
+                    Value::Variant(variant) => variant.tag == tag,
+                    _ => false,
+                };
+                self.push(Value::Boolean(is));
+            }
+            Instruction::IsLength(length) | Instruction::IsLengthAtLeast(length) => {
+                let is = match self.pop()? {
+                    Value::Array(elements) => match instruction {
+                        Instruction::IsLength(_) => elements.borrow().len() == length as usize,
+                        _ => elements.borrow().len() >= length as usize,
+                    },
+                    _ => false,
+                };
+                self.push(Value::Boolean(is));
+            }
+
+            Instruction::Negate | Instruction::Not | Instruction::BitNot => {
+                let value = self.pop()?;
+                let result = unary(instruction, &value)?;
+                self.push(result);
+            }
+            Instruction::Equal | Instruction::NotEqual => {
+                let [left, right] = self.pop_array()?;
+                let equal = left == right;
+                self.push(Value::Boolean(equal == (instruction == Instruction::Equal)));
+            }
+            Instruction::Add
+            | Instruction::Subtract
+            | Instruction::Multiply
+            | Instruction::Divide
+            | Instruction::Remainder
+            | Instruction::Power
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor
+            | Instruction::ShiftLeft
+            | Instruction::ShiftRight
+            | Instruction::Less
+            | Instruction::LessEqual
+            | Instruction::Greater
+            | Instruction::GreaterEqual
+            | Instruction::Concat => {
+                let [left, right] = self.pop_array()?;
+                let result = binary(instruction, left, right)?;
+                self.push(result);
+            }
+            Instruction::Interpolate(count) => {
+                let parts = self.pop_many(count as usize)?;
+                let string: String = parts.iter().map(Value::to_string).collect();
+                self.push(Value::string(&string));
+            }
+
+            Instruction::Jump(target) => self.frame().ip = target as usize,
+            Instruction::JumpIfFalse(target) => match self.pop()? {
+                Value::Boolean(true) => {}
+                Value::Boolean(false) => self.frame().ip = target as usize,
+                other => return Err(operands(instruction, &[other])),
+            },
+            Instruction::Call(count) => {
+                let arguments = self.pop_many(count as usize)?;
+                let function = self.pop()?;
+                self.call_value(function, arguments, Kind::Return)?;
+            }
+            // The callee gets the place of the frame, and whatever was to
+            // become of its value. A continuation puts frames back on top
+            // instead, so the frame stays for what resuming gives.
+            Instruction::TailCall(count) => {
+                let arguments = self.pop_many(count as usize)?;
+                let function = self.pop()?;
+                let kind = match function {
+                    Value::Continuation(_) => Kind::Return,
+                    _ => self.pop_frame(),
+                };
+                self.call_value(function, arguments, kind)?;
+            }
+            Instruction::Return => {
+                let value = self.pop()?;
+                let kind = self.pop_frame();
+                self.finish(kind, value)?;
+            }
+            Instruction::NoMatch => return Err(RuntimeError::NoMatch),
+
+            Instruction::Closure(index) => {
+                let captures = &module
+                    .functions
+                    .get(index as usize)
+                    .ok_or_else(|| RuntimeError::Malformed {
+                        reason: format!("there is no function {}", index),
+                    })?
+                    .captures;
+                let frame = self.frame();
+                let captures = captures
+                    .iter()
+                    .map(|capture| match capture {
+                        Capture::Local(slot) => frame.slots.get(*slot as usize).cloned(),
+                        Capture::Capture(index) => {
+                            frame.closure.captures.get(*index as usize).cloned()
+                        }
+                    })
+                    .collect::<Option<Vec<Value>>>()
+                    .ok_or_else(|| RuntimeError::Malformed {
+                        reason: format!("a capture of function {} is out of range", index),
+                    })?;
+                self.push(Value::Closure(Rc::new(Closure {
+                    function: index,
+                    captures,
+                })));
+            }
+
+            Instruction::Perform {
+                operation,
+                arguments,
+            } => {
+                let (effect, name) = match self.constant(module, operation)? {
+                    Constant::Operation { effect, name } => (effect, name),
+                    constant => {
+                        return Err(RuntimeError::Malformed {
+                            reason: format!("expected an operation, found {:?}", constant),
+                        })
+                    }
+                };
+                let arguments = self.pop_many(arguments as usize)?;
+                self.perform(effect, name, arguments)?;
+            }
+            Instruction::Handler(shape) => {
+                let shape = match self.constant(module, shape)? {
+                    Constant::Handler(shape) => shape.clone(),
+                    constant => {
+                        return Err(RuntimeError::Malformed {
+                            reason: format!("expected a handler, found {:?}", constant),
+                        })
+                    }
+                };
+                let finish = match shape.finish {
+                    true => Some(self.pop()?),
+                    false => None,
+                };
+                let clauses = self.pop_many(shape.clauses.len())?;
+                self.push(Value::Handler(Rc::new(Handler {
+                    layers: vec![Rc::new(Layer {
+                        shape,
+                        clauses,
+                        finish,
+                    })],
+                })));
+            }
+            Instruction::Handle => match self.pop_array()? {
+                [Value::Handler(handler), thunk] => {
+                    let frames = &mut self.thread().frames;
+                    frames.extend(handler.layers.iter().rev().cloned().map(Frame::Handle));
+                    self.call_value(thunk, Vec::new(), Kind::Return)?;
+                }
+                values => return Err(operands(instruction, &values)),
+            },
+            Instruction::Resume => {
+                let [continuation, value] = self.pop_array()?;
+                self.call_value(continuation, vec![value], Kind::Return)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn pop_array<const N: usize>(&mut self) -> Result<[Value; N], RuntimeError> {
+        let values = self.pop_many(N)?;
+        Ok(values.try_into().expect("popped as many"))
+    }
+
+    fn slot(&mut self, slot: u16) -> Result<&mut Value, RuntimeError> {
+        self.frame()
+            .slots
+            .get_mut(slot as usize)
+            .ok_or_else(|| RuntimeError::Malformed {
+                reason: format!("there is no local {}", slot),
+            })
+    }
+
+    /// Takes the running function's frame off, giving what was to become of
+    /// its value.
+    fn pop_frame(&mut self) -> Kind {
+        match self.thread().frames.pop() {
+            Some(Frame::Code(frame)) => frame.kind,
+            _ => unreachable!("instructions run in a code frame"),
+        }
+    }
+
+    /// The global, or what the host provides under its name when the module
+    /// does not set it.
+    fn get_global(&mut self, module: &Module, index: u16) -> Result<Value, RuntimeError> {
+        if let Some(Some(value)) = self.globals.get(index as usize) {
+            return Ok(value.clone());
+        }
+        let name = module
+            .globals
+            .get(index as usize)
+            .ok_or_else(|| RuntimeError::Malformed {
+                reason: format!("there is no global {}", index),
+            })?;
+        host(name).ok_or_else(|| RuntimeError::Undefined { name: name.clone() })
+    }
+
+    /// Calls `function`, doing with its value what `kind` says.
+    fn call_value(
+        &mut self,
+        function: Value,
+        arguments: Vec<Value>,
+        kind: Kind,
+    ) -> Result<(), RuntimeError> {
+        match function {
+            Value::Closure(closure) => {
+                let definition = self
+                    .module
+                    .functions
+                    .get(closure.function as usize)
+                    .ok_or_else(|| RuntimeError::Malformed {
+                        reason: format!("there is no function {}", closure.function),
+                    })?;
+                if definition.arity as usize != arguments.len() {
+                    return Err(RuntimeError::Arity {
+                        expected: definition.arity as usize,
+                        found: arguments.len(),
+                    });
+                }
+                let mut slots = arguments;
+                slots.resize(
+                    definition.locals.max(definition.arity) as usize,
+                    Value::Unit,
+                );
+                self.thread().frames.push(Frame::Code(CodeFrame {
+                    closure,
+                    ip: 0,
+                    slots,
+                    stack: Vec::new(),
+                    kind,
+                }));
+                Ok(())
+            }
+            Value::Continuation(continuation) => {
+                let value = match <[Value; 1]>::try_from(arguments) {
+                    Ok([value]) => value,
+                    Err(arguments) => {
+                        return Err(RuntimeError::Arity {
+                            expected: 1,
+                            found: arguments.len(),
+                        })
+                    }
+                };
+                // Whoever resumes it gets what handling the rest gives.
+                debug_assert!(matches!(kind, Kind::Return));
+                self.resume(&continuation, value)
+            }
+            Value::Native(native) => {
+                if native.arity() != arguments.len() {
+                    return Err(RuntimeError::Arity {
+                        expected: native.arity(),
+                        found: arguments.len(),
+                    });
+                }
+                self.native(native, arguments, kind)
+            }
+            other => Err(RuntimeError::NotCallable {
+                found: other.kind().to_string(),
+            }),
+        }
+    }
+
+    /// Does with `value` what `kind` says.
+    fn finish(&mut self, kind: Kind, value: Value) -> Result<(), RuntimeError> {
+        match kind {
+            Kind::Return => self.deliver(value),
+            Kind::Resume(continuation) => self.resume(&continuation, value),
+        }
+    }
+
+    /// Gives `value` to the frame on top, going through the markers and
+    /// fibers it meets on the way.
+    fn deliver(&mut self, value: Value) -> Result<(), RuntimeError> {
+        loop {
+            let thread = self.thread();
+            match thread.frames.pop() {
+                Some(Frame::Code(mut frame)) => {
+                    frame.stack.push(value);
+                    thread.frames.push(Frame::Code(frame));
+                    return Ok(());
+                }
+                Some(Frame::Handle(layer)) => {
+                    if let Some(finish) = &layer.finish {
+                        return self.call_value(finish.clone(), vec![value], Kind::Return);
+                    }
+                }
+                Some(Frame::Fiber(fiber)) => {
+                    *fiber.result.borrow_mut() = Some(value.clone());
+                    for (thread, kind) in fiber.joiners.take() {
+                        self.ready.push_back((thread, kind, value.clone()));
+                    }
+                }
+                None => {
+                    if thread.root {
+                        self.result = Some(value);
+                    }
+                    self.thread = None;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Puts the frames of `continuation` back on top, giving `value` to the
+    /// one that performed the operation.
+    fn resume(&mut self, continuation: &Continuation, value: Value) -> Result<(), RuntimeError> {
+        let frames = match continuation.multishot {
+            true => continuation.frames.borrow().clone(),
+            false => continuation.frames.borrow_mut().take(),
+        };
+        let frames = frames.ok_or_else(|| RuntimeError::ResumedTwice {
+            operation: continuation.operation.clone(),
+        })?;
+        self.thread().frames.extend(frames);
+        self.deliver(value)
+    }
+
+    /// Suspends the computation up to the nearest handler of `effect`, and
+    /// calls its clause of `operation` in its place.
+    fn perform(
+        &mut self,
+        effect: &str,
+        operation: &str,
+        mut arguments: Vec<Value>,
+    ) -> Result<(), RuntimeError> {
+        let frames = &mut self.thread().frames;
+        let found = frames.iter().rposition(|frame| match frame {
+            Frame::Handle(layer) => layer.clause(effect, operation).is_some(),
+            _ => false,
+        });
+        let marker = found.ok_or_else(|| RuntimeError::Unhandled {
+            effect: effect.to_string(),
+            operation: operation.to_string(),
+        })?;
+        let layer = match &frames[marker] {
+            Frame::Handle(layer) => layer.clone(),
+            _ => unreachable!("found a marker"),
+        };
+        let (clause, tail_resumptive) = layer
+            .clause(effect, operation)
+            .expect("found a marker with the clause");
+
+        let continuation = Rc::new(Continuation {
+            operation: format!("{}.{}", effect, operation),
+            multishot: layer.shape.multishot,
+            frames: RefCell::new(Some(frames.split_off(marker))),
+        });
+        let kind = match tail_resumptive {
+            true => Kind::Resume(continuation.clone()),
+            false => Kind::Return,
+        };
+        arguments.push(Value::Continuation(continuation));
+        self.call_value(clause.clone(), arguments, kind)
+    }
+
+    fn native(
+        &mut self,
+        native: Native,
+        arguments: Vec<Value>,
+        kind: Kind,
+    ) -> Result<(), RuntimeError> {
+        match native {
+            Native::AssertEqual => {
+                let [left, right]: [Value; 2] = arguments.try_into().expect("checked the arity");
+                if left != right {
+                    return Err(RuntimeError::AssertionFailed { left, right });
+                }
+                self.finish(kind, Value::Unit)
+            }
+            // Its continuation is resumed by the clause being tail resumptive.
+            Native::Log => {
+                (self.log)(&arguments[0].to_string());
+                self.finish(kind, Value::Unit)
+            }
+            // The new fiber runs first, with the handlers of this one.
+            Native::Fork(closure) => {
+                let fiber = Rc::new(Fiber::default());
+                let parent = self.thread.take().expect("a fiber is running");
+                let mut frames: Vec<Frame> = parent
+                    .frames
+                    .iter()
+                    .filter(|frame| matches!(frame, Frame::Handle(_)))
+                    .cloned()
+                    .collect();
+                frames.push(Frame::Fiber(fiber.clone()));
+                self.ready.push_back((parent, kind, Value::Fiber(fiber)));
+                self.thread = Some(Thread {
+                    frames,
+                    root: false,
+                });
+                self.call_value(Value::Closure(closure), Vec::new(), Kind::Return)
+            }
+            Native::Join(fiber) => {
+                let result = fiber.result.borrow().clone();
+                match result {
+                    Some(value) => self.finish(kind, value),
+                    None => {
+                        let thread = self.thread.take().expect("a fiber is running");
+                        fiber.joiners.borrow_mut().push((thread, kind));
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What the host provides as globals.
+fn host(name: &str) -> Option<Value> {
+    match name {
+        "assert_equal" => Some(Value::Native(Native::AssertEqual)),
+        "Console.Platform" => Some(Value::Handler(Rc::new(Handler {
+            layers: vec![console()],
+        }))),
+        _ => None,
+    }
+}
+
+/// The handlers of the effects the host handles, which every computation
+/// it starts runs under.
+fn host_handlers() -> Vec<Rc<Layer>> {
+    vec![console()]
+}
+
+/// The host's handler of `Console`, which logs where `Vm::on_log` says.
+fn console() -> Rc<Layer> {
+    Rc::new(Layer {
+        shape: HandlerShape {
+            effect: "Console".to_string(),
+            clauses: vec![ClauseShape {
+                operation: "log".to_string(),
+                tail_resumptive: true,
+            }],
+            finish: false,
+            multishot: false,
+        },
+        clauses: vec![Value::Native(Native::Log)],
+        finish: None,
+    })
+}
+
+fn operands(instruction: Instruction, values: &[Value]) -> RuntimeError {
+    RuntimeError::Operands {
+        instruction: instruction.to_string(),
+        found: values
+            .iter()
+            .map(|value| value.kind().to_string())
+            .collect(),
+    }
+}
+
+fn indices(length: usize) -> Vec<Value> {
+    (0..length as i64).map(Value::Integer).collect()
+}
+
+/// `object[index]`, or `None` when the operands are of the wrong kinds.
+fn index_of(object: &Value, index: &Value) -> Option<Result<Value, RuntimeError>> {
+    let index = match index {
+        Value::Integer(index) => *index,
+        _ => return None,
+    };
+    let element = |length: usize, get: &dyn Fn(usize) -> Value| {
+        usize::try_from(index)
+            .ok()
+            .filter(|at| *at < length)
+            .map(get)
+            .ok_or(RuntimeError::OutOfBounds { index, length })
+    };
+    Some(match object {
+        Value::Array(elements) => {
+            let elements = elements.borrow();
+            element(elements.len(), &|at| elements[at].clone())
+        }
+        Value::Tuple(elements) => element(elements.len(), &|at| elements[at].clone()),
+        Value::String(string) => {
+            let characters: Vec<char> = string.chars().collect();
+            element(characters.len(), &|at| {
+                Value::string(&characters[at].to_string())
+            })
+        }
+        _ => return None,
+    })
+}
+
+fn unary(instruction: Instruction, value: &Value) -> Result<Value, RuntimeError> {
+    let overflow = || RuntimeError::Overflow {
+        instruction: instruction.to_string(),
+    };
+    Ok(match (instruction, value) {
+        (Instruction::Negate, Value::Integer(a)) => {
+            Value::Integer(a.checked_neg().ok_or_else(overflow)?)
+        }
+        (Instruction::Negate, Value::Float(a)) => Value::Float(-a),
+        (Instruction::Negate, Value::BigInteger(a)) => {
+            Value::BigInteger(a.checked_neg().ok_or_else(overflow)?)
+        }
+        (Instruction::Negate, Value::BigDecimal(a)) => {
+            Value::BigDecimal(a.checked_neg().ok_or_else(overflow)?)
+        }
+        (Instruction::Not, Value::Boolean(a)) => Value::Boolean(!a),
+        (Instruction::BitNot, Value::Integer(a)) => Value::Integer(!a),
+        (Instruction::BitNot, Value::BigInteger(a)) => Value::BigInteger(!a),
+        _ => return Err(operands(instruction, std::slice::from_ref(value))),
+    })
+}
+
+fn binary(instruction: Instruction, left: Value, right: Value) -> Result<Value, RuntimeError> {
+    use Instruction::{
+        Add, BitAnd, BitOr, BitXor, Concat, Divide, Greater, GreaterEqual, Less, LessEqual,
+        Multiply, Power, Remainder, ShiftLeft, ShiftRight, Subtract,
+    };
+    let overflow = || RuntimeError::Overflow {
+        instruction: instruction.to_string(),
+    };
+    Ok(match (instruction, &left, &right) {
+        (Divide | Remainder, Value::Integer(_), Value::Integer(0))
+        | (Divide | Remainder, Value::BigInteger(_), Value::BigInteger(0)) => {
+            return Err(RuntimeError::DivisionByZero)
+        }
+        (Divide | Remainder, Value::BigDecimal(_), Value::BigDecimal(b)) if b.is_zero() => {
+            return Err(RuntimeError::DivisionByZero)
+        }
+        (_, Value::Integer(a), Value::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            match instruction {
+                Add => Value::Integer(a.checked_add(b).ok_or_else(overflow)?),
+                Subtract => Value::Integer(a.checked_sub(b).ok_or_else(overflow)?),
+                Multiply => Value::Integer(a.checked_mul(b).ok_or_else(overflow)?),
+                Divide => Value::Integer(a.checked_div(b).ok_or_else(overflow)?),
+                Remainder => Value::Integer(a.checked_rem(b).ok_or_else(overflow)?),
+                Power => {
+                    let exponent = u32::try_from(b).map_err(|_| overflow())?;
+                    Value::Integer(a.checked_pow(exponent).ok_or_else(overflow)?)
+                }
+                BitAnd => Value::Integer(a & b),
+                BitOr => Value::Integer(a | b),
+                BitXor => Value::Integer(a ^ b),
+                ShiftLeft | ShiftRight => {
+                    let shift = u32::try_from(b).map_err(|_| overflow())?;
+                    let shifted = match instruction {
+                        ShiftLeft => a.checked_shl(shift),
+                        _ => a.checked_shr(shift),
+                    };
+                    Value::Integer(shifted.ok_or_else(overflow)?)
+                }
+                _ => return compare(instruction, a.cmp(&b), &left, &right),
+            }
+        }
+        (_, Value::BigInteger(a), Value::BigInteger(b)) => {
+            let (a, b) = (*a, *b);
+            match instruction {
+                Add => Value::BigInteger(a.checked_add(b).ok_or_else(overflow)?),
+                Subtract => Value::BigInteger(a.checked_sub(b).ok_or_else(overflow)?),
+                Multiply => Value::BigInteger(a.checked_mul(b).ok_or_else(overflow)?),
+                Divide => Value::BigInteger(a.checked_div(b).ok_or_else(overflow)?),
+                Remainder => Value::BigInteger(a.checked_rem(b).ok_or_else(overflow)?),
+                Power => {
+                    let exponent = u32::try_from(b).map_err(|_| overflow())?;
+                    Value::BigInteger(a.checked_pow(exponent).ok_or_else(overflow)?)
+                }
+                BitAnd => Value::BigInteger(a & b),
+                BitOr => Value::BigInteger(a | b),
+                BitXor => Value::BigInteger(a ^ b),
+                _ => return compare(instruction, a.cmp(&b), &left, &right),
+            }
+        }
+        (_, Value::BigDecimal(a), Value::BigDecimal(b)) => {
+            let (a, b) = (*a, *b);
+            let result = match instruction {
+                Add => a.checked_add(b),
+                Subtract => a.checked_sub(b),
+                Multiply => a.checked_mul(b),
+                Divide => a.checked_div(b),
+                Remainder => a.checked_rem(b),
+                Power => a.checked_pow(b),
+                _ => return compare(instruction, a.cmp(&b), &left, &right),
+            };
+            Value::BigDecimal(result.ok_or_else(overflow)?)
+        }
+        (_, Value::Float(a), Value::Float(b)) => {
+            let (a, b) = (*a, *b);
+            match instruction {
+                Add => Value::Float(a + b),
+                Subtract => Value::Float(a - b),
+                Multiply => Value::Float(a * b),
+                Divide => Value::Float(a / b),
+                Remainder => Value::Float(a % b),
+                Power => Value::Float(a.powf(b)),
+                _ => match a.partial_cmp(&b) {
+                    Some(ordering) => return compare(instruction, ordering, &left, &right),
+                    // Nothing is ordered with NaN.
+                    None if matches!(instruction, Less | LessEqual | Greater | GreaterEqual) => {
+                        Value::Boolean(false)
+                    }
+                    None => return Err(operands(instruction, &[left, right])),
+                },
+            }
+        }
+        (Add | Concat, Value::String(a), Value::String(b)) => Value::string(&format!("{}{}", a, b)),
+        (_, Value::String(a), Value::String(b)) => {
+            return compare(instruction, a.cmp(b), &left, &right)
+        }
+        (Concat, Value::Array(a), Value::Array(b)) => {
+            let mut elements = a.borrow().clone();
+            elements.extend(b.borrow().iter().cloned());
+            Value::array(elements)
+        }
+        // `a ++ b` handles with `a` inside `b`.
+        (Concat, Value::Handler(a), Value::Handler(b)) => Value::Handler(Rc::new(Handler {
+            layers: a.layers.iter().chain(&b.layers).cloned().collect(),
+        })),
+        _ => return Err(operands(instruction, &[left, right])),
+    })
+}
+
+fn compare(
+    instruction: Instruction,
+    ordering: std::cmp::Ordering,
+    left: &Value,
+    right: &Value,
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(match instruction {
+        Instruction::Less => ordering.is_lt(),
+        Instruction::LessEqual => ordering.is_le(),
+        Instruction::Greater => ordering.is_gt(),
+        Instruction::GreaterEqual => ordering.is_ge(),
+        _ => return Err(operands(instruction, &[left.clone(), right.clone()])),
+    }))
+}
+
+// Frames hold values, and continuations hold frames.
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frame::Code(frame) => write!(f, "Code(#{})", frame.closure.function),
+            Frame::Handle(layer) => write!(f, "Handle({})", layer.shape.effect),
+            Frame::Fiber(_) => write!(f, "Fiber"),
+        }
+    }
+}
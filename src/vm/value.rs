@@ -0,0 +1,281 @@
+//! The values the virtual machine works with. Aggregates that can be
+//! updated in place, arrays, records and cells, are shared between their
+//! copies, as are closures, handlers and continuations; everything else is
+//! copied.
+
+use super::{Continuation, Decimal, Fiber};
+use crate::bytecode::{Constant, HandlerShape};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub enum Value {
+    Unit,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    BigInteger(i128),
+    BigDecimal(Decimal),
+    String(Rc<str>),
+    Symbol(Rc<str>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Tuple(Rc<[Value]>),
+    Record(Rc<RefCell<BTreeMap<String, Value>>>),
+    Variant(Rc<Variant>),
+    Closure(Rc<Closure>),
+    Cell(Rc<RefCell<Value>>),
+    Handler(Rc<Handler>),
+    Continuation(Rc<Continuation>),
+    Native(Native),
+    Fiber(Rc<Fiber>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub tag: String,
+    pub fields: Vec<Value>,
+}
+
+/// A function of the module along with what it captured.
+#[derive(Debug)]
+pub struct Closure {
+    pub function: u16,
+    pub captures: Vec<Value>,
+}
+
+/// A handler is a stack of layers, each handling one effect: the first is
+/// the innermost, so `a ++ b` is the layers of `a` and then those of `b`.
+#[derive(Debug)]
+pub struct Handler {
+    pub layers: Vec<Rc<Layer>>,
+}
+
+#[derive(Debug)]
+pub struct Layer {
+    pub shape: HandlerShape,
+    // The clause of each operation in `shape`, in the same order.
+    pub clauses: Vec<Value>,
+    pub finish: Option<Value>,
+}
+
+impl Layer {
+    /// The clause handling `operation` of `effect`, and whether it is tail
+    /// resumptive.
+    pub fn clause(&self, effect: &str, operation: &str) -> Option<(&Value, bool)> {
+        if self.shape.effect != effect {
+            return None;
+        }
+        self.shape
+            .clauses
+            .iter()
+            .zip(&self.clauses)
+            .find(|(shape, _)| shape.operation == operation)
+            .map(|(shape, clause)| (clause, shape.tail_resumptive))
+    }
+}
+
+/// The functions the host provides.
+#[derive(Debug, Clone)]
+pub enum Native {
+    AssertEqual,
+    // The clause of `log` of `Console.Platform`.
+    Log,
+    // `f.fork` and `fiber.join`, bound to what they were taken from.
+    Fork(Rc<Closure>),
+    Join(Rc<Fiber>),
+}
+
+impl Native {
+    pub fn arity(&self) -> usize {
+        match self {
+            Native::AssertEqual | Native::Log => 2,
+            Native::Fork(_) | Native::Join(_) => 0,
+        }
+    }
+}
+
+impl Value {
+    pub fn string(value: &str) -> Value {
+        Value::String(value.into())
+    }
+
+    pub fn array(elements: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn tuple(elements: Vec<Value>) -> Value {
+        match elements.is_empty() {
+            true => Value::Unit,
+            false => Value::Tuple(elements.into()),
+        }
+    }
+
+    pub fn record(fields: BTreeMap<String, Value>) -> Value {
+        Value::Record(Rc::new(RefCell::new(fields)))
+    }
+
+    pub fn variant(tag: &str, fields: Vec<Value>) -> Value {
+        Value::Variant(Rc::new(Variant {
+            tag: tag.to_string(),
+            fields,
+        }))
+    }
+
+    /// The value of a constant that is one, as opposed to the names and
+    /// shapes instructions refer to.
+    pub fn constant(constant: &Constant) -> Option<Value> {
+        Some(match constant {
+            Constant::Integer(value) => Value::Integer(*value),
+            Constant::Float(value) => Value::Float(*value),
+            Constant::BigInteger(value) => Value::BigInteger(*value),
+            Constant::BigDecimal(value) => Value::BigDecimal(Decimal::parse(value)?),
+            Constant::String(value) => Value::string(value),
+            Constant::Symbol(name) => Value::Symbol(name.as_str().into()),
+            Constant::Keys(_) | Constant::Operation { .. } | Constant::Handler(_) => return None,
+        })
+    }
+
+    /// What kind of value this is, for errors.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::BigInteger(_) => "big integer",
+            Value::BigDecimal(_) => "big decimal",
+            Value::String(_) => "string",
+            Value::Symbol(_) => "symbol",
+            Value::Array(_) => "array",
+            Value::Tuple(_) => "tuple",
+            Value::Record(_) => "record",
+            Value::Variant(_) => "variant",
+            Value::Closure(_) | Value::Native(_) => "function",
+            Value::Cell(_) => "cell",
+            Value::Handler(_) => "handler",
+            Value::Continuation(_) => "continuation",
+            Value::Fiber(_) => "fiber",
+        }
+    }
+}
+
+// Values are equal when they have the same structure. Functions, handlers,
+// continuations and fibers are only equal to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::BigInteger(a), Value::BigInteger(b)) => a == b,
+            (Value::BigDecimal(a), Value::BigDecimal(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Record(a), Value::Record(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Variant(a), Value::Variant(b)) => a == b,
+            (Value::Cell(a), Value::Cell(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            (Value::Handler(a), Value::Handler(b)) => Rc::ptr_eq(a, b),
+            (Value::Continuation(a), Value::Continuation(b)) => Rc::ptr_eq(a, b),
+            (Value::Fiber(a), Value::Fiber(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => match (a, b) {
+                (Native::AssertEqual, Native::AssertEqual) | (Native::Log, Native::Log) => true,
+                (Native::Fork(a), Native::Fork(b)) => Rc::ptr_eq(a, b),
+                (Native::Join(a), Native::Join(b)) => Rc::ptr_eq(a, b),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(value) => write!(f, "{:?}", value),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+/// How values are written out by templates and `Console.log`: strings as
+/// they are, and everything else as it would be written in the source.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter<'_>, values: &[Value]) -> fmt::Result {
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{:?}", value)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::BigInteger(value) => write!(f, "{}n", value),
+            Value::BigDecimal(value) => write!(f, "{}n", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Symbol(name) => write!(f, "Symbol({})", name),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                list(f, &elements.borrow())?;
+                write!(f, "]")
+            }
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                list(f, elements)?;
+                write!(f, ")")
+            }
+            Value::Record(fields) => {
+                let fields = fields.borrow();
+                if fields.is_empty() {
+                    return write!(f, "{{}}");
+                }
+                write!(f, "{{ ")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {:?}", key, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Variant(variant) if variant.fields.is_empty() => write!(f, "{}", variant.tag),
+            Value::Variant(variant) => {
+                write!(f, "{}(", variant.tag)?;
+                list(f, &variant.fields)?;
+                write!(f, ")")
+            }
+            Value::Closure(closure) => write!(f, "<function #{}>", closure.function),
+            Value::Cell(value) => write!(f, "<cell {:?}>", value.borrow()),
+            Value::Handler(handler) => {
+                let effects: Vec<&str> = handler
+                    .layers
+                    .iter()
+                    .map(|layer| layer.shape.effect.as_str())
+                    .collect();
+                write!(f, "<handler {}>", effects.join(" ++ "))
+            }
+            Value::Continuation(continuation) => {
+                write!(f, "<continuation of {}>", continuation.operation)
+            }
+            Value::Native(native) => match native {
+                Native::AssertEqual => write!(f, "<native assert_equal>"),
+                Native::Log => write!(f, "<native Console.log>"),
+                Native::Fork(_) => write!(f, "<native fork>"),
+                Native::Join(_) => write!(f, "<native join>"),
+            },
+            Value::Fiber(_) => write!(f, "<fiber>"),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use asura::bytecode::{Constant, Instruction, Module};
+use asura::compile_bytecode;
+
+fn compile(source: &str) -> Module {
+    compile_bytecode(source).expect("expected compilation to succeed")
+}
+
+fn disassemble(source: &str) -> String {
+    compile(source).disassemble()
+}
+
+#[test]
+fn functions_and_tail_calls() {
+    insta::assert_snapshot!(disassemble(
+        "fun count(n: Int, total: Int): Int {
+  match n { n < 1 -> total, _ -> count(n - 1, total + n) }
+}
+count(10, 0)"
+    ));
+}
+
+#[test]
+fn closures_share_mutable_locals() {
+    insta::assert_snapshot!(disassemble(
+        "fun counter() {
+  count := 0
+  fun next() {
+    count = count + 1
+    count
+  }
+  next
+}"
+    ));
+}
+
+#[test]
+fn aggregates_and_matches() {
+    insta::assert_snapshot!(disassemble(
+        "type Shape = | Circle { radius: Float } | Square(Float)
+fun size(s: Shape): Float {
+  match s { Circle({ radius }) -> radius, Square(side) -> side }
+}
+fun first(xs: [Int]): (Int, { rest: [Int] }) {
+  match xs { [] -> (0, { rest: [] }), [x, ...rest] -> (x, { rest: rest }) }
+}
+size(Shape.Square(2.0))"
+    ));
+}
+
+#[test]
+fn handlers_and_resumptions() {
+    insta::assert_snapshot!(disassemble(
+        "effect Ask() -> Int
+effect Fail() -> Never
+
+impl for Ask {
+  handler() {
+    resume(1)
+  }
+
+  handler constant(value: Int) {
+    return { Ask() { value } }
+  }
+}
+
+impl for Fail {
+  handler() { return { Fail() { 0 } } } {
+    return(a) { a + 1 }
+  }
+}
+
+fun main() { Ask() + Ask() }
+
+main() with Ask ++ Fail
+main() with Ask.constant(2)"
+    ));
+}
+
+#[test]
+fn line_tables_map_instructions_to_their_source() {
+    let source = "fun main(x: Int): Int {
+  x * 2
+}
+main(21)";
+    let module = compile(source);
+    let main = module
+        .functions
+        .iter()
+        .find(|function| function.name == "main")
+        .expect("expected a function `main`");
+
+    let multiply = main
+        .chunk
+        .code
+        .iter()
+        .position(|instruction| *instruction == Instruction::Multiply)
+        .expect("expected a multiplication");
+    let span = main.chunk.span(multiply).expect("expected a span");
+    assert_eq!(&source[span], "x * 2");
+
+    let two = main
+        .chunk
+        .constants
+        .iter()
+        .position(|constant| *constant == Constant::Integer(2))
+        .expect("expected the constant 2");
+    assert!(main.chunk.code.contains(&Instruction::Constant(two as u16)));
+}
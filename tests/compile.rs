@@ -0,0 +1,1326 @@
+use asura::error::{
+    CompileError, CompileWarning, EffectError, LexError, ParseError, ResolveError, SourceId,
+    TypeError,
+};
+use asura::{compile, compile_source};
+
+fn errors(source: &str) -> Vec<CompileError> {
+    compile(source).expect_err("expected compilation to fail")
+}
+
+fn type_errors(source: &str) -> Vec<TypeError> {
+    errors(source)
+        .into_iter()
+        .map(|error| match error {
+            CompileError::Type { error, .. } => error,
+            other => panic!("expected a type error, found {:?}", other),
+        })
+        .collect()
+}
+
+fn resolve_errors(source: &str) -> Vec<ResolveError> {
+    errors(source)
+        .into_iter()
+        .map(|error| match error {
+            CompileError::Resolve { error, .. } => error,
+            other => panic!("expected a resolve error, found {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn compiles_well_typed_programs() {
+    let sources = [
+        "x = 1 + 2",
+        "let s: String = 'a' + \"b\"",
+        "fun add(a: Int, b: Int): Int { a + b }\nadd(1, 2)",
+        "fun fib(n: Int): Int { if (n < 2) { return n }\nfib(n - 1) + fib(n - 2) }",
+        "fun id<A>(a: A): A { a }\nlet x: Int = id<Int>(1)",
+        "f = (a: Int) -> a * 2\ny = f(3)",
+        "counter := 0\ncounter = counter + 1\ncounter += 1",
+        "(a, b) = (1, 'two')\nlet c: String = b",
+        "xs = [1, 2, 3]\nfor x of xs { y = x + 1 }",
+        "p = { x: 1, y: 2.5 }\nlet z: Float = p.y",
+        "import Console from 'std:Console'\nConsole.log(`n = ${1}`)",
+        "import { log as print } from 'std:Console'\nprint('hi')",
+        "type Pair<A> = (A, A)\nfun swap(p: Pair<Int>): Pair<Int> { (p[1], p[0]) }",
+        "fun later(): Int { sooner() }\nfun sooner(): Int { 1 }",
+        "type Either<E, A> = | Left(E) | Right(A)\nlet e: Either<String, Int> = Either.Right(1)",
+        "type CalcError = | CannotDivideByZero\nlet e: CalcError = CalcError.CannotDivideByZero",
+        "type Shape = | Circle { radius: Float } | Square(Float)\ns = Shape.Circle({ radius: 1.5 })",
+        "data Tree<A> = Leaf | Node(Tree<A>, A, Tree<A>)\nt = Tree.Node(Tree.Leaf, 1, Tree.Leaf)",
+        "fun fib(n: Int): Int { match n { n < 2 -> n, _ -> fib(n - 2) + fib(n - 1) } }",
+        "type Op = | Add(Int, Int) | Negate(Int)\nfun eval(op: Op): Int { match op { Add(a, b) -> a + b, Op.Negate(a) -> -a } }",
+        "fun sum(xs: [Int]): Int { match xs { [] -> 0, [head, ...tail] -> head + sum(tail) } }",
+        "fun first(p: (Int, String)): Int { match p { (0, _) -> 1, (n, 'x') -> n, _ -> 2 } }",
+        "type Shape = | Circle { radius: Float } | Square(Float)\nfun size(s: Shape): Float { match s { Circle({ radius }) -> radius, Square(side) -> side } }",
+        "fun describe(o: Option<Int>): Int { match o { _ -> 0 } }",
+    ];
+
+    for source in sources {
+        let output = compile(source)
+            .unwrap_or_else(|errors| panic!("{:?} failed to compile: {:?}", source, errors));
+        assert_eq!(&output.wasm[..4], b"\0asm");
+    }
+}
+
+#[test]
+fn reports_type_mismatches() {
+    assert_eq!(
+        type_errors("let x: Int = 'one'"),
+        vec![TypeError::Mismatch {
+            expected: "Int".to_string(),
+            found: "String".to_string(),
+            span: 13..18,
+        }]
+    );
+
+    assert_eq!(
+        type_errors("if (1) { x = 2 }"),
+        vec![TypeError::Mismatch {
+            expected: "Boolean".to_string(),
+            found: "Int".to_string(),
+            span: 4..5,
+        }]
+    );
+
+    assert_eq!(
+        type_errors("fun f(): String { 1 }"),
+        vec![TypeError::Mismatch {
+            expected: "String".to_string(),
+            found: "Int".to_string(),
+            span: 18..19,
+        }]
+    );
+
+    // Reassigning reports the value, not the name it is assigned to.
+    assert_eq!(
+        type_errors("y := 1; y = \"s\""),
+        vec![TypeError::Mismatch {
+            expected: "Int".to_string(),
+            found: "String".to_string(),
+            span: 12..15,
+        }]
+    );
+    assert_eq!(
+        type_errors("r := { a: 1 }\nr.a = \"s\""),
+        vec![TypeError::Mismatch {
+            expected: "Int".to_string(),
+            found: "String".to_string(),
+            span: 20..23,
+        }]
+    );
+}
+
+#[test]
+fn reports_bad_calls() {
+    assert_eq!(
+        type_errors("fun f(a: Int): Int { a }\nf(1, 2)"),
+        vec![TypeError::ArityMismatch {
+            expected: 1,
+            found: 2,
+            span: 25..32,
+        }]
+    );
+
+    assert_eq!(
+        type_errors("x = 1\nx(2)"),
+        vec![TypeError::NotCallable {
+            found: "Int".to_string(),
+            span: 6..7,
+        }]
+    );
+
+    assert!(matches!(
+        type_errors("fun id<A>(a: A): A { a }\nid<Int, Int>(1)")[..],
+        [TypeError::WrongTypeArgumentCount {
+            expected: 1,
+            found: 2,
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn generalizes_bindings() {
+    let sources = [
+        // Constants bound to values are polymorphic.
+        "id = (x) -> x\nlet a: Int = id(1)\nlet b: String = id('b')",
+        "let pair = (a, b) -> (b, a)\nx = pair(1, 'a')\ny = pair(true, 2.0)",
+        // So are functions with unannotated parameters and implicit returns.
+        "fun first(p) { (a, _) = p\na }\nlet x: Int = first((1, 'a'))\nlet y: String = first(('a', 1))",
+        "fun twice(f, x) { f(f(x)) }\nlet x: Int = twice((n) -> n * 3, 1)\nlet y: String = twice((s) -> s + '!', 'a')",
+        "fun compose(f, g) { (x) -> g(f(x)) }\nlength = compose((n) -> n + 1, (n) -> n > 2)\nlet ok: Boolean = length(1)",
+        // Explicit type arguments only cover the written type parameters.
+        "fun pick<A>(a: A, b) { a }\nx = pick<Int>(1, 'b')",
+        // Generalization happens inside functions as well.
+        "fun f(n: Int) { id = (x) -> x\na = id(n)\nlet b: String = id('a')\nb }",
+    ];
+
+    for source in sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+}
+
+#[test]
+fn generalization_respects_the_environment() {
+    // A parameter's type is shared by everything that captures it.
+    assert!(matches!(
+        &type_errors("fun f(x) { g = () -> x\nlet a: Int = g()\nlet b: String = g()\na }")[..],
+        [TypeError::Mismatch { expected, found, .. }] if expected == "String" && found == "Int"
+    ));
+    // Arrays are mutable, so an empty one is not generalized.
+    assert!(matches!(
+        &type_errors("xs = []\nlet a: [Int] = xs\nlet b: [String] = xs")[..],
+        [TypeError::Mismatch { .. }]
+    ));
+    // Mutable bindings are never generalized.
+    assert!(matches!(
+        &type_errors("id := (x) -> x\na = id(1)\nb = id('b')")[..],
+        [TypeError::Mismatch { .. }]
+    ));
+    // Operators on inferred types are checked once the type is known.
+    assert!(matches!(
+        &type_errors("add = (a, b) -> a + b\nx = add(true, false)")[..],
+        [TypeError::UnsupportedOperator { operator, ty, .. }] if operator == "+" && ty == "Boolean"
+    ));
+    assert!(compile("add = (a, b) -> a + b").is_ok());
+}
+
+#[test]
+fn checks_higher_rank_arguments() {
+    let both = "fun both(f: <A>(A) -> A): (Int, String) { (f(1), f('a')) }\n";
+    let sources = [
+        "both((x) -> x)",
+        "id = (x) -> x\nboth(id)",
+        "fun id<T>(x: T): T { x }\nboth(id)",
+        // A polymorphic argument can be passed on.
+        "fun again(g: <B>(B) -> B) { both(g) }",
+        "let id: <A>(A) -> A = (x) -> x\nboth(id)",
+    ];
+    for source in sources {
+        let source = format!("{}{}", both, source);
+        assert!(
+            compile(&source).is_ok(),
+            "{}: {:?}",
+            source,
+            compile(&source)
+        );
+    }
+
+    // Annotated bindings are polymorphic too.
+    assert!(
+        compile("let id: <A>(A) -> A = (x) -> x\nlet a: Int = id(1)\nlet b: String = id('b')")
+            .is_ok()
+    );
+
+    // An argument that only works for some types is rejected.
+    assert!(matches!(
+        &type_errors(&format!("{}both((x) -> x + 1)", both))[..],
+        [TypeError::Mismatch { expected, found, .. }] if expected == "A" && found == "Int"
+    ));
+    assert!(matches!(
+        &type_errors(&format!(
+            "{}fun inc(n: Int): Int {{ n + 1 }}\nboth(inc)",
+            both
+        ))[..],
+        [TypeError::Mismatch { .. }]
+    ));
+    assert!(matches!(
+        &type_errors(&format!("{}both((x: Int) -> x)", both))[..],
+        [TypeError::Mismatch { .. }]
+    ));
+}
+
+#[test]
+fn reports_escaped_skolems() {
+    // The result of `run` cannot be the generic `S` chosen inside it.
+    assert!(matches!(
+        &type_errors("fun run<R>(f: <S>(S) -> R): Int { 0 }\nx = run((s) -> s)")[..],
+        [TypeError::EscapedSkolem { generic, .. }] if generic == "S"
+    ));
+    // Unannotated parameters are never inferred to be polymorphic.
+    assert!(matches!(
+        &type_errors("fun both(f: <A>(A) -> A) { 0 }\nfun g(h) { both(h) }")[..],
+        [TypeError::EscapedSkolem { generic, .. }] if generic == "A"
+    ));
+    assert!(compile("fun run<R>(f: <S>(S) -> R): R { f(1) }\nlet x: Int = run((s) -> 2)").is_ok());
+}
+
+#[test]
+fn checks_kinds() {
+    let maybe = "type Maybe<A> = | Just(A) | Nothing\n";
+    let sources = [
+        // `F` is inferred to be `* -> *` from its use.
+        "type Apply<F, A> = F<A>\nlet x: Apply<Maybe, Int> = Maybe.Just(1)",
+        "type Fix<F> = | In(F<Fix<F>>)\nlet x: Fix<Maybe> = Fix.In(Maybe.Nothing)",
+        // A partially applied type fills its holes first.
+        "type Either<E, A> = | Left(E) | Right(A)\ntype Apply<F, A> = F<A>\nlet x: Apply<Either<String, _>, Int> = Either.Right(1)",
+        // Unknown constructors are found by inference.
+        "fun wrap<F<_>>(fa: F<Int>): F<Int> { fa }\nlet x: Maybe<Int> = wrap(Maybe.Just(1))",
+        "type Either<E, A> = | Left(E) | Right(A)\nfun wrap<F<_>>(fa: F<Int>): F<Int> { fa }\nlet x: Either<String, Int> = wrap(Either.Right(1))",
+        "type Functor<F<_>> = { map: <A, B>(F<A>, (A) -> B) -> F<B> }\n\
+         fun fmap<F<_>, A, B>(functor: Functor<F>, fa: F<A>, f: (A) -> B): F<B> { functor.map(fa, f) }\n\
+         let functor: Functor<Maybe> = { map: (fa, f) -> match fa { Just(a) -> Maybe.Just(f(a)), Nothing -> Maybe.Nothing } }\n\
+         let x: Maybe<String> = fmap(functor, Maybe.Just(1), (n) -> 'n')",
+    ];
+    for source in sources {
+        let source = format!("{}{}", maybe, source);
+        assert!(
+            compile(&source).is_ok(),
+            "{}: {:?}",
+            source,
+            compile(&source)
+        );
+    }
+
+    let kind_mismatch = |source: &str| match &type_errors(source)[..] {
+        [TypeError::KindMismatch {
+            expected, found, ..
+        }] => (expected.clone(), found.clone()),
+        errors => panic!("expected a kind mismatch, found {:?}", errors),
+    };
+    let star = "*".to_string();
+    let arrow = "* -> *".to_string();
+    assert_eq!(
+        kind_mismatch("let x: Option = 1"),
+        (star.clone(), arrow.clone())
+    );
+    assert_eq!(
+        kind_mismatch("type Apply<F, A> = F<A>\nlet x: Apply<Int, Int> = 1"),
+        (arrow.clone(), star.clone())
+    );
+    assert_eq!(
+        kind_mismatch("fun f<F<_>>(x: F): Int { 0 }"),
+        (star.clone(), arrow.clone())
+    );
+    assert_eq!(
+        kind_mismatch("type T<F> = (F<Int>, F)"),
+        (star, arrow.clone())
+    );
+    assert_eq!(
+        kind_mismatch(
+            "type Either<E, A> = | Left(E) | Right(A)\ntype Apply<F<_>> = F<Int>\nlet x: Apply<Either<_, _>> = 1"
+        ),
+        (arrow, "* -> * -> *".to_string())
+    );
+
+    // A type parameter declared without `<_>` is the one at fault.
+    assert!(matches!(
+        &type_errors("fun f<F, A>(x: F<A>): Int { 0 }")[..],
+        [TypeError::ParameterKindMismatch {
+            parameter,
+            kind,
+            found: 1,
+            ..
+        }] if parameter == "F" && kind == "*"
+    ));
+    assert!(matches!(
+        &type_errors("fun f<F<_>>(x: F<Int, Int>): Int { 0 }")[..],
+        [TypeError::ParameterKindMismatch {
+            parameter,
+            kind,
+            found: 2,
+            ..
+        }] if parameter == "F" && kind == "* -> *"
+    ));
+}
+
+#[test]
+fn checks_subtyping() {
+    let sources = [
+        // Records may have more fields than wanted, at any depth.
+        "fun area(r: { width: Int, height: Int }): Int { r.width * r.height }\narea({ width: 1, height: 2, name: 'r' })",
+        "fun origin(p: { at: { x: Int } }): Int { p.at.x }\norigin({ at: { x: 1, y: 2 } })",
+        "let point: ({ x: Int }, Int) = ({ x: 1, y: 2 }, 3)",
+        // Functions take anything their parameters accept.
+        "fun apply(f: ({ x: Int, y: Int }) -> { x: Int }): Int { f({ x: 1, y: 2 }).x }\napply((p: { x: Int }) -> { x: p.x, z: 0 })",
+        // Named types follow the variance of their parameters.
+        "type Box<out A> = | Box(A)\nfun get(b: Box<{ x: Int }>): Int { 0 }\nget(Box.Box({ x: 1, y: 2 }))",
+        "type Sink<A> = | Sink((A) -> Int)\nfun feed(s: Sink<{ x: Int, y: Int }>): Int { 0 }\nlet s: Sink<{ x: Int }> = Sink.Sink((p) -> p.x)\nfeed(s)",
+        "type List<A> = | Nil | Cons(A, List<A>)\nfun first(l: List<{ x: Int }>): Int { 0 }\nlet l: List<{ x: Int, y: Int }> = List.Nil\nfirst(l)",
+    ];
+    for source in sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    let rejected = [
+        "fun area(r: { width: Int, height: Int }): Int { 0 }\narea({ width: 1 })",
+        "fun apply(f: ({ x: Int }) -> Int): Int { 0 }\napply((p: { x: Int, y: Int }) -> p.y)",
+        // Parameters used both ways are invariant.
+        "type Cell<A> = | Cell(A, (A) -> Unit)\nfun read(c: Cell<{ x: Int }>): Int { 0 }\nlet c: Cell<{ x: Int, y: Int }> = Cell.Cell({ x: 1, y: 2 }, (p) -> ())\nread(c)",
+        "type Sink<A> = | Sink((A) -> Int)\nfun feed(s: Sink<{ x: Int }>): Int { 0 }\nlet s: Sink<{ x: Int, y: Int }> = Sink.Sink((p) -> p.y)\nfeed(s)",
+    ];
+    for source in rejected {
+        assert!(
+            matches!(type_errors(source)[..], [TypeError::Mismatch { .. }]),
+            "{}: {:?}",
+            source,
+            compile(source)
+        );
+    }
+}
+
+#[test]
+fn infers_row_polymorphic_records() {
+    let sources = [
+        // A function reading a field takes any record that has it.
+        "fun getX(r) { r.x }\nlet a: Int = getX({ x: 1, y: 'a' })\nlet b: String = getX({ x: 'b' })",
+        "fun norm(p) { p.x * p.x + p.y * p.y }\nlet n: Int = norm({ x: 1, y: 2, z: 3 })",
+        "fun getX(r) { r.x }\nfun twice(r) { getX(r) + getX(r) }\nlet n: Int = twice({ x: 1, name: 'a' })",
+        // Rows can be written, and keep the fields a function passes on.
+        "fun getX<R>(r: { x: Int, ...R }): Int { r.x }\ngetX({ x: 1, name: 'a' })",
+        "fun move<R>(p: { x: Int, ...R }): { x: Int, ...R } { { ...p, x: p.x + 1 } }\n\
+         moved = move({ x: 1, name: 'a' })\nlet name: String = moved.name",
+        // Spreading copies fields, which later ones override.
+        "base = { x: 1, y: 2 }\np = { ...base, y: 'two', z: true }\nlet y: String = p.y\nlet x: Int = p.x",
+        "fun widen(r) { { ...r, z: 0 } }\nlet n: Int = widen({ x: 1 }).x + widen({ x: 1 }).z",
+        // A rest pattern takes the fields not named before it.
+        "fun withoutX(r) { match r { { x, ...rest } -> rest } }\nq = withoutX({ x: 1, y: 'a' })\nlet s: String = q.y",
+        "fun f(p: { x: Int, y: Int, z: Int }): Int { match p { { x, ...rest } -> x + rest.y + rest.z } }",
+    ];
+    for source in sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    let rejected = [
+        "fun getX(r) { r.x }\ngetX({ y: 1 })",
+        "fun getX<R>(r: { x: Int, ...R }): Int { 0 }\ngetX({ x: 'a' })",
+        // A row says nothing about the fields it stands for.
+        "fun f<R>(r: { x: Int, ...R }): { x: Int, y: Int, ...R } { r }",
+    ];
+    for source in rejected {
+        assert!(
+            matches!(type_errors(source)[..], [TypeError::Mismatch { .. }]),
+            "{}: {:?}",
+            source,
+            compile(source)
+        );
+    }
+
+    for source in [
+        "fun getY<R>(r: { x: Int, ...R }): Int { r.y }",
+        "fun f(p: { x: Int, y: Int }): Int { match p { { x, ...rest } -> rest.x } }",
+    ] {
+        assert!(
+            matches!(type_errors(source)[..], [TypeError::UnknownField { .. }]),
+            "{}: {:?}",
+            source,
+            compile(source)
+        );
+    }
+
+    // Only records can be spread, and one whose fields are not all known
+    // only first.
+    for source in ["p = { ...1, x: 2 }", "fun f(r) { { ...{ a: 1 }, ...r } }"] {
+        assert!(
+            matches!(type_errors(source)[..], [TypeError::InvalidSpread { .. }]),
+            "{}: {:?}",
+            source,
+            compile(source)
+        );
+    }
+}
+
+#[test]
+fn checks_declared_variance() {
+    assert!(compile("type Box<out A> = | Box(A) | Empty").is_ok());
+    assert!(compile("type Sink<in A> = | Sink((A) -> Int)").is_ok());
+    assert!(compile("effect Emit<out A> { emit(A): Unit }").is_ok());
+    assert!(compile("effect Ask<in A> { ask(): A }").is_ok());
+
+    let variance_mismatch = |source: &str| match &type_errors(source)[..] {
+        [TypeError::VarianceMismatch {
+            parameter,
+            declared,
+            ..
+        }] => (parameter.clone(), declared.clone()),
+        errors => panic!("expected a variance mismatch, found {:?}", errors),
+    };
+    assert_eq!(
+        variance_mismatch("type Sink<out A> = | Sink((A) -> Int)"),
+        ("A".to_string(), "out".to_string())
+    );
+    assert_eq!(
+        variance_mismatch("type Box<in A> = | Box(A)"),
+        ("A".to_string(), "in".to_string())
+    );
+    assert_eq!(
+        variance_mismatch("effect Ask<out A> { ask(): A }"),
+        ("A".to_string(), "out".to_string())
+    );
+    // Through another type's variance.
+    assert_eq!(
+        variance_mismatch(
+            "type Sink<in A> = | Sink((A) -> Int)\ntype Wrap<out A> = | Wrap(Sink<A>)"
+        ),
+        ("A".to_string(), "out".to_string())
+    );
+}
+
+#[test]
+fn checks_bounds() {
+    let named = "fun name<A <: { name: String }>(a: A): String { a.name }\n";
+    assert!(compile(&format!("{}name({{ name: 'a', age: 1 }})", named)).is_ok());
+    assert!(compile("fun widen<A <: { x: Int }>(a: A): { x: Int } { a }").is_ok());
+    assert!(matches!(
+        &type_errors(&format!("{}name(1)", named))[..],
+        [TypeError::UnsatisfiedBound { generic, ty, bound, .. }]
+            if generic == "A" && ty == "Int" && bound == "<: { name: String }"
+    ));
+    assert!(matches!(
+        &type_errors(&format!("{}name<{{ age: Int }}>({{ age: 1 }})", named))[..],
+        [TypeError::UnsatisfiedBound { .. }]
+    ));
+}
+
+#[test]
+fn reports_operator_and_field_errors() {
+    assert!(matches!(
+        &type_errors("x = true - false")[..],
+        [TypeError::UnsupportedOperator { operator, ty, .. }] if operator == "-" && ty == "Boolean"
+    ));
+
+    assert!(matches!(
+        &type_errors("p = { x: 1 }\np.y")[..],
+        [TypeError::UnknownField { field, .. }] if field == "y"
+    ));
+
+    assert!(matches!(
+        type_errors("f = (x) -> x(x)")[..],
+        [TypeError::InfiniteType { .. }]
+    ));
+}
+
+#[test]
+fn checks_constructor_applications() {
+    assert_eq!(
+        type_errors(
+            "type Either<E, A> = | Left(E) | Right(A)\nlet e: Either<String, Int> = Either.Left(1)"
+        ),
+        vec![TypeError::Mismatch {
+            expected: "Either<String, Int>".to_string(),
+            found: "Either<Int, ?1>".to_string(),
+            span: 70..84,
+        }]
+    );
+
+    assert!(matches!(
+        type_errors("type Op = | Add(Int, Int)\nOp.Add(1)")[..],
+        [TypeError::ArityMismatch {
+            expected: 2,
+            found: 1,
+            ..
+        }]
+    ));
+
+    assert!(matches!(
+        &resolve_errors("type Op = | Add(Int, Int)\nOp.Remove(1, 2)")[..],
+        [ResolveError::UnknownConstructor { ty, name, .. }] if ty == "Op" && name == "Remove"
+    ));
+
+    // Constructors live in their type's namespace.
+    assert!(matches!(
+        &resolve_errors("type Op = | Add(Int, Int)\nAdd(1, 2)")[..],
+        [ResolveError::UnboundVariable { name, .. }] if name == "Add"
+    ));
+
+    assert!(matches!(
+        resolve_errors("type Op = | Add(Int) | Add(Float)")[..],
+        [ResolveError::DuplicateDefinition { .. }]
+    ));
+}
+
+#[test]
+fn checks_match_arms() {
+    // Every arm must produce the same type.
+    assert!(matches!(
+        &type_errors("x = match 1 { 0 -> 'zero', _ -> 1 }")[..],
+        [TypeError::Mismatch { expected, found, .. }] if expected == "String" && found == "Int"
+    ));
+
+    // Patterns must fit the type being matched.
+    assert!(matches!(
+        &type_errors("fun f(n: Int): Int { match n { 'a' -> 1, _ -> 2 } }")[..],
+        [TypeError::Mismatch { expected, found, .. }] if expected == "Int" && found == "String"
+    ));
+    assert!(matches!(
+        type_errors("type Op = | Add(Int, Int)\nfun f(op: Op): Int { match op { Add(a) -> a } }")[..],
+        [TypeError::ArityMismatch {
+            expected: 2,
+            found: 1,
+            ..
+        }]
+    ));
+
+    // Guards are conditions.
+    assert!(matches!(
+        &type_errors("x = match 1 { n if n -> n }")[..],
+        [TypeError::Mismatch { expected, .. }] if expected == "Boolean"
+    ));
+
+    // Bindings are typed from the pattern and only visible in their arm.
+    assert!(matches!(
+        &type_errors("x = match ('a', true) { (s, b) -> s + b }")[..],
+        [TypeError::Mismatch { expected, found, .. }] if expected == "String" && found == "Boolean"
+    ));
+    assert!(matches!(
+        &resolve_errors("x = match 1 { n -> n }\ny = n")[..],
+        [ResolveError::UnboundVariable { name, .. }] if name == "n"
+    ));
+    assert!(matches!(
+        resolve_errors("x = match (1, 2) { (a, a) -> a }")[..],
+        [ResolveError::DuplicateDefinition { .. }]
+    ));
+}
+
+#[test]
+fn resolves_constructor_patterns() {
+    assert!(matches!(
+        &resolve_errors("x = match 1 { Missing -> 0 }")[..],
+        [ResolveError::UnboundConstructor { name, .. }] if name == "Missing"
+    ));
+    assert!(matches!(
+        &resolve_errors("type A = | Same\ntype B = | Same\nx = (y) -> match y { Same -> 0 }")[..],
+        [ResolveError::AmbiguousConstructor { owners, .. }] if owners == &["A", "B"]
+    ));
+    // The type being matched settles which constructor is meant.
+    assert!(compile(
+        "type A = | Same\ntype B = | Same\nfun f(b: B): Int { match b { Same -> 0 } }"
+    )
+    .is_ok());
+}
+
+fn missing_patterns(source: &str) -> Vec<String> {
+    match &type_errors(source)[..] {
+        [TypeError::NonExhaustiveMatch { missing, .. }] => missing.clone(),
+        errors => panic!("expected a non-exhaustive match, got {:?}", errors),
+    }
+}
+
+fn unreachable_arms(source: &str) -> Vec<std::ops::Range<usize>> {
+    compile(source)
+        .expect("expected compilation to succeed")
+        .warnings
+        .iter()
+        .map(|warning| match warning {
+            CompileWarning::UnreachablePattern { span, .. } => span.clone(),
+            other => panic!("expected an unreachable pattern, found {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn checks_match_exhaustiveness() {
+    let op = "type Op = | Add(Int, Int) | Divide(Int, Int) | Negate(Int)\n";
+    assert_eq!(
+        missing_patterns(&format!(
+            "{}fun f(op: Op): Int {{ match op {{ Add(a, b) -> a, Negate(a) -> a }} }}",
+            op
+        )),
+        ["Divide(_, _)"]
+    );
+    // Guarded arms may not match, so they cover nothing.
+    assert_eq!(
+        missing_patterns(&format!(
+            "{}fun f(op: Op): Int {{ match op {{ Add(a, b) -> a, Divide(a, b) if b != 0 -> a, Negate(a) -> a }} }}",
+            op
+        )),
+        ["Divide(_, _)"]
+    );
+    // Nested constructors are checked field by field.
+    assert_eq!(
+        missing_patterns(&format!(
+            "type Expr = | Lit(Int) | Neg(Expr) | Pair(Expr, Expr)\n{}",
+            "fun f(e: Expr): Int { match e { Lit(n) -> n, Neg(Lit(n)) -> n, Neg(Neg(e)) -> 0, Pair(_, _) -> 0 } }"
+        )),
+        ["Neg(Pair(_, _))"]
+    );
+    assert_eq!(
+        missing_patterns("x = match (true, false) { (true, _) -> 1, (_, true) -> 2 }"),
+        ["(false, false)"]
+    );
+    // Literals other than booleans can only be covered by a catch-all.
+    assert_eq!(
+        missing_patterns("fun f(n: Int): Int { match n { 0 -> 1, 1 -> 1 } }"),
+        ["_"]
+    );
+    assert_eq!(
+        missing_patterns("fun f(p: (Int, String)): Int { match p { (0, _) -> 1, (n, 'x') -> n } }"),
+        ["(_, _)"]
+    );
+    // Array patterns are split by length.
+    assert_eq!(
+        missing_patterns("fun f(xs: [Int]): Int { match xs { [] -> 0, [x] -> x } }"),
+        ["[_, _, ...]"]
+    );
+    assert_eq!(
+        missing_patterns("fun f(xs: [Int]): Int { match xs { [a, ...rest] -> a } }"),
+        ["[]"]
+    );
+    assert_eq!(
+        missing_patterns(
+            "fun f(xs: [Boolean]): Int { match xs { [] -> 0, [true, ...rest] -> 1, [_, _, ...rest] -> 2 } }"
+        ),
+        ["[false]"]
+    );
+    assert_eq!(
+        missing_patterns("type Shape = | Circle { radius: Float } | Square(Float)\nfun f(s: Shape): Float { match s { Square(side) -> side } }"),
+        ["Circle(_)"]
+    );
+
+    assert!(compile(
+        "fun f(xs: [Int]): Int { match xs { [] -> 0, [a, ...[b, ...rest]] -> b, [a] -> a } }"
+    )
+    .is_ok());
+    assert!(compile("fun f(b: Boolean): Int { match b { true -> 1, false -> 0 } }").is_ok());
+    assert!(compile(
+        "fun f(r: { x: Int, y: Boolean }): Int { match r { { y: true } -> 1, { x } -> x } }"
+    )
+    .is_ok());
+}
+
+#[test]
+fn warns_about_unreachable_arms() {
+    assert_eq!(
+        unreachable_arms("x = match 1 { _ -> 0, 1 -> 1 }"),
+        vec![22..23]
+    );
+    assert_eq!(
+        unreachable_arms("x = match (1, true) { (_, true) -> 0, (_, false) -> 1, (2, _) -> 2 }"),
+        vec![55..61]
+    );
+    assert_eq!(
+        unreachable_arms("fun f(xs: [Int]): Int { match xs { [...rest] -> 0, [] -> 1 } }"),
+        vec![51..53]
+    );
+    // An arm after a guarded one can still be reached.
+    assert!(unreachable_arms("x = match 1 { n if n > 0 -> n, _ -> 0 }").is_empty());
+}
+
+#[test]
+fn checks_literal_and_union_types() {
+    let sources = [
+        "let a: \"a\" | \"b\" = \"a\"",
+        "let n: 1 | 2 | 3 = 2\nlet m: Int = n",
+        "fun dir(): \"up\" | \"down\" { if (true) { return \"up\" }\n\"down\" }",
+        "fun f(d: \"up\" | \"down\"): Int { 0 }\nf(\"down\")",
+        "let xs: [\"a\" | \"b\"] = [\"a\", \"b\"]",
+        "let v: Int | String = 1\nlet w: Int | String = 'w'",
+        "let r: { x: Int } & { y: Int } = { x: 1, y: 2 }\nlet x: Int = r.x",
+        // Unions are normalized: nested ones are flattened, `Never` and
+        // repeats are dropped, and a literal is absorbed by its primitive.
+        "let a: (\"a\" | Never) | (\"a\" | String) = 'b'",
+    ];
+    for source in sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    let mismatch = |source: &str| match &type_errors(source)[..] {
+        [TypeError::Mismatch {
+            expected, found, ..
+        }] => (expected.clone(), found.clone()),
+        errors => panic!("expected a mismatch, found {:?}", errors),
+    };
+    assert_eq!(
+        mismatch("let a: \"a\" | \"b\" = \"c\""),
+        ("\"a\" | \"b\"".to_string(), "\"c\"".to_string())
+    );
+    assert_eq!(
+        mismatch("let a: \"a\" | \"b\" = 'a'\nlet b: \"a\" = a"),
+        ("\"a\"".to_string(), "\"a\" | \"b\"".to_string())
+    );
+    // A literal can never be a different one.
+    assert_eq!(
+        mismatch("fun f(d: \"up\" | \"down\"): Boolean { d == \"left\" }"),
+        ("\"up\" | \"down\"".to_string(), "\"left\"".to_string())
+    );
+    assert_eq!(
+        mismatch("let n: 1 & 2 = 1"),
+        ("Never".to_string(), "Int".to_string())
+    );
+}
+
+fn effect_errors(source: &str) -> Vec<EffectError> {
+    errors(source)
+        .into_iter()
+        .map(|error| match error {
+            CompileError::Effect { error, .. } => error,
+            other => panic!("expected an effect error, found {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn infers_effect_rows() {
+    let log = "effect Log(String) -> Unit\nfun log(s: String): Effect<Log, Unit> { () }\n";
+    let fail = "effect Fail<E>(E) -> Never\nfun fail<E>(e: E): Effect<Fail<E>, Unit> { () }\n";
+    let sources = [
+        format!("{}fun greet(): Effect<Log, Unit> {{ log('hi') }}", log),
+        format!("{}fun greet() {{ log('hi') }}\nfun twice(): Effect<Log, Unit> {{ greet()\ngreet() }}", log),
+        format!("{}{}fun both(): Effect<Log | Fail<Int>, Unit> {{ log('a')\nfail(1) }}", log, fail),
+        // Higher-order functions perform whatever the functions they call do.
+        format!("{}fun apply(f, x) {{ f(x) }}\nlet n: Int = apply((x) -> x + 1, 1)\nfun g(): Effect<Log, Unit> {{ apply(log, 'a') }}", log),
+        format!("{}fun each<E>(f: (String) -> Effect<E, Unit>): Effect<E, Unit> {{ f('a') }}\nfun g(): Effect<Log, Unit> {{ each(log) }}\neach((s) -> ())", log),
+        format!("{}f = (s: String) -> log(s)\nfun g(): Effect<Log, Unit> {{ f('a') }}", log),
+        // A handled effect does not escape.
+        format!("{}handle Log {{ log('x') }}", log),
+        format!("{}fun main() {{ log('x') }}\nhandle Log {{ main() }}", log),
+        format!("{}fun quiet(): Unit {{ handle Log {{ log('x') }} }}", log),
+        "import { log } from 'std:Console'\nfun main(): Effect<Console, Unit> { log('hi') }".to_string(),
+    ];
+    for source in &sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    // An effect performed where it is neither declared nor handled.
+    for source in [
+        format!("{}fun greet(): Unit {{ log('hi') }}", log),
+        format!(
+            "{}fun greet() {{ log('hi') }}\nfun quiet(): Int {{ greet()\n1 }}",
+            log
+        ),
+        format!(
+            "{}fun each(f: (String) -> Unit): Unit {{ f('a') }}\neach((s) -> log(s))",
+            log
+        ),
+        format!(
+            "{}{}fun f(): Effect<Fail<Int>, Unit> {{ log('a') }}",
+            log, fail
+        ),
+    ] {
+        assert!(
+            matches!(&effect_errors(&source)[..], [EffectError::Unhandled { effect, .. }] if effect == "Log"),
+            "{}: {:?}",
+            source,
+            compile(&source)
+        );
+    }
+
+    // Effects escaping the top level or `main` are reported once each.
+    for source in [
+        format!("{}log('x')\nlog('y')", log),
+        format!("{}fun main() {{ log('x') }}", log),
+        format!("{}fun main() {{ log('x') }}\nmain()", log),
+        format!(
+            "{}fun main(): Effect<Log, Unit> {{ log('x') }}\nmain()",
+            log
+        ),
+    ] {
+        assert!(
+            matches!(&effect_errors(&source)[..], [EffectError::Unhandled { effect, .. }] if effect == "Log"),
+            "{}: {:?}",
+            source,
+            compile(&source)
+        );
+    }
+
+    let source = format!("{}fun f(): Effect<Fail<String>, Unit> {{ fail(1) }}", fail);
+    assert!(
+        matches!(type_errors(&source)[..], [TypeError::Mismatch { .. }]),
+        "{}: {:?}",
+        source,
+        compile(&source)
+    );
+    for source in [
+        "fun f(): Effect<Int, Unit> { () }",
+        "type Pair = (Int, Int)\nx = handle Pair { 1 }",
+    ] {
+        assert!(
+            matches!(type_errors(source)[..], [TypeError::NotAnEffect { .. }]),
+            "{}: {:?}",
+            source,
+            compile(source)
+        );
+    }
+}
+
+#[test]
+fn checks_effect_declarations() {
+    let state = "effect State<A> {\n  get() -> A\n  modify<B>(A -> (B, A)) -> B\n}\n";
+    let fail = "effect Fail<E>(E) -> Never\n";
+    let calc = "type Op = | Add(Int, Int) | Negate(Int)\neffect Calc(Op) -> Int\n";
+    let queue = "effect Queue<A> {\n  enqueue(A): Boolean\n  dequeue(): Option<A>\n}\n";
+    let sources = [
+        format!("{}fun get(): Effect<State<Int>, Int> {{ State.get() + 1 }}", state),
+        // An operation's own type parameters are chosen at each call.
+        format!("{}fun label(): Effect<State<Int>, String> {{ State.modify((a) -> ('n', a + 1)) }}", state),
+        // `Never` fits wherever a value is expected.
+        format!("{}fun divide(a: Int, b: Int): Effect<Fail<String>, Int> {{ match b {{ 0 -> Fail('zero'), _ -> a / b }} }}", fail),
+        format!("{}fun compute(): Effect<Calc, Int> {{ a = Calc(Op.Add(1, 2))\nCalc(Op.Negate(a)) }}", calc),
+        format!("{}fun enqueue_all<A>(list: [A]): Effect<Queue<A>, Unit> {{ for a of list {{ Queue.enqueue(a) }} }}", queue),
+        format!("{}fun next(): Effect<Queue<String>, Option<String>> {{ Queue.dequeue() }}", queue),
+        // An alias of an applied effect names its operations.
+        format!("{}alias Strings = Queue<String>\nfun next(): Effect<Queue<String>, Option<String>> {{ Strings.dequeue() }}", queue),
+        format!("{}fun skip(): Effect<Queue<Int>, Boolean> {{ match Queue.dequeue() {{ Some(n) -> n > 0, None -> false }} }}", queue),
+        // A function of no arguments can run in a fiber of its own.
+        format!("{}fun worker(): Effect<Queue<Int>, Option<Int>> {{ Queue.dequeue() }}\nfun run(): Effect<Queue<Int>, Option<Int>> {{ fiber = worker.fork()\nQueue.enqueue(1)\nfiber.join() }}", queue),
+    ];
+    for source in &sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    for source in [
+        format!("{}fun f(): Effect<Fail<String>, Int> {{ Fail(1) }}", fail),
+        format!("{}fun f(): Effect<Calc, Int> {{ Calc(1) }}", calc),
+        format!(
+            "{}fun f(): Effect<Queue<Int>, Boolean> {{ Queue.enqueue('a') }}",
+            queue
+        ),
+        format!(
+            "{}fun f(): Effect<State<Int>, String> {{ State.get() }}",
+            state
+        ),
+        format!(
+            "{}alias Ints = Queue<Int>\nfun f(): Effect<Queue<Int>, Boolean> {{ Ints.enqueue('a') }}",
+            queue
+        ),
+    ] {
+        assert!(
+            matches!(type_errors(&source)[..], [TypeError::Mismatch { .. }]),
+            "{}: {:?}",
+            source,
+            compile(&source)
+        );
+    }
+
+    assert!(matches!(
+        &resolve_errors(&format!("{}fun f(): Effect<State<Int>, Int> {{ State.put(1) }}", state))[..],
+        [ResolveError::UnknownOperation { effect, name, .. }] if effect == "State" && name == "put"
+    ));
+    assert!(matches!(
+        &resolve_errors("effect Log { log(String): Unit, log(Int): Unit }")[..],
+        [ResolveError::DuplicateDefinition { name, .. }] if name == "log"
+    ));
+    assert!(matches!(
+        &resolve_errors("effect Log { log(B): Unit }")[..],
+        [ResolveError::UnboundType { name, .. }] if name == "B"
+    ));
+    // Only a function of no arguments can be forked.
+    assert!(matches!(
+        &type_errors("fun f(a: Int): Int { a }\nfiber = f.fork()")[..],
+        [TypeError::UnknownField { field, .. }] if field == "fork"
+    ));
+}
+
+#[test]
+fn checks_handlers() {
+    let state = "effect State<A> {\n  get() -> A\n  modify<B>(A -> (B, A)) -> B\n}\n";
+    let fail = "effect Fail<E>(E) -> Never\ntype Either<E, A> = | Left(E) | Right(A)\n";
+    let calc = "type Op = | Add(Int, Int) | Negate(Int)\neffect Calc(Op) -> Int\n";
+    let sources = [
+        format!(
+            "{}impl<A> for State<A> {{
+               fun get_and_update(f: A -> A): A {{ State.modify((a) -> (a, f(a))) }}
+               handler local(initial: A) {{
+                 current := initial
+                 return {{
+                   get() {{ current }},
+                   modify(f) {{ (b, a) = f(current)\ncurrent = a\nb }},
+                 }}
+               }}
+             }}
+             fun count(): Effect<State<Int>, Int> {{ State.get_and_update((n) -> n + 1) }}",
+            state
+        ),
+        // A clause for an operation returning `Never` aborts with the answer.
+        format!(
+            "{}impl<E> for Fail<E> {{
+               handler try(error: E) {{ Either.Left(error) }} {{ return(a) {{ Either.Right(a) }} }}
+               fun catch<A>(f: (E) -> A) {{ handler(error: E) {{ f(error) }} }}
+             }}
+             t = Fail.try()
+             c = Fail.catch((e: String) -> 0)",
+            fail
+        ),
+        format!(
+            "{}impl for Calc {{
+               handler(op) {{ match op {{ Add(a, b) -> resume(a + b), Negate(a) -> resume(-a) }} }}
+             }}",
+            calc
+        ),
+    ];
+    for source in &sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    assert!(matches!(
+        &resolve_errors(&format!("{}impl<A> for State<A> {{ handler h(a: A) {{ return {{ get() {{ a }} }} }} }}", state))[..],
+        [ResolveError::MissingOperation { effect, name, .. }] if effect == "State" && name == "modify"
+    ));
+    assert!(matches!(
+        &resolve_errors(&format!("{}impl for Calc {{ handler h() {{ return {{ Calc(op) {{ 1 }}, put(a) {{ 1 }} }} }} }}", calc))[..],
+        [ResolveError::UnknownOperation { name, .. }] if name == "put"
+    ));
+    assert!(matches!(
+        &resolve_errors("x = resume(1)")[..],
+        [ResolveError::ResumeOutsideHandler { .. }]
+    ));
+    assert!(matches!(
+        &resolve_errors("x = handler(a) { a }")[..],
+        [ResolveError::HandlerOutsideImpl { .. }]
+    ));
+    assert!(matches!(
+        &type_errors(&format!(
+            "{}impl for Calc {{ handler(op) {{ 'one' }} }}",
+            calc
+        ))[..],
+        [TypeError::Mismatch { .. }]
+    ));
+}
+
+#[test]
+fn composes_handlers() {
+    let log = "effect Log(String) -> Unit\nimpl for Log { handler(s) { () } }\n";
+    let calc = "effect Fail<E>(E) -> Never
+type Either<E, A> = | Left(E) | Right(A)
+impl<E> for Fail<E> {
+  handler try(error: E) { Either.Left(error) } { return(a) { Either.Right(a) } }
+}
+effect Calc(Int, Int) -> Int
+impl for Calc { handler(a, b) { b == 0 ? Fail('zero') : resume(a / b) } }
+fun divide(): Effect<Calc, Int> { Calc(4, 2) }
+";
+    let sources = [
+        "xs = [1] ++ [2, 3]\nlet ys: [Int] = xs ++ xs".to_string(),
+        "let s: String = 'a' ++ 'b'".to_string(),
+        // Without an operand after it, `++` is still an increment.
+        "i := 1\ni++\nxs = [i] ++ [++i]".to_string(),
+        format!("{}fun main(): Effect<Log, Int> {{ Log('hi')\n1 }}\nlet n: Int = main() with Log", log),
+        // The left handler is inside the right one, which handles what
+        // its clauses perform.
+        format!("{}let r: Either<String, Int> = divide() with Calc ++ Fail.try()", calc),
+        format!("{}handlers = Calc ++ Fail.try()\nlet r: Either<String, Int> = divide() with handlers", calc),
+        "import Console from 'std:Console'\nfun main(): Effect<Console, Unit> { Console.log('hi') }\nmain() with Console.Platform".to_string(),
+        "import Console from 'std:Console'\nfun main() { Console.log('hi') }\nx = main() with Console.Platform".to_string(),
+    ];
+    for source in &sources {
+        let output = compile(source);
+        assert!(
+            output
+                .as_ref()
+                .is_ok_and(|output| output.warnings.is_empty()),
+            "{}: {:?}",
+            source,
+            output
+        );
+    }
+
+    // The effects the handlers do not handle are still performed.
+    assert!(matches!(
+        &effect_errors(&format!("{}x = divide() with Fail.try() ++ Calc", calc))[..],
+        [EffectError::Unhandled { effect, .. }] if effect == "Fail<String>"
+    ));
+    assert!(matches!(
+        &effect_errors(&format!("{}x = divide() with Calc", calc))[..],
+        [EffectError::Unhandled { effect, .. }] if effect == "Fail<String>"
+    ));
+    assert!(matches!(
+        &resolve_errors(&format!("{}x = 1 with Fail", calc))[..],
+        [ResolveError::NoDefaultHandler { effect, .. }] if effect == "Fail"
+    ));
+    assert!(matches!(
+        &type_errors("x = 1 with 1")[..],
+        [TypeError::Mismatch { .. }]
+    ));
+    assert!(matches!(
+        &type_errors("x = 1 ++ 2")[..],
+        [TypeError::UnsupportedOperator { operator, .. }] if operator == "++"
+    ));
+
+    let source = format!(
+        "{}fun main(): Effect<Log, Unit> {{ Log('hi') }}\nmain() with Log ++ Log",
+        log
+    );
+    let warnings = compile(&source)
+        .expect("expected compilation to succeed")
+        .warnings;
+    assert!(matches!(
+        &warnings[..],
+        [CompileWarning::ShadowedHandler { effect, span, shadowed_by, .. }]
+            if effect == "Log" && source[span.clone()] == *"Log" && shadowed_by.start < span.start
+    ));
+
+    // A handler bound to a name can be used again, around computations of
+    // other types and even inside itself.
+    let ask = "effect Ask() -> Int
+impl for Ask { handler one() { resume(1) } }
+fun count(): Effect<Ask, Int> { Ask() }
+fun name(): Effect<Ask, String> { Ask()\n'n' }
+h = Ask.one()
+";
+    let source = format!(
+        "{}let n: Int = count() with h\nlet s: String = name() with h",
+        ask
+    );
+    assert!(
+        compile(&source).is_ok(),
+        "{}: {:?}",
+        source,
+        compile(&source)
+    );
+    let source = format!("{}x = count() with h ++ h", ask);
+    let warnings = compile(&source)
+        .expect("expected compilation to succeed")
+        .warnings;
+    assert!(matches!(
+        &warnings[..],
+        [CompileWarning::ShadowedHandler { effect, .. }] if effect == "Ask"
+    ));
+}
+
+#[test]
+fn checks_that_one_shot_handlers_resume_once() {
+    let choice = "effect Choice { flip(): Boolean }\n";
+    let handler = |modifier: &str, clause: &str| {
+        format!(
+            "{}impl for Choice {{ {}handler all() {{ tries := 0\nreturn {{ flip() {{ {} }} }} }} {{ return(a) {{ [a] }} }} }}",
+            choice, modifier, clause
+        )
+    };
+    let sources = [
+        handler("multi ", "resume(true) ++ resume(false)"),
+        handler("multi ", "for x of [1, 2] { resume(true) }\nresume(false)"),
+        // Only one of the paths through the clause is taken.
+        handler("", "if (tries > 0) return resume(false)\nresume(true)"),
+        handler("", "if (tries > 0) { return resume(false) }\nresume(true)"),
+        handler("", "tries > 0 ? resume(false) : resume(true)"),
+        handler("", "match tries { 0 -> resume(true), _ -> resume(false) }"),
+        // A loop left right after its `resume` only resumes once.
+        handler("", "r := []\nwhile (true) { r = resume(true)\nbreak }\nr"),
+        handler(
+            "",
+            "r := []\nwhile (tries < 3) { if (tries > 1) { r = resume(true)\nbreak }\ntries = tries + 1 }\nr",
+        ),
+        handler("", "for x of [1, 2] { return resume(true) }\n[]"),
+        // A continuation that escapes is only checked when it is resumed.
+        handler("", "k = (b) -> resume(b)\nk(true) ++ k(false)"),
+        // A continuation can be stored and resumed later.
+        "effect Wait { wait(): Int }
+         impl for Wait {
+           handler later() {
+             waiters := []
+             return { wait() { waiters = waiters ++ [resume]\nfirst = waiters[0]\nfirst(1) } }
+           }
+         }"
+        .to_string(),
+    ];
+    for source in &sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    for (clause, twice) in [
+        (
+            "resume(true) ++ resume(false)",
+            ["resume(true)", "resume(false)"],
+        ),
+        (
+            "if (tries > 0) { resume(false) }\nresume(true)",
+            ["resume(false)", "resume(true)"],
+        ),
+        ("k = resume\nresume(true)", ["resume", "resume(true)"]),
+        (
+            "for x of [1, 2] { resume(true) }\nresume(false)",
+            ["resume(true)", "resume(true)"],
+        ),
+        // Only leaving the loop ends it: a `continue` goes round again.
+        (
+            "while (true) { resume(true)\ncontinue }\n[]",
+            ["resume(true)", "resume(true)"],
+        ),
+        (
+            "while (true) { resume(true)\nbreak }\nresume(false)",
+            ["resume(true)", "resume(false)"],
+        ),
+    ] {
+        let source = handler("", clause);
+        match &effect_errors(&source)[..] {
+            [EffectError::ResumedTwice {
+                operation,
+                first,
+                span,
+            }] => {
+                assert_eq!(operation, "flip");
+                assert!(source[first.start..].starts_with(twice[0]), "{}", clause);
+                assert!(source[span.start..].starts_with(twice[1]), "{}", clause);
+            }
+            other => panic!("{}: {:?}", clause, other),
+        }
+    }
+
+    assert!(matches!(
+        &resolve_errors("k = resume")[..],
+        [ResolveError::ResumeOutsideHandler { .. }]
+    ));
+}
+
+#[test]
+fn narrows_unions() {
+    let sources = [
+        // Comparing with a literal narrows in both branches.
+        "fun f(d: \"up\" | \"down\" | \"left\"): \"down\" | \"left\" { if (d == \"up\") { return \"down\" }\nd }",
+        "fun f(d: \"up\" | \"down\"): \"up\" { if (d != \"down\") { return d } else { return \"up\" } }",
+        "fun f(d: \"a\" | \"b\" | \"c\"): \"c\" { if (d == \"a\") { return \"c\" } else if (d == \"b\") { return \"c\" } else { return d } }",
+        "fun f(d: \"a\" | \"b\"): \"a\" { d == \"a\" ? d : \"a\" }",
+        "fun f(d: \"a\" | \"b\", e: \"a\" | \"b\"): \"b\" { if (!(d == \"a\") && e == \"b\") { return d }\ne == \"b\" ? e : \"b\" }",
+        // Discriminated unions are narrowed by their tag.
+        "type Shape = { kind: \"circle\", radius: Float } | { kind: \"square\", side: Float }\n\
+         fun area(s: Shape): Float { if (s.kind == \"circle\") { return s.radius * s.radius }\ns.side * s.side }",
+        "type Shape = { kind: \"circle\", radius: Float } | { kind: \"square\", side: Float }\n\
+         fun area(s: Shape): Float { s.kind == \"circle\" && s.radius > 1.0 ? s.radius : 0.0 }",
+        // Each arm of a match sees what its pattern picks out, less what
+        // earlier arms took.
+        "type Shape = { kind: \"circle\", radius: Float } | { kind: \"square\", side: Float }\n\
+         fun area(s: Shape): Float { match s { { kind: \"circle\", radius } -> radius * radius, other -> other.side * other.side } }",
+        "fun f(d: \"a\" | \"b\"): \"b\" { match d { \"a\" -> \"b\", rest -> rest } }",
+        // `??` replaces a missing value.
+        "fun f(name: String | ()): String { name ?? 'anonymous' }",
+        "fun f(d: \"a\" | ()): \"a\" { if (d == ()) { return \"a\" } else { return d } }",
+    ];
+    for source in sources {
+        assert!(compile(source).is_ok(), "{}: {:?}", source, compile(source));
+    }
+
+    // Only a union holds the `()` that `??` replaces; an `Option` is matched.
+    assert!(matches!(
+        &type_errors("fun f(name: Option<String>): String { name ?? 'anonymous' }")[..],
+        [TypeError::UnsupportedOperator { operator, ty, .. }]
+            if operator == "??" && ty == "Option<String>"
+    ));
+    assert!(matches!(
+        &type_errors("fun f(name: Option<String>): Unit { n := name\nn ??= Some('a')\n() }")[..],
+        [TypeError::UnsupportedOperator { operator, .. }] if operator == "??"
+    ));
+
+    // Narrowing lasts only until the name is assigned.
+    assert!(compile("fun f(d: \"a\" | \"b\"): \"b\" { e := d\ne = \"b\"\nreturn \"b\" }").is_ok());
+    assert!(matches!(
+        &type_errors(
+            "fun f(d: \"a\" | \"b\"): \"a\" { e := d\nif (e == \"a\") { e = \"b\"\nreturn e }\n\"a\" }"
+        )[..],
+        [TypeError::Mismatch { .. }]
+    ));
+    // A field only some members have cannot be read before narrowing.
+    assert!(matches!(
+        &type_errors(
+            "type Shape = { kind: \"circle\", radius: Float } | { kind: \"square\", side: Float }\n\
+             fun f(s: Shape): Float { s.radius }"
+        )[..],
+        [TypeError::UnknownField { .. }]
+    ));
+
+    // Matches on unions of literals, and on unions discriminated by a
+    // literal field, can be exhaustive.
+    assert!(compile("fun f(d: \"a\" | \"b\"): Int { match d { \"a\" -> 1, \"b\" -> 2 } }").is_ok());
+    assert_eq!(
+        missing_patterns(
+            "fun f(d: \"a\" | \"b\" | \"c\"): Int { match d { \"a\" -> 1, \"c\" -> 2 } }"
+        ),
+        ["\"b\""]
+    );
+    let shape =
+        "type Shape = { kind: \"circle\", radius: Float } | { kind: \"square\", side: Float }\n";
+    assert!(compile(&format!(
+        "{}fun f(s: Shape): Float {{ match s {{ {{ kind: \"circle\", radius }} -> radius, {{ kind: \"square\", side }} -> side }} }}",
+        shape
+    ))
+    .is_ok());
+    assert_eq!(
+        missing_patterns(&format!(
+            "{}fun f(s: Shape): Float {{ match s {{ {{ kind: \"circle\", radius: 0.0 }} -> 0.0, {{ kind: \"square\", side }} -> side }} }}",
+            shape
+        )),
+        ["{ kind: \"circle\", radius: _, side: _ }"]
+    );
+}
+
+#[test]
+fn reports_resolution_errors() {
+    assert_eq!(
+        resolve_errors("x = y"),
+        vec![ResolveError::UnboundVariable {
+            name: "y".to_string(),
+            span: 4..5,
+        }]
+    );
+
+    assert_eq!(
+        resolve_errors("x = 1\nx = 2"),
+        vec![ResolveError::AssignToImmutable {
+            name: "x".to_string(),
+            declared: 0..1,
+            span: 6..7,
+        }]
+    );
+
+    assert!(matches!(
+        &resolve_errors("let x: Missing = 1")[..],
+        [ResolveError::UnboundType { name, .. }] if name == "Missing"
+    ));
+
+    assert!(matches!(
+        &resolve_errors("import Fs from 'std:Fs'")[..],
+        [ResolveError::UnknownModule { module, .. }] if module == "std:Fs"
+    ));
+
+    assert!(matches!(
+        resolve_errors("fun f() { 1 }\nfun f() { 2 }")[..],
+        [ResolveError::DuplicateDefinition { .. }]
+    ));
+
+    assert!(matches!(
+        resolve_errors("return 1")[..],
+        [ResolveError::ReturnOutsideFunction { .. }]
+    ));
+}
+
+#[test]
+fn reports_every_error_in_a_program() {
+    let errors = errors("a = missing\nlet b: Int = 'b'\nc = 1 + true");
+    assert_eq!(errors.len(), 3);
+    assert!(matches!(errors[0], CompileError::Resolve { .. }));
+    assert!(matches!(errors[1], CompileError::Type { .. }));
+    assert!(matches!(errors[2], CompileError::Type { .. }));
+}
+
+#[test]
+fn lex_and_parse_errors_are_owned() {
+    // The errors outlive the source text they were produced from.
+    let lex_errors = {
+        let source = String::from("x = 1 \\ 2");
+        errors(&source)
+    };
+    assert_eq!(
+        lex_errors,
+        vec![CompileError::Lex {
+            source: SourceId::default(),
+            error: LexError::InvalidToken {
+                text: "\\".to_string(),
+                span: 6..7,
+            },
+        }]
+    );
+
+    let parse_errors = {
+        let source = String::from("x = (1 + ");
+        errors(&source)
+    };
+    assert!(matches!(
+        parse_errors[..],
+        [CompileError::Parse {
+            error: ParseError::Unclosed { found: None, .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn errors_carry_the_source_id() {
+    let errors = compile_source(SourceId::new("main.asura"), "x = y").unwrap_err();
+    assert_eq!(errors[0].source().as_str(), "main.asura");
+    assert_eq!(errors[0].span(), 4..5);
+    assert_eq!(
+        errors[0].to_string(),
+        "main.asura:4..5: cannot find value `y` in this scope"
+    );
+}
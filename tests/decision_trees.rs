@@ -0,0 +1,75 @@
+use asura::decision_trees;
+
+fn dump(source: &str) -> String {
+    decision_trees(source)
+        .expect("expected compilation to succeed")
+        .join("\n")
+}
+
+#[test]
+fn constructor_tags() {
+    insta::assert_snapshot!(dump(
+        "type Op = | Add(Int, Int) | Divide(Int, Int) | Negate(Int)
+fun eval(op: Op): Int {
+  match op { Add(a, b) -> a + b, Divide(a, 0) -> 0, Divide(a, b) -> a / b, Negate(a) -> -a }
+}"
+    ));
+}
+
+#[test]
+fn nested_constructors_share_tests() {
+    insta::assert_snapshot!(dump(
+        "type Expr = | Lit(Int) | Neg(Expr) | Pair(Expr, Expr)
+fun f(e: Expr): Int {
+  match e { Neg(Lit(n)) -> n, Neg(Neg(e)) -> 0, Neg(x) -> 1, Lit(n) -> n, Pair(_, _) -> 0 }
+}"
+    ));
+}
+
+#[test]
+fn literal_switches() {
+    insta::assert_snapshot!(dump(
+        "fun f(p: (Int, String)): Int { match p { (0, _) -> 1, (n, 'x') -> n, (1, 'y') -> 2, _ -> 3 } }"
+    ));
+}
+
+#[test]
+fn boolean_tuples() {
+    insta::assert_snapshot!(dump(
+        "x = match (true, false) { (true, true) -> 0, (false, _) -> 1, (_, false) -> 2 }"
+    ));
+}
+
+#[test]
+fn array_lengths() {
+    insta::assert_snapshot!(dump(
+        "fun f(xs: [Int]): Int { match xs { [] -> 0, [x] -> x, [a, ...[b, ...rest]] -> a + b } }"
+    ));
+}
+
+#[test]
+fn guards_fall_through() {
+    insta::assert_snapshot!(dump(
+        "fun fib(n: Int): Int { match n { n < 2 -> n, _ -> fib(n - 2) + fib(n - 1) } }"
+    ));
+}
+
+#[test]
+fn records_and_nested_matches() {
+    insta::assert_snapshot!(dump(
+        "type Shape = | Circle { radius: Float } | Square(Float)
+fun size(s: Shape): Float {
+  match s { Circle({ radius }) -> radius, Square(side) -> match side { 0.0 -> 0.0, _ -> side } }
+}"
+    ));
+}
+
+#[test]
+fn discriminated_unions() {
+    insta::assert_snapshot!(dump(
+        "type Shape = { kind: \"circle\", radius: Float } | { kind: \"square\", side: Float }
+fun area(s: Shape): Float {
+  match s { { kind: \"circle\", radius: 0.0 } -> 0.0, { kind: \"circle\", radius } -> radius, { side } -> side * side }
+}"
+    ));
+}
@@ -0,0 +1,200 @@
+use asura::compile_source;
+use asura::diagnostics::{render, render_warnings, Diagnostic, Severity, Style};
+use asura::error::{CompileError, EffectError, SourceId};
+
+fn diagnose(source: &str) -> String {
+    let errors = compile_source(SourceId::new("test.asura"), source)
+        .expect_err("expected compilation to fail");
+    render(&errors, source, Style::Plain)
+}
+
+#[test]
+fn invalid_token() {
+    insta::assert_snapshot!(diagnose("x = 1 \\ 2"));
+}
+
+#[test]
+fn invalid_escape() {
+    insta::assert_snapshot!(diagnose("x = \"a\\qb\""));
+}
+
+#[test]
+fn invalid_token_in_interpolation() {
+    insta::assert_snapshot!(diagnose("x = `a ${1 \\ 2} b`"));
+}
+
+#[test]
+fn unterminated_template() {
+    insta::assert_snapshot!(diagnose("x = `total: ${1 + 2}"));
+}
+
+#[test]
+fn integer_too_large() {
+    insta::assert_snapshot!(diagnose("x = 1 + 99999999999999999999"));
+}
+
+#[test]
+fn big_decimal_too_large() {
+    insta::assert_snapshot!(diagnose("x = 99999999999999999999999999999999999999999.5n"));
+}
+
+#[test]
+fn unexpected_token() {
+    insta::assert_snapshot!(diagnose("fun add(a: Int, b: Int) {\n  a +\n}"));
+}
+
+#[test]
+fn unexpected_end_of_input() {
+    insta::assert_snapshot!(diagnose("x = (1, 2"));
+}
+
+#[test]
+fn unbound_variable() {
+    insta::assert_snapshot!(diagnose("fun f(a: Int): Int {\n  a + b\n}"));
+}
+
+#[test]
+fn duplicate_definition() {
+    insta::assert_snapshot!(diagnose("fun f() { 1 }\n\nfun f() { 2 }"));
+}
+
+#[test]
+fn assign_to_immutable() {
+    insta::assert_snapshot!(diagnose("count = 0\ncount = count + 1"));
+}
+
+#[test]
+fn unknown_constructor() {
+    insta::assert_snapshot!(diagnose("type Op =\n  | Add(Int, Int)\nOp.Remove(1, 2)"));
+}
+
+#[test]
+fn ambiguous_constructor() {
+    insta::assert_snapshot!(diagnose(
+        "type A = | Same\ntype B = | Same\nf = (x) -> match x { Same -> 0 }"
+    ));
+}
+
+#[test]
+fn non_exhaustive_match() {
+    insta::assert_snapshot!(diagnose(
+        "type Op = | Add(Int, Int) | Divide(Int, Int)\nfun eval(op: Op): Int {\n  match op { Add(a, b) -> a + b }\n}"
+    ));
+}
+
+#[test]
+fn unreachable_pattern() {
+    let source = "fun sign(n: Int): Int {\n  match n { _ -> 0, 0 -> 1 }\n}";
+    let output = compile_source(SourceId::new("test.asura"), source)
+        .expect("expected compilation to succeed");
+    insta::assert_snapshot!(render_warnings(&output.warnings, source, Style::Plain));
+}
+
+#[test]
+fn shadowed_handler() {
+    let source =
+        "effect Log(String) -> Unit\nimpl for Log { handler(s) { () } }\nLog('hi') with Log ++ Log";
+    let output = compile_source(SourceId::new("test.asura"), source)
+        .expect("expected compilation to succeed");
+    insta::assert_snapshot!(render_warnings(&output.warnings, source, Style::Plain));
+}
+
+#[test]
+fn resumed_twice() {
+    insta::assert_snapshot!(diagnose(
+        "effect Choice { flip(): Boolean }\nimpl for Choice {\n  handler all() {\n    return { flip() { resume(true) ++ resume(false) } }\n  } {\n    return(a) { [a] }\n  }\n}"
+    ));
+}
+
+#[test]
+fn escaped_skolem() {
+    insta::assert_snapshot!(diagnose(
+        "fun run<R>(f: <S>(S) -> R): R {\n  f(0)\n}\nleaked = run((s) -> s)"
+    ));
+}
+
+#[test]
+fn kind_mismatch() {
+    insta::assert_snapshot!(diagnose(
+        "type Functor<F<_>> = { map: <A, B>(F<A>, (A) -> B) -> F<B> }\nlet numbers: Functor<Int> = 1"
+    ));
+}
+
+#[test]
+fn parameter_kind_mismatch() {
+    insta::assert_snapshot!(diagnose("fun size<F, A>(items: F<A>): Int {\n  0\n}"));
+}
+
+#[test]
+fn variance_mismatch() {
+    insta::assert_snapshot!(diagnose("type Handler<out A> = | Handler((A) -> Unit)"));
+}
+
+#[test]
+fn type_mismatch() {
+    insta::assert_snapshot!(diagnose(
+        "fun greet(name: String): String { name }\ngreet(42)"
+    ));
+}
+
+#[test]
+fn several_errors() {
+    insta::assert_snapshot!(diagnose("let a: Int = 'a'\nb = missing\nc = true * 2"));
+}
+
+#[test]
+fn unhandled_effect() {
+    let source = "effect Log(String) -> Unit\nLog('hi')";
+    let error = CompileError::Effect {
+        source: SourceId::new("test.asura"),
+        error: EffectError::Unhandled {
+            effect: "Log".to_string(),
+            declared: 0..26,
+            span: 27..36,
+        },
+    };
+    insta::assert_snapshot!(render(&[error], source, Style::Plain));
+}
+
+#[test]
+fn effect_escaping_main() {
+    insta::assert_snapshot!(diagnose(
+        "effect Log(String) -> Unit\nfun log(s: String): Effect<Log, Unit> { () }\nfun main() { log('hi') }"
+    ));
+}
+
+// A program that calls `main` itself runs it with what handles the call.
+#[test]
+fn effect_escaping_the_top_level() {
+    insta::assert_snapshot!(diagnose(
+        "effect Log(String) -> Unit\nfun log(s: String): Effect<Log, Unit> { () }\nfun main() { log('hi') }\nmain()"
+    ));
+}
+
+#[test]
+fn spans_are_byte_offsets() {
+    // The multi-byte string before the error must not shift the label.
+    insta::assert_snapshot!(diagnose("xs = ['héllo', 1]"));
+}
+
+#[test]
+fn colored_output_uses_escape_codes() {
+    let errors = compile_source(SourceId::default(), "x = y").unwrap_err();
+    let plain = render(&errors, "x = y", Style::Plain);
+    let colored = render(&errors, "x = y", Style::Colored);
+
+    assert!(!plain.contains('\u{1b}'));
+    assert!(colored.contains('\u{1b}'));
+}
+
+#[test]
+fn diagnostics_describe_errors() {
+    let errors = compile_source(SourceId::default(), "x = 1\nx = 2").unwrap_err();
+    let diagnostic = Diagnostic::from(&errors[0]);
+
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.code, "E0205");
+    assert_eq!(diagnostic.primary.span, 6..7);
+    assert_eq!(diagnostic.secondary[0].span, 0..1);
+    assert!(diagnostic.help.is_some());
+}
@@ -0,0 +1,418 @@
+use asura::bytecode::format::{self, VERSION};
+use asura::bytecode::verifier::VerifyError;
+use asura::bytecode::{
+    Capture, Chunk, ClauseShape, Constant, EffectSignature, Function, HandlerShape, Instruction,
+    LoadError, Module, OperationSignature,
+};
+use asura::compile_bytecode;
+use asura::vm::{RuntimeError, Value, Vm};
+use proptest::prelude::*;
+use std::path::Path;
+
+fn example(name: &str) -> Module {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples")
+        .join(name)
+        .with_extension("asura");
+    let source = std::fs::read_to_string(&path).expect("expected the example to exist");
+    compile_bytecode(&source).expect("expected compilation to succeed")
+}
+
+/// A module of one function, the entry, with `code` and `constants`.
+fn module(code: Vec<Instruction>, constants: Vec<Constant>) -> Module {
+    Module {
+        functions: vec![Function {
+            name: "main".to_string(),
+            arity: 0,
+            locals: 1,
+            captures: Vec::new(),
+            chunk: Chunk {
+                code,
+                constants,
+                spans: Vec::new(),
+            },
+        }],
+        globals: vec!["x".to_string()],
+        ..Module::default()
+    }
+}
+
+fn invalid(module: &Module) -> VerifyError {
+    match Module::from_bytes(&module.to_bytes()) {
+        Err(LoadError::Invalid { error, .. }) => error,
+        result => panic!("expected the module to be rejected, found {:?}", result),
+    }
+}
+
+fn name() -> impl Strategy<Value = String> {
+    "[a-zA-Z_.]{0,8}"
+}
+
+fn constant() -> impl Strategy<Value = Constant> {
+    prop_oneof![
+        any::<i64>().prop_map(Constant::Integer),
+        // Any float but NaN, which is not equal to itself.
+        any::<f64>()
+            .prop_filter("NaN", |value| !value.is_nan())
+            .prop_map(Constant::Float),
+        any::<i128>().prop_map(Constant::BigInteger),
+        "[0-9]{1,6}\\.[0-9]{1,6}".prop_map(Constant::BigDecimal),
+        any::<String>().prop_map(Constant::String),
+        name().prop_map(Constant::Symbol),
+        prop::collection::vec(name(), 0..4).prop_map(Constant::Keys),
+        (name(), name()).prop_map(|(effect, name)| Constant::Operation { effect, name }),
+        (
+            name(),
+            prop::collection::vec((name(), any::<bool>()), 0..4),
+            any::<bool>(),
+            any::<bool>()
+        )
+            .prop_map(|(effect, clauses, finish, multishot)| {
+                Constant::Handler(HandlerShape {
+                    effect,
+                    clauses: clauses
+                        .into_iter()
+                        .map(|(operation, tail_resumptive)| ClauseShape {
+                            operation,
+                            tail_resumptive,
+                        })
+                        .collect(),
+                    finish,
+                    multishot,
+                })
+            }),
+    ]
+}
+
+fn instruction() -> impl Strategy<Value = Instruction> {
+    prop_oneof![
+        Just(Instruction::Unit),
+        Just(Instruction::Dup),
+        Just(Instruction::Extend),
+        Just(Instruction::SetIndex),
+        Just(Instruction::Concat),
+        Just(Instruction::Return),
+        Just(Instruction::NoMatch),
+        Just(Instruction::Handle),
+        Just(Instruction::Resume),
+        any::<u16>().prop_map(Instruction::Constant),
+        any::<u16>().prop_map(Instruction::GetCapture),
+        any::<u16>().prop_map(Instruction::Record),
+        any::<u16>().prop_map(Instruction::IsLengthAtLeast),
+        any::<u16>().prop_map(Instruction::Closure),
+        any::<u32>().prop_map(Instruction::Jump),
+        any::<u32>().prop_map(Instruction::JumpIfFalse),
+        any::<u8>().prop_map(Instruction::TailCall),
+        (any::<u16>(), any::<u8>()).prop_map(|(tag, fields)| Instruction::Variant { tag, fields }),
+        (any::<u16>(), any::<u8>()).prop_map(|(operation, arguments)| {
+            Instruction::Perform {
+                operation,
+                arguments,
+            }
+        }),
+    ]
+}
+
+fn function() -> impl Strategy<Value = Function> {
+    (
+        name(),
+        any::<u16>(),
+        any::<u16>(),
+        prop::collection::vec(
+            prop_oneof![
+                any::<u16>().prop_map(Capture::Local),
+                any::<u16>().prop_map(Capture::Capture)
+            ],
+            0..4,
+        ),
+        prop::collection::vec(constant(), 0..8),
+        prop::collection::vec(instruction(), 0..16),
+        prop::collection::vec((any::<u32>(), any::<u32>(), any::<u32>()), 0..4),
+    )
+        .prop_map(
+            |(name, arity, locals, captures, constants, code, spans)| Function {
+                name,
+                arity,
+                locals,
+                captures,
+                chunk: Chunk {
+                    code,
+                    constants,
+                    spans: spans
+                        .into_iter()
+                        .map(|(offset, start, end)| (offset, start as usize..end as usize))
+                        .collect(),
+                },
+            },
+        )
+}
+
+fn any_module() -> impl Strategy<Value = Module> {
+    (
+        prop::collection::vec(function(), 0..4),
+        prop::collection::vec(name(), 0..4),
+        prop::collection::vec(any::<u16>(), 0..4),
+        prop::collection::vec(
+            (
+                name(),
+                prop::collection::vec((name(), any::<u8>(), any::<bool>()), 0..4),
+            ),
+            0..3,
+        ),
+    )
+        .prop_map(|(functions, globals, exports, effects)| Module {
+            functions,
+            globals,
+            exports,
+            effects: effects
+                .into_iter()
+                .map(|(name, operations)| EffectSignature {
+                    name,
+                    operations: operations
+                        .into_iter()
+                        .map(|(name, arity, never)| OperationSignature { name, arity, never })
+                        .collect(),
+                })
+                .collect(),
+        })
+}
+
+proptest! {
+    #[test]
+    fn modules_round_trip(module in any_module()) {
+        prop_assert_eq!(format::decode(&format::encode(&module)), Ok(module));
+    }
+
+    // Whatever a file is cut down to or has overwritten, reading it gives an
+    // error instead of panicking.
+    #[test]
+    fn damaged_files_are_rejected(
+        module in any_module(),
+        cut in any::<prop::sample::Index>(),
+        byte in any::<prop::sample::Index>(),
+        value in any::<u8>(),
+    ) {
+        let bytes = format::encode(&module);
+        let truncated = &bytes[..cut.index(bytes.len())];
+        prop_assert!(Module::from_bytes(truncated).is_err());
+
+        let mut corrupted = bytes.clone();
+        corrupted[byte.index(bytes.len())] = value;
+        let _ = Module::from_bytes(&corrupted);
+    }
+}
+
+#[test]
+fn compiled_modules_load_and_run() {
+    for name in ["fib", "state", "pattern_matching", "queue"] {
+        let module = example(name);
+        let loaded = Module::from_bytes(&module.to_bytes()).expect("expected the module to load");
+        assert_eq!(loaded, module);
+        assert_eq!(Vm::new().load(loaded), Ok(Value::Unit));
+    }
+
+    // Exports and effect signatures are kept along with the code.
+    let module = compile_bytecode(
+        "effect Ask() -> Int
+effect Log { write(String): Unit, stop(): Never }
+export fun main(): Int { 1 }
+fun helper(): Int { 2 }",
+    )
+    .expect("expected compilation to succeed");
+    let exports: Vec<&str> = module
+        .exports
+        .iter()
+        .map(|export| module.globals[*export as usize].as_str())
+        .collect();
+    assert_eq!(exports, ["main"]);
+    assert_eq!(
+        module.effects,
+        [
+            EffectSignature {
+                name: "Ask".to_string(),
+                operations: vec![OperationSignature {
+                    name: "Ask".to_string(),
+                    arity: 0,
+                    never: false
+                }]
+            },
+            EffectSignature {
+                name: "Log".to_string(),
+                operations: vec![
+                    OperationSignature {
+                        name: "write".to_string(),
+                        arity: 1,
+                        never: false
+                    },
+                    OperationSignature {
+                        name: "stop".to_string(),
+                        arity: 0,
+                        never: true
+                    }
+                ]
+            }
+        ]
+    );
+    assert_eq!(Module::from_bytes(&module.to_bytes()), Ok(module));
+}
+
+#[test]
+fn rejects_files_of_other_formats_and_versions() {
+    let bytes = example("fib").to_bytes();
+    assert_eq!(
+        Module::from_bytes(b"fun main() {}"),
+        Err(LoadError::NotBytecode)
+    );
+
+    let mut newer = bytes.clone();
+    newer[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    let error = Module::from_bytes(&newer).unwrap_err();
+    assert_eq!(error, LoadError::UnsupportedVersion { found: VERSION + 1 });
+    assert_eq!(
+        error.to_string(),
+        "the file is of version 2 of the format, but only version 1 is supported"
+    );
+
+    let mut longer = bytes.clone();
+    longer.push(0);
+    assert_eq!(
+        Module::from_bytes(&longer),
+        Err(LoadError::TrailingBytes {
+            offset: bytes.len()
+        })
+    );
+    assert!(matches!(
+        Module::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(LoadError::Truncated { .. })
+    ));
+}
+
+#[test]
+fn rejects_modules_that_cannot_run() {
+    use Instruction::*;
+
+    assert_eq!(
+        invalid(&module(vec![Pop, Unit, Return], Vec::new())),
+        VerifyError::StackUnderflow {
+            depth: 0,
+            needed: 1
+        }
+    );
+    // The two paths meet at the `return` with different stacks.
+    assert_eq!(
+        invalid(&module(
+            vec![True, JumpIfFalse(4), Unit, Unit, Return],
+            Vec::new()
+        )),
+        VerifyError::InconsistentStack {
+            expected: 2,
+            found: 0
+        }
+    );
+    assert_eq!(
+        invalid(&module(vec![Unit], Vec::new())),
+        VerifyError::FallsOffEnd
+    );
+    assert_eq!(
+        invalid(&module(vec![Jump(7)], Vec::new())),
+        VerifyError::NoTarget(7)
+    );
+    assert_eq!(
+        invalid(&module(vec![GetLocal(1), Return], Vec::new())),
+        VerifyError::NoLocal(1)
+    );
+    assert_eq!(
+        invalid(&module(vec![GetGlobal(1), Return], Vec::new())),
+        VerifyError::NoGlobal(1)
+    );
+    assert_eq!(
+        invalid(&module(vec![Constant(0), Return], Vec::new())),
+        VerifyError::NoConstant(0)
+    );
+
+    // Operands must be constants of the right kind.
+    let keys = asura::bytecode::Constant::Keys(vec!["a".to_string()]);
+    assert_eq!(
+        invalid(&module(vec![Constant(0), Return], vec![keys.clone()])),
+        VerifyError::WrongConstant {
+            index: 0,
+            expected: "a value"
+        }
+    );
+    assert_eq!(
+        invalid(&module(vec![Unit, GetKey(0), Return], vec![keys])),
+        VerifyError::WrongConstant {
+            index: 0,
+            expected: "a name"
+        }
+    );
+
+    // Operations of declared effects are performed with their arguments.
+    let mut performs = module(
+        vec![
+            Unit,
+            Perform {
+                operation: 0,
+                arguments: 1,
+            },
+            Return,
+        ],
+        vec![asura::bytecode::Constant::Operation {
+            effect: "Ask".to_string(),
+            name: "Ask".to_string(),
+        }],
+    );
+    performs.effects = vec![EffectSignature {
+        name: "Ask".to_string(),
+        operations: vec![OperationSignature {
+            name: "Ask".to_string(),
+            arity: 0,
+            never: false,
+        }],
+    }];
+    assert_eq!(
+        invalid(&performs),
+        VerifyError::OperationArity {
+            operation: "Ask.Ask".to_string(),
+            expected: 0,
+            found: 1
+        }
+    );
+
+    let error =
+        Module::from_bytes(&module(vec![Pop, Unit, Return], Vec::new()).to_bytes()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "in `main` at instruction 0: the instruction takes 1 values from a stack of 0"
+    );
+    assert_eq!(
+        Module::default().verify(),
+        Err(LoadError::Invalid {
+            function: None,
+            offset: None,
+            error: VerifyError::NoEntry
+        })
+    );
+
+    // The machine verifies what it is given to load before running it.
+    let error = Vm::new()
+        .load(module(vec![Pop, Unit, Return], Vec::new()))
+        .unwrap_err();
+    assert!(matches!(
+        &error,
+        RuntimeError::Invalid {
+            error: LoadError::Invalid {
+                error: VerifyError::StackUnderflow { .. },
+                ..
+            }
+        }
+    ));
+    assert_eq!(
+        error.to_string(),
+        "invalid module: in `main` at instruction 0: the instruction takes 1 values from a stack of 0"
+    );
+    assert!(matches!(
+        Vm::new().load(Module::default()),
+        Err(RuntimeError::Invalid { .. })
+    ));
+}
@@ -0,0 +1,191 @@
+use asura::lexing::escape::unescape;
+use asura::lexing::token::{Lexer, TemplateChunk, Token};
+use std::fs;
+use std::path::Path;
+
+fn example_sources() -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut sources: Vec<(String, String)> = fs::read_dir(dir)
+        .expect("examples directory should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "asura"))
+        .map(|path| {
+            let source = fs::read_to_string(&path).unwrap();
+            (path.display().to_string(), source)
+        })
+        .collect();
+    sources.sort();
+    sources
+}
+
+#[test]
+fn lexes_every_example_without_errors() {
+    let sources = example_sources();
+    assert!(!sources.is_empty());
+
+    for (path, source) in sources {
+        let errors: Vec<_> = Lexer::new(&source)
+            .filter(|(token, _)| *token == Token::Error)
+            .map(|(_, span)| (span.clone(), &source[span]))
+            .collect();
+
+        assert!(errors.is_empty(), "{} has error tokens: {:?}", path, errors);
+    }
+}
+
+#[test]
+fn keeps_comments_as_trivia() {
+    let source = "/// docs\nfun /* inline */ main() {} // trailing\n/** block docs */\n/**/ /***/ /*** divider ***/\n////\n//// divider";
+    let mut lexer = Lexer::new(source);
+    let tokens: Vec<Token> = lexer.by_ref().map(|(token, _)| token).collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Fun,
+            Token::Identifier("main"),
+            Token::LeftParenthesis,
+            Token::RightParenthesis,
+            Token::LeftBrace,
+            Token::RightBrace,
+        ]
+    );
+    assert_eq!(
+        lexer.trivia(),
+        &[
+            (Token::DocComment("/// docs"), 0..8),
+            (Token::BlockComment("/* inline */"), 13..25),
+            (Token::LineComment("// trailing"), 36..47),
+            (Token::DocComment("/** block docs */"), 48..65),
+            // Empty comments and dividers are not doc comments.
+            (Token::BlockComment("/**/"), 66..70),
+            (Token::BlockComment("/***/"), 71..76),
+            (Token::BlockComment("/*** divider ***/"), 77..94),
+            (Token::LineComment("////"), 95..99),
+            (Token::LineComment("//// divider"), 100..112),
+        ]
+    );
+}
+
+#[test]
+fn lexes_example_keywords_and_operators() {
+    let tokens: Vec<Token> = Lexer::new("impl handler with alias x := 'std:Console'")
+        .map(|(token, _)| token)
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Impl,
+            Token::Handler,
+            Token::With,
+            Token::Alias,
+            Token::Identifier("x"),
+            Token::ColonEquals,
+            Token::StringLiteral("'std:Console'"),
+        ]
+    );
+}
+
+// Whether a `++` concatenates or increments is up to the parser, whatever
+// the spacing around it.
+#[test]
+fn lexes_plus_plus_the_same_however_it_is_spaced() {
+    for source in [
+        "a ++ b",
+        "a++b",
+        "a ++b",
+        "a++ b",
+        "a\t++\nb",
+        "a/**/++/**/b",
+    ] {
+        let tokens: Vec<Token> = Lexer::new(source).map(|(token, _)| token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("a"),
+                Token::PlusPlus,
+                Token::Identifier("b")
+            ],
+            "{:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn splits_template_strings_into_chunks() {
+    let source = "`a ${ {x: `b${c}`}.x } d`";
+    let tokens: Vec<(Token, _)> = Lexer::new(source).collect();
+
+    assert_eq!(tokens.len(), 1);
+    let (token, span) = &tokens[0];
+    assert_eq!(*span, 0..source.len());
+
+    let chunks = match token {
+        Token::Template(chunks) => chunks,
+        other => panic!("expected a template, found {:?}", other),
+    };
+
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(chunks[0], TemplateChunk::Literal("a ", 1..3));
+    assert_eq!(chunks[2], TemplateChunk::Literal(" d", 22..24));
+
+    let (inner, inner_span) = match &chunks[1] {
+        TemplateChunk::Interpolation(inner, span) => (inner, span),
+        other => panic!("expected an interpolation, found {:?}", other),
+    };
+    assert_eq!(*inner_span, 3..22);
+    assert_eq!(&source[inner_span.clone()], "${ {x: `b${c}`}.x }");
+
+    let kinds: Vec<&Token> = inner.iter().map(|(token, _)| token).collect();
+    assert_eq!(kinds[0], &Token::LeftBrace);
+    assert_eq!(kinds[1], &Token::Identifier("x"));
+    assert_eq!(kinds[2], &Token::Colon);
+    assert_eq!(
+        kinds[3],
+        &Token::Template(vec![
+            TemplateChunk::Literal("b", 11..12),
+            TemplateChunk::Interpolation(vec![(Token::Identifier("c"), 14..15)], 12..16),
+        ])
+    );
+    assert_eq!(
+        &kinds[4..],
+        &[&Token::RightBrace, &Token::Dot, &Token::Identifier("x")]
+    );
+}
+
+#[test]
+fn keeps_escaped_backticks_and_dollars_inside_templates() {
+    let tokens: Vec<Token> = Lexer::new(r"`\` \${not} $x`")
+        .map(|(token, _)| token)
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![Token::Template(vec![TemplateChunk::Literal(
+            r"\` \${not} $x",
+            1..14
+        )])]
+    );
+}
+
+#[test]
+fn reports_unterminated_templates() {
+    // The rest of the source is taken as part of the template, so nothing in
+    // it is reported a second time.
+    let tokens: Vec<(Token, _)> = Lexer::new("`abc ${d} $").collect();
+
+    assert_eq!(tokens, [(Token::Error, 0..11)]);
+}
+
+#[test]
+fn decodes_escape_sequences() {
+    assert_eq!(unescape("plain").unwrap(), "plain");
+    assert_eq!(
+        unescape(r#"a\nb\t\"c\" \u{1F600} \$"#).unwrap(),
+        "a\nb\t\"c\" \u{1F600} $"
+    );
+    assert_eq!(unescape(r"bad \q").unwrap_err().0, 4);
+    assert!(unescape(r"\u{zz}").is_err());
+}
@@ -0,0 +1,1186 @@
+use asura::lexing::token::Token;
+use asura::parsing::ast::{
+    BinaryOp, DataConstructor, Declaration, Expression, Field, Handler, ImplMember,
+    ImportDeclaration, Literal, Pattern, Program, Spanned, Statement, TemplatePart, Type,
+    TypeConstraint, TypeParameter, UnaryOp, Variance,
+};
+use asura::parsing::parser::{parse as parse_program_source, parse_expression, parse_partial};
+
+/// Renders an expression as an s-expression so precedence is easy to read.
+fn sexp(expression: &Expression) -> String {
+    match expression {
+        Expression::String { value, .. } => format!("{:?}", value),
+        Expression::Template { parts, .. } => {
+            let parts: Vec<String> = parts
+                .iter()
+                .map(|part| match part {
+                    TemplatePart::Literal { value, .. } => format!("{:?}", value),
+                    TemplatePart::Expression { expression, .. } => sexp(expression),
+                })
+                .collect();
+            format!("(template {})", parts.join(" "))
+        }
+        Expression::Integer { value, .. } => value.to_string(),
+        Expression::Decimal { value, .. } => value.to_string(),
+        Expression::BigInteger { value, .. } => format!("{}n", value),
+        Expression::BigDecimal { value, .. } => format!("{}n", value),
+        Expression::Boolean { value, .. } => value.to_string(),
+        Expression::Symbol { name, .. } => format!("Symbol({})", name),
+        Expression::Identifier { name, .. } => name.to_string(),
+        Expression::Array { elements, .. } => format!("[{}]", list(elements)),
+        Expression::Tuple { elements, .. } => format!("(tuple {})", list(elements)),
+        Expression::Record {
+            fields, spreads, ..
+        } => {
+            let spreads: String = spreads
+                .iter()
+                .map(|spread| format!("...{} ", sexp(spread)))
+                .collect();
+            format!("(record {}{})", spreads, fields.len())
+        }
+        Expression::Member {
+            object, property, ..
+        } => format!("(. {} {})", sexp(object), property),
+        Expression::Index { object, index, .. } => {
+            format!("(index {} {})", sexp(object), sexp(index))
+        }
+        Expression::Binary {
+            left, op, right, ..
+        } => format!("({} {} {})", binary(op), sexp(left), sexp(right)),
+        Expression::Unary { op, expr, .. } => format!("({} {})", unary(op), sexp(expr)),
+        Expression::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => format!(
+            "(? {} {} {})",
+            sexp(condition),
+            sexp(then_branch),
+            sexp(else_branch)
+        ),
+        Expression::Assignment {
+            target, op, value, ..
+        } => match op {
+            Some(op) => format!("({}= {} {})", binary(op), sexp(target), sexp(value)),
+            None => format!("(= {} {})", sexp(target), sexp(value)),
+        },
+        Expression::Lambda {
+            parameters, body, ..
+        } => {
+            format!("(lambda/{} {})", parameters.len(), sexp(body))
+        }
+        Expression::FunctionCall {
+            function,
+            type_arguments,
+            arguments,
+            ..
+        } => {
+            let types = if type_arguments.is_empty() {
+                String::new()
+            } else {
+                format!("<{}>", type_arguments.len())
+            };
+            if arguments.is_empty() {
+                format!("(call{} {})", types, sexp(function))
+            } else {
+                format!("(call{} {} {})", types, sexp(function), list(arguments))
+            }
+        }
+        Expression::Block { statements, .. } => format!("(block {})", statements.len()),
+        Expression::Resume { expression, .. } => format!("(resume {})", sexp(expression)),
+        Expression::Continuation { .. } => "resume".to_string(),
+        Expression::Yield { expression, .. } => format!("(yield {})", sexp(expression)),
+        Expression::Perform { expression, .. } => format!("(perform {})", sexp(expression)),
+        Expression::Handle {
+            effect, expression, ..
+        } => format!("(handle {} {})", sexp(effect), sexp(expression)),
+        Expression::With {
+            expression,
+            handler,
+            ..
+        } => format!("(with {} {})", sexp(expression), sexp(handler)),
+        Expression::Handler(handler) => format!(
+            "(handler/{} {})",
+            handler.parameters.len(),
+            handler.body.len()
+        ),
+        Expression::Match {
+            scrutinee, arms, ..
+        } => {
+            let arms: Vec<String> = arms
+                .iter()
+                .map(|arm| match &arm.guard {
+                    Some(guard) => format!(
+                        "({} if {} {})",
+                        pattern(&arm.pattern),
+                        sexp(guard),
+                        sexp(&arm.body)
+                    ),
+                    None => format!("({} {})", pattern(&arm.pattern), sexp(&arm.body)),
+                })
+                .collect();
+            format!("(match {} {})", sexp(scrutinee), arms.join(" "))
+        }
+        Expression::Error { .. } => "error".to_string(),
+    }
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    let patterns = |patterns: &[Pattern]| {
+        patterns
+            .iter()
+            .map(self::pattern)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    match pattern {
+        Pattern::Wildcard { .. } => "_".to_string(),
+        Pattern::Binding { name, .. } => format!("${}", name),
+        Pattern::Literal { value, .. } => match value {
+            Literal::Integer(value) => value.to_string(),
+            Literal::Decimal(value) => value.to_string(),
+            Literal::BigInteger(value) => format!("{}n", value),
+            Literal::BigDecimal(value) => format!("{}n", value),
+            Literal::Boolean(value) => value.to_string(),
+            Literal::String(value) => format!("{:?}", value),
+            Literal::Symbol(name) => format!("Symbol({})", name),
+        },
+        Pattern::Tuple { elements, .. } => format!("(tuple {})", patterns(elements)),
+        Pattern::Array { elements, rest, .. } => match rest {
+            Some(rest) => format!("[{} ...{}]", patterns(elements), self::pattern(rest)),
+            None => format!("[{}]", patterns(elements)),
+        },
+        Pattern::Record { fields, rest, .. } => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, field)| format!("{}: {}", name, self::pattern(field)))
+                .chain(
+                    rest.iter()
+                        .map(|rest| format!("...{}", self::pattern(rest))),
+                )
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Pattern::Constructor {
+            owner,
+            name,
+            arguments,
+            ..
+        } => {
+            let name = match owner {
+                Some(owner) => format!("{}.{}", owner, name),
+                None => name.to_string(),
+            };
+            if arguments.is_empty() {
+                name
+            } else {
+                format!("({} {})", name, patterns(arguments))
+            }
+        }
+    }
+}
+
+fn list(expressions: &[Expression]) -> String {
+    expressions.iter().map(sexp).collect::<Vec<_>>().join(" ")
+}
+
+fn binary(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Addition(_) => "+",
+        BinaryOp::Subtraction(_) => "-",
+        BinaryOp::Multiplication(_) => "*",
+        BinaryOp::Division(_) => "/",
+        BinaryOp::Modulus(_) => "%",
+        BinaryOp::Exponentiation(_) => "**",
+        BinaryOp::Equal(_) => "==",
+        BinaryOp::NotEqual(_) => "!=",
+        BinaryOp::LessThan(_) => "<",
+        BinaryOp::LessThanOrEqual(_) => "<=",
+        BinaryOp::GreaterThan(_) => ">",
+        BinaryOp::GreaterThanOrEqual(_) => ">=",
+        BinaryOp::LogicalAnd(_) => "&&",
+        BinaryOp::LogicalOr(_) => "||",
+        BinaryOp::BitwiseAnd(_) => "&",
+        BinaryOp::BitwiseOr(_) => "|",
+        BinaryOp::BitwiseXor(_) => "^",
+        BinaryOp::LeftShift(_) => "<<",
+        BinaryOp::RightShift(_) => ">>",
+        BinaryOp::NullishCoalescing(_) => "??",
+        BinaryOp::PipeOperator(_) => "|>",
+        BinaryOp::OptionalChaining(_) => "?.",
+        BinaryOp::Concatenation(_) => "++",
+    }
+}
+
+fn unary(op: &UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::Negation(_) => "neg",
+        UnaryOp::LogicalNot(_) => "!",
+        UnaryOp::BitwiseNot(_) => "~",
+        UnaryOp::PreIncrement(_) => "++pre",
+        UnaryOp::PostIncrement(_) => "post++",
+        UnaryOp::PreDecrement(_) => "--pre",
+        UnaryOp::PostDecrement(_) => "post--",
+    }
+}
+
+fn parse(source: &str) -> String {
+    match parse_expression(source) {
+        Ok(expression) => sexp(&expression),
+        Err(errors) => panic!("failed to parse `{}`: {:?}", source, errors),
+    }
+}
+
+#[test]
+fn binary_operator_precedence() {
+    let cases = [
+        ("1 + 2 * 3", "(+ 1 (* 2 3))"),
+        ("1 * 2 + 3", "(+ (* 1 2) 3)"),
+        ("1 - 2 - 3", "(- (- 1 2) 3)"),
+        ("8 / 4 % 3", "(% (/ 8 4) 3)"),
+        ("2 ** 3 ** 2", "(** 2 (** 3 2))"),
+        ("2 * 3 ** 2", "(* 2 (** 3 2))"),
+        ("1 + 2 << 3", "(<< (+ 1 2) 3)"),
+        ("a >> 1 + 2", "(>> a (+ 1 2))"),
+        ("1 << 2 < 3", "(< (<< 1 2) 3)"),
+        ("a < b == c >= d", "(== (< a b) (>= c d))"),
+        ("a == b & c", "(& (== a b) c)"),
+        ("a & b ^ c | d", "(| (^ (& a b) c) d)"),
+        ("a | b && c", "(&& (| a b) c)"),
+        ("a && b || c && d", "(|| (&& a b) (&& c d))"),
+        ("a || b ?? c", "(?? (|| a b) c)"),
+        ("a ?? b ?? c", "(?? (?? a b) c)"),
+        ("a ?? b |> f", "(|> (?? a b) f)"),
+        ("x |> f |> g", "(|> (|> x f) g)"),
+        ("a + b |> f", "(|> (+ a b) f)"),
+        ("xs ++ [a]", "(++ xs [a])"),
+        ("a ++ b + c ++ d", "(++ (+ (++ a b) c) d)"),
+        ("a ++ b |> f", "(|> (++ a b) f)"),
+    ];
+
+    for (source, expected) in cases {
+        assert_eq!(parse(source), expected, "parsing `{}`", source);
+    }
+}
+
+#[test]
+fn unary_and_postfix_precedence() {
+    let cases = [
+        ("-a * b", "(* (neg a) b)"),
+        ("-2 ** 2", "(** (neg 2) 2)"),
+        ("!a && b", "(&& (! a) b)"),
+        ("~-a", "(~ (neg a))"),
+        ("- -a", "(neg (neg a))"),
+        ("a - -b", "(- a (neg b))"),
+        // The smallest `Int` is written negated, as its literal alone is
+        // too large.
+        ("-9223372036854775808", "-9223372036854775808"),
+        ("- -9223372036854775808", "(neg -9223372036854775808)"),
+        ("-9223372036854775808 + 1", "(+ -9223372036854775808 1)"),
+        ("++a", "(++pre a)"),
+        ("a++ + 1", "(+ (post++ a) 1)"),
+        // A `++` followed by an operand is concatenation, however spaced.
+        ("a ++[0]", "(++ a [0])"),
+        ("A++B", "(++ A B)"),
+        ("A ++ B", "(++ A B)"),
+        ("i++ ++ [0]", "(++ (post++ i) [0])"),
+        ("a++ - 1", "(- (post++ a) 1)"),
+        ("[a++]", "[(post++ a)]"),
+        ("-f(x)", "(neg (call f x))"),
+        ("-a.b", "(neg (. a b))"),
+        ("a.b.c", "(. (. a b) c)"),
+        ("a?.b.c", "(. (?. a b) c)"),
+        ("a?.b ?? c", "(?? (?. a b) c)"),
+        ("a[0].b", "(. (index a 0) b)"),
+        ("f(x)(y)", "(call (call f x) y)"),
+        ("Console.log(x)", "(call (. Console log) x)"),
+        ("resume(a + b)", "(resume (+ a b))"),
+        // Without an operand, `resume` is the continuation as a value.
+        ("[resume]", "[resume]"),
+        ("waiters ++ [resume]", "(++ waiters [resume])"),
+        ("f(resume, 1)", "(call f resume 1)"),
+        ("perform f(x) + 1", "(+ (perform (call f x)) 1)"),
+    ];
+
+    for (source, expected) in cases {
+        assert_eq!(parse(source), expected, "parsing `{}`", source);
+    }
+}
+
+#[test]
+fn conditional_and_assignment() {
+    let cases = [
+        ("a ? b : c", "(? a b c)"),
+        ("a || b ? c + 1 : d", "(? (|| a b) (+ c 1) d)"),
+        ("a ? b : c ? d : e", "(? a b (? c d e))"),
+        ("x = 1 + 2", "(= x (+ 1 2))"),
+        ("x = y = 3", "(= x (= y 3))"),
+        ("x += 1", "(+= x 1)"),
+        ("x ??= y ?? z", "(??= x (?? y z))"),
+        ("x >>= 2", "(>>= x 2)"),
+        ("x **= 2", "(**= x 2)"),
+        ("x = a |> f", "(= x (|> a f))"),
+        ("main() with h", "(with (call main) h)"),
+        (
+            "x = f() with Calc ++ Fail.try()",
+            "(= x (with (call f) (++ Calc (call (. Fail try)))))",
+        ),
+        (
+            "f() with a with b |> g",
+            "(with (with (call f) a) (|> b g))",
+        ),
+        ("a ? b : c with h", "(? a b (with c h))"),
+    ];
+
+    for (source, expected) in cases {
+        assert_eq!(parse(source), expected, "parsing `{}`", source);
+    }
+}
+
+#[test]
+fn literals_and_collections() {
+    let cases = [
+        ("42", "42"),
+        ("1.5", "1.5"),
+        ("10n", "10n"),
+        ("1.25n", "1.25n"),
+        ("true", "true"),
+        ("\"a\\n\"", "\"a\\n\""),
+        ("'single'", "\"single\""),
+        ("Symbol(id)", "Symbol(id)"),
+        ("[1, 2, 3,]", "[1 2 3]"),
+        ("[]", "[]"),
+        ("()", "(tuple )"),
+        ("(1)", "1"),
+        ("(1,)", "(tuple 1)"),
+        ("(a, b + 1)", "(tuple a (+ b 1))"),
+        ("(1 + 2) * 3", "(* (+ 1 2) 3)"),
+        ("{ a: 1, \"b\": 2 }", "(record 2)"),
+        ("{}", "(record 0)"),
+        ("{ ...a, ...f(b), c: 1 }", "(record ...a ...(call f b) 1)"),
+        (
+            "`fib(${n}): ${fib(n)}.`",
+            "(template \"fib(\" n \"): \" (call fib n) \".\")",
+        ),
+    ];
+
+    for (source, expected) in cases {
+        assert_eq!(parse(source), expected, "parsing `{}`", source);
+    }
+}
+
+#[test]
+fn lambdas_and_type_arguments() {
+    let cases = [
+        ("(a) -> a * 3", "(lambda/1 (* a 3))"),
+        ("() -> 0", "(lambda/0 0)"),
+        ("(a: Int, b = 2) -> a + b", "(lambda/2 (+ a b))"),
+        (
+            "State.modify((a) -> (a, f(a)))",
+            "(call (. State modify) (lambda/1 (tuple a (call f a))))",
+        ),
+        ("f<Int>(x)", "(call<1> f x)"),
+        ("f<Option<Int>, [String]>()", "(call<2> f)"),
+        ("f<(Int) -> Int>(g)", "(call<1> f g)"),
+        ("a < b", "(< a b)"),
+        ("a < b > c", "(> (< a b) c)"),
+    ];
+
+    for (source, expected) in cases {
+        assert_eq!(parse(source), expected, "parsing `{}`", source);
+    }
+}
+
+#[test]
+fn match_expressions() {
+    assert_eq!(
+        parse("match n { n < 2 -> n, _ -> fib(n - 1) }"),
+        "(match n ($n if (< n 2) n) (_ (call fib (- n 1))))"
+    );
+    assert_eq!(
+        parse("match (b) { 0 -> 1, -1 -> 2, 'a' -> 3, true -> 4, x if x > 9 -> x }"),
+        "(match b (0 1) (-1 2) (\"a\" 3) (true 4) ($x if (> x 9) x))"
+    );
+    assert_eq!(
+        parse("match n { -9223372036854775808 -> 0, _ -> 1 }"),
+        "(match n (-9223372036854775808 0) (_ 1))"
+    );
+    assert_eq!(
+        parse("match op { Add(a, b) -> a + b, Op.Negate(a) -> -a, None -> 0, }"),
+        "(match op ((Add $a $b) (+ a b)) ((Op.Negate $a) (neg a)) (None 0))"
+    );
+    assert_eq!(
+        parse("match xs { [] -> 0, [x] -> x, [head, ...tail] -> { head } }"),
+        "(match xs ([] 0) ([$x] x) ([$head ...$tail] (block 1)))"
+    );
+    assert_eq!(
+        parse("match p { (a, _) -> a, { x, y: [_, ...] } -> x, Some({ radius: r }) -> r }"),
+        "(match p ((tuple $a _) a) ({x: $x, y: [_ ..._]} x) ((Some {radius: $r}) r))"
+    );
+    assert_eq!(
+        parse("match p { { x, ...rest } -> rest, { y: 0, ... } -> p }"),
+        "(match p ({x: $x, ...$rest} rest) ({y: 0, ..._} p))"
+    );
+}
+
+#[test]
+fn rejects_malformed_patterns() {
+    for source in [
+        "match xs { [...a, b] -> 0 }",
+        "match xs { [...a, ...b] -> 0 }",
+        "match p { { x, x } -> 0 }",
+        "match p { { ...rest, x } -> 0 }",
+        "match p { { x, ...a, ...b } -> 0 }",
+        "match s { -'a' -> 0 }",
+        "match s { a + 1 }",
+        "match n { 9223372036854775808 -> 0 }",
+    ] {
+        assert!(
+            parse_expression(source).is_err(),
+            "`{}` should not parse",
+            source
+        );
+    }
+}
+
+#[test]
+fn spans_cover_whole_expressions() {
+    let source = "-foo.bar(1) + 2 * x";
+    let expression = parse_expression(source).unwrap();
+    assert_eq!(expression.span(), 0..source.len());
+
+    match expression {
+        Expression::Binary {
+            left, right, op, ..
+        } => {
+            assert_eq!(left.span(), 0..11);
+            assert_eq!(right.span(), 14..19);
+            assert_eq!(op, BinaryOp::Addition(12..13));
+        }
+        other => panic!("expected a binary expression, found {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_malformed_expressions() {
+    for source in [
+        "1 +",
+        "(1, 2",
+        "a ? b",
+        "f(,)",
+        "a > > b",
+        "{ a: 1, a: 2 }",
+        "9223372036854775808",
+        "a - 9223372036854775808",
+    ] {
+        assert!(
+            parse_expression(source).is_err(),
+            "`{}` should not parse",
+            source
+        );
+    }
+}
+
+fn parse_program(source: &str) -> Program<'_> {
+    match parse_program_source(source) {
+        Ok(program) => program,
+        Err(errors) => panic!("failed to parse program: {:?}", errors),
+    }
+}
+
+#[test]
+fn if_statements_collect_else_if_chains() {
+    let program = parse_program(
+        "if (n < 2) return n
+         else if n == 2 { return 1 }
+         else if n == 3 { return 2 }
+         else { x := n; x += 1 }",
+    );
+
+    match &program.statements[..] {
+        [Statement::If {
+            condition,
+            then_branch,
+            else_if_branches,
+            else_branch: Some(else_branch),
+            ..
+        }] => {
+            assert_eq!(sexp(condition), "(< n 2)");
+            assert!(matches!(then_branch[..], [Statement::Return { .. }]));
+            assert_eq!(else_if_branches.len(), 2);
+            assert_eq!(sexp(&else_if_branches[1].0), "(== n 3)");
+            assert!(matches!(
+                else_branch[..],
+                [
+                    Statement::Declaration(Declaration::Let { mutable: true, .. }),
+                    Statement::Expression { .. }
+                ]
+            ));
+        }
+        other => panic!("expected an if statement, found {:?}", other),
+    }
+}
+
+#[test]
+fn loops() {
+    let program = parse_program(
+        "while (running) { break }
+         for (i = 0; i < 10; i += 1) continue
+         for (a of list) { Queue.enqueue(a) }
+         for waiter of waiters { waiter(None) }
+         for key in record {}",
+    );
+
+    assert!(matches!(program.statements[0], Statement::While { .. }));
+    match &program.statements[1] {
+        Statement::For {
+            initializer,
+            condition,
+            increment,
+            body,
+            ..
+        } => {
+            assert_eq!(sexp(initializer), "(= i 0)");
+            assert_eq!(sexp(condition), "(< i 10)");
+            assert_eq!(sexp(increment), "(+= i 1)");
+            assert!(matches!(body[..], [Statement::Continue { .. }]));
+        }
+        other => panic!("expected a for statement, found {:?}", other),
+    }
+    for statement in &program.statements[2..4] {
+        assert!(matches!(statement, Statement::ForOf { .. }));
+    }
+    assert!(matches!(program.statements[4], Statement::ForIn { .. }));
+
+    // A loop over the values that match a pattern is a loop over a `match`
+    // that breaks out when one does not.
+    let program = parse_program("while(Some(value) = queue.dequeue()) { log(value) }");
+    match &program.statements[0] {
+        Statement::While {
+            condition, body, ..
+        } => {
+            assert_eq!(sexp(condition), "true");
+            match &body[..] {
+                [Statement::Expression { expr, .. }] => assert_eq!(
+                    sexp(expr),
+                    "(match (call (. queue dequeue)) ((Some $value) (block 1)) (_ (block 1)))"
+                ),
+                other => panic!("expected a match, found {:?}", other),
+            }
+        }
+        other => panic!("expected a while statement, found {:?}", other),
+    }
+    // Only a constructor is matched; anything else is a condition.
+    assert!(matches!(
+        &parse_program("while (done = check()) {}").statements[0],
+        Statement::While { condition, .. } if sexp(condition) == "(= done (call check))"
+    ));
+}
+
+#[test]
+fn imports() {
+    let program = parse_program(
+        "import Console from 'std:Console'
+         import { log, warn as warning } from \"std:Console\"",
+    );
+
+    match &program.statements[..] {
+        [Statement::Import {
+            module: "std:Console",
+            declaration:
+                ImportDeclaration::NamespaceImport {
+                    name: "Console", ..
+                },
+            ..
+        }, Statement::Import {
+            module: "std:Console",
+            declaration: ImportDeclaration::NamedImports { imports, .. },
+            ..
+        }] => {
+            assert_eq!(imports[0].name, "log");
+            assert_eq!(imports[1].alias.as_deref(), Some("warning"));
+        }
+        other => panic!("expected two imports, found {:?}", other),
+    }
+}
+
+#[test]
+fn function_declarations() {
+    let source = "export fun add<A, out B <: A>(a: Int, b: Int = 2): Int { a + b }
+                  fun get_and_update(f: A -> A) -> A { State.modify((a) -> (a, f(a))) }";
+    let program = parse_program(source);
+
+    match &program.statements[0] {
+        Statement::Declaration(Declaration::Function {
+            exported: true,
+            name: "add",
+            type_parameters,
+            parameters,
+            return_type: Some(Type::Integer { .. }),
+            body,
+            span,
+        }) => {
+            assert_eq!(*span, 0..source.find('\n').unwrap());
+            assert!(matches!(
+                type_parameters[1],
+                TypeParameter::Generic {
+                    name: "B",
+                    variance: Some(Variance::Out),
+                    constraint: Some(TypeConstraint::Subtype { .. }),
+                    ..
+                }
+            ));
+            assert!(matches!(
+                parameters[1],
+                Field::Named {
+                    name: "b",
+                    default: Some(Expression::Integer { value: 2, .. }),
+                    ..
+                }
+            ));
+            assert_eq!(body.len(), 1);
+        }
+        other => panic!("expected a function, found {:?}", other),
+    }
+
+    match &program.statements[1] {
+        Statement::Declaration(Declaration::Function { parameters, .. }) => match &parameters[0] {
+            Field::Named {
+                annotation: Some(Type::Function { parameters, .. }),
+                ..
+            } => assert_eq!(parameters.len(), 1),
+            other => panic!("expected a function type, found {:?}", other),
+        },
+        other => panic!("expected a function, found {:?}", other),
+    }
+}
+
+#[test]
+fn polymorphic_function_types() {
+    let program = parse_program("fun both(f: <A, B>(A, B) -> A, g: <C>C -> C) { f }");
+    match &program.statements[0] {
+        Statement::Declaration(Declaration::Function { parameters, .. }) => {
+            match &parameters[0] {
+                Field::Named {
+                    annotation:
+                        Some(Type::Function {
+                            type_parameters,
+                            parameters,
+                            ..
+                        }),
+                    ..
+                } => {
+                    assert!(matches!(
+                        type_parameters[..],
+                        [
+                            TypeParameter::Generic { name: "A", .. },
+                            TypeParameter::Generic { name: "B", .. }
+                        ]
+                    ));
+                    assert_eq!(parameters.len(), 2);
+                }
+                other => panic!("expected a polymorphic function type, found {:?}", other),
+            }
+            // The binder can also introduce an unparenthesised parameter.
+            assert!(matches!(
+                &parameters[1],
+                Field::Named { annotation: Some(Type::Function { type_parameters, .. }), .. }
+                    if type_parameters.len() == 1
+            ));
+        }
+        other => panic!("expected a function, found {:?}", other),
+    }
+}
+
+#[test]
+fn type_declarations() {
+    let program = parse_program(
+        "data Option<A> = None | Some(A)
+         brand UserId = UserId(Int)
+         type Pair<A> = (A, A)
+         alias IntQueue = Queue<Int>
+         let answer: Int = 42",
+    );
+
+    match &program.statements[0] {
+        Statement::Declaration(Declaration::Data {
+            name: "Option",
+            data_constructors,
+            ..
+        }) => {
+            assert!(matches!(
+                data_constructors[0],
+                DataConstructor::Void { name: "None", .. }
+            ));
+            assert!(matches!(
+                data_constructors[1],
+                DataConstructor::Tuple { name: "Some", .. }
+            ));
+        }
+        other => panic!("expected a data declaration, found {:?}", other),
+    }
+    assert!(matches!(
+        program.statements[1],
+        Statement::Declaration(Declaration::Brand { name: "UserId", .. })
+    ));
+    assert!(matches!(
+        program.statements[2],
+        Statement::Declaration(Declaration::TypeAlias {
+            name: "Pair",
+            alias: Type::Tuple { .. },
+            ..
+        })
+    ));
+    assert!(matches!(
+        program.statements[3],
+        Statement::Declaration(Declaration::TypeAlias {
+            name: "IntQueue",
+            ..
+        })
+    ));
+    assert!(matches!(
+        program.statements[4],
+        Statement::Declaration(Declaration::Let {
+            mutable: false,
+            name: "answer",
+            annotation: Some(Type::Integer { .. }),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn union_intersection_and_literal_types() {
+    fn annotation(source: &str) -> Type<'_> {
+        match parse_program(source).statements.remove(0) {
+            Statement::Declaration(Declaration::Let {
+                annotation: Some(annotation),
+                ..
+            }) => annotation,
+            other => panic!("expected an annotated let, found {:?}", other),
+        }
+    }
+
+    match annotation("let a: \"a\" | 'b' | -1 | 2n | true = x") {
+        Type::Union { types, .. } => assert!(matches!(
+            &types[..],
+            [
+                Type::StringLiteral { value, .. },
+                Type::StringLiteral { .. },
+                Type::IntegerLiteral { value: -1, .. },
+                Type::BigIntegerLiteral { value: 2, .. },
+                Type::BooleanLiteral { value: true, .. },
+            ] if value == "a"
+        )),
+        other => panic!("expected a union, found {:?}", other),
+    }
+
+    // `&` binds tighter than `|`.
+    match annotation("let b: A & B | C = x") {
+        Type::Union { types, .. } => assert!(matches!(
+            &types[..],
+            [Type::Intersection { types, .. }, Type::Reference { name: "C", .. }]
+                if types.len() == 2
+        )),
+        other => panic!("expected a union, found {:?}", other),
+    }
+
+    // A function type returns the whole union after its arrow.
+    assert!(matches!(
+        annotation("let f: (Int) -> Int | String = x"),
+        Type::Function { return_type, .. } if matches!(*return_type, Type::Union { .. })
+    ));
+    assert!(matches!(
+        annotation("let g: ((Int) -> Int) | String = x"),
+        Type::Union { .. }
+    ));
+}
+
+#[test]
+fn open_records_and_spreads() {
+    match parse_program("let p: { x: Int, ...R } = q")
+        .statements
+        .remove(0)
+    {
+        Statement::Declaration(Declaration::Let {
+            annotation: Some(Type::Record { fields, rest, .. }),
+            ..
+        }) => {
+            assert_eq!(fields.len(), 1);
+            assert!(matches!(
+                rest.as_deref(),
+                Some(Type::Reference { name: "R", .. })
+            ));
+        }
+        other => panic!("expected an open record type, found {:?}", other),
+    }
+
+    // What is spread comes before the fields that override it, and the rest
+    // of a record type or pattern comes last.
+    for source in ["x = { x: 1, ...p }", "let p: { ...R, x: Int } = q"] {
+        assert!(
+            parse_program_source(source).is_err(),
+            "`{}` should not parse",
+            source
+        );
+    }
+}
+
+#[test]
+fn effect_declarations() {
+    let program = parse_program(
+        "effect State<A> {
+           get() -> A
+           modify<B>(A -> (B, A)) -> B
+         }
+         effect Fail<E>(E) -> Never",
+    );
+
+    match &program.statements[0] {
+        Statement::Declaration(Declaration::Effect { fields, .. }) => {
+            let names: Vec<&str> = fields.iter().map(|field| field.name).collect();
+            assert_eq!(names, ["get", "modify"]);
+            assert_eq!(fields[1].type_parameters.len(), 1);
+            assert!(matches!(&fields[1].parameters[..], [Type::Function { .. }]));
+            assert!(matches!(
+                fields[1].return_type,
+                Type::Reference { name: "B", .. }
+            ));
+        }
+        other => panic!("expected an effect, found {:?}", other),
+    }
+
+    match &program.statements[1] {
+        Statement::Declaration(Declaration::Effect { name, fields, .. }) => {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(fields[0].name, *name);
+            assert!(matches!(
+                &fields[0].parameters[..],
+                [Type::Reference { name: "E", .. }]
+            ));
+            assert!(matches!(
+                fields[0].return_type,
+                Type::Reference { name: "Never", .. }
+            ));
+        }
+        other => panic!("expected an effect, found {:?}", other),
+    }
+}
+
+#[test]
+fn impl_declarations() {
+    let program = parse_program(
+        "impl<A> for State<A> {
+           fun get_and_update(f: A -> A) -> A { State.modify((a) -> (a, f(a))) }
+           handler local(initial: A) {
+             current := initial
+             return {
+               get() { current },
+               modify(f) { (b, a) = f(current)\ncurrent = a\nb },
+             }
+           }
+         }
+         impl<E> for Fail<E> {
+           handler try(error: E) { Either.Left(error) } { return(a) { Either.Right(a) } }
+           fun catch<A>(f: (E) -> A) { handler(error: E) { f(error) } }
+         }
+         impl for Calc { handler(op) { resume(1) } }",
+    );
+
+    match &program.statements[0] {
+        Statement::Declaration(Declaration::Impl {
+            type_parameters,
+            effect: Type::Reference { name: "State", .. },
+            members,
+            ..
+        }) => {
+            assert_eq!(type_parameters.len(), 1);
+            assert!(matches!(
+                &members[0],
+                ImplMember::Function(Declaration::Function {
+                    name: "get_and_update",
+                    ..
+                })
+            ));
+            let handler = match &members[1] {
+                ImplMember::Handler(handler) => handler,
+                other => panic!("expected a handler, found {:?}", other),
+            };
+            assert_eq!(handler.name, Some("local"));
+            assert_eq!(handler.parameters.len(), 1);
+            assert_eq!(handler.body.len(), 1);
+            let clauses: Vec<(&str, usize)> = handler
+                .operations
+                .iter()
+                .map(|clause| (clause.name, clause.parameters.len()))
+                .collect();
+            assert_eq!(clauses, [("get", 0), ("modify", 1)]);
+            assert!(handler.return_clause.is_none());
+        }
+        other => panic!("expected an impl, found {:?}", other),
+    }
+
+    match &program.statements[1] {
+        Statement::Declaration(Declaration::Impl { members, .. }) => {
+            match &members[0] {
+                ImplMember::Handler(handler) => {
+                    assert_eq!(handler.name, Some("try"));
+                    assert!(handler.operations.is_empty());
+                    let clause = handler.return_clause.as_ref().expect("a return clause");
+                    assert!(matches!(clause.parameter, Field::Named { name: "a", .. }));
+                }
+                other => panic!("expected a handler, found {:?}", other),
+            }
+            match &members[1] {
+                ImplMember::Function(Declaration::Function { body, .. }) => {
+                    assert!(matches!(
+                        &body[..],
+                        [Statement::Expression { expr, .. }] if sexp(expr) == "(handler/1 1)"
+                    ));
+                }
+                other => panic!("expected a function, found {:?}", other),
+            }
+        }
+        other => panic!("expected an impl, found {:?}", other),
+    }
+
+    assert!(matches!(
+        &program.statements[2],
+        Statement::Declaration(Declaration::Impl { members, .. })
+            if matches!(&members[..], [ImplMember::Handler(Handler { name: None, multishot: false, .. })])
+    ));
+
+    // `multi` is only a keyword before `handler`.
+    let program = parse_program(
+        "impl for Choice { multi handler all() { return { flip() { resume(true) ++ resume(false) } } } }
+         multi = 1",
+    );
+    assert!(matches!(
+        &program.statements[0],
+        Statement::Declaration(Declaration::Impl { members, .. })
+            if matches!(&members[..], [ImplMember::Handler(Handler { name: Some("all"), multishot: true, .. })])
+    ));
+    assert!(matches!(
+        &program.statements[1],
+        Statement::Expression { .. }
+    ));
+
+    assert!(parse_program_source(
+        "impl for Log { handler h() { return { log(s) { () } }\nx = 1 } }"
+    )
+    .is_err());
+
+    // Nor is it one of the tokens a member is expected to start with.
+    let errors = parse_program_source("impl for Choice { 1 }").unwrap_err();
+    let expected: Vec<_> = errors[0].expected().flatten().collect();
+    assert!(expected.contains(&&Token::Handler), "{:?}", expected);
+    assert!(
+        !expected.contains(&&Token::Identifier("multi")),
+        "{:?}",
+        expected
+    );
+}
+
+/// Parses with recovery, returning each top-level statement as an
+/// s-expression alongside the spans of the errors found.
+fn recover(source: &str) -> (Vec<String>, Vec<std::ops::Range<usize>>) {
+    let (program, errors) = parse_partial(source);
+    let program = program.expect("recovery should produce a program");
+    let statements = program
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Expression { expr, .. } => sexp(expr),
+            Statement::Error { .. } => "error".to_string(),
+            other => format!("{:?}", std::mem::discriminant(other)),
+        })
+        .collect();
+    (
+        statements,
+        errors.iter().map(|error| error.span()).collect(),
+    )
+}
+
+#[test]
+fn recovers_after_a_broken_operand() {
+    let (statements, errors) = recover("x = (1 + ) \ny = 2");
+    assert_eq!(statements, ["(= x (+ 1 error))", "(= y 2)"]);
+    assert_eq!(errors, vec![9..10]);
+}
+
+#[test]
+fn reports_every_broken_statement() {
+    let (statements, errors) = recover("a = 1 + ;\nb = 2\nc = * 3");
+    assert_eq!(statements[0], "(= a (+ 1 error))");
+    assert_eq!(statements[1], "(= b 2)");
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn recovers_inside_nested_delimiters() {
+    let (statements, errors) = recover("f(1, , 2)\nxs = [1, (2 + ]), 3]\ny = 1");
+    assert_eq!(statements[0], "(call f error)");
+    assert_eq!(statements.last().unwrap(), "(= y 1)");
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn skips_stray_closing_braces() {
+    let (statements, errors) = recover("x = 1 }\ny = 2");
+    assert_eq!(statements, ["(= x 1)", "error", "(= y 2)"]);
+    assert_eq!(errors, vec![6..7]);
+}
+
+#[test]
+fn recovers_inside_blocks_and_at_declarations() {
+    let (statements, errors) = recover("fun f( { 1 }\nfun g(): Int { y = ; 2 }\ng()");
+    assert_eq!(statements.last().unwrap(), "(call g)");
+    assert_eq!(errors.first(), Some(&(7..8)));
+    assert!(errors.contains(&(32..33)));
+
+    match &parse_partial("fun g(): Int { y = ; z = 2 }")
+        .0
+        .unwrap()
+        .statements[0]
+    {
+        Statement::Declaration(Declaration::Function { body, .. }) => {
+            let body: Vec<String> = body
+                .iter()
+                .map(|statement| match statement {
+                    Statement::Expression { expr, .. } => sexp(expr),
+                    _ => "other".to_string(),
+                })
+                .collect();
+            assert_eq!(body, ["(= y error)", "(= z 2)"]);
+        }
+        other => panic!("expected a function, found {:?}", other),
+    }
+}
+
+#[test]
+fn sum_type_declarations() {
+    let program = parse_program(
+        "type Either<E, A> =
+           | Left(E)
+           | Right(A)
+         type Shape = | Circle { radius: Float } | Square(Float)
+         type Unit = ()",
+    );
+
+    match &program.statements[0] {
+        Statement::Declaration(Declaration::Data {
+            name: "Either",
+            type_parameters,
+            data_constructors,
+            ..
+        }) => {
+            assert_eq!(type_parameters.len(), 2);
+            let names: Vec<&str> = data_constructors
+                .iter()
+                .map(|constructor| match constructor {
+                    DataConstructor::Tuple { name, .. } => *name,
+                    other => panic!("expected a tuple constructor, found {:?}", other),
+                })
+                .collect();
+            assert_eq!(names, ["Left", "Right"]);
+        }
+        other => panic!("expected a data declaration, found {:?}", other),
+    }
+    assert!(matches!(
+        &program.statements[1],
+        Statement::Declaration(Declaration::Data { data_constructors, .. })
+            if matches!(data_constructors[0], DataConstructor::Record { name: "Circle", .. })
+    ));
+    assert!(matches!(
+        program.statements[2],
+        Statement::Declaration(Declaration::TypeAlias { name: "Unit", .. })
+    ));
+}
+
+/// Each top-level expression statement of `source` as an s-expression.
+fn statement_sexps(source: &str) -> Vec<String> {
+    parse_program(source)
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Expression { expr, .. } => sexp(expr),
+            other => panic!("expected an expression statement, found {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn calls_indexes_and_resumed_values_stay_on_their_line() {
+    assert_eq!(
+        statement_sexps("a = Choice.flip()\nb = Choice.flip()\n(a, b)"),
+        [
+            "(= a (call (. Choice flip)))",
+            "(= b (call (. Choice flip)))",
+            "(tuple a b)"
+        ]
+    );
+    assert_eq!(
+        statement_sexps("xs = [1, 2]\n[a, b] = xs"),
+        ["(= xs [1 2])", "(= [a b] xs)"]
+    );
+    assert_eq!(
+        statement_sexps("k = resume\nk(true)"),
+        ["(= k resume)", "(call k true)"]
+    );
+    // A comment between the lines changes nothing, and on the same line the
+    // call, index and resumed value continue the expression as before.
+    assert_eq!(
+        statement_sexps("f() // first\n(1, 2)\nf() /* call */ (1)\nxs[0]\nresume k"),
+        [
+            "(call f)",
+            "(tuple 1 2)",
+            "(call (call f) 1)",
+            "(index xs 0)",
+            "(resume k)"
+        ]
+    );
+    // Member access still continues a chain onto the next line.
+    assert_eq!(
+        statement_sexps("list\n  .map(f)\n  .filter(g)"),
+        ["(call (. (call (. list map) f) filter) g)"]
+    );
+}
+
+#[test]
+fn statements_on_one_line_are_separated_by_semicolons() {
+    assert_eq!(
+        statement_sexps("x = 1; y = 2\nz = 3;"),
+        ["(= x 1)", "(= y 2)", "(= z 3)"]
+    );
+    for source in ["x = 1 y = 2", "w = 3 3", "fun f() { a = 1 b = 2 }"] {
+        assert!(
+            parse_program_source(source).is_err(),
+            "`{}` should not parse",
+            source
+        );
+    }
+    // The statement after is still parsed once the missing `;` is reported.
+    let (statements, errors) = recover("x = 1 y = 2");
+    assert_eq!(statements, ["(= x 1)", "(= y 2)"]);
+    assert_eq!(errors, vec![6..7]);
+}
+
+#[test]
+fn a_bare_return_ends_at_the_line_break() {
+    let program = parse_program("return\nf()");
+    match &program.statements[..] {
+        [Statement::Return { expr, .. }, Statement::Expression { expr: next, .. }] => {
+            assert!(
+                matches!(**expr, Expression::Tuple { ref elements, .. } if elements.is_empty())
+            );
+            assert_eq!(sexp(next), "(call f)");
+        }
+        other => panic!("expected a return and a call, found {:?}", other),
+    }
+    assert!(matches!(
+        &parse_program("return f()").statements[..],
+        [Statement::Return { .. }]
+    ));
+}
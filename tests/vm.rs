@@ -0,0 +1,297 @@
+use asura::compile_bytecode;
+use asura::vm::{RuntimeError, Value, Vm};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+/// A machine with `source` loaded, the result of its top-level statements
+/// and what it logged.
+fn run(source: &str) -> (Vm, Result<Value, RuntimeError>, Vec<String>) {
+    let module = compile_bytecode(source).expect("expected compilation to succeed");
+    let logged = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = Vm::new();
+    let sink = logged.clone();
+    vm.on_log(move |message| sink.borrow_mut().push(message.to_string()));
+    let result = vm.load(module);
+    let logged = logged.borrow().clone();
+    (vm, result, logged)
+}
+
+fn run_example(name: &str) -> (Vm, Result<Value, RuntimeError>, Vec<String>) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples")
+        .join(name)
+        .with_extension("asura");
+    let source = std::fs::read_to_string(&path).expect("expected the example to exist");
+    run(&source)
+}
+
+#[test]
+fn runs_the_examples() {
+    let (_, result, logged) = run_example("fib");
+    assert_eq!(result, Ok(Value::Unit));
+    assert_eq!(
+        logged,
+        ["Calculating fib(10)...", "Calculated fib(10): 55."]
+    );
+
+    let (vm, result, _) = run_example("state");
+    assert_eq!(result, Ok(Value::Unit));
+    assert_eq!(vm.global("a"), Some(Value::Integer(10)));
+    assert_eq!(vm.global("b"), Some(Value::Integer(30)));
+
+    let (vm, result, _) = run_example("pattern_matching");
+    assert_eq!(result, Ok(Value::Unit));
+    assert_eq!(
+        vm.global("try_result"),
+        Some(Value::variant("Right", vec![Value::Integer(4)]))
+    );
+    assert_eq!(vm.global("catch_result"), Some(Value::Integer(4)));
+
+    // The worker waits on the queue in a fiber of its own, and `main` only
+    // finishes once it has seen every value and the queue shut down.
+    let (_, result, logged) = run_example("queue");
+    assert_eq!(result, Ok(Value::Unit));
+    assert_eq!(logged, ["Received 1", "Received 2", "Received 3"]);
+}
+
+#[test]
+fn the_host_handles_console() {
+    let (_, result, logged) = run("import Console from \"std:Console\"\nConsole.log(\"x\")");
+    assert_eq!(result, Ok(Value::Unit));
+    assert_eq!(logged, ["x"]);
+
+    // Also in a fiber, and from a function that is called later.
+    let (mut vm, result, logged) = run("import { log } from \"std:Console\"
+fun main() { (() -> log('a')).fork().join()\nlog('b') }
+main()");
+    assert_eq!(result, Ok(Value::Unit));
+    assert_eq!(logged, ["a", "b"]);
+    assert_eq!(vm.call("main", Vec::new()), Ok(Value::Unit));
+}
+
+#[test]
+fn calls_functions_of_the_loaded_module() {
+    let (mut vm, _, _) = run_example("fib");
+    assert_eq!(
+        vm.call("fib", vec![Value::Integer(20)]),
+        Ok(Value::Integer(6765))
+    );
+    assert_eq!(
+        vm.call("fib", Vec::new()),
+        Err(RuntimeError::Arity {
+            expected: 1,
+            found: 0
+        })
+    );
+    assert_eq!(
+        vm.call("missing", Vec::new()),
+        Err(RuntimeError::Undefined {
+            name: "missing".to_string()
+        })
+    );
+
+    // Without the handler `main` is given in the example, nothing handles
+    // its operations.
+    let (mut vm, _, _) = run_example("state");
+    assert_eq!(
+        vm.call("main", Vec::new()),
+        Err(RuntimeError::Unhandled {
+            effect: "State".to_string(),
+            operation: "modify".to_string()
+        })
+    );
+}
+
+#[test]
+fn tail_calls_run_in_constant_space() {
+    let (mut vm, _, _) = run("fun count(n: Int, total: Int): Int {
+  match n { n < 1 -> total, _ -> count(n - 1, total + n) }
+}");
+    assert_eq!(
+        vm.call("count", vec![Value::Integer(100_000), Value::Integer(0)]),
+        Ok(Value::Integer(5_000_050_000))
+    );
+}
+
+#[test]
+fn resumes_continuations() {
+    // A tail resumptive clause, one that resumes explicitly, and a return
+    // clause.
+    let (_, result, _) = run("effect Ask() -> Int
+effect Fail() -> Never
+
+impl for Ask {
+  handler() { 1 }
+  handler twice() { return { Ask() { resume(2) * 2 } } }
+}
+
+impl for Fail {
+  handler() { return { Fail() { 0 } } } {
+    return(a) { a + 1 }
+  }
+}
+
+fun main() { Ask() + Ask() }
+fun fails() { Fail() }
+
+assert_equal(main() with Ask, 2)
+assert_equal(main() with Ask.twice(), 16)
+assert_equal(main() with Fail ++ Ask, 3)
+fails() with Fail");
+    assert_eq!(result, Ok(Value::Integer(0)));
+
+    // A multi-shot handler runs the rest of the computation once for each
+    // resumption.
+    let (_, result, _) = run("effect Flip() -> Boolean
+impl for Flip {
+  multi handler both() { return { Flip() { resume(true) + resume(false) } } }
+}
+fun main() { match Flip() { true -> 1, _ -> 10 } }
+main() with Flip.both()");
+    assert_eq!(result, Ok(Value::Integer(11)));
+
+    // A one-shot continuation resumed again from where it was stored.
+    let (_, result, _) = run("effect Wait { wait(): Int }
+impl for Wait {
+  handler later() {
+    waiters := []
+    return { wait() { waiters = waiters ++ [resume]\nfirst = waiters[0]\nfirst(1) + first(2) } }
+  }
+}
+Wait.wait() with Wait.later()");
+    assert_eq!(
+        result,
+        Err(RuntimeError::ResumedTwice {
+            operation: "Wait.wait".to_string()
+        })
+    );
+}
+
+#[test]
+fn big_decimals_are_exact() {
+    let (_, result, logged) = run("import Console from \"std:Console\"
+assert_equal(0.1n + 0.2n, 0.3n)
+assert_equal(1.5n + 2.5n, 4.0n)
+assert_equal(1.5n - 2.25n, -0.75n)
+assert_equal(2.5n * 2.5n, 6.25n)
+assert_equal(7.5n % 2.0n, 1.5n)
+assert_equal(1.5n ** 2.0n, 2.25n)
+assert_equal(1.0n / 8.0n, 0.125n)
+assert_equal(1.50n == 1.5n, true)
+assert_equal(1.5n < 2.25n, true)
+assert_equal(10.0n > 9.99n, true)
+assert_equal(-1.5n < 0.0n, true)
+Console.log(`${2.50n * 2.0n}`)
+Console.log(`${-(0.05n)}`)
+1.0n / 3.0n");
+    assert_eq!(logged, ["5.000n", "-0.05n"]);
+    // A quotient that does not end is cut off.
+    assert_eq!(
+        result.map(|value| value.to_string()),
+        Ok("0.33333333333333333333n".to_string())
+    );
+
+    let (_, result, _) = run("1.5n / 0.0n");
+    assert_eq!(result, Err(RuntimeError::DivisionByZero));
+    // Only whole powers are exact.
+    let (_, result, _) = run("2.0n ** 0.5n");
+    assert!(matches!(result, Err(RuntimeError::Overflow { .. })));
+}
+
+#[test]
+fn reports_failures() {
+    let (_, result, _) = run("assert_equal([1, 2], [1, 3])");
+    assert_eq!(
+        result,
+        Err(RuntimeError::AssertionFailed {
+            left: Value::array(vec![Value::Integer(1), Value::Integer(2)]),
+            right: Value::array(vec![Value::Integer(1), Value::Integer(3)]),
+        })
+    );
+
+    let (_, result, _) = run("fun divide(a: Int, b: Int): Int { a / b }\ndivide(1, 0)");
+    assert_eq!(result, Err(RuntimeError::DivisionByZero));
+
+    // Joining a fiber that waits for a value nobody enqueues.
+    let (_, result, _) = run("effect Queue { dequeue(): Int }
+impl for Queue {
+  handler empty() {
+    waiters := []
+    return { dequeue() { waiters = waiters ++ [resume]; 0 } }
+  }
+}
+fun worker() { Queue.dequeue() }
+fun main() { worker.fork().join() }
+main() with Queue.empty()");
+    assert_eq!(result, Err(RuntimeError::Deadlock));
+}
+
+// Flips twice and gives the number of heads, with handlers that collect
+// every way the flips can go, take the first, and let the continuation
+// escape.
+const CHOICE: &str = "effect Choice { flip(): Boolean }
+
+impl for Choice {
+  multi handler all() { return { flip() { resume(true) ++ resume(false) } } } {
+    return(a) { [a] }
+  }
+  handler first() { return { flip() { resume(true) } } }
+  handler escaping() { return { flip() { k = (b) -> resume(b)\nk(true) ++ k(false) } } } {
+    return(a) { [a] }
+  }
+}
+
+fun heads() {
+  a = Choice.flip()
+  b = Choice.flip()
+  (a ? 1 : 0) + (b ? 1 : 0)
+}
+";
+
+fn heads(source: &str) -> Result<Value, RuntimeError> {
+    run(&format!("{}{}", CHOICE, source)).1
+}
+
+fn integers(values: &[i64]) -> Value {
+    Value::array(values.iter().copied().map(Value::Integer).collect())
+}
+
+#[test]
+fn multi_shot_handlers_backtrack() {
+    assert_eq!(
+        heads("heads() with Choice.all()"),
+        Ok(integers(&[2, 1, 1, 0]))
+    );
+}
+
+#[test]
+fn one_shot_handlers_resume_once() {
+    assert_eq!(heads("heads() with Choice.first()"), Ok(Value::Integer(2)));
+    assert_eq!(
+        heads("heads() with Choice.escaping()"),
+        Err(RuntimeError::ResumedTwice {
+            operation: "Choice.flip".to_string()
+        })
+    );
+}
+
+#[test]
+fn forwards_operations_a_handler_does_not_handle() {
+    // `write` passes through the handler of `Choice`, which runs the rest of
+    // the computation for each flip without writing again.
+    assert_eq!(
+        heads(
+            "effect Log { write(String): Int }
+impl for Log {
+  handler counted() {
+    writes := 0
+    return { write(message) { writes = writes + 1\nresume(writes) } }
+  }
+}
+fun logged() { Log.write('a')\nn = Log.write('b')\nheads() + n * 10 }
+logged() with Choice.all() with Log.counted()"
+        ),
+        Ok(integers(&[22, 21, 21, 20]))
+    );
+}